@@ -0,0 +1,151 @@
+//! The hash-chained audit log
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write, BufRead, BufReader};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+
+use crate::{error::EraseError, report::*};
+
+pub fn canonical_json(value: &serde_json::Value) -> String {
+    // serde_json objects are ordered maps, so keys come out sorted
+    value.to_string()
+}
+
+/// Where root's invocations are audited when --audit-log isn't given
+#[cfg(unix)]
+pub const DEFAULT_AUDIT_LOG: &str = "/var/log/secure-eraser/audit.log";
+
+pub(crate) const AUDIT_GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log. `hash` covers `prev` and the rest of the
+/// entry, so editing or removing a line breaks every hash after it.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AuditEntry {
+    seq: u64,
+    time: String,                     // RFC 3339, UTC
+    pid: u32,
+    prev: String,
+    event: serde_json::Value,
+    hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let body = serde_json::json!({
+            "seq": self.seq,
+            "time": self.time,
+            "pid": self.pid,
+            "event": self.event,
+        });
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.prev.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(canonical_json(&body).as_bytes());
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+pub(crate) static AUDIT_LOG: std::sync::OnceLock<std::sync::Mutex<File>> = std::sync::OnceLock::new();
+
+/// Open the audit log for this process. Failure is only a warning: the
+/// log must never be the reason a wipe doesn't happen.
+pub fn open_audit_log(path: &Path) {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    match OpenOptions::new().read(true).append(true).create(true).open(path) {
+        Ok(file) => {
+            let _ = AUDIT_LOG.set(std::sync::Mutex::new(file));
+        }
+        Err(e) => warn!("Warning: cannot open audit log {}: {}", path.display(), e),
+    }
+}
+
+/// The last entry of the log, to chain the next one onto. A torn or garbled
+/// last line is an error: chaining past it would restart the sequence.
+pub(crate) fn last_audit_entry(file: &mut File) -> io::Result<Option<AuditEntry>> {
+    const TAIL: u64 = 1024 * 1024;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let tail = String::from_utf8_lossy(&tail);
+    let Some(last) = tail.lines().rev().find(|line| !line.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let broken = |why: &str| io::Error::new(io::ErrorKind::InvalidData,
+                                            format!("the last entry is {}; not appending until the log is checked with `audit verify`", why));
+    if !tail.ends_with('\n') {
+        return Err(broken("incomplete"));
+    }
+    serde_json::from_str(last).map(Some).map_err(|_| broken("not an audit entry"))
+}
+
+/// Append an event to the audit log, if one is open. Other instances may
+/// share the file, so the tail is read and the line written under a lock.
+pub fn audit(event: serde_json::Value) {
+    let Some(log) = AUDIT_LOG.get() else {
+        return;
+    };
+    let Ok(mut file) = log.lock() else {
+        return;
+    };
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::flock(file.as_raw_fd(), libc::LOCK_EX);
+    }
+    let result = last_audit_entry(&mut file).and_then(|last| {
+        let mut entry = AuditEntry {
+            seq: last.as_ref().map_or(0, |e| e.seq + 1),
+            time: rfc3339(unix_time()),
+            pid: std::process::id(),
+            prev: last.map_or_else(|| AUDIT_GENESIS.to_string(), |e| e.hash),
+            event,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        let line = serde_json::to_string(&entry).map_err(io::Error::other)? + "\n";
+        file.write_all(line.as_bytes())
+    });
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::flock(file.as_raw_fd(), libc::LOCK_UN);
+    }
+    if let Err(e) = result {
+        warn!("Warning: audit log write failed: {}", e);
+    }
+}
+
+/// Walk the chain. Returns the entry count and the final hash; keeping a
+/// copy of that hash elsewhere is what makes truncation of the tail visible.
+pub fn verify_audit_log(path: &Path) -> Result<(u64, String), EraseError> {
+    let file = File::open(path).map_err(|e| EraseError::Failed { what: format!("Cannot open {}", path.display()), source: e })?;
+    let mut prev = AUDIT_GENESIS.to_string();
+    let mut count = 0u64;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_no = index + 1;
+        let entry: AuditEntry = serde_json::from_str(&line)
+            .map_err(|e| EraseError::Other(format!("line {}: not an audit entry: {}", line_no, e)))?;
+        if entry.seq != count {
+            return Err(EraseError::Other(format!("line {}: sequence {} where {} was expected (lines removed?)", line_no, entry.seq, count)));
+        }
+        if entry.prev != prev {
+            return Err(EraseError::Other(format!("line {}: does not chain to the previous entry", line_no)));
+        }
+        if entry.compute_hash() != entry.hash {
+            return Err(EraseError::Other(format!("line {}: hash mismatch, the entry was modified", line_no)));
+        }
+        prev = entry.hash;
+        count += 1;
+    }
+    Ok((count, prev))
+}
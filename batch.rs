@@ -0,0 +1,202 @@
+//! A command-line erase of one or more devices: each target in turn, with
+//! its hooks, status file and run record
+
+use tracing::{error, info};
+
+use crate::{audit::*, device::*, erase::*, error::*, hooks::*, patterns::*, report::*, ui::*, verify::*};
+
+/// How every target of a batch is erased
+pub struct BatchOptions {
+    pub pattern: WipePattern,
+    pub verify: Option<VerifyMode>,
+    pub sync_mode: SyncMode,
+    pub direct_io: DirectIo,
+    pub quick: bool,  // `quick_erase` instead of the passes
+    pub auto_tune: bool,
+    pub keep_going: bool,
+    pub fail_fast: bool,  // Leave the rest of the batch alone after a failure
+    pub stamp_key: Option<Vec<u8>>,
+    pub init_table: Option<TableKind>,
+    pub format_after: Option<(FsKind, Option<String>)>,
+    pub pre_cmd: Option<String>,  // A failing command vetoes its device
+    pub status_file: Option<String>,
+    pub confirmation: Confirmation,
+}
+
+/// What a batch did to each target, in order
+pub struct BatchOutcome {
+    pub results: Vec<BatchResult>,
+    pub records: Vec<RunRecord>,
+    pub error: Option<EraseError>,  // A single target's failure, returned as it was
+    pub bad_blocks: Option<Vec<BadBlock>>,  // Of the last target that got far enough to have a map
+}
+
+/// Erase each target in turn. A failure is recorded and the next device is
+/// still erased unless `fail_fast`; after an interrupt the rest are left
+/// alone. `ranges` and `confirmed_as` go with `targets` one for one.
+pub fn run_batch(
+    eraser: &mut SecureEraser,
+    targets: &[DeviceInfo],
+    ranges: &[Option<ByteRange>],
+    confirmed_as: &[ExpectedDevice],
+    options: &BatchOptions,
+) -> BatchOutcome {
+    let BatchOptions { pattern, verify, sync_mode, direct_io, quick, fail_fast, init_table, .. } = *options;
+    let batch = targets.len() > 1;
+    let host = hostname();
+    let status_file = options.status_file.as_deref().map(StatusFile::new);
+    let mut results: Vec<BatchResult> = Vec::new();
+    let mut records: Vec<RunRecord> = Vec::new();
+    let mut error = None;
+    let mut bad_blocks = None;
+    for (index, target) in targets.iter().enumerate() {
+        let _device_span = tracing::info_span!("erase", device = %target.path.display()).entered();
+        let identity = eraser.device_provider().identity(target);
+        audit(serde_json::json!({ "event": "device", "identity": identity }));
+        let started_at = rfc3339(unix_time());
+        // After an interrupt, the rest of the batch is left alone
+        if is_interrupted() || (fail_fast && results.iter().any(|r| !r.succeeded())) {
+            let result = BatchResult {
+                device: target.path.clone(),
+                size: target.size,
+                attempted: false,
+                verified: None,
+                error: None,
+                errno: None,
+                offset: None,
+                unwritable: 0,
+                duration_secs: 0.0,
+            };
+            records.push(RunRecord {
+                tool_version: env!("CARGO_PKG_VERSION").to_string(),
+                hostname: host.clone(),
+                device: identity,
+                method: pattern,
+                passes: pattern.pass_count(),
+                operator: options.confirmation.user.clone(),
+                started_at: started_at.clone(),
+                finished_at: started_at,
+                succeeded: false,
+                outcome: result.clone(),
+                report: None,
+            });
+            results.push(result);
+            continue;
+        }
+        if batch {
+            info!("\n=== [{}/{}] {} ===", index + 1, targets.len(), target.path.display());
+        }
+
+        let mut status = status_file.as_ref().map(|file| file.for_device(&target.path, index, targets.len()));
+        let progress_callback: Option<ProgressCallback> = status.clone().map(|mut status| -> ProgressCallback {
+            Box::new(move |update: &ProgressUpdate| status.progress(update))
+        });
+
+        // A failing --pre-cmd vetoes the device before anything is written
+        let vetoed = options.pre_cmd.as_deref().and_then(|command| {
+            let vars = hook_env(&identity, pattern);
+            match run_hook(command, &vars) {
+                Ok(status) if status.success() => None,
+                Ok(status) => Some(format!("--pre-cmd failed ({}); {} was not touched", status, target.path.display())),
+                Err(e) => Some(format!("Cannot run --pre-cmd: {}", e)),
+            }
+        });
+        let attempted = vetoed.is_none();
+
+        // Perform the erase
+        let started = std::time::Instant::now();
+        let outcome = match vetoed {
+            Some(reason) => Err(EraseError::Other(reason)),
+            None if quick => {
+                eraser.expect_device(Some(confirmed_as[index].clone()));
+                eraser.set_range(ranges[index]);
+                eraser.quick_erase(&target.path, sync_mode, direct_io, progress_callback)
+            }
+            None => {
+                let mut job = EraseJob::new(&target.path)
+                    .pattern(pattern)
+                    .verify(verify)
+                    .sync_mode(sync_mode)
+                    .direct_io(direct_io)
+                    .auto_tune(options.auto_tune)
+                    .keep_going(options.keep_going)
+                    .stamp_key(options.stamp_key.as_deref())
+                    .range(ranges[index])
+                    .expect_device(Some(confirmed_as[index].clone()));
+                if let Some(callback) = progress_callback {
+                    job = job.on_progress(callback);
+                }
+                job.build().and_then(|job| eraser.run(job))
+            }
+        };
+        let mut result = BatchResult {
+            device: target.path.clone(),
+            size: target.size,
+            attempted,
+            verified: None,
+            error: None,
+            errno: None,
+            offset: None,
+            unwritable: 0,
+            duration_secs: started.elapsed().as_secs_f64(),
+        };
+        if let Err(ref e) = outcome {
+            emit(ProgressEvent::Error { device: &target.path, error: e.to_string() });
+        }
+        if let Some(status) = status.as_mut() {
+            status.finish(outcome.as_ref().err().map(|e| e.to_string()).as_deref());
+        }
+        let mut erase_report = None;
+        match outcome {
+            Ok(mut report) => {
+                report.confirmation = Some(options.confirmation.clone());
+                if let Some(kind) = init_table {
+                    init_partition_table(eraser, kind, &mut report);
+                }
+                if let Some((kind, ref label)) = options.format_after {
+                    format_filesystem(eraser, kind, label.as_deref(), &mut report);
+                }
+                emit(ProgressEvent::Result(&report));
+                print_report(&report);
+                result.verified = report.verification_passed();
+                result.unwritable = report.unwritable.iter().map(|bad| bad.length).sum();
+                bad_blocks = Some(report.bad_blocks.clone());
+                erase_report = Some(report);
+            }
+            Err(e) => {
+                let interrupted = matches!(e, EraseError::Interrupted(_) | EraseError::Cancelled);
+                if interrupted {
+                    human!("\n{}", e);
+                } else if batch {
+                    error!("Error: {}", e);
+                }
+                if let EraseError::VerificationFailed(failed) = &e {
+                    result.verified = Some(false);
+                    bad_blocks = Some(failed.bad_blocks.clone());
+                }
+                (result.errno, result.offset) = (e.errno(), e.offset());
+                result.error = Some(e.to_string());
+                // A single device reports its error as before, once the
+                // report file is written
+                if !batch && !interrupted {
+                    error = Some(e);
+                }
+            }
+        }
+        records.push(RunRecord {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            hostname: host.clone(),
+            device: identity,
+            method: pattern,
+            passes: pattern.pass_count(),
+            operator: options.confirmation.user.clone(),
+            started_at,
+            finished_at: rfc3339(unix_time()),
+            succeeded: result.succeeded(),
+            outcome: result.clone(),
+            report: erase_report,
+        });
+        results.push(result);
+    }
+    BatchOutcome { results, records, error, bad_blocks }
+}
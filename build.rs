@@ -0,0 +1,26 @@
+//! With the `ffi` feature, writes the C header for ffi.rs to
+//! <target dir>/include/memerase.h
+
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        use std::path::PathBuf;
+
+        let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let target_dir = std::env::var_os("CARGO_TARGET_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| crate_dir.join("target"));
+        let source = crate_dir.join("ffi.rs");
+        println!("cargo:rerun-if-changed={}", source.display());
+
+        cbindgen::Builder::new()
+            .with_language(cbindgen::Language::C)
+            .with_include_guard("MEMERASE_H")
+            .with_header("/* Generated from ffi.rs by build.rs; do not edit. */")
+            .with_cpp_compat(true)  // extern "C" around the declarations for the C++ side
+            .with_src(&source)
+            .generate()
+            .expect("cbindgen could not read ffi.rs")
+            .write_to_file(target_dir.join("include").join("memerase.h"));
+    }
+}
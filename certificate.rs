@@ -0,0 +1,133 @@
+//! Signed erasure certificates: Ed25519 keys, signing a run's records, and
+//! checking a certificate later
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use crate::{audit::canonical_json, error::EraseError, report::*};
+
+const CERTIFICATE_FORMAT: &str = "memerase-certificate-v1";
+
+/// A certificate as written by `--certificate`. The signature covers the
+/// canonical form of `certificate`: compact JSON with sorted keys.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedCertificate {
+    pub certificate: serde_json::Value,
+    pub signature: String,            // Ed25519, hex
+    pub public_key: String,           // hex
+    pub fingerprint: String,
+}
+
+/// Contiguous lowercase hex, e.g. "00ff3a"
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err("not a hex string".to_string());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| "not a hex string".to_string()))
+        .collect()
+}
+
+/// Short identifier of a public key, printed by generate-key and embedded
+/// in certificates so the signer can be checked at a glance
+pub fn key_fingerprint(key: &ed25519_dalek::VerifyingKey) -> String {
+    blake3::hash(key.as_bytes()).to_hex()[..32].to_string()
+}
+
+/// Write a new key pair: the secret key to `path` (owner-only), the public
+/// key to `path.pub`
+pub fn generate_signing_key(path: &Path) -> Result<ed25519_dalek::VerifyingKey, EraseError> {
+    let key = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)
+        .map_err(|e| EraseError::Other(format!("Cannot create {}: {}", path.display(), e)))?;
+    file.write_all(format!("{}\n", hex_encode(key.as_bytes())).as_bytes())?;
+    let mut public = path.as_os_str().to_owned();
+    public.push(".pub");
+    std::fs::write(&public, format!("{}\n", hex_encode(key.verifying_key().as_bytes())))?;
+    Ok(key.verifying_key())
+}
+
+pub fn load_signing_key(path: &Path) -> Result<ed25519_dalek::SigningKey, EraseError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| EraseError::Other(format!("Cannot read signing key {}: {}", path.display(), e)))?;
+    let bytes: [u8; 32] = hex_decode(&text)
+        .map_err(EraseError::Other)?
+        .try_into()
+        .map_err(|_| EraseError::Other(format!("{} is not an Ed25519 secret key", path.display())))?;
+    Ok(ed25519_dalek::SigningKey::from_bytes(&bytes))
+}
+
+pub fn parse_public_key(text: &str) -> Result<ed25519_dalek::VerifyingKey, EraseError> {
+    let bytes: [u8; 32] = hex_decode(text)
+        .map_err(EraseError::Other)?
+        .try_into()
+        .map_err(|_| EraseError::Other("not an Ed25519 public key".to_string()))?;
+    ed25519_dalek::VerifyingKey::from_bytes(&bytes).map_err(|e| EraseError::Other(e.to_string()))
+}
+
+/// Build and sign the certificate for a finished run
+pub fn sign_certificate(
+    key: &ed25519_dalek::SigningKey,
+    fields: &std::collections::BTreeMap<String, String>,
+    records: &[RunRecord],
+) -> SignedCertificate {
+    use ed25519_dalek::Signer;
+
+    let certificate = serde_json::json!({
+        "format": CERTIFICATE_FORMAT,
+        "issued_at": rfc3339(unix_time()),
+        "fields": fields,
+        "devices": records,
+    });
+    let signature = key.sign(canonical_json(&certificate).as_bytes());
+    SignedCertificate {
+        certificate,
+        signature: hex_encode(&signature.to_bytes()),
+        public_key: hex_encode(key.verifying_key().as_bytes()),
+        fingerprint: key_fingerprint(&key.verifying_key()),
+    }
+}
+
+/// Check a certificate's signature and return the key that made it. The
+/// embedded key only proves integrity; pass `trusted` to also prove who
+/// signed it.
+pub fn verify_certificate(
+    signed: &SignedCertificate,
+    trusted: Option<&ed25519_dalek::VerifyingKey>,
+) -> Result<ed25519_dalek::VerifyingKey, EraseError> {
+    let key = parse_public_key(&signed.public_key)?;
+    if key_fingerprint(&key) != signed.fingerprint {
+        return Err(EraseError::Other("Fingerprint does not match the embedded public key".to_string()));
+    }
+    if let Some(trusted) = trusted {
+        if trusted != &key {
+            return Err(EraseError::Other(format!("Signed by {}, not by the trusted key {}",
+                                                 signed.fingerprint, key_fingerprint(trusted))));
+        }
+    }
+    let signature: [u8; 64] = hex_decode(&signed.signature)
+        .map_err(EraseError::Other)?
+        .try_into()
+        .map_err(|_| EraseError::Other("Malformed signature".to_string()))?;
+    key.verify_strict(
+        canonical_json(&signed.certificate).as_bytes(),
+        &ed25519_dalek::Signature::from_bytes(&signature),
+    )
+    .map_err(|_| EraseError::Other("Signature does not match: the certificate was altered or signed with another key".to_string()))?;
+    Ok(key)
+}
@@ -0,0 +1,155 @@
+//! The TOML config file: defaults for the command line, and the protected list
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+use crate::{device::*, error::*, patterns::*, report::*, verify::*};
+
+/// Site-wide defaults; a user's own file overrides them key by key
+pub const SYSTEM_CONFIG: &str = "/etc/memerase.toml";
+
+const CONFIG_KEYS: [&str; 7] = ["pattern", "verify", "block_size", "sync_mode", "report_dir", "protected", "blacklist"];
+
+/// Defaults from a TOML file. Every key is optional, and anything given on
+/// the command line wins over it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub pattern: Option<String>,
+    pub verify: Option<String>,        // sample, full or hash; --no-verify turns it off
+    pub block_size: Option<String>,
+    pub sync_mode: Option<String>,
+    pub report_dir: Option<PathBuf>,   // A JSON report per run, unless --report is given
+    pub protected: Vec<String>,        // Serials, WWNs or by-id paths never to erase
+    blacklist: Vec<String>,            // The old name for protected; a file may use both
+    #[serde(skip)]
+    origin: HashMap<&'static str, PathBuf>,  // The file that set each key
+    #[serde(skip)]
+    protected_by: Vec<PathBuf>,        // The file each protected entry came from
+    #[serde(skip)]
+    warnings: Vec<String>,             // Reported once logging is up
+}
+
+/// The system file, then $XDG_CONFIG_HOME (or ~/.config)/memerase/config.toml
+fn default_config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(SYSTEM_CONFIG)];
+    let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = user_dir {
+        paths.push(dir.join("memerase").join("config.toml"));
+    }
+    paths
+}
+
+impl Config {
+    /// An explicit --config must exist; missing default files are skipped
+    pub fn load(explicit: Option<&Path>) -> Result<Config, EraseError> {
+        let mut config = Config::default();
+        match explicit {
+            Some(path) => config.merge_file(path)?,
+            None => {
+                for path in default_config_paths().iter().filter(|p| p.exists()) {
+                    config.merge_file(path)?;
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    fn merge_file(&mut self, path: &Path) -> Result<(), EraseError> {
+        let invalid = |e: &dyn std::fmt::Display| EraseError::InvalidArgument(format!("Invalid config {}: {}", path.display(), e));
+        let text = std::fs::read_to_string(path)
+            .map_err(|source| EraseError::Failed { what: format!("Cannot read config {}", path.display()), source })?;
+        let table: toml::Table = text.parse().map_err(|e| invalid(&e))?;
+        for key in table.keys().filter(|key| !CONFIG_KEYS.contains(&key.as_str())) {
+            self.warnings.push(format!("Warning: {}: unknown key '{}' ignored", path.display(), key));
+        }
+        let mut file: Config = table.try_into().map_err(|e| invalid(&e))?;
+        file.protected.append(&mut file.blacklist);
+        file.validate().map_err(|e| invalid(&e))?;
+
+        let mut set = |key: &'static str, present: bool| {
+            if present {
+                self.origin.insert(key, path.to_path_buf());
+            }
+        };
+        set("pattern", file.pattern.is_some());
+        set("verify", file.verify.is_some());
+        set("block_size", file.block_size.is_some());
+        set("sync_mode", file.sync_mode.is_some());
+        set("report_dir", file.report_dir.is_some());
+        set("protected", !file.protected.is_empty());
+        self.pattern = file.pattern.or(self.pattern.take());
+        self.verify = file.verify.or(self.verify.take());
+        self.block_size = file.block_size.or(self.block_size.take());
+        self.sync_mode = file.sync_mode.or(self.sync_mode.take());
+        self.report_dir = file.report_dir.or(self.report_dir.take());
+        // Both files' entries are protected
+        for rule in &file.protected {
+            if rule.starts_with("/dev/") && !rule.starts_with("/dev/disk/") {
+                self.warnings.push(format!("Warning: {}: protected entry '{}' is a kernel name, which can change \
+                                            between boots; prefer a serial, WWN or /dev/disk/by-id path", path.display(), rule));
+            }
+        }
+        self.protected_by.extend(std::iter::repeat_n(path.to_path_buf(), file.protected.len()));
+        self.protected.extend(file.protected);
+        Ok(())
+    }
+
+    /// Check values up front, so a bad file is reported by name rather than
+    /// as a bad command-line default
+    fn validate(&self) -> Result<(), String> {
+        if let Some(pattern) = &self.pattern {
+            if !PATTERN_NAMES.iter().any(|name| name.eq_ignore_ascii_case(pattern)) {
+                return Err(format!("pattern: unknown pattern '{}' (expected one of {})", pattern, PATTERN_NAMES.join(", ")));
+            }
+        }
+        if let Some(verify) = &self.verify {
+            verify.parse::<VerifyMode>().map_err(|e| format!("verify: {}", e))?;
+        }
+        if let Some(size) = &self.block_size {
+            parse_size(size).map_err(|e| format!("block_size: {}", e))?;
+        }
+        if let Some(mode) = &self.sync_mode {
+            mode.parse::<SyncMode>().map_err(|e| format!("sync_mode: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// The protected entry covering this device, what it matched on, and the
+    /// file it came from. An entry can be a serial, a WWN (with or without
+    /// its naa./eui./0x prefix), or a path that resolves to the device.
+    pub fn protecting_rule(&self, device: &DeviceInfo) -> Option<(&str, &'static str, &Path)> {
+        let serial = device_serial(&device.name);
+        let wwn = device_wwn(&device.name).map(|wwn| normalize_wwn(&wwn));
+        let path = std::fs::canonicalize(&device.path).unwrap_or_else(|_| device.path.clone());
+        self.protected.iter().zip(&self.protected_by).find_map(|(rule, file)| {
+            let matched = if serial.as_deref() == Some(rule.as_str()) {
+                "serial"
+            } else if wwn.is_some() && wwn.as_deref() == Some(normalize_wwn(rule).as_str()) {
+                "WWN"
+            } else if rule.starts_with('/') && std::fs::canonicalize(rule).is_ok_and(|p| p == path) {
+                "path"
+            } else {
+                return None;
+            };
+            Some((rule.as_str(), matched, file.as_path()))
+        })
+    }
+
+    /// Where a key's value came from: `config <file>`, or "default"
+    pub fn source(&self, key: &str) -> String {
+        match self.origin.get(key) {
+            Some(path) => format!("config {}", path.display()),
+            None => "default".to_string(),
+        }
+    }
+
+    /// Problems that didn't stop the load, e.g. unknown keys
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
@@ -0,0 +1,423 @@
+//! --daemon: erase jobs requested over a Unix socket, each on a thread of
+//! its own, with their state and reports kept for the clients
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "dbus")]
+use crate::dbus::DbusService;
+use crate::{audit::*, config::*, device::*, erase::*, error::*, metrics::*, patterns::*, provider::*, report::*, ui::*, verify::*};
+
+/// A wipe started over the daemon socket. The table keeps finished jobs
+/// too, so their reports can still be fetched.
+pub(crate) struct DaemonJob {
+    pub(crate) id: u64,
+    pub(crate) device: PathBuf,
+    method: WipePattern,
+    cancel: CancellationToken,
+    pub(crate) state: std::sync::Mutex<JobState>,
+}
+
+#[derive(Default)]
+pub(crate) struct JobState {
+    pub(crate) status: &'static str,       // "running", "succeeded", "failed" or "cancelled"
+    pub(crate) percent: f64,
+    pub(crate) eta_secs: Option<f64>,
+    pub(crate) error: Option<String>,
+    record: Option<RunRecord>,
+}
+
+impl DaemonJob {
+    fn is_running(&self) -> bool {
+        self.state.lock().unwrap().status == "running"
+    }
+
+    pub(crate) fn status(&self) -> serde_json::Value {
+        let state = self.state.lock().unwrap();
+        serde_json::json!({
+            "job": self.id,
+            "device": self.device,
+            "method": self.method,
+            "state": state.status,
+            "percent": state.percent,
+            "eta_secs": state.eta_secs,
+            "error": state.error,
+        })
+    }
+}
+
+/// What every daemon job falls back to when the request leaves a setting out
+#[derive(Clone, Copy)]
+pub struct JobDefaults {
+    pub pattern: WipePattern,
+    pub verify: Option<VerifyMode>,
+    pub sync_mode: SyncMode,
+    pub direct_io: DirectIo,
+    pub block_size: usize,
+    pub write_timeout: Option<std::time::Duration>,  // Warning only: one hung device can't be allowed to exit the daemon
+}
+
+pub(crate) struct Daemon {
+    config: Config,
+    defaults: JobDefaults,
+    metrics: Option<Arc<Metrics>>,
+    pub(crate) jobs: std::sync::Mutex<Vec<Arc<DaemonJob>>>,
+    workers: std::sync::Mutex<Vec<std::thread::JoinHandle<()>>>,
+    next_id: std::sync::atomic::AtomicU64,
+}
+
+/// Serve wipe jobs over a Unix socket until the process-wide interrupt
+/// (see `enable_interrupt`). Each request and response is one JSON object
+/// per line; see `Daemon::handle` for the commands. The socket is owner
+/// only, or shared with `group`; `dbus` also offers the jobs on the system
+/// bus.
+pub fn run_daemon(
+    socket: &Path,
+    group: Option<u32>,
+    dbus: bool,
+    config: Config,
+    defaults: JobDefaults,
+    metrics: Option<Arc<Metrics>>,
+) -> Result<(), EraseError> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if dbus && cfg!(not(feature = "dbus")) {
+        return Err(EraseError::InvalidArgument("--dbus needs a build with the dbus feature".to_string()));
+    }
+    // A socket file nobody answers on is left over from a daemon that died
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(EraseError::InUse {
+                device: socket.to_path_buf(),
+                source: io::Error::new(io::ErrorKind::AddrInUse, "another daemon is already listening on it"),
+            });
+        }
+        std::fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)
+        .map_err(|source| EraseError::Failed { what: format!("Cannot listen on {}", socket.display()), source })?;
+    // Anyone who can connect can erase disks: root only, plus one group if asked
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(if group.is_some() { 0o660 } else { 0o600 }))?;
+    if let Some(gid) = group {
+        std::os::unix::fs::chown(socket, None, Some(gid))?;
+    }
+    listener.set_nonblocking(true)?;
+
+    let daemon = Arc::new(Daemon {
+        config,
+        defaults,
+        metrics,
+        jobs: std::sync::Mutex::new(Vec::new()),
+        workers: std::sync::Mutex::new(Vec::new()),
+        next_id: std::sync::atomic::AtomicU64::new(1),
+    });
+    // Jobs run side by side, each with a bar of its own
+    MultiBars::enable();
+    human!("Listening on {}", socket.display());
+    #[cfg(feature = "dbus")]
+    let mut bus = match dbus.then(|| DbusService::register(&daemon)).transpose() {
+        Ok(bus) => bus,
+        Err(e) => {
+            let _ = std::fs::remove_file(socket);
+            return Err(e);
+        }
+    };
+    audit(serde_json::json!({ "event": "daemon-start", "socket": socket, "gid": group, "dbus": dbus }));
+
+    while !is_interrupted() {
+        #[cfg(feature = "dbus")]
+        if let Some(bus) = bus.as_mut() {
+            bus.publish(&daemon);
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let daemon = Arc::clone(&daemon);
+                std::thread::spawn(move || {
+                    if let Err(e) = daemon.serve(stream) {
+                        debug!("daemon client: {}", e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(std::time::Duration::from_millis(200)),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => warn!("Warning: accept failed on {}: {}", socket.display(), e),
+        }
+    }
+
+    // Running jobs saw the same signal and unwind on their own
+    human!("Shutting down; waiting for running jobs to stop");
+    let workers = std::mem::take(&mut *daemon.workers.lock().unwrap());
+    for worker in workers {
+        let _ = worker.join();
+    }
+    let _ = std::fs::remove_file(socket);
+    audit(serde_json::json!({ "event": "daemon-stop" }));
+    Ok(())
+}
+
+/// Who is on the other end of a daemon connection, for the audit trail
+#[cfg(target_os = "linux")]
+fn peer_user(stream: &std::os::unix::net::UnixStream) -> String {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED,
+                         &mut cred as *mut libc::ucred as *mut libc::c_void, &mut len)
+    };
+    if rc == 0 {
+        format!("uid {} (pid {}, via daemon socket)", cred.uid, cred.pid)
+    } else {
+        "unknown (via daemon socket)".to_string()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peer_user(_stream: &std::os::unix::net::UnixStream) -> String {
+    "unknown (via daemon socket)".to_string()
+}
+
+impl Daemon {
+    /// Answer requests on one connection until the client hangs up
+    fn serve(self: &Arc<Self>, stream: std::os::unix::net::UnixStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        let operator = peer_user(&stream);
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<serde_json::Value>(&line) {
+                Ok(request) => self.handle(&request, &operator)
+                    .unwrap_or_else(|e| serde_json::json!({ "ok": false, "error": e })),
+                Err(e) => serde_json::json!({ "ok": false, "error": format!("invalid JSON: {}", e) }),
+            };
+            writeln!(writer, "{}", response)?;
+        }
+        Ok(())
+    }
+
+    /// Commands, selected by the "cmd" field:
+    ///   list                          attached devices
+    ///   start   device [method] [verify] [sync_mode]
+    ///   jobs                          every job and its state
+    ///   status  job                   progress of one job
+    ///   cancel  job                   stop a running job
+    ///   report  job                   the run record of a finished job
+    pub(crate) fn handle(self: &Arc<Self>, request: &serde_json::Value, operator: &str) -> Result<serde_json::Value, String> {
+        let field = |name: &str| request.get(name).and_then(|v| v.as_str());
+        let job = || -> Result<Arc<DaemonJob>, String> {
+            let id = request.get("job").and_then(|v| v.as_u64()).ok_or("missing \"job\"")?;
+            self.jobs.lock().unwrap().iter()
+                .find(|job| job.id == id)
+                .cloned()
+                .ok_or_else(|| format!("no job {}", id))
+        };
+        match field("cmd").ok_or("missing \"cmd\"")? {
+            "list" => {
+                let devices = SecureEraser::new().list_devices().map_err(|e| e.to_string())?;
+                let listed: Vec<serde_json::Value> = devices.iter()
+                    .map(|device| {
+                        let mut entry = serde_json::to_value(device).unwrap_or_default();
+                        entry["protected"] = self.config.protecting_rule(device).is_some().into();
+                        entry
+                    })
+                    .collect();
+                Ok(serde_json::json!({ "ok": true, "devices": listed }))
+            }
+            "start" => {
+                let device = field("device").ok_or("missing \"device\"")?;
+                let mut settings = self.defaults;
+                if let Some(method) = field("method") {
+                    settings.pattern = method.parse()?;
+                }
+                if let Some(verify) = request.get("verify") {
+                    settings.verify = match verify.as_str() {
+                        None if verify.is_null() => None,
+                        Some("none") => None,
+                        Some(mode) => Some(mode.parse()?),
+                        None => return Err("\"verify\" must be a string or null".to_string()),
+                    };
+                }
+                if let Some(sync_mode) = field("sync_mode") {
+                    settings.sync_mode = sync_mode.parse()?;
+                }
+                let job = self.start(Path::new(device), settings, operator)?;
+                Ok(serde_json::json!({ "ok": true, "job": job.id }))
+            }
+            "jobs" => {
+                let jobs: Vec<serde_json::Value> = self.jobs.lock().unwrap().iter().map(|job| job.status()).collect();
+                Ok(serde_json::json!({ "ok": true, "jobs": jobs }))
+            }
+            "status" => {
+                let mut status = job()?.status();
+                status["ok"] = true.into();
+                Ok(status)
+            }
+            "cancel" => {
+                let job = job()?;
+                if !job.is_running() {
+                    return Err(format!("job {} is not running", job.id));
+                }
+                job.cancel.cancel();
+                audit(serde_json::json!({ "event": "job-cancel", "job": job.id, "operator": operator }));
+                Ok(serde_json::json!({ "ok": true, "job": job.id }))
+            }
+            "report" => {
+                let job = job()?;
+                let state = job.state.lock().unwrap();
+                match &state.record {
+                    Some(record) => Ok(serde_json::json!({ "ok": true, "job": job.id, "report": record })),
+                    None => Err(format!("job {} is still running", job.id)),
+                }
+            }
+            other => Err(format!("unknown command {:?}", other)),
+        }
+    }
+
+    /// Run the same checks as a command-line erase, then wipe on a thread
+    /// of its own. There is no prompt: being allowed on the socket is the
+    /// confirmation.
+    fn start(self: &Arc<Self>, path: &Path, settings: JobDefaults, operator: &str) -> Result<Arc<DaemonJob>, String> {
+        let provider = system_provider();
+        let devices = provider.list().map_err(|e| e.to_string())?;
+        let device = resolve_target(provider.as_ref(), path, &devices, false).map_err(|e| e.to_string())?;
+        if let Some((rule, matched, file)) = self.config.protecting_rule(&device) {
+            return Err(format!("{} is protected by '{}' ({}) in {}", device.path.display(), rule, matched, file.display()));
+        }
+        if system_disks().contains(&device.name) {
+            return Err(format!("{} holds the running system", device.path.display()));
+        }
+        if device.is_mounted || !provider.holders(&device.name).is_empty() {
+            return Err(format!("{} is mounted or in use", device.path.display()));
+        }
+
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            if let Some(busy) = jobs.iter().find(|job| job.device == device.path && job.is_running()) {
+                return Err(format!("{} is already being erased by job {}", device.path.display(), busy.id));
+            }
+            let job = Arc::new(DaemonJob {
+                id: self.next_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+                device: device.path.clone(),
+                method: settings.pattern,
+                cancel: CancellationToken::new(),
+                state: std::sync::Mutex::new(JobState { status: "running", ..Default::default() }),
+            });
+            jobs.push(Arc::clone(&job));
+            job
+        };
+
+        let identity = provider.identity(&device);
+        audit(serde_json::json!({ "event": "job-start", "job": job.id, "identity": identity, "operator": operator }));
+        info!("Job {}: erasing {} with {:?}", job.id, device.path.display(), settings.pattern);
+        if let Some(metrics) = &self.metrics {
+            metrics.start(&device.path, identity.serial.as_deref());
+        }
+        let worker = {
+            let job = Arc::clone(&job);
+            let operator = operator.to_string();
+            let metrics = self.metrics.clone();
+            std::thread::spawn(move || run_job(&job, device, identity, settings, operator, metrics))
+        };
+        let mut workers = self.workers.lock().unwrap();
+        workers.retain(|worker| !worker.is_finished());
+        workers.push(worker);
+        Ok(job)
+    }
+}
+
+/// Body of a daemon job's thread
+fn run_job(job: &Arc<DaemonJob>, device: DeviceInfo, identity: DeviceIdentity, settings: JobDefaults, operator: String,
+           metrics: Option<Arc<Metrics>>) {
+    let _job_span = tracing::info_span!("job", id = job.id, device = %device.path.display()).entered();
+    let started_at = rfc3339(unix_time());
+    let started = std::time::Instant::now();
+
+    let mut eraser = SecureEraser::new();
+    eraser.set_cancellation(Some(job.cancel.clone()));
+    eraser.set_write_timeout(settings.write_timeout, None);
+    let progress = Arc::clone(job);
+    let progress_metrics = metrics.clone();
+    let progress_device = device.path.clone();
+    let outcome = EraseJob::new(&device.path)
+        .pattern(settings.pattern)
+        .verify(settings.verify)
+        .sync_mode(settings.sync_mode)
+        .direct_io(settings.direct_io)
+        .block_size(settings.block_size)
+        .expect_device(Some(ExpectedDevice { size: device.size, serial: identity.serial.clone() }))
+        .on_progress(move |update| {
+            let mut state = progress.state.lock().unwrap();
+            state.percent = update.percent();
+            state.eta_secs = update.eta_secs;
+            if let Some(bars) = MULTI_BARS.get() {
+                bars.job_progress(progress.id, update.bytes_done, update.total_bytes);
+            }
+            if let Some(metrics) = &progress_metrics {
+                metrics.progress(&progress_device, update);
+            }
+        })
+        .build()
+        .and_then(|job| eraser.run(job));
+
+    let mut result = BatchResult {
+        device: device.path.clone(),
+        size: device.size,
+        attempted: true,
+        verified: None,
+        error: None,
+        errno: None,
+        offset: None,
+        unwritable: 0,
+        duration_secs: started.elapsed().as_secs_f64(),
+    };
+    let mut erase_report = None;
+    let status = match outcome {
+        Ok(mut report) => {
+            report.confirmation = Some(Confirmation { method: "daemon request".to_string(), user: operator.clone() });
+            result.verified = report.verification_passed();
+            result.unwritable = report.unwritable.iter().map(|bad| bad.length).sum();
+            erase_report = Some(report);
+            if result.verified == Some(false) { "failed" } else { "succeeded" }
+        }
+        Err(e) => {
+            if matches!(e, EraseError::VerificationFailed(_)) {
+                result.verified = Some(false);
+            }
+            (result.errno, result.offset) = (e.errno(), e.offset());
+            result.error = Some(e.to_string());
+            if matches!(e, EraseError::Interrupted(_) | EraseError::Cancelled) { "cancelled" } else { "failed" }
+        }
+    };
+    if let Some(bars) = MULTI_BARS.get() {
+        bars.job_done(job.id);
+    }
+    info!("Job {}: {} {}", job.id, device.path.display(), status);
+    let record = RunRecord {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        hostname: hostname(),
+        device: identity,
+        method: settings.pattern,
+        passes: settings.pattern.pass_count(),
+        operator,
+        started_at,
+        finished_at: rfc3339(unix_time()),
+        succeeded: result.succeeded(),
+        outcome: result,
+        report: erase_report,
+    };
+    audit(serde_json::json!({ "event": "job-finish", "job": job.id, "state": status, "succeeded": record.succeeded }));
+    if let Some(metrics) = &metrics {
+        metrics.finish(&device.path, status, record.report.as_ref());
+    }
+
+    let mut state = job.state.lock().unwrap();
+    state.status = status;
+    state.error = record.outcome.error.clone();
+    state.record = Some(record);
+}
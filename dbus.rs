@@ -0,0 +1,159 @@
+//! --dbus: the daemon's jobs on the system bus, for desktop front-ends.
+//! Every call goes through `Daemon::handle`, so the checks and the job
+//! table are the socket's; who may call what is up to the bus policy
+//! (dbus/org.memerase.Eraser1.conf).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::{daemon::*, error::EraseError};
+
+const DBUS_NAME: &str = "org.memerase.Eraser1";
+const DBUS_PATH: &str = "/org/memerase/Eraser1";
+
+/// What ListDevices returns for each disk: path, name, size, removable,
+/// mounted, protected
+type DbusDevice = (String, String, u64, bool, bool, bool);
+
+struct DbusEraser {
+    daemon: Arc<Daemon>,
+}
+
+impl DbusEraser {
+    fn call(&self, request: serde_json::Value, operator: &str) -> zbus::fdo::Result<serde_json::Value> {
+        self.daemon.handle(&request, operator).map_err(zbus::fdo::Error::Failed)
+    }
+}
+
+#[zbus::interface(name = "org.memerase.Eraser1")]
+impl DbusEraser {
+    fn list_devices(&self) -> zbus::fdo::Result<Vec<DbusDevice>> {
+        let listed = self.call(serde_json::json!({ "cmd": "list" }), "")?;
+        Ok(listed["devices"].as_array().into_iter().flatten()
+            .map(|device| (
+                device["path"].as_str().unwrap_or_default().to_string(),
+                device["name"].as_str().unwrap_or_default().to_string(),
+                device["size"].as_u64().unwrap_or_default(),
+                device["is_removable"].as_bool().unwrap_or_default(),
+                device["is_mounted"].as_bool().unwrap_or_default(),
+                device["protected"].as_bool().unwrap_or_default(),
+            ))
+            .collect())
+    }
+
+    /// Start erasing `device`; an empty `method` is the daemon's default.
+    /// `options` takes "verify" and "sync_mode" as strings, as the socket
+    /// does. Returns the job id.
+    fn start_erase(&self, #[zbus(header)] header: zbus::message::Header<'_>, device: &str, method: &str,
+                   options: HashMap<String, zbus::zvariant::OwnedValue>) -> zbus::fdo::Result<u64> {
+        let mut request = serde_json::json!({ "cmd": "start", "device": device });
+        if !method.is_empty() {
+            request["method"] = method.into();
+        }
+        for (key, value) in options {
+            let value = String::try_from(value)
+                .map_err(|_| zbus::fdo::Error::InvalidArgs(format!("option {:?} must be a string", key)))?;
+            request[key] = value.into();
+        }
+        let operator = match header.sender() {
+            Some(sender) => format!("{} (via D-Bus)", sender),
+            None => "unknown (via D-Bus)".to_string(),
+        };
+        let started = self.call(request, &operator)?;
+        Ok(started["job"].as_u64().unwrap_or_default())
+    }
+
+    fn cancel(&self, #[zbus(header)] header: zbus::message::Header<'_>, job: u64) -> zbus::fdo::Result<()> {
+        let operator = header.sender().map_or("unknown".to_string(), |sender| sender.to_string());
+        self.call(serde_json::json!({ "cmd": "cancel", "job": job }), &format!("{} (via D-Bus)", operator))?;
+        Ok(())
+    }
+
+    /// The run record of a finished job, as JSON
+    fn get_report(&self, job: u64) -> zbus::fdo::Result<String> {
+        let report = self.call(serde_json::json!({ "cmd": "report", "job": job }), "")?;
+        Ok(report["report"].to_string())
+    }
+
+    /// (job, device, state, percent) of every job, finished ones included
+    #[zbus(property)]
+    fn jobs(&self) -> Vec<(u64, String, String, f64)> {
+        self.daemon.jobs.lock().unwrap().iter()
+            .map(|job| {
+                let status = job.status();
+                (job.id, job.device.display().to_string(), status["state"].as_str().unwrap_or_default().to_string(),
+                 status["percent"].as_f64().unwrap_or_default())
+            })
+            .collect()
+    }
+
+    #[zbus(signal)]
+    async fn progress(context: &zbus::SignalContext<'_>, job: u64, percent: f64, eta_secs: f64) -> zbus::Result<()>;
+
+    /// `state` is "succeeded", "failed" or "cancelled"; `error` is empty
+    /// unless it failed
+    #[zbus(signal)]
+    async fn finished(context: &zbus::SignalContext<'_>, job: u64, state: &str, error: &str) -> zbus::Result<()>;
+}
+
+/// The daemon's registration on the system bus, and what it has announced
+pub(crate) struct DbusService {
+    connection: zbus::blocking::Connection,
+    announced: HashMap<u64, &'static str>,  // Last state signalled for each job
+    last_progress: std::time::Instant,
+}
+
+impl DbusService {
+    pub(crate) fn register(daemon: &Arc<Daemon>) -> Result<Self, EraseError> {
+        let connection = zbus::blocking::connection::Builder::system()
+            .and_then(|builder| builder.name(DBUS_NAME))
+            .and_then(|builder| builder.serve_at(DBUS_PATH, DbusEraser { daemon: Arc::clone(daemon) }))
+            .and_then(|builder| builder.build())
+            .map_err(|e| EraseError::Other(format!("Cannot register {} on the system bus: {}", DBUS_NAME, e)))?;
+        human!("Registered {} on the system bus", DBUS_NAME);
+        Ok(DbusService { connection, announced: HashMap::new(), last_progress: std::time::Instant::now() })
+    }
+
+    /// Signal what changed in the job table since the last call: Progress
+    /// for running jobs about once a second, Finished once per job
+    pub(crate) fn publish(&mut self, daemon: &Daemon) {
+        let iface = match self.connection.object_server().interface::<_, DbusEraser>(DBUS_PATH) {
+            Ok(iface) => iface,
+            Err(e) => return debug!("D-Bus interface gone: {}", e),
+        };
+        let context = iface.signal_context();
+        let send_progress = self.last_progress.elapsed() >= std::time::Duration::from_secs(1);
+        if send_progress {
+            self.last_progress = std::time::Instant::now();
+        }
+        let mut changed = false;
+        let jobs: Vec<Arc<DaemonJob>> = daemon.jobs.lock().unwrap().clone();
+        for job in jobs {
+            let (status, percent, eta_secs, error) = {
+                let state = job.state.lock().unwrap();
+                (state.status, state.percent, state.eta_secs, state.error.clone())
+            };
+            let previous = self.announced.insert(job.id, status);
+            changed |= previous != Some(status);
+            let sent = if status == "running" {
+                if !send_progress {
+                    continue;
+                }
+                zbus::block_on(DbusEraser::progress(context, job.id, percent, eta_secs.unwrap_or(-1.0)))
+            } else if previous != Some(status) {
+                zbus::block_on(DbusEraser::finished(context, job.id, status, error.as_deref().unwrap_or_default()))
+            } else {
+                continue;
+            };
+            if let Err(e) = sent {
+                debug!("D-Bus signal for job {}: {}", job.id, e);
+            }
+        }
+        if changed {
+            if let Err(e) = zbus::block_on(iface.get().jobs_changed(context)) {
+                debug!("D-Bus Jobs property: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,1693 @@
+//! Device discovery, identification and the low-level writer
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::Serialize;
+use tracing::{debug, error, warn};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, RawFd};
+
+#[cfg(windows)]
+use winapi::um::{
+    fileapi::*,
+    handleapi::*,
+    winioctl::*,
+    errhandlingapi::GetLastError,
+};
+
+use crate::{audit::*, error::*, patterns::*, provider::*, ui::*};
+use crate::*;
+
+/// When written data is forced out to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SyncMode {
+    OSync,          // Open with O_SYNC: every write is synchronous
+    PerPass,        // fdatasync once at the end of each pass
+    Periodic(u64),  // fdatasync every N MB written, and at the end of each pass
+    None,           // Leave it to the OS (final pass is still synced before verifying)
+}
+
+impl std::str::FromStr for SyncMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if let Some(mb) = s.strip_prefix("periodic:") {
+            return match mb.parse::<u64>() {
+                Ok(mb) if mb > 0 => Ok(SyncMode::Periodic(mb)),
+                _ => Err(format!("Invalid periodic sync interval: {}", mb)),
+            };
+        }
+        match s.as_str() {
+            "o_sync" | "osync" => Ok(SyncMode::OSync),
+            "per-pass" => Ok(SyncMode::PerPass),
+            "none" => Ok(SyncMode::None),
+            _ => Err(format!("Invalid sync mode: {}", s)),
+        }
+    }
+}
+
+/// Whether writes bypass the page cache with O_DIRECT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectIo {
+    Auto,   // On for block devices
+    On,
+    Off,
+}
+
+impl std::str::FromStr for DirectIo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(DirectIo::Auto),
+            "on" => Ok(DirectIo::On),
+            "off" => Ok(DirectIo::Off),
+            _ => Err(format!("Invalid direct I/O setting: {}", s)),
+        }
+    }
+}
+
+/// Heap buffer with a guaranteed start alignment, as O_DIRECT requires
+pub struct AlignedBuffer {
+    ptr: std::ptr::NonNull<u8>,
+    len: usize,
+    layout: std::alloc::Layout,
+}
+
+impl AlignedBuffer {
+    pub fn new(len: usize, align: usize) -> Self {
+        let layout = std::alloc::Layout::from_size_align(len.max(1), align)
+            .expect("invalid buffer alignment");
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        let ptr = std::ptr::NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+        Self { ptr, len, layout }
+    }
+
+    pub fn from_slice(data: &[u8], align: usize) -> Self {
+        let mut buffer = Self::new(data.len(), align);
+        buffer.copy_from_slice(data);
+        buffer
+    }
+}
+
+impl std::ops::Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl std::ops::DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+// The buffer owns its allocation exclusively
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+/// How pass data is submitted to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum IoBackend {
+    Sync,    // One blocking write at a time
+    Uring,   // Many in-flight writes through io_uring (Linux only)
+}
+
+impl std::str::FromStr for IoBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sync" => Ok(IoBackend::Sync),
+            "uring" | "io_uring" => Ok(IoBackend::Uring),
+            _ => Err(format!("Invalid I/O backend: {}", s)),
+        }
+    }
+}
+
+/// I/O scheduling class requested for the wipe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoClass {
+    Idle,         // Only use the disk when nothing else wants it
+    BestEffort,   // Normal class at a chosen level (0 highest, 7 lowest)
+    Realtime,     // Ahead of everything else; needs CAP_SYS_ADMIN
+}
+
+impl std::str::FromStr for IoClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "idle" => Ok(IoClass::Idle),
+            "best-effort" | "be" => Ok(IoClass::BestEffort),
+            "realtime" | "rt" => Ok(IoClass::Realtime),
+            _ => Err(format!("Invalid I/O class: {}", s)),
+        }
+    }
+}
+
+/// A pending io_uring write: the slot's buffer holds `len` bytes destined
+/// for `offset`, of which `done` have completed so far
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy)]
+pub(crate) struct UringWrite {
+    offset: u64,
+    len: usize,
+    done: usize,
+}
+
+/// Fixed set of buffers cycling through an io_uring. Data is copied into a
+/// free slot on submission, so callers can reuse their buffer immediately;
+/// short writes are resubmitted for the remainder and failures carry the
+/// device offset they hit.
+#[cfg(target_os = "linux")]
+pub(crate) struct UringQueue {
+    ring: io_uring::IoUring,
+    slots: Vec<Option<AlignedBuffer>>,
+    pending: Vec<Option<UringWrite>>,
+    free: Vec<usize>,
+}
+
+#[cfg(target_os = "linux")]
+impl UringQueue {
+    pub(crate) fn new(depth: u32) -> io::Result<Self> {
+        let depth = depth.max(1);
+        Ok(UringQueue {
+            ring: io_uring::IoUring::new(depth)?,
+            slots: (0..depth).map(|_| None).collect(),
+            pending: vec![None; depth as usize],
+            free: (0..depth as usize).rev().collect(),
+        })
+    }
+
+    /// Queue `data` for `offset`, returning the bytes completed meanwhile
+    fn submit(&mut self, fd: RawFd, data: &[u8], offset: u64) -> io::Result<u64> {
+        let mut completed = 0;
+        while self.free.is_empty() {
+            completed += self.reap(fd, 1)?;
+        }
+
+        let slot = self.free.pop().unwrap();
+        let buffer = match self.slots[slot].take() {
+            Some(buffer) if buffer.len() >= data.len() => buffer,
+            _ => AlignedBuffer::new(data.len(), DIRECT_IO_ALIGN),
+        };
+        let buffer = self.slots[slot].insert(buffer);
+        buffer[..data.len()].copy_from_slice(data);
+        self.pending[slot] = Some(UringWrite { offset, len: data.len(), done: 0 });
+        self.push(fd, slot)?;
+
+        Ok(completed + self.reap(fd, 0)?)
+    }
+
+    fn push(&mut self, fd: RawFd, slot: usize) -> io::Result<()> {
+        let write = self.pending[slot].unwrap();
+        let buffer = &self.slots[slot].as_ref().unwrap()[write.done..write.len];
+        let entry = io_uring::opcode::Write::new(io_uring::types::Fd(fd), buffer.as_ptr(), buffer.len() as u32)
+            .offset(write.offset + write.done as u64)
+            .build()
+            .user_data(slot as u64);
+
+        // Safety: the slot buffer stays allocated and untouched until its
+        // completion is reaped, and Drop waits for everything in flight
+        unsafe {
+            self.ring.submission().push(&entry)
+                .map_err(|_| io::Error::other("io_uring submission queue full"))?;
+        }
+        retry_interrupted(|| self.ring.submit())?;
+        Ok(())
+    }
+
+    /// Handle finished writes, blocking until at least `wait` are available
+    fn reap(&mut self, fd: RawFd, wait: usize) -> io::Result<u64> {
+        if wait > 0 {
+            retry_interrupted(|| self.ring.submit_and_wait(wait))?;
+        }
+
+        let finished: Vec<(u64, i32)> = self.ring.completion().map(|c| (c.user_data(), c.result())).collect();
+        let mut completed = 0u64;
+        for (slot, result) in finished {
+            let slot = slot as usize;
+            let mut write = self.pending[slot].take().unwrap();
+            let at = write.offset + write.done as u64;
+
+            // Transient: issue the rest of the write again
+            if result == -libc::EINTR || result == -libc::EAGAIN {
+                self.pending[slot] = Some(write);
+                self.push(fd, slot)?;
+                continue;
+            }
+            if result < 0 {
+                self.free.push(slot);
+                return Err(at_offset(io::Error::from_raw_os_error(-result), at));
+            }
+            if result == 0 {
+                self.free.push(slot);
+                return Err(at_offset(io::Error::new(io::ErrorKind::WriteZero, "write made no progress"), at));
+            }
+
+            write.done += result as usize;
+            completed += result as u64;
+            if write.done < write.len {
+                self.pending[slot] = Some(write);
+                self.push(fd, slot)?;
+            } else {
+                self.free.push(slot);
+            }
+        }
+        Ok(completed)
+    }
+
+    /// Wait for every in-flight write, returning the bytes they completed
+    fn drain(&mut self, fd: RawFd) -> io::Result<u64> {
+        let mut completed = 0;
+        while self.free.len() < self.slots.len() {
+            completed += self.reap(fd, 1)?;
+        }
+        Ok(completed)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for UringQueue {
+    fn drop(&mut self) {
+        // The kernel may still be reading slot buffers after an error
+        let in_flight = self.pending.iter().filter(|w| w.is_some()).count();
+        if in_flight > 0 {
+            let _ = retry_interrupted(|| self.ring.submit_and_wait(in_flight));
+        }
+    }
+}
+
+/// Continuous writeback for buffered writes. Without it the page cache
+/// soaks up gigabytes and the end-of-pass sync stalls for minutes.
+#[derive(Debug, Clone, Copy)]
+pub struct Writeback {
+    pub(crate) interval: u64,   // Bytes between sync_file_range(WRITE) kicks
+    pub(crate) max_dirty: u64,  // Outstanding bytes allowed before waiting
+    pub(crate) kicked: u64,     // Offset up to which writeback has been started
+    pub(crate) settled: u64,    // Offset up to which writeback is known complete
+}
+
+/// Sequential writer over the target that hides the O_DIRECT details: whole
+/// sectors go through the direct descriptor, an unaligned tail goes through a
+/// second buffered descriptor, and a device that rejects O_DIRECT (EINVAL)
+/// is transparently reopened buffered.
+pub(crate) struct DeviceWriter {
+    pub(crate) file: File,
+    pub(crate) path: PathBuf,
+    pub(crate) sync_mode: SyncMode,
+    pub(crate) direct: bool,
+    pub(crate) fua: bool,
+    pub(crate) sector_size: usize,
+    pub(crate) position: u64,
+    pub(crate) tail: Option<File>,
+    #[cfg(target_os = "linux")]
+    pub(crate) uring: Option<UringQueue>,
+    pub(crate) writeback: Option<Writeback>,
+    pub(crate) warnings: Vec<String>,
+    pub(crate) retries: u64,    // Writes repeated after falling back to buffered I/O
+    pub(crate) watch: Option<Arc<WriteWatch>>,  // Shared with the watchdog while one runs
+    pub(crate) max_write: Option<usize>,  // Cap on a single write once the device failed larger ones
+}
+
+impl DeviceWriter {
+    /// Write `data` at the current position, returning how many bytes have
+    /// reached the device since the last call (everything, unless io_uring
+    /// still has writes in flight)
+    pub(crate) fn write(&mut self, data: &[u8]) -> io::Result<u64> {
+        let at = self.position;
+        self.watch_begin(at);
+        #[cfg(target_os = "linux")]
+        if self.uring.is_some() {
+            let result = self.write_uring(data).map_err(|e| at_offset(e, at));
+            self.watch_end();
+            return result;
+        }
+
+        let result = self.write_sync(data).map_err(|e| at_offset(e, at));
+        self.watch_end();
+        result.map(|()| data.len() as u64)
+    }
+
+    /// Tell the watchdog, if there is one, that a write at `offset` is in flight
+    fn watch_begin(&self, offset: u64) {
+        if let Some(ref watch) = self.watch {
+            watch.begin(offset);
+        }
+    }
+
+    fn watch_end(&self) {
+        if let Some(ref watch) = self.watch {
+            watch.end();
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_uring(&mut self, data: &[u8]) -> io::Result<u64> {
+        let aligned_len = data.len() / self.sector_size * self.sector_size;
+        let mut completed = 0;
+        if aligned_len > 0 {
+            let fd = self.file.as_raw_fd();
+            let queue = self.uring.as_mut().unwrap();
+            completed += queue.submit(fd, &data[..aligned_len], self.position)?;
+            self.position += aligned_len as u64;
+        }
+        if aligned_len < data.len() {
+            self.write_tail(&data[aligned_len..])?;
+            completed += (data.len() - aligned_len) as u64;
+        }
+        Ok(completed)
+    }
+
+    /// Write `data` `count` times back to back, pushing all copies through
+    /// one vectored write where possible. Short writes resume mid-iovec, so
+    /// the returned count is exactly what was written.
+    pub(crate) fn write_repeated(&mut self, data: &[u8], count: usize) -> io::Result<u64> {
+        let vectored = count > 1 && self.backend() == IoBackend::Sync && self.max_write.is_none()
+            && (!self.direct || data.len().is_multiple_of(self.sector_size));
+        if !vectored {
+            let mut completed = 0;
+            for _ in 0..count {
+                completed += self.write(data)?;
+            }
+            return Ok(completed);
+        }
+
+        self.watch_begin(self.position);
+        let result = self.write_vectored_copies(data, count);
+        self.watch_end();
+        result
+    }
+
+    fn write_vectored_copies(&mut self, data: &[u8], count: usize) -> io::Result<u64> {
+        let total = data.len() * count;
+        let mut written = 0;
+        loop {
+            match write_copies(&mut self.file, data, count, &mut written) {
+                Ok(()) => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput && self.direct => {
+                    self.position += written as u64;
+                    self.fall_back_to_buffered(&e)?;
+                    self.position -= written as u64;
+                }
+                Err(e) => return Err(at_offset(e, self.position + written as u64)),
+            }
+        }
+
+        self.position += total as u64;
+        self.push_writeback()?;
+        Ok(total as u64)
+    }
+
+    /// Whether the device already holds `block` at the current position, in
+    /// which case the position moves past it. Sub-sector tails can't be read
+    /// with O_DIRECT and are reported as not matching.
+    pub(crate) fn already_holds(&mut self, block: &[u8], scratch: &mut [u8]) -> io::Result<bool> {
+        if self.direct && !block.len().is_multiple_of(self.sector_size) {
+            return Ok(false);
+        }
+
+        let scratch = &mut scratch[..block.len()];
+        self.file.seek(SeekFrom::Start(self.position))?;
+        self.file.read_exact(scratch)?;
+        if first_difference(scratch, block).is_none() {
+            self.position += block.len() as u64;
+            return Ok(true);
+        }
+        self.file.seek(SeekFrom::Start(self.position))?;
+        Ok(false)
+    }
+
+    /// Wait for in-flight writes, returning the bytes they completed
+    pub(crate) fn flush(&mut self) -> io::Result<u64> {
+        #[cfg(target_os = "linux")]
+        if let Some(ref mut queue) = self.uring {
+            if let Some(ref watch) = self.watch {
+                watch.begin(self.position);
+            }
+            let result = queue.drain(self.file.as_raw_fd());
+            self.watch_end();
+            return result;
+        }
+        Ok(0)
+    }
+
+    pub(crate) fn backend(&self) -> IoBackend {
+        #[cfg(target_os = "linux")]
+        if self.uring.is_some() {
+            return IoBackend::Uring;
+        }
+        IoBackend::Sync
+    }
+
+    fn write_sync(&mut self, data: &[u8]) -> io::Result<()> {
+        if let Some(cap) = self.max_write.filter(|&cap| data.len() > cap) {
+            for chunk in data.chunks(cap) {
+                self.write_sync(chunk)?;
+            }
+            return Ok(());
+        }
+        if !self.direct {
+            write_fully(&mut self.file, data, &mut self.position)?;
+            return self.push_writeback();
+        }
+
+        let aligned_len = data.len() / self.sector_size * self.sector_size;
+        if aligned_len > 0 {
+            let start = self.position;
+            match write_fully(&mut self.file, &data[..aligned_len], &mut self.position) {
+                Ok(()) => {}
+                // Typically a short write left the rest misaligned; carry on
+                // buffered from exactly where the device stopped
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    self.fall_back_to_buffered(&e)?;
+                    return self.write_sync(&data[(self.position - start) as usize..]);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if aligned_len < data.len() {
+            self.write_tail(&data[aligned_len..])?;
+        }
+        Ok(())
+    }
+
+    /// Write the sub-sector remainder of the device with buffered I/O
+    fn write_tail(&mut self, data: &[u8]) -> io::Result<()> {
+        let position = self.position;
+        let tail = self.buffered_handle()?;
+        tail.seek(SeekFrom::Start(position))?;
+        tail.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    /// Kick off writeback every interval and, once too much is outstanding,
+    /// wait for the oldest kicked range so the cache can't run far ahead
+    fn push_writeback(&mut self) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if let Some(ref mut wb) = self.writeback {
+            use std::os::unix::io::AsRawFd;
+
+            let fd = self.file.as_raw_fd();
+            if self.position - wb.kicked >= wb.interval {
+                let len = self.position - wb.kicked;
+                if unsafe { libc::sync_file_range(fd, wb.kicked as i64, len as i64, libc::SYNC_FILE_RANGE_WRITE) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                wb.kicked = self.position;
+            }
+
+            if wb.kicked - wb.settled > wb.max_dirty {
+                let end = wb.kicked - wb.max_dirty / 2;
+                let flags = libc::SYNC_FILE_RANGE_WAIT_BEFORE | libc::SYNC_FILE_RANGE_WRITE | libc::SYNC_FILE_RANGE_WAIT_AFTER;
+                if unsafe { libc::sync_file_range(fd, wb.settled as i64, (end - wb.settled) as i64, flags) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                wb.settled = end;
+            }
+        }
+        Ok(())
+    }
+
+    fn fall_back_to_buffered(&mut self, cause: &io::Error) -> io::Result<()> {
+        debug!("Retrying at offset {} with buffered I/O: {}", self.position, cause);
+        self.warnings.push(format!("O_DIRECT rejected ({}), falling back to buffered I/O", cause));
+        self.retries += 1;
+        // The descriptor holds the device's O_EXCL claim, which a second
+        // exclusive open would fail on; switch it to buffered in place
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let fd = self.file.as_raw_fd();
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_DIRECT) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            self.file = open_for_writing(&self.path, self.sync_mode, false, self.fua)?;
+        }
+        self.file.seek(SeekFrom::Start(self.position))?;
+        self.tail = None;
+        self.direct = false;
+        Ok(())
+    }
+
+    /// Buffered descriptor for unaligned writes such as the tail or the stamp
+    pub(crate) fn buffered_handle(&mut self) -> io::Result<&mut File> {
+        if !self.direct {
+            return Ok(&mut self.file);
+        }
+        if self.tail.is_none() {
+            self.tail = Some(reopen_buffered(&self.file, &self.path, self.sync_mode, self.fua)?);
+        }
+        Ok(self.tail.as_mut().unwrap())
+    }
+
+    pub(crate) fn rewind(&mut self) -> io::Result<()> {
+        self.seek_to(0)
+    }
+
+    pub(crate) fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.flush()?;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.position = offset;
+        if let Some(ref mut wb) = self.writeback {
+            wb.kicked = offset;
+            wb.settled = offset;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.watch_begin(self.position);
+        let result = retry_interrupted(|| self.file.sync_data()).and_then(|()| match self.tail {
+            Some(ref tail) => retry_interrupted(|| tail.sync_data()),
+            None => Ok(()),
+        });
+        self.watch_end();
+        result
+    }
+}
+
+/// `write_all` that moves `position` with every partial write, so when it
+/// fails part way the position (and the offset the error is tagged with)
+/// is where the device really stopped. EINTR is retried.
+pub fn write_fully(out: &mut impl Write, data: &[u8], position: &mut u64) -> io::Result<()> {
+    let mut written = 0;
+    while written < data.len() {
+        match out.write(&data[written..]) {
+            Ok(0) => return Err(at_offset(io::Error::new(io::ErrorKind::WriteZero, "write made no progress"), *position)),
+            Ok(n) => {
+                written += n;
+                *position += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(at_offset(e, *position)),
+        }
+    }
+    Ok(())
+}
+
+/// Write `count` copies of `data` with vectored writes, the first iovec
+/// starting mid-copy after a short write. `written` is how much of the
+/// whole run is done, so after an error the caller can call again to carry
+/// on from exactly there. EINTR is retried.
+pub fn write_copies(out: &mut impl Write, data: &[u8], count: usize, written: &mut usize) -> io::Result<()> {
+    let total = data.len() * count;
+    while *written < total {
+        let (first, within) = (*written / data.len(), *written % data.len());
+        let mut slices = Vec::with_capacity(count - first);
+        slices.push(io::IoSlice::new(&data[within..]));
+        slices.extend((first + 1..count).map(|_| io::IoSlice::new(data)));
+
+        match out.write_vectored(&slices) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "vectored write made no progress")),
+            Ok(n) => *written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Repeat `op` for as long as a signal interrupts it
+pub(crate) fn retry_interrupted<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match op() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            result => return result,
+        }
+    }
+}
+
+/// The write or flush in flight, shared between the writer and the
+/// watchdog thread. Two relaxed stores per write is all the writer pays.
+pub(crate) struct WriteWatch {
+    epoch: std::time::Instant,
+    issued: std::sync::atomic::AtomicU64,  // Milliseconds after `epoch`, plus one; 0 while idle
+    offset: std::sync::atomic::AtomicU64,
+    pass: std::sync::atomic::AtomicU64,    // 1-based, for the not-responding message
+    passes: std::sync::atomic::AtomicU64,
+}
+
+impl WriteWatch {
+    fn begin(&self, offset: u64) {
+        use std::sync::atomic::Ordering::Relaxed;
+        self.offset.store(offset, Relaxed);
+        self.issued.store(self.epoch.elapsed().as_millis() as u64 + 1, Relaxed);
+    }
+
+    fn end(&self) {
+        self.issued.store(0, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_pass(&self, pass: usize, passes: usize) {
+        use std::sync::atomic::Ordering::Relaxed;
+        self.pass.store(pass as u64, Relaxed);
+        self.passes.store(passes as u64, Relaxed);
+    }
+}
+
+/// Called from the watchdog thread when it gives a device up. The stuck
+/// write can't be unblocked, so a caller that can't wait for it to come
+/// back (the command line) ends the process here.
+pub type NotRespondingHook = Arc<dyn Fn(&NotRespondingError) + Send + Sync>;
+
+/// Thread that notices a write not coming back. After `warn_after` it logs
+/// the stuck offset and keeps a warning for the report; after `abort_after`
+/// it gives the device up: it records why in `gave_up`, cancels the erase
+/// so it fails with NotResponding once the write returns, and calls the hook.
+pub(crate) struct WriteWatchdog {
+    pub(crate) watch: Arc<WriteWatch>,
+    stalls: Arc<std::sync::Mutex<Vec<String>>>,
+    stop: Arc<std::sync::atomic::AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl WriteWatchdog {
+    pub(crate) fn start(device: &Path, warn_after: std::time::Duration, abort_after: Option<std::time::Duration>,
+                        cancel: CancellationToken, gave_up: Arc<std::sync::Mutex<Option<NotRespondingError>>>,
+                        hook: Option<NotRespondingHook>) -> Self {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        let watch = Arc::new(WriteWatch {
+            epoch: std::time::Instant::now(),
+            issued: std::sync::atomic::AtomicU64::new(0),
+            offset: std::sync::atomic::AtomicU64::new(0),
+            pass: std::sync::atomic::AtomicU64::new(1),
+            passes: std::sync::atomic::AtomicU64::new(1),
+        });
+        let stalls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let poll = (warn_after / 4).clamp(std::time::Duration::from_millis(10), std::time::Duration::from_secs(1));
+
+        let thread = {
+            let (watch, stalls, stop) = (watch.clone(), stalls.clone(), stop.clone());
+            let device = device.to_path_buf();
+            std::thread::spawn(move || {
+                let mut warned = 0;  // `issued` of the write last warned about
+                while !stop.load(Relaxed) {
+                    std::thread::park_timeout(poll);
+                    let issued = watch.issued.load(Relaxed);
+                    if issued == 0 {
+                        continue;
+                    }
+                    let now = watch.epoch.elapsed().as_millis() as u64 + 1;
+                    let stuck = std::time::Duration::from_millis(now.saturating_sub(issued));
+                    let offset = watch.offset.load(Relaxed);
+                    if abort_after.is_some_and(|limit| stuck >= limit) {
+                        let error = device_not_responding(&device, offset, watch.pass.load(Relaxed) as usize,
+                                                          watch.passes.load(Relaxed) as usize, stuck);
+                        *gave_up.lock().unwrap() = Some(error.clone());
+                        cancel.cancel();
+                        if let Some(ref hook) = hook {
+                            hook(&error);
+                        }
+                        break;
+                    }
+                    if stuck >= warn_after && warned != issued {
+                        warned = issued;
+                        let message = format!("A write at offset {} has not returned after {}; the device may have hung",
+                                              offset, format_duration(stuck.as_secs_f64()));
+                        warn!("{}", message);
+                        stalls.lock().unwrap().push(message);
+                    }
+                }
+            })
+        };
+
+        Self { watch, stalls, stop, thread: Some(thread) }
+    }
+
+    /// Writes that outlasted the warning threshold so far
+    pub(crate) fn stalls(&self) -> Vec<String> {
+        self.stalls.lock().unwrap().clone()
+    }
+}
+
+impl Drop for WriteWatchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Give up on a device whose write has been stuck past --write-abort:
+/// log and audit it, and say why for the erase to fail with
+pub(crate) fn device_not_responding(device: &Path, offset: u64, pass: usize, passes: usize, stuck: std::time::Duration)
+                                    -> NotRespondingError {
+    let error = NotRespondingError { device: device.to_path_buf(), offset, pass, passes, stuck };
+    error!("{}", error);
+    error!("{} of {} passes completed; pass {} stopped at offset {}, so the device is not erased", pass - 1, passes, pass, offset);
+    audit(serde_json::json!({
+        "event": "not-responding",
+        "device": device.display().to_string(),
+        "offset": offset,
+        "pass": pass,
+        "passes": passes,
+        "stuck_secs": stuck.as_secs(),
+    }));
+    error
+}
+
+/// Identity of an erased disk, as recorded by `--report`
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceIdentity {
+    pub path: PathBuf,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub size: u64,
+    pub logical_sector_size: usize,
+    pub physical_sector_size: usize,
+}
+
+/// Drive write-cache state around an erase, as recorded in the report
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WriteCacheState {
+    pub before: bool,     // Enabled when the erase started
+    pub during: bool,     // Enabled while passes were written
+    pub restored: bool,   // Original setting put back afterwards
+}
+
+/// Disables a drive's volatile write cache for as long as it lives and puts
+/// the previous setting back when dropped, so error returns restore it too
+pub(crate) struct WriteCacheGuard {
+    device: PathBuf,
+    pub(crate) previous: bool,
+    restored: bool,
+}
+
+impl WriteCacheGuard {
+    pub(crate) fn disable(device: &Path) -> io::Result<Self> {
+        let previous = write_cache_enabled(device)?;
+        if previous {
+            set_write_cache(device, false)?;
+        }
+        Ok(WriteCacheGuard { device: device.to_path_buf(), previous, restored: !previous })
+    }
+
+    pub(crate) fn state(&self) -> WriteCacheState {
+        WriteCacheState {
+            before: self.previous,
+            during: false,
+            restored: self.restored,
+        }
+    }
+
+    pub(crate) fn restore(&mut self) -> io::Result<()> {
+        if !self.restored {
+            set_write_cache(&self.device, self.previous)?;
+            self.restored = true;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WriteCacheGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            warn!("Warning: could not restore write cache on {}: {}", self.device.display(), e);
+        }
+    }
+}
+
+/// Token bucket holding write throughput to a fixed rate. The bucket only
+/// holds a quarter second of credit, so an unthrottled stretch (e.g. a slow
+/// sync) can't be followed by a burst well above the limit.
+pub(crate) struct RateLimiter {
+    bytes_per_sec: f64,
+    capacity: f64,
+    tokens: f64,
+    last: std::time::Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(bytes_per_sec: f64) -> Self {
+        RateLimiter {
+            bytes_per_sec,
+            capacity: bytes_per_sec / 4.0,
+            tokens: 0.0,
+            last: std::time::Instant::now(),
+        }
+    }
+
+    /// Sleep until `bytes` may be written
+    pub(crate) fn take(&mut self, bytes: usize) {
+        let now = std::time::Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.bytes_per_sec;
+        self.tokens = (self.tokens + refill).min(self.capacity);
+        self.last = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            std::thread::sleep(std::time::Duration::from_secs_f64(-self.tokens / self.bytes_per_sec));
+            self.tokens = 0.0;
+            self.last = std::time::Instant::now();
+        }
+    }
+}
+
+/// A storage device found by `SecureEraser::list_devices`
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub is_removable: bool,
+    pub is_mounted: bool,
+    pub bus: Option<BusType>,  // None when sysfs doesn't say, and for files
+    pub fs_type: Option<String>,  // Filesystem on the whole disk, rather than in a partition
+    pub label: Option<String>,
+    pub partitions: Vec<PartitionInfo>,
+}
+
+/// A partition the kernel found on a listed disk, and what is on it
+#[derive(Debug, Clone, Serialize)]
+pub struct PartitionInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub fs_type: Option<String>,  // None when no signature probe_filesystem knows was found
+    pub label: Option<String>,
+}
+
+/// How a disk is attached, as far as --bus cares
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BusType {
+    Usb,
+    Sata,
+    Nvme,
+}
+
+impl std::str::FromStr for BusType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "usb" => Ok(BusType::Usb),
+            "sata" => Ok(BusType::Sata),
+            "nvme" => Ok(BusType::Nvme),
+            _ => Err(format!("Unknown bus: {} (expected usb, sata or nvme)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for BusType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            BusType::Usb => "usb",
+            BusType::Sata => "sata",
+            BusType::Nvme => "nvme",
+        })
+    }
+}
+
+/// The --removable-only, --min-size, --max-size and --bus filters. A device
+/// is shown only if it passes every one given.
+#[derive(Debug, Default)]
+pub struct DeviceFilter {
+    pub removable_only: bool,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub bus: Option<BusType>,
+}
+
+impl DeviceFilter {
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        (!self.removable_only || device.is_removable)
+            && self.min_size.is_none_or(|min| device.size >= min)
+            && self.max_size.is_none_or(|max| device.size <= max)
+            && self.bus.is_none_or(|bus| device.bus == Some(bus))
+    }
+}
+
+/// The device as the operator confirmed it, checked again once it is open
+#[derive(Debug, Clone)]
+pub struct ExpectedDevice {
+    pub size: u64,
+    pub serial: Option<String>,  // None when the kernel reports none; not checked then
+}
+
+pub(crate) fn open_for_writing(device_path: &Path, sync_mode: SyncMode, direct: bool, fua: bool) -> io::Result<File> {
+    // On a block device O_EXCL fails with EBUSY while the kernel has the
+    // disk or a partition mounted, or another program has claimed it
+    let exclusive = cfg!(target_os = "linux") && std::fs::metadata(device_path).is_ok_and(|meta| is_block_device(&meta));
+    open_with_flags(device_path, sync_mode, direct, fua, exclusive)
+}
+
+/// A second, buffered descriptor on whatever `file` has open. On Linux it
+/// is opened through /proc/self/fd, so it is the same device even if the
+/// path has changed, and it takes no O_EXCL claim of its own: `file`
+/// already holds the device's, and a second one would fail with EBUSY.
+pub(crate) fn reopen_buffered(file: &File, device_path: &Path, sync_mode: SyncMode, fua: bool) -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let _ = device_path;
+        open_with_flags(Path::new(&format!("/proc/self/fd/{}", file.as_raw_fd())), sync_mode, false, fua, false)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = file;
+        open_for_writing(device_path, sync_mode, false, fua)
+    }
+}
+
+fn open_with_flags(device_path: &Path, sync_mode: SyncMode, direct: bool, fua: bool, exclusive: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true);
+
+    #[cfg(unix)]
+    {
+        let mut flags = 0;
+        if sync_mode == SyncMode::OSync {
+            flags |= libc::O_SYNC; // Synchronous writes
+        }
+        if fua {
+            flags |= libc::O_DSYNC; // With O_DIRECT the kernel issues FUA writes
+        }
+        #[cfg(target_os = "linux")]
+        if direct {
+            flags |= libc::O_DIRECT; // Bypass the page cache
+        }
+        if exclusive {
+            flags |= libc::O_EXCL;
+        }
+        options.custom_flags(flags);
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        // Windows implementation would require CreateFile with specific flags
+        let _ = (sync_mode, direct, exclusive);
+        if fua {
+            options.custom_flags(winapi::um::winbase::FILE_FLAG_WRITE_THROUGH);
+        }
+    }
+
+    options.open(device_path)
+}
+
+/// NVMe admin command passed through NVME_IOCTL_ADMIN_CMD
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct NvmeAdminCmd {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) const NVME_IOCTL_ADMIN_CMD: libc::c_ulong = 0xC048_4E41;
+#[cfg(target_os = "linux")]
+pub(crate) const NVME_FEAT_VOLATILE_WC: u32 = 0x06;
+
+/// Run a Get (0x0a) or Set (0x09) Features command for the volatile write cache
+#[cfg(target_os = "linux")]
+pub(crate) fn nvme_write_cache_feature(device: &Path, opcode: u8, value: u32) -> io::Result<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = File::open(device)?;
+    let mut cmd = NvmeAdminCmd {
+        opcode,
+        cdw10: NVME_FEAT_VOLATILE_WC,
+        cdw11: value,
+        ..Default::default()
+    };
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), NVME_IOCTL_ADMIN_CMD, &mut cmd as *mut NvmeAdminCmd) };
+    if result != 0 {
+        return Err(if result < 0 {
+            io::Error::last_os_error()
+        } else {
+            io::Error::other(format!("NVMe status 0x{:x}", result))
+        });
+    }
+    Ok(cmd.result)
+}
+
+/// sysfs cache_type attribute of a SCSI/SATA disk ("write back" or "write through")
+#[cfg(target_os = "linux")]
+pub(crate) fn scsi_cache_type_path(device: &Path) -> io::Result<PathBuf> {
+    let name = device.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let dir = PathBuf::from(format!("/sys/block/{}/device/scsi_disk", name));
+    std::fs::read_dir(&dir)?
+        .flatten()
+        .map(|entry| entry.path().join("cache_type"))
+        .find(|path| path.exists())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no cache_type under {}", dir.display())))
+}
+
+/// Whether the drive's volatile write cache is enabled
+pub(crate) fn write_cache_enabled(device: &Path) -> io::Result<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        let name = device.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if name.starts_with("nvme") {
+            return Ok(nvme_write_cache_feature(device, 0x0a, 0)? & 1 == 1);
+        }
+        let cache_type = std::fs::read_to_string(scsi_cache_type_path(device)?)?;
+        Ok(cache_type.trim().starts_with("write back"))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = device;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "write cache control is only implemented on Linux"))
+    }
+}
+
+/// Turn the drive's volatile write cache on or off
+pub(crate) fn set_write_cache(device: &Path, enabled: bool) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let name = device.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if name.starts_with("nvme") {
+            return nvme_write_cache_feature(device, 0x09, enabled as u32).map(|_| ());
+        }
+        std::fs::write(scsi_cache_type_path(device)?, if enabled { "write back" } else { "write through" })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (device, enabled);
+        Err(io::Error::new(io::ErrorKind::Unsupported, "write cache control is only implemented on Linux"))
+    }
+}
+
+/// Typical sustained write rate for a device, from its name and sysfs flags
+pub(crate) fn nominal_write_rate(device: &DeviceInfo) -> f64 {
+    const MB: f64 = 1024.0 * 1024.0;
+
+    let rotational = std::fs::read_to_string(format!("/sys/block/{}/queue/rotational", device.name))
+        .map(|s| s.trim() == "1")
+        .unwrap_or(false);
+    if device.is_removable {
+        30.0 * MB
+    } else if rotational {
+        150.0 * MB
+    } else if device.name.starts_with("nvme") {
+        1500.0 * MB
+    } else {
+        400.0 * MB
+    }
+}
+
+/// Lower the process's I/O and CPU priority so a wipe can run in the
+/// background. Returns what was applied and warnings for anything the
+/// system refused; neither is fatal.
+pub fn apply_scheduling_priority(class: IoClass, level: u8) -> (Vec<String>, Vec<String>) {
+    let mut applied = Vec::new();
+    let mut warnings = Vec::new();
+
+    #[cfg(target_os = "linux")]
+    {
+        const IOPRIO_CLASS_SHIFT: u32 = 13;
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+        let (class_id, level) = match class {
+            IoClass::Realtime => (1, level),
+            IoClass::BestEffort => (2, level),
+            IoClass::Idle => (3, 0),
+        };
+        let ioprio = (class_id << IOPRIO_CLASS_SHIFT) | level as u32;
+        let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) };
+        if result == 0 {
+            applied.push(match class {
+                IoClass::Idle => "I/O class idle".to_string(),
+                _ => format!("I/O class {:?} level {}", class, level),
+            });
+        } else {
+            warnings.push(format!("Could not set I/O priority: {}", io::Error::last_os_error()));
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        let nice = match class {
+            IoClass::Idle => Some(19),
+            IoClass::BestEffort => Some(10),
+            IoClass::Realtime => None,
+        };
+        if let Some(nice) = nice {
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == 0 {
+                applied.push(format!("CPU niceness {}", nice));
+            } else {
+                warnings.push(format!("Could not lower CPU priority: {}", io::Error::last_os_error()));
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        warnings.push("I/O priority classes are only supported on Linux".to_string());
+    }
+
+    #[cfg(windows)]
+    {
+        use winapi::um::processthreadsapi::{GetCurrentProcess, SetPriorityClass};
+        use winapi::um::winbase::PROCESS_MODE_BACKGROUND_BEGIN;
+
+        // Background mode lowers both CPU and I/O priority; there is no
+        // per-level equivalent, so anything but realtime maps onto it
+        if class == IoClass::Realtime {
+            warnings.push("Realtime I/O priority is not supported on Windows".to_string());
+        } else if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } != 0 {
+            applied.push("background processing mode".to_string());
+        } else {
+            warnings.push(format!("Could not enter background mode: {}", io::Error::last_os_error()));
+        }
+        let _ = level;
+    }
+
+    (applied, warnings)
+}
+
+/// Logical sector size of a block device, 512 if it can't be determined
+pub(crate) fn logical_block_size(file: &File) -> usize {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let mut size: libc::c_int = 0;
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), libc::BLKSSZGET, &mut size as *mut libc::c_int) };
+        if result == 0 && size > 0 {
+            return size as usize;
+        }
+    }
+
+    let _ = file;
+    512
+}
+
+/// Physical sector size, or the logical one where the device doesn't say
+pub(crate) fn physical_block_size(file: &File) -> usize {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let mut size: libc::c_uint = 0;
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), libc::BLKPBSZGET, &mut size as *mut libc::c_uint) };
+        if result == 0 && size > 0 {
+            return size as usize;
+        }
+    }
+
+    logical_block_size(file)
+}
+
+/// The flags `open_for_writing` passes, for --verbose
+pub(crate) fn describe_open_flags(sync_mode: SyncMode, direct: bool, fua: bool) -> String {
+    let mut flags = vec!["read", "write"];
+    if cfg!(windows) {
+        if fua {
+            flags.push("FILE_FLAG_WRITE_THROUGH");
+        }
+        return flags.join("|");
+    }
+    if direct {
+        flags.push("O_DIRECT");
+    }
+    if sync_mode == SyncMode::OSync {
+        flags.push("O_SYNC");
+    }
+    if fua {
+        flags.push("O_DSYNC");
+    }
+    flags.join("|")
+}
+
+pub fn describe_sync_strategy(sync_mode: SyncMode, writeback: Option<&Writeback>) -> String {
+    let base = match sync_mode {
+        SyncMode::None => "single sync after the final pass".to_string(),
+        SyncMode::PerPass => "sync after every pass".to_string(),
+        SyncMode::Periodic(mb) => format!("sync every {} MB", mb),
+        SyncMode::OSync => "synchronous writes (O_SYNC)".to_string(),
+    };
+    match writeback {
+        Some(wb) => format!("{}, writeback every {} MB with at most {} MB dirty",
+                            base, wb.interval / (1024 * 1024), wb.max_dirty / (1024 * 1024)),
+        None => base,
+    }
+}
+
+/// Data regions of a file as (offset, length), found with SEEK_DATA and
+/// SEEK_HOLE. Where those aren't supported the whole file counts as data.
+pub(crate) fn data_extents(file: &mut File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = file.as_raw_fd();
+        let mut extents = Vec::new();
+        let mut pos: u64 = 0;
+        while pos < size {
+            let data = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+            if data < 0 {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::ENXIO) => break, // Only a hole remains
+                    Some(libc::EINVAL) => return Ok(vec![(0, size)]),
+                    _ => return Err(e),
+                }
+            }
+            let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+            if hole < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let (data, hole) = (data as u64, std::cmp::min(hole as u64, size));
+            if hole > data {
+                extents.push((data, hole - data));
+            }
+            pos = hole;
+        }
+        file.seek(SeekFrom::Start(0))?;
+        Ok(extents)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = file;
+        Ok(vec![(0, size)])
+    }
+}
+
+/// Flush the directory entry changes for `path`; best effort, and a no-op
+/// where directories can't be opened (Windows)
+pub(crate) fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = path;
+    }
+}
+
+/// Available space on the filesystem holding `path`
+pub(crate) fn free_space(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let cpath = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(cpath.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Disks backing the root filesystem, following device-mapper and md
+/// down to the physical disks. Empty when it can't be worked out.
+#[cfg(target_os = "linux")]
+pub fn system_disks() -> Vec<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    fn disks_under(node: &Path, disks: &mut Vec<String>) {
+        let slaves: Vec<PathBuf> = std::fs::read_dir(node.join("slaves"))
+            .map(|entries| entries.flatten().filter_map(|e| std::fs::canonicalize(e.path()).ok()).collect())
+            .unwrap_or_default();
+        if !slaves.is_empty() {
+            for slave in slaves {
+                disks_under(&slave, disks);
+            }
+            return;
+        }
+        // A partition's sysfs node sits inside its disk's
+        let disk = if node.join("partition").exists() { node.parent() } else { Some(node) };
+        if let Some(name) = disk.and_then(|d| d.file_name()) {
+            disks.push(name.to_string_lossy().into_owned());
+        }
+    }
+
+    let mut disks = Vec::new();
+    if let Some(node) = std::fs::metadata("/").ok().and_then(|meta| sysfs_block_node(meta.dev())) {
+        disks_under(&node, &mut disks);
+    }
+    disks
+}
+
+/// The /sys/dev/block node for a device number, resolved to its real path
+#[cfg(target_os = "linux")]
+pub(crate) fn sysfs_block_node(dev: u64) -> Option<PathBuf> {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    std::fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)).ok()
+}
+
+/// Ask the kernel to re-read the partition table (BLKRRPART)
+#[cfg(target_os = "linux")]
+pub(crate) fn reread_partition_table(file: &File) -> io::Result<()> {
+    // _IO(0x12, 95); not exported by the libc crate
+    use std::os::unix::fs::FileTypeExt;
+
+    const BLKRRPART: libc::c_ulong = 0x125f;
+
+    if !file.metadata()?.file_type().is_block_device() {
+        return Ok(());
+    }
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKRRPART) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn reread_partition_table(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Kernel name of the disk behind an open descriptor. Unlike the path, it
+/// can't have been swapped for another device since the file was opened.
+#[cfg(target_os = "linux")]
+pub(crate) fn open_device_name(file: &File) -> Option<String> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let meta = file.metadata().ok()?;
+    if !meta.file_type().is_block_device() {
+        return None;
+    }
+    let node = sysfs_block_node(meta.rdev())?;
+    node.file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn open_device_name(_file: &File) -> Option<String> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn system_disks() -> Vec<String> {
+    Vec::new()
+}
+
+/// Filesystem device a path lives on
+pub(crate) fn device_id(metadata: &std::fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        metadata.dev()
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = metadata;
+        0
+    }
+}
+
+/// Filesystems where overwriting a file writes new blocks instead of the old ones
+pub fn is_copy_on_write_fs(path: &Path) -> Option<&'static str> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        const BTRFS_SUPER_MAGIC: i64 = 0x9123_683e;
+        const ZFS_SUPER_MAGIC: i64 = 0x2fc1_2fc1;
+        const BCACHEFS_SUPER_MAGIC: i64 = 0xca45_1a4e;
+
+        let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let cpath = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(cpath.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+        match stat.f_type as i64 {
+            BTRFS_SUPER_MAGIC => Some("btrfs"),
+            ZFS_SUPER_MAGIC => Some("zfs"),
+            BCACHEFS_SUPER_MAGIC => Some("bcachefs"),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// The filesystem type and label at the start of a disk or partition, read
+/// from the vfat, exFAT, NTFS or ext2/3/4 superblock. Both are None when
+/// the node can't be opened (no media, no permission) or holds none of
+/// those. The node is opened read-only and only ever read.
+pub fn probe_filesystem(path: &Path) -> (Option<String>, Option<String>) {
+    let Ok(mut file) = File::open(path) else {
+        return (None, None);
+    };
+    let mut head = Vec::with_capacity(4096);
+    if Read::by_ref(&mut file).take(4096).read_to_end(&mut head).is_err() || head.len() < 512 {
+        return (None, None);
+    }
+    let u16_at = |at: usize| u16::from_le_bytes([head[at], head[at + 1]]);
+
+    let (fs_type, label) = if &head[3..11] == b"NTFS    " {
+        ("ntfs", ntfs_label(&mut file, &head))
+    } else if &head[3..11] == b"EXFAT   " {
+        ("exfat", exfat_label(&mut file, &head))
+    } else if head[510..512] == [0x55, 0xaa] && &head[82..87] == b"FAT32" {
+        ("vfat", fat_label(&head[71..82]))
+    } else if head[510..512] == [0x55, 0xaa] && &head[54..57] == b"FAT" {
+        ("vfat", fat_label(&head[43..54]))
+    } else if head.len() >= 2048 && u16_at(1024 + 0x38) == 0xef53 {
+        // Extents or 64-bit block numbers mean ext4, a journal alone ext3
+        let compat = u16_at(1024 + 0x5c);
+        let incompat = u16_at(1024 + 0x60);
+        let kind = if incompat & 0x00c0 != 0 {
+            "ext4"
+        } else if compat & 0x0004 != 0 {
+            "ext3"
+        } else {
+            "ext2"
+        };
+        (kind, text_label(&head[1024 + 0x78..1024 + 0x88]))
+    } else {
+        return (None, None);
+    };
+    (Some(fs_type.to_string()), label)
+}
+
+/// `len` bytes at `offset`, read in whole sectors so Windows volume
+/// handles take it
+fn read_region(file: &mut File, offset: u64, len: usize) -> Option<Vec<u8>> {
+    let start = offset / 512 * 512;
+    let end = (offset + len as u64).div_ceil(512) * 512;
+    let mut buffer = vec![0u8; (end - start) as usize];
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.read_exact(&mut buffer).ok()?;
+    let skip = (offset - start) as usize;
+    Some(buffer[skip..skip + len].to_vec())
+}
+
+/// A space or NUL padded label; None if blank
+fn text_label(raw: &[u8]) -> Option<String> {
+    let label = String::from_utf8_lossy(raw);
+    let label = label.trim_end_matches(['\0', ' ']);
+    (!label.is_empty()).then(|| label.to_string())
+}
+
+fn utf16_label(raw: &[u8]) -> Option<String> {
+    let chars: Vec<u16> = raw.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    let label = String::from_utf16_lossy(&chars);
+    let label = label.trim_end_matches(['\0', ' ']);
+    (!label.is_empty()).then(|| label.to_string())
+}
+
+/// The label in a FAT boot sector; "NO NAME" is what format leaves when
+/// none was given
+fn fat_label(raw: &[u8]) -> Option<String> {
+    text_label(raw).filter(|label| label != "NO NAME")
+}
+
+/// The volume label entry in the first cluster of the exFAT root directory
+fn exfat_label(file: &mut File, boot: &[u8]) -> Option<String> {
+    let (sector_shift, cluster_shift) = (boot[108] as u32, boot[109] as u32);
+    if !(9..=12).contains(&sector_shift) || sector_shift + cluster_shift > 25 {
+        return None;
+    }
+    let heap = u32::from_le_bytes(boot[88..92].try_into().ok()?) as u64;
+    let root = u32::from_le_bytes(boot[96..100].try_into().ok()?) as u64;
+    let cluster = 1usize << (sector_shift + cluster_shift);
+    let offset = (heap << sector_shift) + root.checked_sub(2)? * cluster as u64;
+    let directory = read_region(file, offset, cluster.min(64 * 1024))?;
+    for entry in directory.chunks_exact(32) {
+        match entry[0] {
+            0x00 => break,  // End of directory
+            0x83 => return utf16_label(&entry[2..2 + 2 * (entry[1] as usize).min(11)]),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The $VOLUME_NAME attribute of $Volume, record 3 of the MFT
+fn ntfs_label(file: &mut File, boot: &[u8]) -> Option<String> {
+    let sector = u16::from_le_bytes([boot[11], boot[12]]) as u64;
+    if !(512..=4096).contains(&sector) || !sector.is_power_of_two() {
+        return None;
+    }
+    let cluster = sector * boot[13] as u64;
+    // Negative: the record is 2^-n bytes, otherwise n clusters
+    let record = match boot[64] as i8 {
+        n if (-16..0).contains(&n) => 1u64 << -n,
+        n if n > 0 => n as u64 * cluster,
+        _ => return None,
+    };
+    if record > 64 * 1024 {
+        return None;
+    }
+    let mft = u64::from_le_bytes(boot[48..56].try_into().ok()?).checked_mul(cluster)?;
+    let mut entry = read_region(file, mft + 3 * record, record as usize)?;
+    if &entry[..4] != b"FILE" {
+        return None;
+    }
+    let u16_at = |entry: &[u8], at: usize| entry.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let u32_at = |entry: &[u8], at: usize| entry.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+
+    // Put back the last two bytes of each 512 byte stride, which the update
+    // sequence array holds while the record is on disk
+    let (usa, count) = (u16_at(&entry, 4)?, u16_at(&entry, 6)?);
+    for i in 1..count {
+        let end = i * 512 - 2;
+        let saved = entry.get(usa + 2 * i..usa + 2 * i + 2)?.to_vec();
+        entry.get_mut(end..end + 2)?.copy_from_slice(&saved);
+    }
+
+    let mut at = u16_at(&entry, 0x14)?;
+    loop {
+        let kind = u32_at(&entry, at)?;
+        let len = u32_at(&entry, at + 4)?;
+        if kind == 0xffff_ffff || len == 0 {
+            return None;
+        }
+        if kind == 0x60 && entry.get(at + 8) == Some(&0) {  // Resident $VOLUME_NAME
+            let (size, offset) = (u32_at(&entry, at + 0x10)?, u16_at(&entry, at + 0x14)?);
+            return utf16_label(entry.get(at + offset..at + offset + size)?);
+        }
+        at += len;
+    }
+}
+
+pub fn device_model(name: &str) -> Option<String> {
+    SysfsProvider::new().model(name)
+}
+
+/// Look up a `--device` argument among `devices`, following links through
+/// `provider`. Regular files are only accepted with `allow_file`, and they
+/// skip the block-device checks (mounted, removable) but nothing else.
+pub fn resolve_target(provider: &dyn DeviceProvider, path: &Path, devices: &[DeviceInfo], allow_file: bool)
+                      -> Result<DeviceInfo, EraseError> {
+    // /dev/disk/by-id and other links name the disk they point at
+    let listed = devices.iter().find(|d| d.path == path || provider.resolve(path).is_some_and(|p| p == d.path));
+    if let Some(device) = listed {
+        return Ok(device.clone());
+    }
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_file() => {
+            if !allow_file {
+                return Err(EraseError::InvalidArgument(format!(
+                    "{} is a regular file; pass --allow-file to erase files and disk images", path.display())));
+            }
+            Ok(DeviceInfo {
+                path: path.to_path_buf(),
+                name: path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+                size: meta.len(),
+                is_removable: false,
+                is_mounted: false,
+                bus: None,
+                fs_type: None,
+                label: None,
+                partitions: Vec::new(),
+            })
+        }
+        _ => Err(EraseError::NotFound { path: path.to_path_buf() }),
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn is_block_device(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.mode() & libc::S_IFMT) == libc::S_IFBLK
+}
+
+#[cfg(windows)]
+pub(crate) fn is_block_device(_metadata: &std::fs::Metadata) -> bool {
+    // On Windows, we'd need different logic to determine if it's a block device
+    true
+}
+
+/// Serial number the kernel reports for a disk, if any (virtio disks keep
+/// theirs outside device/)
+pub fn device_serial(name: &str) -> Option<String> {
+    SysfsProvider::new().serial(name)
+}
+
+/// World Wide Name from sysfs: the disk's own wwid, or the SCSI device's
+pub fn device_wwn(name: &str) -> Option<String> {
+    ["wwid", "device/wwid"].iter()
+        .filter_map(|attr| std::fs::read_to_string(format!("/sys/block/{}/{}", name, attr)).ok())
+        .map(|raw| raw.trim().to_string())
+        .find(|wwn| !wwn.is_empty())
+}
+
+/// sysfs says "naa.5002538e...", lsblk says "0x5002538e..."; compare the digits
+pub fn normalize_wwn(wwn: &str) -> String {
+    let wwn = wwn.trim().to_lowercase();
+    ["naa.", "eui.", "0x"].iter()
+        .find_map(|prefix| wwn.strip_prefix(prefix))
+        .unwrap_or(&wwn)
+        .to_string()
+}
@@ -0,0 +1,3391 @@
+//! The erase engine: `SecureEraser`, its passes, and cancellation
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use rand::Rng;
+use indicatif::ProgressStyle;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{device::*, error::*, patterns::*, provider::*, report::*, ui::*, verify::*};
+use crate::*;
+
+pub(crate) const ETA_ANNOUNCE_AFTER: u64 = 1024 * 1024 * 1024; // Print a whole-run estimate once this much is written
+pub(crate) const FAT32_MAX_SIZE: u64 = 32 * 1024 * 1024 * 1024; // Largest device --format-after fat32 takes; beyond this, exFAT
+/// Rewrite `pass` over [start, end) after a write there failed, halving the
+/// write size down to single sectors. A sector still failing after
+/// `retries` attempts is skipped. Returns what was skipped, merged and in
+/// device order, and leaves the writer at `end`.
+pub(crate) fn write_around_bad_sectors(writer: &mut DeviceWriter, pass: PassFill, start: u64, end: u64, retries: u32) -> io::Result<Vec<ByteRange>> {
+    let sector = writer.sector_size as u64;
+    let mut buffer = AlignedBuffer::new((end - start) as usize, DIRECT_IO_ALIGN);
+    pass.fill(start, &mut buffer);
+
+    let mut skipped: Vec<ByteRange> = Vec::new();
+    // Pieces still to write; the first half of a split is pushed last so it goes first
+    let mut pieces = vec![ByteRange { offset: start, length: end - start }];
+    while let Some(piece) = pieces.pop() {
+        check_interrupted()?;
+        let data = &buffer[(piece.offset - start) as usize..(piece.end() - start) as usize];
+        let single = piece.length <= sector;
+        let mut written = false;
+        for _ in 0..if single { retries } else { 1 } {
+            writer.seek_to(piece.offset)?;
+            match writer.write(data) {
+                Ok(_) => {
+                    written = true;
+                    break;
+                }
+                Err(e) if is_media_error(&e) && !device_removed(&e, &writer.path) => debug!("{}", e),
+                Err(e) => return Err(e),
+            }
+        }
+        if written {
+            continue;
+        }
+        if single {
+            match skipped.last_mut() {
+                Some(last) if last.end() == piece.offset => last.length += piece.length,
+                _ => skipped.push(piece),
+            }
+        } else {
+            let half = piece.length.div_ceil(sector) / 2 * sector;
+            pieces.push(ByteRange { offset: piece.offset + half, length: piece.length - half });
+            pieces.push(ByteRange { offset: piece.offset, length: half });
+        }
+    }
+    writer.seek_to(end)?;
+    Ok(skipped)
+}
+
+/// Write sizes to fall back through, largest first, when the device fails a
+/// write in a way its size could explain; the sector size comes last
+pub(crate) const WRITE_SIZE_STEPS: [usize; 3] = [1024 * 1024, 256 * 1024, 64 * 1024];
+
+/// Rewrite `block` `repeat` times from `at` after a write there failed with
+/// `failed`, capping each write at the next smaller step until the region
+/// goes through. The cap that worked stays on the writer for the rest of the
+/// run and is returned. Once even sector-sized writes fail the old cap is
+/// put back and the last error returned, leaving the sector to be treated
+/// as bad.
+pub(crate) fn shrink_writes(writer: &mut DeviceWriter, block: &[u8], repeat: usize, at: u64, failed: io::Error) -> io::Result<usize> {
+    // The writer's sector size is 1 without O_DIRECT
+    let sector = logical_block_size(&writer.file);
+    let previous = writer.max_write;
+    let current = previous.map_or(block.len() * repeat, |cap| cap.min(block.len() * repeat));
+    let cause = failed.to_string();
+    let mut error = failed;
+    for size in WRITE_SIZE_STEPS.into_iter().chain([sector]).filter(|&size| size < current && size % sector == 0) {
+        check_interrupted()?;
+        debug!("{}; retrying with {} writes", error, format_size(size as u64));
+        writer.max_write = Some(size);
+        writer.seek_to(at)?;
+        match writer.write_repeated(block, repeat) {
+            Ok(_) => {
+                let note = format!("Writes of {} failed ({}); continued with {} writes, at lower throughput",
+                                   format_size(current as u64), cause, format_size(size as u64));
+                warn!("{}", note);
+                writer.warnings.push(note);
+                return Ok(size);
+            }
+            Err(e) if is_size_error(&e) && !device_removed(&e, &writer.path) => error = e,
+            Err(e) => {
+                writer.max_write = previous;
+                return Err(e);
+            }
+        }
+    }
+    writer.max_write = previous;
+    writer.seek_to(at)?;
+    Err(error)
+}
+
+/// Add `new` to the sorted, non-overlapping `ranges`, joining any that
+/// touch; the same bad sector tends to fail in every pass
+pub(crate) fn merge_ranges(ranges: &mut Vec<ByteRange>, new: Vec<ByteRange>) {
+    if new.is_empty() {
+        return;
+    }
+    ranges.extend(new);
+    ranges.sort_by_key(|range| range.offset);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges.drain(..) {
+        match merged.last_mut() {
+            Some(last) if range.offset <= last.end() => last.length = last.length.max(range.end() - last.offset),
+            _ => merged.push(range),
+        }
+    }
+    *ranges = merged;
+}
+
+/// Progress saved by `--checkpoint`, enough to continue an interrupted erase
+/// with the same passes (random keys included) from the last synced offset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub device: PathBuf,
+    pub device_size: u64,        // With the serial, identifies the target
+    pub serial: Option<String>,
+    pub method: WipePattern,
+    pub passes: Vec<PassFill>,
+    pub block_size: usize,
+    pub pass: usize,             // Index of the pass in progress
+    pub offset: u64,             // Bytes of that pass known to be on the device
+    pub updated_at: u64,         // Seconds since the Unix epoch, UTC
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self, EraseError> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data).map_err(|e| EraseError::Other(format!("Invalid checkpoint {}: {}", path.display(), e)))
+    }
+
+    /// Write through a temporary file and rename, so a crash leaves either
+    /// the old checkpoint or the new one
+    pub fn save(&mut self, path: &Path, pass: usize, offset: u64) -> io::Result<()> {
+        self.pass = pass;
+        self.offset = offset;
+        self.updated_at = unix_time();
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut file = File::create(&temp)?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)?;
+        sync_parent_dir(path);
+        Ok(())
+    }
+
+    /// Refuse to continue on anything but the disk the checkpoint was taken on
+    pub fn check_target(&self, device: &DeviceInfo) -> Result<(), String> {
+        if device.size != self.device_size {
+            return Err(format!("Checkpoint is for a {} byte device, {} has {} bytes",
+                               self.device_size, device.path.display(), device.size));
+        }
+        match (&self.serial, device_serial(&device.name)) {
+            (Some(expected), Some(actual)) if *expected != actual => {
+                Err(format!("Checkpoint is for serial {}, {} has serial {}", expected, device.path.display(), actual))
+            }
+            (Some(expected), None) => {
+                Err(format!("Checkpoint is for serial {}, {} reports none", expected, device.path.display()))
+            }
+            // Without a serial the path is all there is to go on
+            (None, _) if device.path != self.device => {
+                Err(format!("Checkpoint is for {}, not {}", self.device.display(), device.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Small authenticated record written to the last sector after a wipe, so a
+/// drive coming back from storage can be checked months later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EraseStamp {
+    pub magic: String,
+    pub tool_version: String,
+    pub method: String,
+    pub timestamp: u64,        // Seconds since the Unix epoch, UTC
+    pub passes: usize,
+    pub verification: String,  // "passed", "failed" or "not-run"
+    pub mac: String,           // Keyed BLAKE3 over the other fields
+}
+
+impl EraseStamp {
+    pub fn new(method: WipePattern, passes: usize, verification: &str, key: &[u8]) -> Self {
+        let mut stamp = Self {
+            magic: STAMP_MAGIC.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            method: format!("{:?}", method),
+            timestamp: unix_time(),
+            passes,
+            verification: verification.to_string(),
+            mac: String::new(),
+        };
+        stamp.mac = stamp.compute_mac(key);
+        stamp
+    }
+
+    fn compute_mac(&self, key: &[u8]) -> String {
+        let unsigned = Self { mac: String::new(), ..self.clone() };
+        let message = serde_json::to_vec(&unsigned).unwrap_or_default();
+        let mac_key = blake3::derive_key("memerase erase stamp v1", key);
+        blake3::keyed_hash(&mac_key, &message).to_hex().to_string()
+    }
+
+    pub fn is_authentic(&self, key: &[u8]) -> bool {
+        self.magic == STAMP_MAGIC && self.compute_mac(key) == self.mac
+    }
+
+    /// Serialize into exactly one zero-padded sector
+    fn to_sector(&self) -> Result<Vec<u8>, EraseError> {
+        let mut sector = serde_json::to_vec(self).map_err(|e| EraseError::Other(e.to_string()))?;
+        if sector.len() > STAMP_SIZE as usize {
+            return Err(EraseError::Other("Erase stamp does not fit in one sector".to_string()));
+        }
+        sector.resize(STAMP_SIZE as usize, 0);
+        Ok(sector)
+    }
+
+    fn from_sector(sector: &[u8]) -> Option<Self> {
+        let end = sector.iter().position(|&b| b == 0).unwrap_or(sector.len());
+        serde_json::from_slice::<Self>(&sector[..end])
+            .ok()
+            .filter(|stamp| stamp.magic == STAMP_MAGIC)
+    }
+}
+
+/// One pass of a planned erase
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedPass {
+    pub pass: usize,
+    pub pattern: String,
+    pub bytes: u64,
+}
+
+/// What `--dry-run` reports: the erase exactly as it would run
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasePlan {
+    pub device: PathBuf,
+    pub device_size: u64,
+    pub sector_size: usize,
+    pub block_size: usize,
+    pub method: WipePattern,
+    pub passes: Vec<PlannedPass>,
+    pub bytes_total: u64,
+    pub range: Option<ByteRange>,  // Only this part of the device (--offset/--length)
+    pub sync_mode: SyncMode,
+    pub direct_io: bool,
+    pub fua: bool,
+    pub verification: Option<VerifyMode>,
+    pub estimate: DurationEstimate,
+    pub blockers: Vec<String>,  // Reasons the real run would refuse; empty if it would proceed
+}
+
+/// Expected length of an erase, shown before the final confirmation
+#[derive(Debug, Clone, Serialize)]
+pub struct DurationEstimate {
+    pub passes: usize,
+    pub mb_per_s: f64,
+    pub total_secs: f64,
+    pub source: &'static str,  // "measured" or "device class"
+}
+
+/// Measured write rate for one candidate block size
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockSizeRate {
+    pub block_size: usize,
+    pub mb_per_s: f64,
+}
+
+/// Outcome of the --auto-tune preflight
+#[derive(Debug, Clone, Serialize)]
+pub struct AutoTuneResult {
+    pub burst_bytes: u64,
+    pub rates: Vec<BlockSizeRate>,
+    pub chosen: usize,
+}
+
+/// How much of the device an erase covered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EraseScope {
+    Sanitized,  // Every byte, by every pass
+    Quick,      // Partition tables and filesystem headers only (--method quick)
+    Partial,    // Every pass, but only over --offset/--length
+}
+
+/// Empty partition table laid down by --init-table after a verified erase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableKind {
+    Gpt,
+    Mbr,
+}
+
+impl std::fmt::Display for TableKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TableKind::Gpt => "GPT",
+            TableKind::Mbr => "MBR",
+        })
+    }
+}
+
+/// The table --init-table wrote, with the identifier it was given
+#[derive(Debug, Clone, Serialize)]
+pub struct NewPartitionTable {
+    pub kind: TableKind,
+    pub disk_guid: Option<String>,       // GPT
+    pub disk_signature: Option<String>,  // MBR, 8 hex digits as fdisk shows it
+}
+
+/// What --pre-hash read off the device before destroying it: SHA-256 of
+/// sample regions picked by `seed` (see pre_hash_regions), so the same
+/// regions of an image or another drive can be hashed and compared.
+/// Unrelated to the post-wipe verification digests.
+#[derive(Debug, Clone, Serialize)]
+pub struct PreWipeFingerprint {
+    pub seed: String,         // 16 hex digits
+    pub region_size: u64,
+    pub regions: Vec<SampleDigest>,
+    pub complete: bool,       // False when PRE_HASH_DEADLINE cut sampling short
+    pub duration_secs: f64,
+}
+
+/// One --pre-hash sample region
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleDigest {
+    pub offset: u64,
+    pub length: u64,
+    pub sha256: Option<String>,  // None when the region could not be read
+    pub error: Option<String>,
+}
+
+/// Filesystem laid down by --format-after on a wiped removable device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsKind {
+    Fat32,
+    Exfat,
+}
+
+impl std::fmt::Display for FsKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FsKind::Fat32 => "FAT32",
+            FsKind::Exfat => "exFAT",
+        })
+    }
+}
+
+/// The filesystem --format-after created
+#[derive(Debug, Clone, Serialize)]
+pub struct NewFilesystem {
+    pub kind: FsKind,
+    pub label: Option<String>,
+    pub volume_id: String,  // XXXX-XXXX, as blkid and Windows show it
+    pub cluster_size: u32,
+}
+
+/// What it takes to create a filesystem: byte ranges to clear first (they
+/// still hold the last pass), then the structures to write over them
+pub struct FsImage {
+    zero: Vec<ByteRange>,
+    sectors: Vec<(u64, Vec<u8>)>,  // (byte offset, data)
+    cluster_size: u32,
+}
+
+/// A span of the device, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ByteRange {
+    pub fn whole(device_size: u64) -> Self {
+        ByteRange { offset: 0, length: device_size }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
+/// Outcome of wiping a filesystem's free space
+#[derive(Debug, Clone, Serialize)]
+pub struct FreeSpaceReport {
+    pub path: PathBuf,
+    pub method: WipePattern,
+    pub passes: usize,
+    pub free_before: Option<u64>,
+    pub files_created: usize,
+    pub bytes_overwritten: u64,   // Free space covered by each pass
+    pub cleanup_errors: Vec<String>,
+}
+
+/// Filler files created by `wipe-free`; removed when dropped so an error or
+/// interrupt never leaves the filesystem full
+pub(crate) struct FillerFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl FillerFiles {
+    fn remove_all(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for path in self.paths.drain(..) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                errors.push(format!("{}: {}", path.display(), e));
+            }
+        }
+        errors
+    }
+}
+
+impl Drop for FillerFiles {
+    fn drop(&mut self) {
+        for error in self.remove_all() {
+            warn!("Warning: could not remove filler file {}", error);
+        }
+    }
+}
+
+/// How `shred` treats holes in sparse files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SparseMode {
+    AllocatedOnly,  // Overwrite only mapped extents: every byte that ever reached the disk
+    Full,           // Write the whole logical length, allocating the holes
+}
+
+impl std::str::FromStr for SparseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allocated-only" | "allocated" => Ok(SparseMode::AllocatedOnly),
+            "full" => Ok(SparseMode::Full),
+            _ => Err(format!("Invalid sparse mode: {}", s)),
+        }
+    }
+}
+
+/// Outcome of shredding one file
+#[derive(Debug, Clone, Serialize)]
+pub struct ShredResult {
+    pub path: PathBuf,
+    pub kind: &'static str,      // "file", or "symlink"/"directory" which are only removed
+    pub size: u64,               // Logical length
+    pub bytes_overwritten: u64,  // Per pass; below `size` when holes were skipped
+    pub sparse_mode: Option<SparseMode>,
+    pub passes: usize,
+    pub verified: Option<bool>,  // None when verification wasn't requested
+    pub error: Option<String>,   // Set if the file could not be fully shredded
+}
+
+/// Rolling write rate over the last few seconds. A cumulative average hides
+/// stalls (a bridge dropping to 2 MB/s an hour in barely moves it), so the
+/// live display and the per-pass min/max use this instead.
+pub(crate) struct ThroughputMeter {
+    samples: std::collections::VecDeque<(std::time::Instant, u64)>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl ThroughputMeter {
+    fn new() -> Self {
+        let mut samples = std::collections::VecDeque::new();
+        samples.push_back((std::time::Instant::now(), 0));
+        ThroughputMeter { samples, min: None, max: None }
+    }
+
+    /// Note that `total` bytes have been written so far
+    fn record(&mut self, total: u64) {
+        let now = std::time::Instant::now();
+        self.samples.push_back((now, total));
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= THROUGHPUT_WINDOW {
+            self.samples.pop_front();
+        }
+
+        // Extremes only count once a full window has been observed
+        if now.duration_since(self.samples[0].0) >= THROUGHPUT_WINDOW {
+            let rate = self.rate();
+            self.min = Some(self.min.map_or(rate, |m| m.min(rate)));
+            self.max = Some(self.max.map_or(rate, |m| m.max(rate)));
+        }
+    }
+
+    /// Shift the window past a pause, so the gap doesn't read as a stall
+    fn skip(&mut self, gap: std::time::Duration) {
+        for sample in self.samples.iter_mut() {
+            sample.0 += gap;
+        }
+    }
+
+    /// Bytes per second across the window
+    fn rate(&self) -> f64 {
+        let (first_at, first) = self.samples[0];
+        let (last_at, last) = *self.samples.back().unwrap();
+        let secs = last_at.duration_since(first_at).as_secs_f64();
+        if secs > 0.0 { (last - first) as f64 / secs } else { 0.0 }
+    }
+}
+
+/// What an erase is busy with when it reports progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressPhase {
+    Writing,
+    Syncing,    // Waiting for a pass to reach the medium
+    Verifying,
+}
+
+/// Where an erase stands, handed to the progress callback of `secure_erase`
+/// and `quick_erase`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProgressUpdate {
+    pub phase: ProgressPhase,
+    pub pass: usize,         // 1-based
+    pub passes: usize,
+    pub pass_bytes: u64,     // Written so far in this pass
+    pub pass_total: u64,     // Bytes each pass writes
+    pub bytes_done: u64,     // All passes together
+    pub total_bytes: u64,
+    pub device_size: u64,
+    pub rate: f64,           // Bytes per second over the last few seconds
+    pub average_rate: f64,   // Bytes per second over the pass so far, pauses excluded
+    pub eta_secs: Option<f64>,
+}
+
+impl ProgressUpdate {
+    /// Of the whole run, all passes together
+    pub fn percent(&self) -> f64 {
+        self.bytes_done as f64 / self.total_bytes.max(1) as f64 * 100.0
+    }
+}
+
+/// Called at most every PROGRESS_CALLBACK_INTERVAL while writing, on every
+/// phase change and when a pass finishes
+pub type ProgressCallback = Box<dyn FnMut(&ProgressUpdate)>;
+
+/// What an EraseTask resolves to: whatever `secure_erase` returned
+pub type AsyncEraseResult = Result<EraseReport, EraseError>;
+
+/// Progress updates an EraseTask keeps for its ProgressReceiver; beyond
+/// this the oldest are dropped, so nobody listening costs nothing
+pub(crate) const PROGRESS_QUEUE: usize = 64;
+
+/// State shared between an EraseTask, its ProgressReceiver and the thread
+/// doing the erase
+#[derive(Default)]
+pub(crate) struct AsyncErase {
+    updates: std::collections::VecDeque<ProgressUpdate>,
+    result: Option<AsyncEraseResult>,
+    finished: bool,  // The result has been set (and possibly taken since)
+    task_waker: Option<std::task::Waker>,
+    progress_waker: Option<std::task::Waker>,
+}
+
+/// A secure erase running on its own thread, started by
+/// `SecureEraser::secure_erase_async`. Awaiting it yields the report.
+/// Dropping it cancels the erase, which stops after the block in flight
+/// and syncs what it wrote in the background instead of blocking the
+/// executor.
+pub struct EraseTask {
+    shared: Arc<std::sync::Mutex<AsyncErase>>,
+    cancel: CancellationToken,
+}
+
+impl EraseTask {
+    /// Cancel without giving up the result; the task then resolves to
+    /// InterruptedError with the progress so far
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+impl std::future::Future for EraseTask {
+    type Output = AsyncEraseResult;
+
+    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.result.take() {
+            Some(result) => std::task::Poll::Ready(result),
+            None if shared.finished => std::task::Poll::Ready(Err(EraseError::Other("EraseTask polled after it completed".to_string()))),
+            None => {
+                shared.task_waker = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for EraseTask {
+    fn drop(&mut self) {
+        if !self.shared.lock().unwrap().finished {
+            self.cancel.cancel();
+        }
+    }
+}
+
+/// Progress of an EraseTask, as the blocking API's ProgressCallback sees it
+pub struct ProgressReceiver {
+    shared: Arc<std::sync::Mutex<AsyncErase>>,
+}
+
+impl ProgressReceiver {
+    /// The next update, or None once the erase has finished and every
+    /// update has been received
+    pub async fn recv(&mut self) -> Option<ProgressUpdate> {
+        std::future::poll_fn(|cx| {
+            let mut shared = self.shared.lock().unwrap();
+            if let Some(update) = shared.updates.pop_front() {
+                return std::task::Poll::Ready(Some(update));
+            }
+            if shared.finished {
+                return std::task::Poll::Ready(None);
+            }
+            shared.progress_waker = Some(cx.waker().clone());
+            std::task::Poll::Pending
+        }).await
+    }
+}
+
+impl SecureEraser {
+    /// `secure_erase` for async callers: the erase runs on a thread of its
+    /// own, progress arrives through the returned receiver, and the task
+    /// resolves to the same report. Works under any executor. The eraser
+    /// is consumed; a token given to set_cancellation becomes the task's,
+    /// so cancelling either stops the erase.
+    pub fn secure_erase_async(
+        mut self,
+        device_path: PathBuf,
+        pattern: WipePattern,
+        verify: Option<VerifyMode>,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+        keep_going: bool,
+    ) -> io::Result<(EraseTask, ProgressReceiver)> {
+        let shared = Arc::new(std::sync::Mutex::new(AsyncErase::default()));
+        let cancel = self.cancel.get_or_insert_with(CancellationToken::new).clone();
+
+        let worker = Arc::clone(&shared);
+        std::thread::Builder::new().name("memerase-erase".to_string()).spawn(move || {
+            let shared = worker;
+            let progress = Arc::clone(&shared);
+            let callback: ProgressCallback = Box::new(move |update| {
+                let mut shared = progress.lock().unwrap();
+                if shared.updates.len() == PROGRESS_QUEUE {
+                    shared.updates.pop_front();
+                }
+                shared.updates.push_back(*update);
+                if let Some(waker) = shared.progress_waker.take() {
+                    waker.wake();
+                }
+            });
+            let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                self.secure_erase(&device_path, pattern, verify, sync_mode, direct_io, false, keep_going, None, Some(callback))
+            }));
+
+            let mut shared = shared.lock().unwrap();
+            shared.result = Some(outcome.unwrap_or_else(|_| Err(EraseError::Other("the erase thread panicked".to_string()))));
+            shared.finished = true;
+            for waker in [shared.task_waker.take(), shared.progress_waker.take()].into_iter().flatten() {
+                waker.wake();
+            }
+        })?;
+
+        Ok((EraseTask { shared: Arc::clone(&shared), cancel }, ProgressReceiver { shared }))
+    }
+}
+
+/// One erase of one device: what to write, how to check it and what to
+/// report to. Put together with `EraseJob::new(device)`, checked by
+/// `EraseJobBuilder::build` and run with `SecureEraser::run`.
+pub struct EraseJob {
+    pub(crate) device: PathBuf,
+    pub(crate) pattern: WipePattern,
+    pub(crate) verify: Option<VerifyMode>,
+    pub(crate) sync_mode: SyncMode,
+    pub(crate) direct_io: DirectIo,
+    pub(crate) auto_tune: bool,
+    pub(crate) keep_going: bool,
+    pub(crate) stamp_key: Option<Vec<u8>>,
+    pub(crate) range: Option<ByteRange>,  // None erases the whole device
+    pub(crate) expected: Option<ExpectedDevice>,
+    // Left unset, these fall back to what the eraser was given
+    pub(crate) block_size: Option<usize>,
+    pub(crate) cancel: Option<CancellationToken>,
+    pub(crate) progress: Option<ProgressCallback>,
+}
+
+impl EraseJob {
+    /// A job with the command line's defaults: a single zero pass, no
+    /// verification, a sync per pass and O_DIRECT where it applies
+    ///
+    /// ```
+    /// use memerase::{EraseError, EraseJob, VerifyMode, WipePattern};
+    ///
+    /// let job = EraseJob::new("/dev/sdx")
+    ///     .pattern(WipePattern::Random)
+    ///     .verify(Some(VerifyMode::Full))
+    ///     .block_size(3 << 20)
+    ///     .build();
+    /// assert!(matches!(job, Err(EraseError::InvalidArgument(_))));
+    /// ```
+    #[allow(clippy::new_ret_no_self)]  // The job only exists once it is built
+    pub fn new(device: impl Into<PathBuf>) -> EraseJobBuilder {
+        EraseJobBuilder {
+            job: EraseJob {
+                device: device.into(),
+                pattern: WipePattern::Zeros,
+                verify: None,
+                sync_mode: SyncMode::PerPass,
+                direct_io: DirectIo::Auto,
+                auto_tune: false,
+                keep_going: false,
+                stamp_key: None,
+                range: None,
+                expected: None,
+                block_size: None,
+                cancel: None,
+                progress: None,
+            },
+        }
+    }
+
+    pub fn device(&self) -> &Path {
+        &self.device
+    }
+}
+
+/// Builds an EraseJob; see `EraseJob::new`
+pub struct EraseJobBuilder {
+    job: EraseJob,
+}
+
+impl EraseJobBuilder {
+    pub fn pattern(mut self, pattern: WipePattern) -> Self {
+        self.job.pattern = pattern;
+        self
+    }
+
+    /// Read the final pass back this way; None skips verification
+    pub fn verify(mut self, verify: Option<VerifyMode>) -> Self {
+        self.job.verify = verify;
+        self
+    }
+
+    pub fn sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.job.sync_mode = sync_mode;
+        self
+    }
+
+    pub fn direct_io(mut self, direct_io: DirectIo) -> Self {
+        self.job.direct_io = direct_io;
+        self
+    }
+
+    /// Benchmark block sizes on the device first and erase with the fastest
+    pub fn auto_tune(mut self, auto_tune: bool) -> Self {
+        self.job.auto_tune = auto_tune;
+        self
+    }
+
+    /// Skip sectors that stay unwritable instead of failing the erase
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.job.keep_going = keep_going;
+        self
+    }
+
+    /// After the last pass and any verification, write one `EraseStamp`
+    /// keyed with `key` into the device's last sector. Later verify-only
+    /// runs recognise the stamp and leave that sector out.
+    pub fn stamp_key(mut self, key: Option<&[u8]>) -> Self {
+        self.job.stamp_key = key.map(<[u8]>::to_vec);
+        self
+    }
+
+    /// Size of each write and verification read, as `set_block_size`
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.job.block_size = Some(block_size);
+        self
+    }
+
+    /// Erase only `range` of the device, as `set_range`
+    pub fn range(mut self, range: Option<ByteRange>) -> Self {
+        self.job.range = range;
+        self
+    }
+
+    /// Refuse the erase unless the opened device still matches, as
+    /// `expect_device`
+    pub fn expect_device(mut self, expected: Option<ExpectedDevice>) -> Self {
+        self.job.expected = expected;
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.job.cancel = Some(token);
+        self
+    }
+
+    pub fn on_progress(mut self, callback: impl FnMut(&ProgressUpdate) + 'static) -> Self {
+        self.job.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Check the job before anything is opened for writing: the block size
+    /// must be one `set_block_size` accepts, and a range must lie within the
+    /// device as it is now. Running it checks the device again.
+    pub fn build(self) -> Result<EraseJob, EraseError> {
+        let job = self.job;
+        if let Some(block_size) = job.block_size {
+            check_block_size(block_size).map_err(EraseError::InvalidArgument)?;
+        }
+        if let Some(range) = job.range {
+            let device_size = system_provider().size(&job.device)?;
+            if range.length == 0 || range.end() > device_size {
+                return Err(EraseError::InvalidArgument(format!("Range {} at offset {} does not fit in {} ({} bytes)",
+                                                               format_size(range.length), range.offset, job.device.display(), device_size)));
+            }
+        }
+        Ok(job)
+    }
+}
+
+/// The block sizes `set_block_size` accepts
+fn check_block_size(block_size: usize) -> Result<(), String> {
+    if !block_size.is_power_of_two() || !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+        return Err(format!(
+            "Block size must be a power of two between {} and {} bytes, got {}",
+            MIN_BLOCK_SIZE, MAX_BLOCK_SIZE, block_size
+        ));
+    }
+    Ok(())
+}
+
+/// Overwrites devices and files and reads them back. One eraser holds the
+/// I/O settings (block size, backend, rate limit, retries and so on) and is
+/// reused for every operation; create it with `new` and adjust it with the
+/// `set_*` methods.
+///
+/// Nothing is printed: progress goes to the optional `ProgressCallback`,
+/// messages go out as `tracing` events, and progress bars are only drawn
+/// once `ui::VERBOSITY` is raised above 0.
+pub struct SecureEraser {
+    pub(crate) rng: rand_chacha::ChaCha20Rng,  // Not ThreadRng, so an eraser can move to a worker thread
+    seed: u64,  // What `rng` was last seeded with, recorded in each report
+    pub(crate) block_size: usize,
+    gen_threads: usize,
+    io_backend: IoBackend,
+    queue_depth: u32,
+    max_rate: Option<f64>,
+    writeback: Option<(u64, u64)>,
+    write_batch: usize,
+    skip_matching: bool,
+    tail_first: bool,
+    pre_hash: bool,
+    write_retries: u32,
+    write_timeout: Option<(std::time::Duration, Option<std::time::Duration>)>,  // Warn, then give up, after a write takes this long
+    pub(crate) unwritable: Vec<ByteRange>,  // Of the erase in progress, so verification can allow for them
+    fua: bool,
+    disable_write_cache: bool,
+    checkpoint: Option<(PathBuf, std::time::Duration)>,
+    resume: Option<Checkpoint>,
+    expected: Option<ExpectedDevice>,
+    range: Option<ByteRange>,  // --offset/--length; None erases the whole device
+    pub(crate) cancel: Option<CancellationToken>,
+    gave_up: Arc<std::sync::Mutex<Option<NotRespondingError>>>,  // Set by the watchdog of the erase in progress
+    not_responding_hook: Option<NotRespondingHook>,
+    devices: Box<dyn DeviceProvider>,
+}
+
+impl Default for SecureEraser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecureEraser {
+    /// An eraser with the defaults the command line starts from: 1MB
+    /// blocks, synchronous writes, writeback every 32MB and the write
+    /// watchdog armed
+    pub fn new() -> Self {
+        Self::with_seed(rand::random())
+    }
+
+    /// An eraser like `new` whose random passes, sampled verification and
+    /// generated identifiers all come from `seed`, so the same seed and the
+    /// same sequence of calls give the same bytes
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: rand::SeedableRng::seed_from_u64(seed),
+            seed,
+            block_size: BLOCK_SIZE,
+            gen_threads: default_gen_threads(),
+            io_backend: IoBackend::Sync,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            max_rate: None,
+            writeback: Some((DEFAULT_WRITEBACK_MB, DEFAULT_MAX_DIRTY_MB)),
+            write_batch: DEFAULT_WRITE_BATCH,
+            skip_matching: false,
+            tail_first: true,
+            pre_hash: false,
+            write_retries: DEFAULT_WRITE_RETRIES,
+            write_timeout: Some((std::time::Duration::from_secs(DEFAULT_WRITE_TIMEOUT),
+                                 Some(std::time::Duration::from_secs(DEFAULT_WRITE_ABORT)))),
+            unwritable: Vec::new(),
+            fua: false,
+            disable_write_cache: false,
+            checkpoint: None,
+            resume: None,
+            expected: None,
+            range: None,
+            cancel: None,
+            gave_up: Arc::default(),
+            not_responding_hook: None,
+            devices: system_provider(),
+        }
+    }
+
+    /// Stop erases and verification once `token` is cancelled, as Ctrl-C
+    /// does; see CancellationToken
+    pub fn set_cancellation(&mut self, token: Option<CancellationToken>) {
+        self.cancel = token;
+    }
+
+    /// Restrict erases, their verification and their estimates to `range`
+    /// of the device. Checked against each device's size when it is opened.
+    pub fn set_range(&mut self, range: Option<ByteRange>) {
+        self.range = range;
+    }
+
+    /// Refuse the next erase unless the opened device still matches what was
+    /// confirmed. The path alone isn't enough: pull a USB stick during the
+    /// prompt and another one can enumerate under the same name.
+    pub fn expect_device(&mut self, expected: Option<ExpectedDevice>) {
+        self.expected = expected;
+    }
+
+    /// Save progress to `path` every `interval` (and at each pass boundary)
+    /// while erasing; the file is removed once the erase succeeds
+    pub fn set_checkpoint(&mut self, path: &Path, interval: std::time::Duration) {
+        self.checkpoint = Some((path.to_path_buf(), interval));
+    }
+
+    /// Continue the erase recorded in `checkpoint` instead of starting over.
+    /// The caller is expected to have checked it against the target.
+    pub fn set_resume(&mut self, checkpoint: Checkpoint) {
+        self.resume = Some(checkpoint);
+    }
+
+    /// Turn the drive's volatile write cache off for the erase and restore
+    /// the previous setting afterwards
+    pub fn set_disable_write_cache(&mut self, disable: bool) {
+        self.disable_write_cache = disable;
+    }
+
+    /// Force every write to the medium rather than the drive's volatile
+    /// cache: O_DIRECT|O_DSYNC (FUA) on Linux, FILE_FLAG_WRITE_THROUGH on
+    /// Windows. Expect a large throughput drop, most severe on HDDs and
+    /// cheap flash where the cache normally absorbs writes.
+    pub fn set_fua(&mut self, fua: bool) {
+        self.fua = fua;
+    }
+
+    /// Read each block of a constant-fill pass first and only write it if
+    /// the device doesn't already hold the pattern. Random passes always write.
+    pub fn set_skip_matching(&mut self, skip: bool) {
+        self.skip_matching = skip;
+    }
+
+    /// Overwrite the last TAIL_FIRST_BYTES right after the first ones at the
+    /// start of pass 1, so an erase cancelled early still takes the backup
+    /// GPT with it. On by default.
+    pub fn set_tail_first(&mut self, tail_first: bool) {
+        self.tail_first = tail_first;
+    }
+
+    /// Fingerprint the device (see PreWipeFingerprint) before the first
+    /// write of each erase, tuning bursts included
+    pub fn set_pre_hash(&mut self, pre_hash: bool) {
+        self.pre_hash = pre_hash;
+    }
+
+    /// When a write fails with a media error, rewrite the block in ever
+    /// smaller pieces down to single sectors, try each failing sector
+    /// `retries` times, then skip it and carry on. 0 ends the erase at the
+    /// first failed write instead. Only synchronous O_DIRECT writes fail
+    /// where the bad sector is; io_uring and buffered errors still end it.
+    pub fn set_write_retries(&mut self, retries: u32) {
+        self.write_retries = retries;
+    }
+
+    /// Warn with the stuck offset when a single write or flush has not
+    /// returned after `warn_after`, and give the device up once it has been
+    /// stuck for `abort_after`: the erase is cancelled and fails with
+    /// `EraseError::NotResponding` when the write returns. None disables either.
+    pub fn set_write_timeout(&mut self, warn_after: Option<std::time::Duration>, abort_after: Option<std::time::Duration>) {
+        self.write_timeout = match (warn_after, abort_after) {
+            (None, None) => None,
+            (warn, abort) => Some((warn.or(abort).unwrap(), abort)),
+        };
+    }
+
+    /// Called on the watchdog thread as soon as it gives a device up, before
+    /// the stuck write has returned, if it ever does
+    pub fn set_not_responding_hook(&mut self, hook: Option<NotRespondingHook>) {
+        self.not_responding_hook = hook;
+    }
+
+    /// Start the --write-timeout watchdog over `writer`'s writes, cancelling
+    /// through `cancel` when it gives up
+    fn watch_writes(&self, writer: &mut DeviceWriter, device_path: &Path, cancel: &CancellationToken) -> Option<WriteWatchdog> {
+        let (warn_after, abort_after) = self.write_timeout?;
+        let watchdog = WriteWatchdog::start(device_path, warn_after, abort_after, cancel.clone(),
+                                            self.gave_up.clone(), self.not_responding_hook.clone());
+        writer.watch = Some(watchdog.watch.clone());
+        Some(watchdog)
+    }
+
+    /// Blocks per vectored write for constant-fill passes; 1 disables batching
+    pub fn set_write_batch(&mut self, blocks: usize) {
+        self.write_batch = blocks.clamp(1, MAX_WRITE_BATCH);
+    }
+
+    /// Start writeback of buffered data every `interval_mb` and wait once
+    /// more than `max_dirty_mb` is outstanding; an interval of 0 disables it
+    pub fn set_writeback(&mut self, interval_mb: u64, max_dirty_mb: u64) {
+        self.writeback = if interval_mb == 0 {
+            None
+        } else {
+            Some((interval_mb, max_dirty_mb.max(interval_mb)))
+        };
+    }
+
+    /// Cap write throughput at `mb_per_s` for each device erased
+    pub fn set_max_rate(&mut self, mb_per_s: Option<f64>) {
+        self.max_rate = mb_per_s.filter(|&rate| rate > 0.0);
+    }
+
+    /// Select the write backend and, for io_uring, how many writes stay in flight
+    pub fn set_io_backend(&mut self, backend: IoBackend, queue_depth: u32) {
+        self.io_backend = backend;
+        self.queue_depth = queue_depth.max(1);
+    }
+
+    /// Number of threads generating random pass data
+    pub fn set_gen_threads(&mut self, threads: usize) {
+        self.gen_threads = threads.max(1);
+    }
+
+    /// Set the size of each write and verification read. Must be a power of
+    /// two between 512 bytes and 256 MB.
+    pub fn set_block_size(&mut self, block_size: usize) -> Result<(), String> {
+        check_block_size(block_size)?;
+        self.block_size = block_size;
+        Ok(())
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// List available storage devices
+    pub fn list_devices(&self) -> Result<Vec<DeviceInfo>, EraseError> {
+        self.devices.list()
+    }
+
+    /// Take device lists, sizes, mounts, holders and identities from
+    /// `provider` instead of the running system
+    pub fn set_device_provider(&mut self, provider: Box<dyn DeviceProvider>) {
+        self.devices = provider;
+    }
+
+    pub fn device_provider(&self) -> &dyn DeviceProvider {
+        self.devices.as_ref()
+    }
+
+    /// Restart the random stream from `seed` (--seed)
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = rand::SeedableRng::seed_from_u64(seed);
+        self.seed = seed;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Describe the passes of the selected method. Nothing is allocated
+    /// here: the write loop expands each descriptor block by block into a
+    /// couple of reusable buffers, and random passes get a fresh key so their
+    /// data differs across the whole device and between passes.
+    pub fn pass_plan(&mut self, pattern: WipePattern) -> Vec<PassFill> {
+        let mut random = || PassFill::Random { key: self.rng.gen() };
+
+        match pattern {
+            WipePattern::Zeros => vec![PassFill::Fill(0x00)],
+            WipePattern::Ones => vec![PassFill::Fill(0xFF)],
+            WipePattern::Random => vec![random()],
+            WipePattern::Dod3Pass => vec![PassFill::Fill(0x00), PassFill::Fill(0xFF), random()],
+            WipePattern::Gutmann35 => {
+                let mut passes: Vec<PassFill> = (0..4).map(|_| random()).collect();
+                passes.extend([0x55, 0xAA, 0x92, 0x49, 0x24].iter().map(|&b| PassFill::Fill(b)));
+                passes
+            }
+        }
+    }
+
+    /// `run` with the job spelled out as arguments, and the range and
+    /// expected device taken from `set_range` and `expect_device`. The
+    /// argument list from before `EraseJob`, kept for existing callers.
+    #[allow(clippy::too_many_arguments)]
+    pub fn secure_erase(
+        &mut self,
+        device_path: &Path,
+        pattern: WipePattern,
+        verify: Option<VerifyMode>,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+        auto_tune: bool,
+        keep_going: bool,
+        stamp_key: Option<&[u8]>,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<EraseReport, EraseError> {
+        let job = EraseJob {
+            range: self.range,
+            expected: self.expected.take(),
+            progress: progress_callback,
+            ..EraseJob::new(device_path)
+                .pattern(pattern)
+                .verify(verify)
+                .sync_mode(sync_mode)
+                .direct_io(direct_io)
+                .auto_tune(auto_tune)
+                .keep_going(keep_going)
+                .stamp_key(stamp_key)
+                .job
+        };
+        self.run(job)
+    }
+
+    /// Overwrite the job's device with every pass of its pattern, then read
+    /// the final pass back as it asks. The job's range and expected device
+    /// replace the eraser's; a block size or cancellation token it sets
+    /// stays with the eraser afterwards. The returned report records each pass, any sectors
+    /// skipped under `keep_going` and the verification result; an
+    /// EraseError says why it failed.
+    pub fn run(&mut self, job: EraseJob) -> Result<EraseReport, EraseError> {
+        let result = self.run_passes(job);
+        self.unless_given_up(result)
+    }
+
+    /// A failed erase whose write the watchdog gave up on failed because of
+    /// that, whatever the cancellation unwound as
+    fn unless_given_up(&mut self, result: Result<EraseReport, EraseError>) -> Result<EraseReport, EraseError> {
+        match (result, self.gave_up.lock().unwrap().take()) {
+            (Err(_), Some(stuck)) => Err(EraseError::NotResponding(stuck)),
+            (result, _) => result,
+        }
+    }
+
+    fn run_passes(&mut self, job: EraseJob) -> Result<EraseReport, EraseError> {
+        let EraseJob {
+            device, pattern, verify, sync_mode, direct_io, auto_tune, keep_going, stamp_key,
+            range, expected, block_size, cancel, progress: mut progress_callback,
+        } = job;
+        if let Some(block_size) = block_size {
+            self.block_size = block_size;
+        }
+        self.range = range;
+        self.expected = expected;
+        if cancel.is_some() {
+            self.cancel = cancel;
+        }
+        let device_path = device.as_path();
+        let stamp_key = stamp_key.as_deref();
+        info!("Starting secure erase of: {}", device_path.display());
+        // The watchdog cancels through this when a write never returns
+        let cancel = self.cancel.clone().unwrap_or_default();
+        let _cancel_scope = CancelScope::enter(Some(cancel.clone()));
+        let started_at = unix_time();
+        let clock = std::time::Instant::now();
+
+        // Open device for direct access
+        let mut writer = self.open_device_for_writing(device_path, sync_mode, direct_io)?;
+        let watchdog = self.watch_writes(&mut writer, device_path, &cancel);
+        
+        // Get device size
+        let device_size = self.get_device_size(&writer.file, device_path)?;
+        self.check_unchanged(&writer.file, device_path, device_size)?;
+        info!("Device size: {}", size_label(device_size));
+        // Everything below writes, checks and counts within `range`
+        let range = self.range.unwrap_or(ByteRange::whole(device_size));
+        let partial = range != ByteRange::whole(device_size);
+        // Only an empty file gets here with nothing to write, and then
+        // nothing is done
+        if (partial && range.length == 0) || range.end() > device_size {
+            return Err(EraseError::InvalidArgument(format!("Range {} at offset {} does not fit in {} ({} bytes)",
+                                                           format_size(range.length), range.offset, device_path.display(), device_size)));
+        }
+        if partial {
+            info!("Erasing only {}", describe_range(range));
+            if self.checkpoint.is_some() || self.resume.is_some() || auto_tune || stamp_key.is_some() {
+                return Err(EraseError::InvalidArgument("--offset/--length can't be combined with checkpoints, --auto-tune or --stamp".to_string()));
+            }
+        }
+        debug!("Open flags: {}", describe_open_flags(sync_mode, writer.direct, writer.fua));
+        if writer.direct {
+            debug!("Using O_DIRECT with {} byte sectors", writer.sector_size);
+        }
+        if writer.fua {
+            info!("Using FUA writes: each write is forced to the medium");
+        }
+        // A resumed erase keeps the block size its offsets were counted in
+        let resume = self.resume.take();
+        if let Some(ref checkpoint) = resume {
+            self.block_size = checkpoint.block_size;
+        }
+        let sector_size = logical_block_size(&writer.file);
+        debug!("Sector size: {} bytes logical, {} bytes physical", sector_size, physical_block_size(&writer.file));
+        if !self.block_size.is_multiple_of(sector_size) {
+            return Err(EraseError::InvalidArgument(format!(
+                "Block size {} is not a multiple of the device's {} byte logical sector size",
+                self.block_size, sector_size
+            )));
+        }
+        // O_DIRECT writes must start on a sector; a ragged end goes through the tail path
+        if writer.direct && !range.offset.is_multiple_of(sector_size as u64) {
+            return Err(EraseError::InvalidArgument(format!("--offset {} is not a multiple of the {} byte sector size",
+                                                           range.offset, sector_size)));
+        }
+        // A resumed erase has already overwritten part of what would be sampled
+        let pre_wipe = if self.pre_hash && resume.is_none() {
+            Some(self.pre_wipe_fingerprint(device_path, range)?)
+        } else {
+            None
+        };
+
+        // Must be off before anything is written, including tuning bursts
+        let mut cache_guard = if self.disable_write_cache {
+            let guard = WriteCacheGuard::disable(device_path)
+                .map_err(|e| EraseError::Failed { what: "Could not disable the write cache".to_string(), source: e })?;
+            info!("Drive write cache: {} -> disabled", if guard.previous { "enabled" } else { "already disabled" });
+            Some(guard)
+        } else {
+            None
+        };
+
+        // The tuning bursts land at the start of the device, which pass 1
+        // overwrites like everything else (but a resumed pass would not)
+        let auto_tune = if auto_tune && resume.is_none() {
+            let result = self.auto_tune_block_size(&mut writer, device_size, sector_size)?;
+            for rate in &result.rates {
+                info!("  {:>8} KB blocks: {:>8.1} MB/s", rate.block_size / 1024, rate.mb_per_s);
+            }
+            info!("Auto-tune selected {} KB blocks", result.chosen / 1024);
+            self.block_size = result.chosen;
+            Some(result)
+        } else {
+            None
+        };
+
+        let patterns = match resume {
+            Some(ref checkpoint) => checkpoint.passes.clone(),
+            None => self.pass_plan(pattern),
+        };
+        let (start_pass, start_offset) = resume.as_ref().map_or((0, 0), |c| (c.pass, c.offset));
+        let total_blocks = range.length.div_ceil(self.block_size as u64);
+        let mut verification_status = "not-run";
+        if writer.backend() == IoBackend::Uring {
+            debug!("Using io_uring with {} writes in flight", self.queue_depth);
+        }
+        debug!("Sync strategy: {}", describe_sync_strategy(sync_mode, writer.writeback.as_ref()));
+        debug!("RNG seed: {}", self.seed);
+        // One limiter per device, so each drive gets the full allowance
+        let mut limiter = self.max_rate.map(|mb| {
+            info!("Limiting writes to {:.1} MB/s", mb);
+            RateLimiter::new(mb * 1024.0 * 1024.0)
+        });
+        let mut report = EraseReport {
+            device: device_path.to_path_buf(),
+            device_size,
+            method: pattern,
+            sync_mode,
+            direct_io: writer.direct,
+            fua: writer.fua,
+            io_backend: writer.backend(),
+            block_size: self.block_size,
+            write_size: None,
+            gen_threads: self.gen_threads,
+            auto_tune,
+            seed: self.seed,
+            passes: Vec::new(),
+            bytes_written: 0,
+            verification: None,
+            stamped: false,
+            partition_table: None,
+            filesystem: None,
+            pre_wipe,
+            unwritable: Vec::new(),
+            bad_blocks: Vec::new(),
+            write_cache: None,
+            confirmation: None,
+            started_at,
+            finished_at: started_at,
+            paused_secs: 0.0,
+            warnings: Vec::new(),
+            stats: RunStats::default(),
+            scope: if partial { EraseScope::Partial } else { EraseScope::Sanitized },
+            regions: if partial { vec![range] } else { Vec::new() },
+        };
+        if self.pre_hash && resume.is_some() {
+            report.warnings.push("No pre-wipe fingerprint: the resumed erase had already overwritten part of the device".to_string());
+        }
+
+        // Create progress bar
+        let pb = progress_bar(patterns.len() as u64 * total_blocks, "erase");
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&bar_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} blocks ({percent}%) {msg}"))
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        let _slot = label_bar(&pb, device_path);
+
+        if self.skip_matching && patterns.iter().any(|p| p.is_random()) {
+            report.warnings.push("--skip-matching does not apply to random passes, which are always written".to_string());
+        }
+        if resume.is_some() {
+            let message = format!("Resumed from checkpoint at pass {}/{}, offset {}",
+                                  start_pass + 1, patterns.len(), start_offset);
+            info!("{}", message);
+            report.warnings.push(message);
+            pb.set_position(start_pass as u64 * total_blocks + start_offset / self.block_size as u64);
+        }
+
+        // The plan (random keys included) is saved before anything is
+        // written, since without it the first pass couldn't be continued
+        let mut checkpoint = match self.checkpoint.clone() {
+            Some((path, interval)) => {
+                let mut state = resume.clone().unwrap_or_else(|| Checkpoint {
+                    device: device_path.to_path_buf(),
+                    device_size,
+                    serial: device_path.file_name().and_then(|name| device_serial(&name.to_string_lossy())),
+                    method: pattern,
+                    passes: patterns.clone(),
+                    block_size: self.block_size,
+                    pass: 0,
+                    offset: 0,
+                    updated_at: 0,
+                });
+                state.save(&path, start_pass, start_offset)
+                    .map_err(|e| EraseError::Failed { what: format!("Could not write checkpoint {}", path.display()), source: e })?;
+                Some((path, interval, state))
+            }
+            None => None,
+        };
+        let mut last_checkpoint = std::time::Instant::now();
+
+        emit(ProgressEvent::Start {
+            device: device_path,
+            method: pattern,
+            passes: patterns.len(),
+            device_size,
+            total_bytes: range.length * patterns.len() as u64,
+        });
+
+        let mut eta_announced = false;
+        let mut update = ProgressUpdate {
+            phase: ProgressPhase::Writing,
+            pass: start_pass + 1,
+            passes: patterns.len(),
+            pass_bytes: 0,
+            pass_total: range.length,
+            bytes_done: 0,
+            total_bytes: range.length * patterns.len() as u64,
+            device_size,
+            rate: 0.0,
+            average_rate: 0.0,
+            eta_secs: None,
+        };
+        self.unwritable.clear();
+        for (pass_num, &pass) in patterns.iter().enumerate().skip(start_pass) {
+            let _pass_span = tracing::info_span!("pass", n = pass_num + 1).entered();
+            if let Some(ref watchdog) = watchdog {
+                watchdog.watch.set_pass(pass_num + 1, patterns.len());
+            }
+            pb.set_message(format!("Pass {}/{}", pass_num + 1, patterns.len()));
+            debug!(target: "note", "Pass {}/{}: {}{}", pass_num + 1, patterns.len(), pass.describe(),
+                   pass.seed_hex().map(|seed| format!(", key {}", seed)).unwrap_or_default());
+            
+            // Back to the beginning of the device, or to where a resumed pass left off
+            let pass_offset = if pass_num == start_pass { start_offset } else { 0 };
+            // Both ends go first, so a run aborted seconds in leaves no
+            // partition table to rebuild from. The sequential pass then
+            // rewrites them, keeping progress, checkpoints and hashes in
+            // plain device order.
+            if self.tail_first && pass_num == 0 && pass_offset == 0 && !partial && device_size >= 2 * TAIL_FIRST_BYTES
+                && (pass.is_random() || !self.skip_matching) {
+                match self.write_ends_first(&mut writer, pass, device_size) {
+                    // Left to the sequential pass, which finds where a short device really ends
+                    Err(e) if is_out_of_space(&e) => debug!("Could not write the end first: {}", e),
+                    result => result?,
+                }
+            }
+            writer.seek_to(range.offset + pass_offset)?;
+            
+            let mut bytes_written = 0u64;
+            let mut bytes_since_sync = 0u64;
+            let is_final_pass = pass_num == patterns.len() - 1;
+            let pass_started = std::time::Instant::now();
+
+            // Digest what the final pass writes so random data can be re-checked;
+            // a pass resumed midway can only be read back in full
+            let mut hasher = if verify == Some(VerifyMode::Hash) && is_final_pass && pass_offset == 0 {
+                Some(SegmentHasher::new(HASH_SEGMENT_SIZE))
+            } else {
+                None
+            };
+
+            // Generation of the next blocks overlaps with writing the current one;
+            // random data fans out over several generator threads
+            let generators = if pass.is_random() { self.gen_threads } else { 1 };
+            let fill = |offset: u64, buffer: &mut [u8]| pass.fill(range.offset + pass_offset + offset, buffer);
+            // Progress follows completed bytes, which lag submission with io_uring
+            let block_size = self.block_size as u64;
+            let max_rate = self.max_rate;
+            let pass_count = patterns.len();
+            let mut meter = ThroughputMeter::new();
+            let mut last_refresh = std::time::Instant::now();
+            let written_before = report.bytes_written;
+            let mut paused = std::time::Duration::ZERO;
+            let mut last_callback = std::time::Instant::now();
+            let mut advance = |completed: u64| {
+                bytes_written += completed;
+                let pass_blocks = (pass_offset + bytes_written).div_ceil(block_size);
+                pb.set_position(pass_num as u64 * total_blocks + pass_blocks);
+
+                // Remaining work covers every pass still to come, not just this one
+                meter.record(bytes_written);
+                let remaining = (pass_count - pass_num - 1) as u64 * range.length + (range.length - pass_offset - bytes_written);
+                let rate = meter.rate();
+                let eta = if rate > 0.0 { Some(remaining as f64 / rate) } else { None };
+
+                let pass_bytes = pass_offset + bytes_written;
+                if let Some(ref mut callback) = progress_callback {
+                    if last_callback.elapsed() >= PROGRESS_CALLBACK_INTERVAL || pass_bytes == range.length {
+                        last_callback = std::time::Instant::now();
+                        let writing = pass_started.elapsed().saturating_sub(paused).as_secs_f64();
+                        update = ProgressUpdate {
+                            phase: ProgressPhase::Writing,
+                            pass: pass_num + 1,
+                            pass_bytes,
+                            bytes_done: pass_num as u64 * range.length + pass_bytes,
+                            rate,
+                            average_rate: if writing > 0.0 { bytes_written as f64 / writing } else { 0.0 },
+                            eta_secs: eta,
+                            ..update
+                        };
+                        callback(&update);
+                    }
+                }
+
+                if !eta_announced && written_before + bytes_written >= ETA_ANNOUNCE_AFTER {
+                    if let Some(secs) = eta {
+                        progress_note(format!("Estimated time remaining: {} for {} pass(es)",
+                                                   format_duration(secs), pass_count - pass_num));
+                        eta_announced = true;
+                    }
+                }
+
+                if last_refresh.elapsed() >= PROGRESS_REFRESH {
+                    last_refresh = std::time::Instant::now();
+                    let mb = rate / (1024.0 * 1024.0);
+                    emit(ProgressEvent::Progress {
+                        pass: pass_num + 1,
+                        passes: pass_count,
+                        bytes_done: pass_offset + bytes_written,
+                        total: range.length,
+                        rate_mb_s: mb,
+                        eta_secs: eta,
+                    });
+                    let eta = eta.map(|secs| format!(" ETA {}", format_duration(secs))).unwrap_or_default();
+                    // Show the cap alongside so an active limiter is obvious
+                    pb.set_message(match max_rate {
+                        Some(limit) => format!("Pass {}/{} {:.1} MB/s (limit {:.1}){}", pass_num + 1, pass_count, mb, limit, eta),
+                        None => format!("Pass {}/{} {:.1} MB/s{}", pass_num + 1, pass_count, mb, eta),
+                    });
+                }
+
+                // Holding here keeps the next write from being issued
+                let gap = wait_while_paused(&pb);
+                if !gap.is_zero() {
+                    meter.skip(gap);
+                    paused += gap;
+                }
+            };
+            // Writes `block` `repeat` times in a row, or with `skip_matching`
+            // leaves a single block alone if the device already holds it
+            let skip_matching = self.skip_matching && !pass.is_random();
+            let mut scratch = if skip_matching { Some(AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN)) } else { None };
+            let (mut blocks_written, mut blocks_skipped, mut bytes_skipped) = (0u64, 0u64, 0u64);
+            let write_retries = if writer.backend() == IoBackend::Sync { self.write_retries } else { 0 };
+            // With io_uring a failure surfaces after later writes went out, too late to retry smaller
+            let shrink = writer.backend() == IoBackend::Sync;
+            let mut unwritable = Vec::new();
+            let mut write_block = |block: &[u8], repeat: usize| -> io::Result<()> {
+                // Stop between blocks, never inside one
+                check_interrupted()?;
+                if let Some(ref mut hasher) = hasher {
+                    for _ in 0..repeat {
+                        hasher.update(block);
+                    }
+                }
+
+                let at = writer.position;
+                if let Some(ref mut scratch) = scratch {
+                    match writer.already_holds(block, scratch) {
+                        Ok(true) => {
+                            blocks_skipped += 1;
+                            bytes_skipped += block.len() as u64;
+                            advance(block.len() as u64);
+                            return Ok(());
+                        }
+                        Ok(false) => {}
+                        // Unreadable is no reason not to try writing it
+                        Err(e) if write_retries > 0 && is_media_error(&e) && !device_removed(&e, device_path) => writer.seek_to(at)?,
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                if let Some(ref mut limiter) = limiter {
+                    limiter.take(block.len() * repeat);
+                }
+                let len = (block.len() * repeat) as u64;
+                let written = match writer.write_repeated(block, repeat) {
+                    Err(e) if shrink && is_size_error(&e) && !device_removed(&e, device_path) => {
+                        shrink_writes(&mut writer, block, repeat, at, e).map(|_| len)
+                    }
+                    written => written,
+                };
+                let mut completed = match written {
+                    Ok(completed) => completed,
+                    Err(e) if write_retries > 0 && is_media_error(&e) && !device_removed(&e, device_path) => {
+                        warn!("{}; retrying around the failed sectors", e);
+                        let skipped = write_around_bad_sectors(&mut writer, pass, at, at + len, write_retries)?;
+                        for bad in &skipped {
+                            warn!("Skipping {} unwritable bytes at offset {}", bad.length, bad.offset);
+                        }
+                        unwritable.extend(skipped);
+                        len
+                    }
+                    Err(e) => return Err(e),
+                };
+                blocks_written += repeat as u64;
+
+                bytes_since_sync += (block.len() * repeat) as u64;
+                if let SyncMode::Periodic(mb) = sync_mode {
+                    if bytes_since_sync >= mb * 1024 * 1024 {
+                        completed += writer.flush()?;
+                        writer.sync_data()?;
+                        bytes_since_sync = 0;
+                    }
+                }
+
+                // Only offsets already synced go into the checkpoint
+                if let Some((path, interval, state)) = checkpoint.as_mut() {
+                    if last_checkpoint.elapsed() >= *interval {
+                        completed += writer.flush()?;
+                        writer.sync_data()?;
+                        state.save(path, pass_num, writer.position)?;
+                        last_checkpoint = std::time::Instant::now();
+                    }
+                }
+
+                advance(completed);
+                Ok(())
+            };
+
+            // A constant fill needs no generator: one buffer, written in batches
+            // (one block at a time when each is checked first)
+            let outcome = if !pass.is_random() && (self.write_batch > 1 || skip_matching) {
+                let write_batch = if skip_matching { 1 } else { self.write_batch as u64 };
+                let mut buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+                pass.fill(0, &mut buffer);
+                let full_blocks = range.length / block_size;
+                let mut fill_pass = || -> io::Result<()> {
+                    let mut done = pass_offset / block_size;
+                    while done < full_blocks {
+                        let batch = std::cmp::min(write_batch, full_blocks - done);
+                        write_block(&buffer, batch as usize)?;
+                        done += batch;
+                    }
+                    let tail = (range.length % block_size) as usize;
+                    if tail > 0 && pass_offset < range.length {
+                        write_block(&buffer[..tail], 1)?;
+                    }
+                    Ok(())
+                };
+                fill_pass()
+            } else {
+                pipelined_pass(range.length - pass_offset, self.block_size, generators, fill, |block| write_block(block, 1))
+            };
+            if let Err(e) = outcome {
+                // Nothing more can be written or synced; report how far it got
+                if device_removed(&e, device_path) {
+                    pb.abandon_with_message("Device removed");
+                    let (errno, offset) = failure_details(&e);
+                    let done = pass_num as u64 * range.length + pass_offset + bytes_written;
+                    return Err(EraseError::DeviceRemoved(DeviceRemovedError {
+                        pass: pass_num + 1,
+                        passes: patterns.len(),
+                        offset: Some(offset.unwrap_or(writer.position)),
+                        percent: done as f64 / (patterns.len() as u64 * range.length).max(1) as f64 * 100.0,
+                        errno,
+                    }));
+                }
+                // Writing past the real end of the device
+                if is_out_of_space(&e) {
+                    pb.abandon_with_message("Device smaller than reported");
+                    let (errno, offset) = failure_details(&e);
+                    return Err(EraseError::ShortDevice(ShortDeviceError {
+                        pass: pass_num + 1,
+                        passes: patterns.len(),
+                        expected: range.end(),
+                        reached: offset.unwrap_or(writer.position),
+                        size_now: self.get_device_size(&writer.file, device_path).ok(),
+                        errno,
+                    }));
+                }
+                if !is_interrupted() {
+                    return Err(EraseError::from(e).in_pass(pass_num + 1));
+                }
+                // Let what was issued land and make it durable, so the
+                // reported progress is what the device really holds
+                let completed = writer.flush()?;
+                advance(completed);
+                writer.sync_data()?;
+                pb.abandon_with_message("Interrupted");
+                if let Some((path, _, state)) = checkpoint.as_mut() {
+                    match state.save(path, pass_num, writer.position) {
+                        Ok(()) => info!("Checkpoint saved; continue with --resume {}", path.display()),
+                        Err(e) => warn!("Warning: could not save checkpoint {}: {}", path.display(), e),
+                    }
+                }
+                report.bytes_written += bytes_written - bytes_skipped;
+                let done = pass_num as u64 * range.length + pass_offset + bytes_written;
+                return Err(EraseError::Interrupted(InterruptedError {
+                    pass: pass_num + 1,
+                    passes: patterns.len(),
+                    percent: done as f64 / (patterns.len() as u64 * range.length).max(1) as f64 * 100.0,
+                    bytes_written: report.bytes_written,
+                    verifying: false,
+                }));
+            }
+            let completed = writer.flush()?;
+            advance(completed);
+            // Every write may have gone through and still not reached the
+            // end, so the pass must be seen to cover the whole range
+            let reached = writer.position.min(range.offset + pass_offset + bytes_written);
+            if reached != range.end() {
+                pb.abandon_with_message("Device smaller than reported");
+                return Err(EraseError::ShortDevice(ShortDeviceError {
+                    pass: pass_num + 1,
+                    passes: patterns.len(),
+                    expected: range.end(),
+                    reached,
+                    size_now: self.get_device_size(&writer.file, device_path).ok(),
+                    errno: None,
+                }));
+            }
+            merge_ranges(&mut self.unwritable, unwritable);
+            let (min_mb_s, max_mb_s) = (meter.min, meter.max);
+            report.paused_secs += paused.as_secs_f64();
+
+            // The final pass is always made durable before verification reads
+            // it, and every pass before its completion is checkpointed
+            if is_final_pass || checkpoint.is_some() || matches!(sync_mode, SyncMode::PerPass | SyncMode::Periodic(_)) {
+                pb.set_message(format!("Pass {}/{}: syncing", pass_num + 1, patterns.len()));
+                if let Some(ref mut callback) = progress_callback {
+                    update.phase = ProgressPhase::Syncing;
+                    callback(&update);
+                }
+                writer.sync_data()?;
+            }
+            // After the final pass only verification is left, which a resume
+            // repeats from the end of that pass
+            if let Some((path, _, state)) = checkpoint.as_mut() {
+                let (next_pass, next_offset) = if is_final_pass { (pass_num, device_size) } else { (pass_num + 1, 0) };
+                state.save(path, next_pass, next_offset)?;
+            }
+
+            let duration_secs = pass_started.elapsed().saturating_sub(paused).as_secs_f64();
+            report.bytes_written += bytes_written - bytes_skipped;
+            report.passes.push(PassReport {
+                pass: pass_num + 1,
+                pattern: pass.describe(),
+                seed: pass.seed_hex(),
+                bytes_written: bytes_written - bytes_skipped,
+                blocks_written,
+                blocks_skipped,
+                duration_secs,
+                throughput_mb_s: if duration_secs > 0.0 {
+                    bytes_written as f64 / (1024.0 * 1024.0) / duration_secs
+                } else {
+                    0.0
+                },
+                min_mb_s: min_mb_s.map(|r| r / (1024.0 * 1024.0)),
+                max_mb_s: max_mb_s.map(|r| r / (1024.0 * 1024.0)),
+            });
+            emit(ProgressEvent::PassComplete(report.passes.last().unwrap()));
+            progress_note(format!("Pass {} completed", pass_num + 1));
+
+            // Verify final pass if requested
+            if let Some(mode) = verify {
+                if is_final_pass {
+                    pb.set_message("Verifying final pass...");
+                    if let Some(ref mut callback) = progress_callback {
+                        update.phase = ProgressPhase::Verifying;
+                        callback(&update);
+                    }
+                    let verified = match hasher.take() {
+                        Some(hasher) => self.verify_hashes(device_path, &hasher.finish(), range),
+                        None if mode == VerifyMode::Hash => {
+                            report.warnings.push("Hash verification needs the whole final pass; read it back in full instead".to_string());
+                            self.run_verification(device_path, pass, range, VerifyMode::Full)
+                        }
+                        None => self.run_verification(device_path, pass, range, mode),
+                    };
+                    // Unreadable blocks on a device whose node is gone were
+                    // not a failed erase either
+                    let removed = match verified {
+                        Err(EraseError::Io { ref source, .. }) if device_removed(source, device_path) => Some(os_error_code(source)),
+                        Ok(ref result) if result.first_mismatch.is_some() && !device_path.exists() => Some(None),
+                        _ => None,
+                    };
+                    if let Some(errno) = removed {
+                        pb.abandon_with_message("Device removed");
+                        return Err(EraseError::DeviceRemoved(DeviceRemovedError {
+                            pass: pass_num + 1,
+                            passes: patterns.len(),
+                            offset: None,
+                            percent: 100.0,
+                            errno,
+                        }));
+                    }
+                    if verified.is_err() && is_interrupted() {
+                        pb.abandon_with_message("Interrupted");
+                        return Err(EraseError::Interrupted(InterruptedError {
+                            pass: pass_num + 1,
+                            passes: patterns.len(),
+                            percent: 100.0,
+                            bytes_written: report.bytes_written,
+                            verifying: true,
+                        }));
+                    }
+                    let mut result = verified?;
+                    let span = if result.mode == VerifyMode::Hash { HASH_SEGMENT_SIZE } else { self.block_size as u64 };
+                    result.excuse(&self.unwritable, span);
+                    emit(ProgressEvent::Verify(&result));
+                    for line in describe_verification(&result) {
+                        progress_note(line);
+                    }
+
+                    if let Some(first_mismatch) = result.first_mismatch {
+                        if !keep_going {
+                            pb.abandon_with_message("Verification failed");
+                            return Err(EraseError::VerificationFailed(VerificationError {
+                                pass: pass_num + 1,
+                                first_mismatch,
+                                bytes_verified: result.bytes_verified,
+                                bad_blocks: bad_block_map(&self.unwritable, Some(&result)),
+                            }));
+                        }
+                        verification_status = "failed";
+                        report.warnings.push(format!(
+                            "Verification failed at offset {} (continued due to --keep-going)",
+                            first_mismatch
+                        ));
+                    } else {
+                        verification_status = "passed";
+                    }
+                    report.verification = Some(result);
+                }
+            }
+        }
+
+        // The stamp goes in after verification so it never counts as a mismatch
+        if let Some(key) = stamp_key {
+            let stamp = EraseStamp::new(pattern, patterns.len(), verification_status, key);
+            self.write_stamp(writer.buffered_handle()?, device_size, &stamp)?;
+            progress_note("Erase stamp written to final sector");
+            report.stamped = true;
+        }
+
+        if verification_status == "failed" {
+            pb.abandon_with_message("Erase finished, but verification FAILED");
+        } else {
+            pb.finish_with_message("Secure erase completed successfully!");
+        }
+
+        if let Some(ref mut guard) = cache_guard {
+            if let Err(e) = guard.restore() {
+                report.warnings.push(format!("Could not restore the drive write cache: {}", e));
+            }
+            report.write_cache = Some(guard.state());
+        }
+
+        if let Some((path, _, _)) = checkpoint {
+            if let Err(e) = std::fs::remove_file(&path) {
+                report.warnings.push(format!("Could not remove checkpoint {}: {}", path.display(), e));
+            }
+        }
+
+        if !self.unwritable.is_empty() {
+            let bytes: u64 = self.unwritable.iter().map(|bad| bad.length).sum();
+            report.warnings.push(format!("{} in {} range(s) could not be written and still hold their old contents",
+                                         format_size(bytes), self.unwritable.len()));
+        }
+        report.unwritable = std::mem::take(&mut self.unwritable);
+        report.bad_blocks = bad_block_map(&report.unwritable, report.verification.as_ref());
+        report.write_size = writer.max_write;
+        report.warnings.append(&mut writer.warnings);
+        if let Some(ref watchdog) = watchdog {
+            report.warnings.extend(watchdog.stalls());
+        }
+        report.finished_at = unix_time();
+        report.stats = RunStats::from_report(&report, clock.elapsed().as_secs_f64(), writer.retries);
+        Ok(report)
+    }
+
+    /// Refuse to write if the open device isn't the one that was confirmed,
+    /// or was mounted in the meantime
+    fn check_unchanged(&self, file: &File, device_path: &Path, device_size: u64) -> Result<(), EraseError> {
+        if let Some(expected) = &self.expected {
+            let serial = open_device_name(file).and_then(|name| device_serial(&name));
+            let serial_changed = expected.serial.is_some() && serial != expected.serial;
+            if device_size != expected.size || serial_changed {
+                let describe = |size: u64, serial: &Option<String>| format!("{} bytes, serial {}", size,
+                                                                           serial.as_deref().unwrap_or("none"));
+                return Err(EraseError::DeviceChanged {
+                    device: device_path.to_path_buf(),
+                    detail: format!("{} was {}, the open device is {}", device_path.display(),
+                                    describe(expected.size, &expected.serial), describe(device_size, &serial)),
+                });
+            }
+        }
+        // Mounted in the meantime? Checked again right before the first write
+        if let Some(mountpoint) = self.devices.mount_point(device_path)? {
+            return Err(EraseError::Mounted { device: device_path.to_path_buf(), mountpoint });
+        }
+        Ok(())
+    }
+
+    /// Zero only what makes a disk look used: both ends (MBR, primary and
+    /// backup GPT, most superblocks) and the head of every partition, then
+    /// have the kernel re-read the now empty partition table. Takes seconds,
+    /// but everything in between stays recoverable.
+    pub fn quick_erase(
+        &mut self,
+        device_path: &Path,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<EraseReport, EraseError> {
+        let result = self.quick_erase_regions(device_path, sync_mode, direct_io, progress_callback);
+        self.unless_given_up(result)
+    }
+
+    fn quick_erase_regions(
+        &mut self,
+        device_path: &Path,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+        mut progress_callback: Option<ProgressCallback>,
+    ) -> Result<EraseReport, EraseError> {
+        info!("Starting quick wipe of: {}", device_path.display());
+        let cancel = self.cancel.clone().unwrap_or_default();
+        let _cancel_scope = CancelScope::enter(Some(cancel.clone()));
+        let started_at = unix_time();
+        let clock = std::time::Instant::now();
+
+        let mut writer = self.open_device_for_writing(device_path, sync_mode, direct_io)?;
+        let watchdog = self.watch_writes(&mut writer, device_path, &cancel);
+        let device_size = self.get_device_size(&writer.file, device_path)?;
+        self.check_unchanged(&writer.file, device_path, device_size)?;
+        info!("Device size: {}", size_label(device_size));
+
+        let name = open_device_name(&writer.file)
+            .or_else(|| device_path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        let regions = quick_regions(&name, device_size);
+        let total: u64 = regions.iter().map(|region| region.length).sum();
+        let pre_wipe = if self.pre_hash {
+            Some(self.pre_wipe_fingerprint(device_path, ByteRange::whole(device_size))?)
+        } else {
+            None
+        };
+        for region in &regions {
+            debug!("Quick wipe region: {} bytes at offset {}", region.length, region.offset);
+        }
+
+        let pb = progress_bar(total, "erase");
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&bar_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {msg}"))
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        let _slot = label_bar(&pb, device_path);
+        pb.set_message("Quick wipe");
+        emit(ProgressEvent::Start {
+            device: device_path,
+            method: WipePattern::Zeros,
+            passes: 1,
+            device_size,
+            total_bytes: total,
+        });
+
+        let buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+        let pass_started = std::time::Instant::now();
+        let mut bytes_written = 0u64;
+        let mut blocks_written = 0u64;
+        let mut last_callback = std::time::Instant::now();
+        let mut update = ProgressUpdate {
+            phase: ProgressPhase::Writing,
+            pass: 1,
+            passes: 1,
+            pass_bytes: 0,
+            pass_total: total,
+            bytes_done: 0,
+            total_bytes: total,
+            device_size,
+            rate: 0.0,
+            average_rate: 0.0,
+            eta_secs: None,
+        };
+        let mut outcome: io::Result<()> = Ok(());
+        'regions: for region in &regions {
+            if let Err(e) = writer.seek_to(region.offset) {
+                outcome = Err(e);
+                break;
+            }
+            let mut done = 0u64;
+            while done < region.length {
+                let len = std::cmp::min(self.block_size as u64, region.length - done) as usize;
+                if let Err(e) = check_interrupted().and_then(|_| writer.write(&buffer[..len])) {
+                    outcome = Err(e);
+                    break 'regions;
+                }
+                done += len as u64;
+                bytes_written += len as u64;
+                blocks_written += 1;
+                pb.set_position(bytes_written);
+                if let Some(ref mut callback) = progress_callback {
+                    if last_callback.elapsed() >= PROGRESS_CALLBACK_INTERVAL || bytes_written == total {
+                        last_callback = std::time::Instant::now();
+                        // Seeks between regions make a windowed rate meaningless
+                        let secs = pass_started.elapsed().as_secs_f64();
+                        let rate = if secs > 0.0 { bytes_written as f64 / secs } else { 0.0 };
+                        update.pass_bytes = bytes_written;
+                        update.bytes_done = bytes_written;
+                        update.rate = rate;
+                        update.average_rate = rate;
+                        update.eta_secs = if rate > 0.0 { Some((total - bytes_written) as f64 / rate) } else { None };
+                        callback(&update);
+                    }
+                }
+            }
+        }
+        if let Err(e) = outcome {
+            if device_removed(&e, device_path) {
+                pb.abandon_with_message("Device removed");
+                let (errno, offset) = failure_details(&e);
+                return Err(EraseError::DeviceRemoved(DeviceRemovedError {
+                    pass: 1,
+                    passes: 1,
+                    offset: Some(offset.unwrap_or(writer.position)),
+                    percent: bytes_written as f64 / total.max(1) as f64 * 100.0,
+                    errno,
+                }));
+            }
+            if !is_interrupted() {
+                return Err(e.into());
+            }
+            writer.sync_data()?;
+            pb.abandon_with_message("Interrupted");
+            return Err(EraseError::Interrupted(InterruptedError {
+                pass: 1,
+                passes: 1,
+                percent: bytes_written as f64 / total.max(1) as f64 * 100.0,
+                bytes_written,
+                verifying: false,
+            }));
+        }
+        if let Some(ref mut callback) = progress_callback {
+            update.phase = ProgressPhase::Syncing;
+            callback(&update);
+        }
+        writer.sync_data()?;
+        pb.finish_with_message("Quick wipe completed");
+
+        let mut warnings = std::mem::take(&mut writer.warnings);
+        if let Some(ref watchdog) = watchdog {
+            warnings.extend(watchdog.stalls());
+        }
+        if let Err(e) = reread_partition_table(&writer.file) {
+            warnings.push(format!("The kernel did not re-read the partition table ({}); replug the device or reboot", e));
+        }
+
+        let duration_secs = pass_started.elapsed().as_secs_f64();
+        let mut report = EraseReport {
+            device: device_path.to_path_buf(),
+            device_size,
+            method: WipePattern::Zeros,
+            sync_mode,
+            direct_io: writer.direct,
+            fua: writer.fua,
+            io_backend: writer.backend(),
+            block_size: self.block_size,
+            write_size: None,
+            gen_threads: 1,
+            auto_tune: None,
+            seed: self.seed,
+            passes: vec![PassReport {
+                pass: 1,
+                pattern: "zeros (quick)".to_string(),
+                seed: None,
+                bytes_written,
+                blocks_written,
+                blocks_skipped: 0,
+                duration_secs,
+                throughput_mb_s: if duration_secs > 0.0 { bytes_written as f64 / (1024.0 * 1024.0) / duration_secs } else { 0.0 },
+                min_mb_s: None,
+                max_mb_s: None,
+            }],
+            bytes_written,
+            verification: None,
+            stamped: false,
+            partition_table: None,
+            filesystem: None,
+            pre_wipe,
+            unwritable: Vec::new(),
+            bad_blocks: Vec::new(),
+            write_cache: None,
+            confirmation: None,
+            started_at,
+            finished_at: unix_time(),
+            paused_secs: 0.0,
+            warnings,
+            stats: RunStats::default(),
+            scope: EraseScope::Quick,
+            regions,
+        };
+        emit(ProgressEvent::PassComplete(&report.passes[0]));
+        report.stats = RunStats::from_report(&report, clock.elapsed().as_secs_f64(), writer.retries);
+        Ok(report)
+    }
+
+    /// Write `pass` over the first and then the last TAIL_FIRST_BYTES and make
+    /// it durable. Overhead outside the pass's own accounting, like the
+    /// auto-tune bursts.
+    fn write_ends_first(&self, writer: &mut DeviceWriter, pass: PassFill, device_size: u64) -> io::Result<()> {
+        let mut buffer = AlignedBuffer::new(TAIL_FIRST_BYTES as usize, DIRECT_IO_ALIGN);
+        for offset in [0, device_size - TAIL_FIRST_BYTES] {
+            pass.fill(offset, &mut buffer);
+            writer.seek_to(offset)?;
+            match writer.write(&buffer) {
+                Err(e) if writer.backend() == IoBackend::Sync && is_size_error(&e) && !device_removed(&e, &writer.path) => {
+                    shrink_writes(writer, &buffer, 1, offset, e)?;
+                }
+                result => {
+                    result?;
+                }
+            }
+        }
+        writer.sync_data()?;
+        debug!("Wrote the first and last {} of pass 1 ahead of the rest", format_size(TAIL_FIRST_BYTES));
+        Ok(())
+    }
+
+    /// Time a short burst of writes at each candidate block size and return
+    /// the fastest one
+    fn auto_tune_block_size(
+        &self,
+        writer: &mut DeviceWriter,
+        device_size: u64,
+        sector_size: usize,
+    ) -> Result<AutoTuneResult, EraseError> {
+        let burst_bytes = std::cmp::min(AUTO_TUNE_BURST, device_size);
+        let mut rates = Vec::new();
+
+        info!("Auto-tuning block size with {} MB bursts...", burst_bytes / (1024 * 1024));
+
+        for &block_size in AUTO_TUNE_CANDIDATES.iter().filter(|&&b| b % sector_size == 0) {
+            let buffer = AlignedBuffer::new(block_size, DIRECT_IO_ALIGN);
+            let started = std::time::Instant::now();
+
+            writer.rewind()?;
+            let mut written = 0u64;
+            while written < burst_bytes {
+                let len = std::cmp::min(block_size as u64, burst_bytes - written) as usize;
+                writer.write(&buffer[..len])?;
+                written += len as u64;
+            }
+            writer.sync_data()?;
+
+            let secs = started.elapsed().as_secs_f64();
+            rates.push(BlockSizeRate {
+                block_size,
+                mb_per_s: if secs > 0.0 { written as f64 / (1024.0 * 1024.0) / secs } else { 0.0 },
+            });
+        }
+
+        let chosen = rates.iter()
+            .max_by(|a, b| a.mb_per_s.total_cmp(&b.mb_per_s))
+            .map(|r| r.block_size)
+            .unwrap_or(self.block_size);
+
+        Ok(AutoTuneResult { burst_bytes, rates, chosen })
+    }
+
+    /// Estimate wall-clock time for erasing a device with `pattern`. Unless
+    /// `measure` is false this times a short burst of writes to the start of
+    /// the device, so only call it once the user has agreed to lose the data;
+    /// otherwise (or if the burst fails) a nominal rate for the device class
+    /// is used.
+    pub fn estimate_duration(
+        &self,
+        device: &DeviceInfo,
+        pattern: WipePattern,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+        measure: bool,
+    ) -> DurationEstimate {
+        let measured = if measure { self.measure_write_rate(&device.path, device.size, sync_mode, direct_io).ok() } else { None };
+        let (mut bytes_per_sec, source) = match measured {
+            Some(rate) => (rate, "measured"),
+            None => (nominal_write_rate(device), "device class"),
+        };
+        if let Some(limit) = self.max_rate {
+            bytes_per_sec = bytes_per_sec.min(limit * 1024.0 * 1024.0);
+        }
+
+        let passes = pattern.pass_count();
+        DurationEstimate {
+            passes,
+            mb_per_s: bytes_per_sec / (1024.0 * 1024.0),
+            total_secs: passes as f64 * self.range.map_or(device.size, |range| range.length) as f64 / bytes_per_sec,
+            source,
+        }
+    }
+
+    fn measure_write_rate(
+        &self,
+        device_path: &Path,
+        device_size: u64,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+    ) -> Result<f64, EraseError> {
+        let mut writer = self.open_device_for_writing(device_path, sync_mode, direct_io)?;
+        let burst_bytes = std::cmp::min(ESTIMATE_BURST, device_size);
+        let buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+        let started = std::time::Instant::now();
+
+        let mut written = 0u64;
+        while written < burst_bytes {
+            let len = std::cmp::min(self.block_size as u64, burst_bytes - written) as usize;
+            writer.write(&buffer[..len])?;
+            written += len as u64;
+        }
+        writer.sync_data()?;
+
+        let secs = started.elapsed().as_secs_f64();
+        if secs <= 0.0 {
+            return Err(EraseError::Other("Write burst finished too quickly to time".to_string()));
+        }
+        Ok(written as f64 / secs)
+    }
+
+    fn write_stamp(&self, file: &mut File, device_size: u64, stamp: &EraseStamp) -> Result<(), EraseError> {
+        if device_size < STAMP_SIZE {
+            return Err(EraseError::Unsupported { what: "Device is too small to hold an erase stamp".to_string() });
+        }
+
+        file.seek(SeekFrom::Start(device_size - STAMP_SIZE))?;
+        file.write_all(&stamp.to_sector()?)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// --pre-hash: digest the sample regions a fresh seed picks within
+    /// `range`, reading only. A region that can't be read is recorded, not
+    /// fatal; the erase goes ahead either way.
+    fn pre_wipe_fingerprint(&mut self, device_path: &Path, range: ByteRange) -> Result<PreWipeFingerprint, EraseError> {
+        let mut file = File::open(device_path).map_err(|e| EraseError::open(device_path, e))?;
+        let seed: u64 = self.rng.gen();
+        let regions = pre_hash_regions(seed, range);
+        info!("Fingerprinting {} regions before the first write (seed {:016x})", regions.len(), seed);
+
+        let started = std::time::Instant::now();
+        let mut buffer = vec![0u8; PRE_HASH_REGION as usize];
+        let mut digests = Vec::with_capacity(regions.len());
+        for region in &regions {
+            if started.elapsed() > PRE_HASH_DEADLINE {
+                warn!("Pre-wipe sampling stopped after {} of {} regions ({} limit)",
+                      digests.len(), regions.len(), format_duration(PRE_HASH_DEADLINE.as_secs_f64()));
+                break;
+            }
+            check_interrupted()?;
+            let data = &mut buffer[..region.length as usize];
+            let read = file.seek(SeekFrom::Start(region.offset)).and_then(|_| file.read_exact(data));
+            let (sha256, error) = match read {
+                Ok(()) => (Some(format!("{:x}", Sha256::digest(&*data))), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            digests.push(SampleDigest { offset: region.offset, length: region.length, sha256, error });
+        }
+        Ok(PreWipeFingerprint {
+            seed: format!("{:016x}", seed),
+            region_size: PRE_HASH_REGION.min(range.length),
+            complete: digests.len() == regions.len(),
+            regions: digests,
+            duration_secs: started.elapsed().as_secs_f64(),
+        })
+    }
+
+    /// Write an empty partition table, with a random disk GUID or signature.
+    /// Only the sectors the table lives in are written.
+    pub fn write_partition_table(&mut self, device_path: &Path, kind: TableKind) -> Result<NewPartitionTable, EraseError> {
+        let mut file = OpenOptions::new().write(true).open(device_path)
+            .map_err(|e| EraseError::open(device_path, e))?;
+        let device_size = self.get_device_size(&file, device_path)?;
+        let sector_size = logical_block_size(&file) as u64;
+
+        let (sectors, table) = match kind {
+            TableKind::Gpt => {
+                let mut guid = [0u8; 16];
+                self.rng.fill(&mut guid);
+                // Random (version 4) GUID; fields 1-3 are stored little-endian
+                guid[7] = (guid[7] & 0x0f) | 0x40;
+                guid[8] = (guid[8] & 0x3f) | 0x80;
+                let sectors = empty_gpt(device_size, sector_size, &guid).map_err(|what| EraseError::Unsupported { what })?;
+                (sectors, NewPartitionTable { kind, disk_guid: Some(format_guid(&guid)), disk_signature: None })
+            }
+            TableKind::Mbr => {
+                let signature: u32 = self.rng.gen_range(1..=u32::MAX);
+                let mut mbr = vec![0u8; sector_size as usize];
+                mbr[440..444].copy_from_slice(&signature.to_le_bytes());
+                mbr[510] = 0x55;
+                mbr[511] = 0xaa;
+                (vec![(0, mbr)], NewPartitionTable { kind, disk_guid: None, disk_signature: Some(format!("{:08x}", signature)) })
+            }
+        };
+        for (lba, data) in sectors {
+            file.seek(SeekFrom::Start(lba * sector_size))?;
+            file.write_all(&data)?;
+        }
+        file.sync_all()?;
+        Ok(table)
+    }
+
+    /// Create an empty FAT32 or exFAT filesystem over the whole device, with
+    /// no partition table, as removable media often ship. A label is checked
+    /// with `filesystem_label` before anything is written.
+    pub fn write_filesystem(&mut self, device_path: &Path, kind: FsKind, label: Option<&str>) -> Result<NewFilesystem, EraseError> {
+        let label = label.map(|label| filesystem_label(kind, label)).transpose().map_err(EraseError::InvalidArgument)?;
+        let label = label.as_deref();
+        let mut file = OpenOptions::new().write(true).open(device_path)
+            .map_err(|e| EraseError::open(device_path, e))?;
+        let device_size = self.get_device_size(&file, device_path)?;
+        let sector_size = logical_block_size(&file);
+        if sector_size != 512 {
+            return Err(EraseError::Unsupported {
+                what: format!("{} has {} byte sectors; --format-after only handles 512", device_path.display(), sector_size),
+            });
+        }
+
+        let volume_id: u32 = self.rng.gen();
+        let image = filesystem_image(kind, device_size, volume_id, label).map_err(EraseError::InvalidArgument)?;
+        let zeros = vec![0u8; 1024 * 1024];
+        for range in &image.zero {
+            file.seek(SeekFrom::Start(range.offset))?;
+            let mut left = range.length;
+            while left > 0 {
+                let chunk = left.min(zeros.len() as u64) as usize;
+                file.write_all(&zeros[..chunk])?;
+                left -= chunk as u64;
+            }
+        }
+        for (offset, data) in &image.sectors {
+            file.seek(SeekFrom::Start(*offset))?;
+            file.write_all(data)?;
+        }
+        file.sync_all()?;
+        Ok(NewFilesystem {
+            kind,
+            label: label.map(str::to_string),
+            volume_id: format!("{:04X}-{:04X}", volume_id >> 16, volume_id & 0xffff),
+            cluster_size: image.cluster_size,
+        })
+    }
+
+    /// Read the erase stamp from the final sector, if one is present
+    pub fn read_stamp(&self, device_path: &Path) -> Result<Option<EraseStamp>, EraseError> {
+        let mut file = File::open(device_path).map_err(|e| EraseError::open(device_path, e))?;
+        let device_size = self.get_device_size(&file, device_path)?;
+        if device_size < STAMP_SIZE {
+            return Ok(None);
+        }
+
+        let mut sector = vec![0u8; STAMP_SIZE as usize];
+        file.seek(SeekFrom::Start(device_size - STAMP_SIZE))?;
+        file.read_exact(&mut sector)?;
+        Ok(EraseStamp::from_sector(&sector))
+    }
+
+    /// Overwrite a regular file in place with every pass of `pattern`, then
+    /// truncate and unlink it. The file is streamed block by block, so its
+    /// size doesn't matter; directories are refused. With `scramble`, the
+    /// name and timestamps are destroyed too before the unlink. In
+    /// allocated-only mode just the data extents are written, and any
+    /// verification reads all of them back in full.
+    pub fn shred_file(
+        &mut self,
+        path: &Path,
+        pattern: WipePattern,
+        verify: Option<VerifyMode>,
+        scramble: bool,
+        sparse_mode: SparseMode,
+    ) -> Result<ShredResult, EraseError> {
+        let metadata = std::fs::symlink_metadata(path)?;
+        if metadata.is_dir() {
+            return Err(EraseError::Unsupported { what: format!("{} is a directory", path.display()) });
+        }
+        if !metadata.is_file() {
+            return Err(EraseError::Unsupported { what: format!("{} is not a regular file", path.display()) });
+        }
+
+        let size = metadata.len();
+        let passes = self.pass_plan(pattern);
+        let mut file = open_for_writing(path, SyncMode::PerPass, false, false)?;
+        let mut verified = None;
+
+        // Extents are mapped once up front: overwriting them in place
+        // doesn't change the file's allocation
+        let extents = match sparse_mode {
+            SparseMode::Full => vec![(0, size)],
+            SparseMode::AllocatedOnly => data_extents(&mut file, size)?,
+        };
+        let bytes_overwritten: u64 = extents.iter().map(|&(_, len)| len).sum();
+        let whole_file = extents == [(0, size)];
+
+        for (pass_num, &pass) in passes.iter().enumerate() {
+            let is_final_pass = pass_num == passes.len() - 1;
+            let mut hasher = if verify == Some(VerifyMode::Hash) && is_final_pass && whole_file {
+                Some(SegmentHasher::new(HASH_SEGMENT_SIZE))
+            } else {
+                None
+            };
+
+            let generators = if pass.is_random() { self.gen_threads } else { 1 };
+            for &(start, len) in &extents {
+                file.seek(SeekFrom::Start(start))?;
+                let fill = |offset: u64, buffer: &mut [u8]| pass.fill(start + offset, buffer);
+                pipelined_pass(len, self.block_size, generators, fill, |block| {
+                    if let Some(ref mut hasher) = hasher {
+                        hasher.update(block);
+                    }
+                    file.write_all(block)
+                })?;
+            }
+            file.sync_data()?;
+
+            if let (Some(mode), true) = (verify, is_final_pass) {
+                let passed = if !whole_file {
+                    self.verify_extents(path, pass, size, &extents)?
+                } else {
+                    let result = match hasher.take() {
+                        Some(hasher) => self.verify_hashes(path, &hasher.finish(), ByteRange::whole(size))?,
+                        None => self.run_verification(path, pass, ByteRange::whole(size), mode)?,
+                    };
+                    result.passed()
+                };
+                verified = Some(passed);
+            }
+        }
+
+        // Leave the overwritten data in place if verification says it isn't
+        if verified == Some(false) {
+            return Ok(ShredResult {
+                path: path.to_path_buf(),
+                kind: "file",
+                size,
+                bytes_overwritten,
+                sparse_mode: Some(sparse_mode),
+                passes: passes.len(),
+                verified,
+                error: Some("verification failed; file left in place".to_string()),
+            });
+        }
+
+        file.set_len(0)?;
+        if scramble {
+            let epoch = std::time::SystemTime::UNIX_EPOCH;
+            file.set_times(std::fs::FileTimes::new().set_accessed(epoch).set_modified(epoch))?;
+        }
+        file.sync_all()?;
+        drop(file);
+
+        let final_path = if scramble { self.scramble_name(path)? } else { path.to_path_buf() };
+        std::fs::remove_file(&final_path)?;
+        sync_parent_dir(&final_path);
+
+        Ok(ShredResult {
+            path: path.to_path_buf(),
+            kind: "file",
+            size,
+            bytes_overwritten,
+            sparse_mode: Some(sparse_mode),
+            passes: passes.len(),
+            verified,
+            error: None,
+        })
+    }
+
+    /// Rename a file through random names of decreasing length in its own
+    /// directory, syncing the directory after each step so the original name
+    /// doesn't linger in directory entries or the journal. Returns the final path.
+    fn scramble_name(&mut self, path: &Path) -> io::Result<PathBuf> {
+        const NAME_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut current = path.to_path_buf();
+        let mut len = path.file_name().map_or(1, |n| n.len()).max(1);
+
+        for _ in 0..SCRAMBLE_RENAMES {
+            // A few tries in case the random name is taken
+            let next = (0..16)
+                .map(|_| {
+                    let name: String = (0..len)
+                        .map(|_| NAME_CHARS[self.rng.gen_range(0..NAME_CHARS.len())] as char)
+                        .collect();
+                    dir.join(name)
+                })
+                .find(|candidate| std::fs::symlink_metadata(candidate).is_err());
+            let Some(next) = next else { break };
+
+            std::fs::rename(&current, &next)?;
+            sync_parent_dir(&next);
+            current = next;
+            if len == 1 {
+                break;
+            }
+            len = (len / 2).max(1);
+        }
+        Ok(current)
+    }
+
+    /// Overwrite the free space of the filesystem holding `dir` by filling it
+    /// with files until it runs out of space, then deleting them. The first
+    /// pass finds the space and immediately gives `headroom` back; later
+    /// passes rewrite the same files.
+    pub fn wipe_free_space(
+        &mut self,
+        dir: &Path,
+        pattern: WipePattern,
+        headroom: u64,
+        file_size: u64,
+    ) -> Result<FreeSpaceReport, EraseError> {
+        if !std::fs::metadata(dir)?.is_dir() {
+            return Err(EraseError::InvalidArgument(format!("{} is not a directory", dir.display())));
+        }
+
+        let free_before = free_space(dir);
+        let passes = self.pass_plan(pattern);
+        let mut fillers = FillerFiles { paths: Vec::new() };
+        let mut sizes: Vec<u64> = Vec::new();
+        let mut buffer = vec![0u8; self.block_size];
+        let (mut bytes_overwritten, mut files_created) = (0u64, 0usize);
+
+        let pb = progress_bar(free_before.unwrap_or(0), "free-space wipe");
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&bar_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {msg}"))
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+
+        for (pass_num, &pass) in passes.iter().enumerate() {
+            pb.set_message(format!("Pass {}/{}", pass_num + 1, passes.len()));
+            pb.set_position(0);
+            let mut written_total = 0u64;
+            let mut index = 0;
+
+            loop {
+                // Pass 1 discovers the files; later passes revisit them
+                let (mut file, limit) = if pass_num == 0 {
+                    let path = dir.join(format!(".memerase-free-{}-{}", std::process::id(), index));
+                    let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+                    fillers.paths.push(path);
+                    (file, file_size)
+                } else if index < sizes.len() {
+                    (OpenOptions::new().write(true).open(&fillers.paths[index])?, sizes[index])
+                } else {
+                    break;
+                };
+
+                let mut written = 0u64;
+                let mut full = false;
+                while written < limit {
+                    check_interrupted()?;
+                    let len = std::cmp::min(self.block_size as u64, limit - written) as usize;
+                    pass.fill(written, &mut buffer[..len]);
+                    match file.write_all(&buffer[..len]) {
+                        Ok(()) => written += len as u64,
+                        Err(e) if is_out_of_space(&e) => {
+                            written = file.stream_position()?;
+                            full = true;
+                            break;
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                    pb.set_position(written_total + written);
+                }
+                file.sync_data()?;
+                written_total += written;
+
+                if pass_num == 0 {
+                    sizes.push(written);
+                    if full {
+                        break;
+                    }
+                }
+                index += 1;
+            }
+
+            // Don't leave the filesystem at 100% while later passes run
+            if pass_num == 0 {
+                bytes_overwritten = written_total;
+                files_created = fillers.paths.len();
+                let mut released = 0;
+                while released < headroom {
+                    let Some(last) = sizes.last_mut() else { break };
+                    let give = std::cmp::min(*last, headroom - released);
+                    *last -= give;
+                    released += give;
+                    OpenOptions::new().write(true).open(fillers.paths.last().unwrap())?.set_len(*last)?;
+                    if *last == 0 {
+                        sizes.pop();
+                        std::fs::remove_file(fillers.paths.pop().unwrap())?;
+                    }
+                }
+            }
+            progress_note(format!("Pass {} completed: {} bytes of free space overwritten", pass_num + 1, written_total));
+        }
+        pb.finish_and_clear();
+
+        let cleanup_errors = fillers.remove_all();
+        Ok(FreeSpaceReport {
+            path: dir.to_path_buf(),
+            method: pattern,
+            passes: passes.len(),
+            free_before,
+            files_created,
+            bytes_overwritten,
+            cleanup_errors,
+        })
+    }
+
+    /// Whether writes to `device_path` should bypass the page cache
+    fn wants_direct_io(&self, device_path: &Path, direct_io: DirectIo) -> bool {
+        match direct_io {
+            DirectIo::On => true,
+            DirectIo::Off => false,
+            // FUA only reaches the device when the page cache is bypassed
+            DirectIo::Auto if self.fua && cfg!(target_os = "linux") => true,
+            DirectIo::Auto => std::fs::metadata(device_path)
+                .map(|m| is_block_device(&m))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Everything an erase would do, worked out without opening the device
+    /// for writing. `blockers` lists what would make the real run refuse.
+    pub fn plan_erase(
+        &mut self,
+        device: &DeviceInfo,
+        pattern: WipePattern,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+        verify: Option<VerifyMode>,
+    ) -> Result<ErasePlan, EraseError> {
+        let file = File::open(&device.path)?;
+        let device_size = self.get_device_size(&file, &device.path)?;
+        let sector_size = logical_block_size(&file);
+        drop(file);
+
+        let mut blockers = Vec::new();
+        if device.is_mounted {
+            blockers.push("device is mounted".to_string());
+        }
+        let holders = self.devices.holders(&device.name);
+        if !holders.is_empty() {
+            blockers.push(format!("device is held by {}", holders.join(", ")));
+        }
+        if !self.block_size.is_multiple_of(sector_size) {
+            blockers.push(format!(
+                "block size {} is not a multiple of the {} byte sector size",
+                self.block_size, sector_size
+            ));
+        }
+
+        let span = self.range.unwrap_or(ByteRange::whole(device_size));
+        if span.end() > device_size {
+            blockers.push(format!("range ends at byte {}, past the end of the device", span.end()));
+        }
+
+        let passes = self.pass_plan(pattern).iter().enumerate()
+            .map(|(i, pass)| PlannedPass { pass: i + 1, pattern: pass.describe(), bytes: span.length })
+            .collect::<Vec<_>>();
+
+        Ok(ErasePlan {
+            device: device.path.clone(),
+            device_size,
+            sector_size,
+            block_size: self.block_size,
+            method: pattern,
+            bytes_total: span.length * passes.len() as u64,
+            range: self.range,
+            passes,
+            sync_mode,
+            direct_io: self.wants_direct_io(&device.path, direct_io) && cfg!(target_os = "linux"),
+            fua: self.fua,
+            verification: verify,
+            estimate: self.estimate_duration(device, pattern, sync_mode, direct_io, false),
+            blockers,
+        })
+    }
+
+    fn open_device_for_writing(
+        &self,
+        device_path: &Path,
+        sync_mode: SyncMode,
+        direct_io: DirectIo,
+    ) -> Result<DeviceWriter, EraseError> {
+        let mut warnings = Vec::new();
+        let want_direct = self.wants_direct_io(device_path, direct_io);
+
+        let (file, direct) = if want_direct && cfg!(target_os = "linux") {
+            match open_for_writing(device_path, sync_mode, true, self.fua) {
+                Ok(file) => (file, true),
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    warnings.push(format!("O_DIRECT not supported here ({}), using buffered I/O", e));
+                    let file = open_for_writing(device_path, sync_mode, false, self.fua)
+                        .map_err(|e| EraseError::open(device_path, e))?;
+                    (file, false)
+                }
+                Err(e) => return Err(EraseError::open(device_path, e)),
+            }
+        } else {
+            if want_direct {
+                warnings.push("O_DIRECT is only available on Linux, using buffered I/O".to_string());
+            }
+            let file = open_for_writing(device_path, sync_mode, false, self.fua)
+                .map_err(|e| EraseError::open(device_path, e))?;
+            (file, false)
+        };
+
+        let sector_size = if direct { logical_block_size(&file) } else { 1 };
+
+        #[cfg(target_os = "linux")]
+        let uring = match self.io_backend {
+            IoBackend::Uring => match UringQueue::new(self.queue_depth) {
+                Ok(queue) => Some(queue),
+                Err(e) => {
+                    warnings.push(format!("io_uring unavailable ({}), using synchronous writes", e));
+                    None
+                }
+            },
+            IoBackend::Sync => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        if self.io_backend == IoBackend::Uring {
+            warnings.push("io_uring is only available on Linux, using synchronous writes".to_string());
+        }
+
+        // Only buffered writes dirty the page cache
+        let writeback = match self.writeback {
+            Some((interval, max_dirty)) if !direct && !self.fua && sync_mode != SyncMode::OSync && cfg!(target_os = "linux") => {
+                Some(Writeback {
+                    interval: interval * 1024 * 1024,
+                    max_dirty: max_dirty * 1024 * 1024,
+                    kicked: 0,
+                    settled: 0,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(DeviceWriter {
+            file,
+            path: device_path.to_path_buf(),
+            sync_mode,
+            direct,
+            sector_size,
+            fua: self.fua,
+            position: 0,
+            tail: None,
+            #[cfg(target_os = "linux")]
+            uring,
+            writeback,
+            warnings,
+            retries: 0,
+            watch: None,
+            max_write: None,
+        })
+    }
+
+    pub(crate) fn get_device_size(&self, file: &File, device_path: &Path) -> Result<u64, EraseError> {
+        #[cfg(unix)]
+        {
+            let _ = file;
+            self.devices.size(device_path)
+        }
+
+        #[cfg(windows)]
+        {
+            // For Windows, we'd need to use GetFileSizeEx or DeviceIoControl
+            // This is a simplified version
+            let metadata = file.metadata()?;
+            Ok(metadata.len())
+        }
+    }
+}
+
+/// Run one pass as a pipeline: `workers` generator threads fill reusable
+/// aligned buffers while the calling thread writes them in device order.
+/// Worker `w` produces blocks `w, w + workers, ...` on its own channel, so the
+/// writer restores ordering simply by reading the channels round-robin. An
+/// error on the writing side drops its channel ends, which stops the
+/// generators, and the scope joins them before returning.
+pub(crate) fn pipelined_pass<F, W>(total_bytes: u64, block_size: usize, workers: usize, fill: F, mut write: W) -> io::Result<()>
+where
+    F: Fn(u64, &mut [u8]) + Sync,
+    W: FnMut(&[u8]) -> io::Result<()>,
+{
+    let workers = workers.max(1);
+    let total_blocks = total_bytes.div_ceil(block_size as u64);
+
+    std::thread::scope(|scope| {
+        let mut filled_rxs = Vec::new();
+        let mut empty_txs = Vec::new();
+
+        for worker in 0..workers as u64 {
+            let (filled_tx, filled_rx) = std::sync::mpsc::sync_channel::<(AlignedBuffer, usize)>(PIPELINE_DEPTH);
+            let (empty_tx, empty_rx) = std::sync::mpsc::sync_channel::<AlignedBuffer>(PIPELINE_DEPTH);
+            for _ in 0..PIPELINE_DEPTH {
+                let _ = empty_tx.send(AlignedBuffer::new(block_size, DIRECT_IO_ALIGN));
+            }
+            filled_rxs.push(filled_rx);
+            empty_txs.push(empty_tx);
+
+            let fill = &fill;
+            scope.spawn(move || {
+                let mut block = worker;
+                while block < total_blocks {
+                    let Ok(mut buffer) = empty_rx.recv() else { return };
+                    let offset = block * block_size as u64;
+                    let len = std::cmp::min(block_size as u64, total_bytes - offset) as usize;
+                    fill(offset, &mut buffer[..len]);
+                    if filled_tx.send((buffer, len)).is_err() {
+                        return;
+                    }
+                    block += workers as u64;
+                }
+            });
+        }
+
+        for block in 0..total_blocks {
+            let worker = (block % workers as u64) as usize;
+            let Ok((buffer, len)) = filled_rxs[worker].recv() else { break };
+            write(&buffer[..len])?;
+            let _ = empty_txs[worker].send(buffer);
+        }
+        Ok(())
+    })
+}
+
+/// min(4, available cores)
+pub(crate) fn default_gen_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| std::cmp::min(4, n.get()))
+        .unwrap_or(1)
+}
+
+/// Stops `secure_erase`, `quick_erase` or `verify_device` once cancelled:
+/// the erase finishes the block in flight, syncs what it wrote and returns
+/// InterruptedError with the progress so far. Clones share one flag, so
+/// one can be handed to the eraser and another kept to cancel with.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<std::sync::atomic::AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+/// Cancelled by Ctrl-C and SIGTERM; set before the handlers are installed
+pub(crate) static INTERRUPT: std::sync::OnceLock<CancellationToken> = std::sync::OnceLock::new();
+
+thread_local! {
+    /// Token of the erase running on this thread, so the daemon can cancel
+    /// one job through the same unwinding as Ctrl-C without touching the others
+    static CANCELLED: std::cell::RefCell<Option<CancellationToken>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Makes an eraser's token this thread's for as long as an erase runs
+pub(crate) struct CancelScope {
+    previous: Option<Option<CancellationToken>>,  // None when no token was entered
+}
+
+impl CancelScope {
+    pub(crate) fn enter(token: Option<CancellationToken>) -> Self {
+        CancelScope { previous: token.map(|token| CANCELLED.with(|flag| flag.borrow_mut().replace(token))) }
+    }
+}
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CANCELLED.with(|flag| *flag.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Cancel the process-wide token, returning whether it already was. Only
+/// an atomic swap, so signal handlers can call it.
+pub fn interrupt() -> bool {
+    INTERRUPT.get().is_some_and(|token| token.0.swap(true, std::sync::atomic::Ordering::SeqCst))
+}
+
+/// Create the process-wide token that `interrupt` cancels, so long loops
+/// poll it and unwind through their guards (filler files, write cache)
+/// instead of dying mid-write. Call it before installing the handlers.
+pub fn enable_interrupt() {
+    INTERRUPT.get_or_init(CancellationToken::new);
+}
+
+/// "4.0 MiB at offset 1.0 GiB (bytes 1073741824..1077936128)"
+pub fn describe_range(range: ByteRange) -> String {
+    format!("{} at offset {} (bytes {}..{})", format_size(range.length), format_size(range.offset), range.offset, range.end())
+}
+
+/// What `--method quick` overwrites: the first and last QUICK_EDGE bytes
+/// and the first QUICK_PARTITION_HEAD of each partition, sorted and merged
+pub fn quick_regions(name: &str, device_size: u64) -> Vec<ByteRange> {
+    let edge = |start: u64| (start, (start + QUICK_EDGE).min(device_size));
+    let mut ranges = vec![edge(0), edge(device_size.saturating_sub(QUICK_EDGE))];
+    for start in partition_starts(name) {
+        ranges.push((start, (start + QUICK_PARTITION_HEAD).min(device_size)));
+    }
+    ranges.sort();
+
+    let mut regions: Vec<ByteRange> = Vec::new();
+    for (start, end) in ranges.into_iter().filter(|(start, end)| start < end) {
+        match regions.last_mut() {
+            Some(last) if start <= last.offset + last.length => {
+                last.length = last.length.max(end - last.offset);
+            }
+            _ => regions.push(ByteRange { offset: start, length: end - start }),
+        }
+    }
+    regions
+}
+
+/// Byte offsets of the partitions the kernel found on a disk
+pub(crate) fn partition_starts(name: &str) -> Vec<u64> {
+    let Ok(entries) = std::fs::read_dir(format!("/sys/block/{}", name)) else {
+        return Vec::new();
+    };
+    entries.flatten()
+        .filter(|entry| entry.path().join("partition").exists())
+        // sysfs counts in 512 byte sectors whatever the device's sector size
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("start")).ok()?.trim().parse::<u64>().ok())
+        .map(|sectors| sectors * 512)
+        .collect()
+}
+
+/// Sectors of an empty GPT as (LBA, data): protective MBR, primary header
+/// and entry array at the start, backup entries and header at the end
+pub(crate) fn empty_gpt(device_size: u64, sector_size: u64, disk_guid: &[u8; 16]) -> Result<Vec<(u64, Vec<u8>)>, String> {
+    const ENTRIES: u32 = 128;
+    const ENTRY_SIZE: u32 = 128;
+
+    let entries_len = (ENTRIES * ENTRY_SIZE) as u64;
+    let entry_lbas = entries_len.div_ceil(sector_size);
+    let total_lbas = device_size / sector_size;
+    if total_lbas < 2 * (entry_lbas + 2) + 1 {
+        return Err(format!("{} is too small for a GPT", format_size(device_size)));
+    }
+    let last_lba = total_lbas - 1;
+    let entries = vec![0u8; (entry_lbas * sector_size) as usize];
+    let entries_crc = crc32(&entries[..entries_len as usize]);
+
+    let header = |my_lba: u64, alternate_lba: u64, entries_lba: u64| {
+        let mut header = vec![0u8; sector_size as usize];
+        header[0..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());  // Revision 1.0
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());          // Header size
+        header[24..32].copy_from_slice(&my_lba.to_le_bytes());
+        header[32..40].copy_from_slice(&alternate_lba.to_le_bytes());
+        header[40..48].copy_from_slice(&(2 + entry_lbas).to_le_bytes());            // First usable LBA
+        header[48..56].copy_from_slice(&(last_lba - 1 - entry_lbas).to_le_bytes()); // Last usable LBA
+        header[56..72].copy_from_slice(disk_guid);
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&ENTRIES.to_le_bytes());
+        header[84..88].copy_from_slice(&ENTRY_SIZE.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let crc = crc32(&header[..92]);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        header
+    };
+
+    // One 0xEE partition over the whole disk, as far as 32 bits reach
+    let mut mbr = vec![0u8; sector_size as usize];
+    mbr[446..454].copy_from_slice(&[0x00, 0x00, 0x02, 0x00, 0xee, 0xff, 0xff, 0xff]);
+    mbr[454..458].copy_from_slice(&1u32.to_le_bytes());
+    mbr[458..462].copy_from_slice(&(last_lba.min(u32::MAX as u64) as u32).to_le_bytes());
+    mbr[510] = 0x55;
+    mbr[511] = 0xaa;
+
+    let backup_entries_lba = last_lba - entry_lbas;
+    Ok(vec![
+        (0, mbr),
+        (1, header(1, last_lba, 2)),
+        (2, entries.clone()),
+        (backup_entries_lba, entries),
+        (last_lba, header(last_lba, 1, backup_entries_lba)),
+    ])
+}
+
+/// CRC-32 (IEEE, as used by GPT)
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A GUID in its usual text form; the first three fields are little-endian
+pub(crate) fn format_guid(guid: &[u8; 16]) -> String {
+    format!("{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{}",
+            u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+            u16::from_le_bytes([guid[4], guid[5]]),
+            u16::from_le_bytes([guid[6], guid[7]]),
+            guid[8], guid[9],
+            guid[10..].iter().map(|b| format!("{:02X}", b)).collect::<String>())
+}
+
+/// The --pre-hash sample regions for `seed`: `range` is split into
+/// PRE_HASH_REGIONS equal runs of whole regions, and region i is the one at
+/// index (first 8 bytes of SHA-256(seed LE || i LE), as LE) modulo the run
+/// length within run i. Anyone with the seed can pick the same regions.
+pub(crate) fn pre_hash_regions(seed: u64, range: ByteRange) -> Vec<ByteRange> {
+    let length = PRE_HASH_REGION.min(range.length);
+    let slots = range.length / length;
+    let count = PRE_HASH_REGIONS.min(slots);
+    (0..count)
+        .map(|i| {
+            let first = slots * i / count;
+            let run = slots * (i + 1) / count - first;
+            let digest = Sha256::new().chain_update(seed.to_le_bytes()).chain_update(i.to_le_bytes()).finalize();
+            let pick = u64::from_le_bytes(digest[..8].try_into().unwrap()) % run;
+            ByteRange { offset: range.offset + (first + pick) * length, length }
+        })
+        .collect()
+}
+
+/// --label checked against what the filesystem can hold. FAT32 labels are
+/// stored upper-cased, so that's what the report records.
+pub fn filesystem_label(kind: FsKind, label: &str) -> Result<String, String> {
+    let label = label.trim_end();
+    if label.is_empty() {
+        return Err("--label is empty".to_string());
+    }
+    match kind {
+        FsKind::Fat32 => {
+            if label.len() > 11 || !label.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+                return Err(format!("FAT32 labels are up to 11 ASCII characters, not {:?}", label));
+            }
+            if let Some(c) = label.chars().find(|c| "\"*+,./:;<=>?[\\]|".contains(*c)) {
+                return Err(format!("FAT32 labels cannot contain '{}'", c));
+            }
+            Ok(label.to_ascii_uppercase())
+        }
+        FsKind::Exfat => {
+            if label.encode_utf16().count() > 11 || label.chars().any(char::is_control) {
+                return Err(format!("exFAT labels are up to 11 characters, not {:?}", label));
+            }
+            if let Some(c) = label.chars().find(|c| "\"*/:<>?\\|".contains(*c)) {
+                return Err(format!("exFAT labels cannot contain '{}'", c));
+            }
+            Ok(label.to_string())
+        }
+    }
+}
+
+/// Structures of an empty filesystem spanning `device_size` bytes of 512
+/// byte sectors
+pub fn filesystem_image(kind: FsKind, device_size: u64, volume_id: u32, label: Option<&str>) -> Result<FsImage, String> {
+    match kind {
+        FsKind::Fat32 => fat32_image(device_size, volume_id, label),
+        FsKind::Exfat => exfat_image(device_size, volume_id, label),
+    }
+}
+
+/// FAT32 boot code: halt, rather than run off into zeros if a BIOS tries to
+/// boot the stick
+pub(crate) const HALT_LOOP: [u8; 4] = [0xfa, 0xf4, 0xeb, 0xfd];  // cli; hlt; jmp $-1
+
+/// An empty FAT32 volume, laid out as the FAT specification recommends:
+/// 32 reserved sectors (boot sector and FSInfo, with copies at 6 and 7),
+/// two FATs, and the root directory in cluster 2
+pub(crate) fn fat32_image(device_size: u64, volume_id: u32, label: Option<&str>) -> Result<FsImage, String> {
+    const RESERVED: u64 = 32;
+
+    if device_size > FAT32_MAX_SIZE {
+        return Err(format!("{} is too large for FAT32 (at most {}); use exFAT", format_size(device_size), format_size(FAT32_MAX_SIZE)));
+    }
+    let total = device_size / 512;
+    // Cluster sizes Windows' format picks for these sizes
+    let cluster_sectors: u64 = match total {
+        0..=532_480 => 1,
+        532_481..=16_777_216 => 8,
+        16_777_217..=33_554_432 => 16,
+        _ => 32,
+    };
+    // The specification's FAT size formula; errs on the generous side
+    let per_fat_sector = (256 * cluster_sectors + 2) / 2;
+    let fat_sectors = total.saturating_sub(RESERVED).div_ceil(per_fat_sector);
+    let data_start = RESERVED + 2 * fat_sectors;
+    let clusters = total.saturating_sub(data_start) / cluster_sectors;
+    // Fewer clusters than this and every driver reads the volume as FAT16
+    if clusters < 65_525 {
+        return Err(format!("{} is too small for FAT32", format_size(device_size)));
+    }
+
+    let volume_label = fat_label_field(label);
+    let mut boot = vec![0u8; 512];
+    boot[0..3].copy_from_slice(&[0xeb, 0x58, 0x90]);
+    boot[3..11].copy_from_slice(b"MSWIN4.1");
+    boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+    boot[13] = cluster_sectors as u8;
+    boot[14..16].copy_from_slice(&(RESERVED as u16).to_le_bytes());
+    boot[16] = 2;                                         // FATs
+    boot[21] = 0xf8;                                      // Media: fixed
+    boot[24..26].copy_from_slice(&32u16.to_le_bytes());   // Sectors per track
+    boot[26..28].copy_from_slice(&64u16.to_le_bytes());   // Heads
+    boot[32..36].copy_from_slice(&(total as u32).to_le_bytes());
+    boot[36..40].copy_from_slice(&(fat_sectors as u32).to_le_bytes());
+    boot[44..48].copy_from_slice(&2u32.to_le_bytes());    // Root directory cluster
+    boot[48..50].copy_from_slice(&1u16.to_le_bytes());    // FSInfo sector
+    boot[50..52].copy_from_slice(&6u16.to_le_bytes());    // Backup boot sector
+    boot[64] = 0x80;                                      // Drive number
+    boot[66] = 0x29;                                      // Extended boot signature
+    boot[67..71].copy_from_slice(&volume_id.to_le_bytes());
+    boot[71..82].copy_from_slice(&volume_label);
+    boot[82..90].copy_from_slice(b"FAT32   ");
+    boot[90..94].copy_from_slice(&HALT_LOOP);
+    boot[510] = 0x55;
+    boot[511] = 0xaa;
+
+    let mut fsinfo = vec![0u8; 512];
+    fsinfo[0..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+    fsinfo[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+    fsinfo[488..492].copy_from_slice(&((clusters - 1) as u32).to_le_bytes());  // Free: all but the root
+    fsinfo[492..496].copy_from_slice(&3u32.to_le_bytes());                     // Next free cluster
+    fsinfo[508..512].copy_from_slice(&0xaa55_0000u32.to_le_bytes());
+
+    // Media descriptor, end-of-chain marker, and the root directory's chain
+    let mut fat = vec![0u8; 512];
+    fat[0..4].copy_from_slice(&0x0fff_fff8u32.to_le_bytes());
+    fat[4..8].copy_from_slice(&0x0fff_ffffu32.to_le_bytes());
+    fat[8..12].copy_from_slice(&0x0fff_ffffu32.to_le_bytes());
+
+    let mut sectors = vec![
+        (0, boot.clone()),
+        (512, fsinfo.clone()),
+        (6 * 512, boot),
+        (7 * 512, fsinfo),
+        (RESERVED * 512, fat.clone()),
+        ((RESERVED + fat_sectors) * 512, fat),
+    ];
+    if label.is_some() {
+        let mut entry = vec![0u8; 512];
+        entry[0..11].copy_from_slice(&volume_label);
+        entry[11] = 0x08;  // Volume label attribute
+        sectors.push((data_start * 512, entry));
+    }
+    Ok(FsImage {
+        zero: vec![ByteRange { offset: 0, length: (data_start + cluster_sectors) * 512 }],
+        sectors,
+        cluster_size: (cluster_sectors * 512) as u32,
+    })
+}
+
+/// The 11 byte, space-padded label field of a FAT boot sector
+pub(crate) fn fat_label_field(label: Option<&str>) -> [u8; 11] {
+    let mut field = *b"NO NAME    ";
+    if let Some(label) = label {
+        field = [b' '; 11];
+        field[..label.len()].copy_from_slice(label.as_bytes());
+    }
+    field
+}
+
+/// An empty exFAT volume: main and backup boot regions, one FAT at 1 MiB,
+/// and a cluster heap holding the allocation bitmap, the up-case table and
+/// the root directory, in that order
+pub(crate) fn exfat_image(device_size: u64, volume_id: u32, label: Option<&str>) -> Result<FsImage, String> {
+    const FAT_OFFSET: u64 = 2048;
+    const UPCASE_CHARS: u64 = 128;  // ASCII; characters past the table map to themselves
+
+    let total = device_size / 512;
+    // Cluster sizes Windows' format picks: 4 KiB, 32 KiB, then 128 KiB
+    let cluster_shift: u32 = match device_size {
+        0..=0x1000_0000 => 3,
+        0x1000_0001..=0x8_0000_0000 => 6,
+        _ => 8,
+    };
+    let cluster_sectors = 1u64 << cluster_shift;
+    let cluster_bytes = cluster_sectors * 512;
+    let estimate = total.saturating_sub(FAT_OFFSET) / cluster_sectors;
+    let fat_sectors = ((estimate + 2) * 4).div_ceil(512);
+    let fat_sectors = fat_sectors.div_ceil(cluster_sectors) * cluster_sectors;
+    let heap_offset = FAT_OFFSET + fat_sectors;
+    let clusters = total.saturating_sub(heap_offset) / cluster_sectors;
+    let bitmap_bytes = clusters.div_ceil(8);
+    let bitmap_clusters = bitmap_bytes.div_ceil(cluster_bytes);
+    let used = bitmap_clusters + 2;  // Bitmap, up-case table, root directory
+    if clusters <= used || clusters > 0xffff_fff5 {
+        return Err(format!("{} is too small for exFAT", format_size(device_size)));
+    }
+    let upcase_cluster = 2 + bitmap_clusters;
+    let root_cluster = upcase_cluster + 1;
+
+    let mut boot = vec![0u8; 512];
+    boot[0..3].copy_from_slice(&[0xeb, 0x76, 0x90]);
+    boot[3..11].copy_from_slice(b"EXFAT   ");
+    boot[72..80].copy_from_slice(&total.to_le_bytes());
+    boot[80..84].copy_from_slice(&(FAT_OFFSET as u32).to_le_bytes());
+    boot[84..88].copy_from_slice(&(fat_sectors as u32).to_le_bytes());
+    boot[88..92].copy_from_slice(&(heap_offset as u32).to_le_bytes());
+    boot[92..96].copy_from_slice(&(clusters as u32).to_le_bytes());
+    boot[96..100].copy_from_slice(&(root_cluster as u32).to_le_bytes());
+    boot[100..104].copy_from_slice(&volume_id.to_le_bytes());
+    boot[104..106].copy_from_slice(&0x0100u16.to_le_bytes());  // Revision 1.0
+    boot[108] = 9;                                              // 512 byte sectors
+    boot[109] = cluster_shift as u8;
+    boot[110] = 1;                                              // FATs
+    boot[111] = 0x80;
+    boot[112] = (used * 100 / clusters) as u8;                  // Percent in use
+    boot[120..510].fill(0xf4);                                  // Boot code: hlt, as the spec asks
+    boot[510] = 0x55;
+    boot[511] = 0xaa;
+
+    // Boot sector, 8 extended boot sectors, OEM parameters, reserved, then
+    // the checksum of the 11 before it, skipping the fields that change
+    let mut region = vec![0u8; 12 * 512];
+    region[..512].copy_from_slice(&boot);
+    for sector in 1..=8 {
+        region[sector * 512 + 508..sector * 512 + 512].copy_from_slice(&0xaa55_0000u32.to_le_bytes());
+    }
+    let checksum = region[..11 * 512].iter().enumerate()
+        .filter(|&(i, _)| i != 106 && i != 107 && i != 112)
+        .fold(0u32, |sum, (_, &byte)| sum.rotate_right(1).wrapping_add(byte as u32));
+    for word in region[11 * 512..].chunks_mut(4) {
+        word.copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    let mut fat = vec![0u8; ((used + 2) * 4 + 511) as usize / 512 * 512];
+    let mut set = |cluster: u64, next: u32| {
+        fat[cluster as usize * 4..cluster as usize * 4 + 4].copy_from_slice(&next.to_le_bytes());
+    };
+    set(0, 0xffff_fff8);
+    set(1, 0xffff_ffff);
+    for cluster in 2..upcase_cluster {
+        set(cluster, if cluster + 1 == upcase_cluster { 0xffff_ffff } else { cluster as u32 + 1 });
+    }
+    set(upcase_cluster, 0xffff_ffff);
+    set(root_cluster, 0xffff_ffff);
+
+    let mut bitmap = vec![0u8; used.div_ceil(8) as usize];
+    for bit in 0..used as usize {
+        bitmap[bit / 8] |= 1 << (bit % 8);
+    }
+
+    let upcase: Vec<u8> = (0..UPCASE_CHARS as u16)
+        .map(|c| if (b'a' as u16..=b'z' as u16).contains(&c) { c - 32 } else { c })
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let upcase_checksum = upcase.iter().fold(0u32, |sum, &byte| sum.rotate_right(1).wrapping_add(byte as u32));
+
+    let mut root = Vec::with_capacity(96);
+    if let Some(label) = label {
+        let chars: Vec<u16> = label.encode_utf16().collect();
+        let mut entry = [0u8; 32];
+        entry[0] = 0x83;  // Volume label
+        entry[1] = chars.len() as u8;
+        for (i, c) in chars.iter().enumerate() {
+            entry[2 + i * 2..4 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        root.extend_from_slice(&entry);
+    }
+    let mut entry = [0u8; 32];
+    entry[0] = 0x81;  // Allocation bitmap
+    entry[20..24].copy_from_slice(&2u32.to_le_bytes());
+    entry[24..32].copy_from_slice(&bitmap_bytes.to_le_bytes());
+    root.extend_from_slice(&entry);
+    let mut entry = [0u8; 32];
+    entry[0] = 0x82;  // Up-case table
+    entry[4..8].copy_from_slice(&upcase_checksum.to_le_bytes());
+    entry[20..24].copy_from_slice(&(upcase_cluster as u32).to_le_bytes());
+    entry[24..32].copy_from_slice(&(UPCASE_CHARS * 2).to_le_bytes());
+    root.extend_from_slice(&entry);
+
+    let cluster_offset = |cluster: u64| (heap_offset + (cluster - 2) * cluster_sectors) * 512;
+    Ok(FsImage {
+        zero: vec![
+            ByteRange { offset: 0, length: (FAT_OFFSET + fat_sectors) * 512 },
+            ByteRange { offset: heap_offset * 512, length: used * cluster_bytes },
+        ],
+        sectors: vec![
+            (0, region.clone()),
+            (12 * 512, region),
+            (FAT_OFFSET * 512, fat),
+            (cluster_offset(2), bitmap),
+            (cluster_offset(upcase_cluster), upcase),
+            (cluster_offset(root_cluster), root),
+        ],
+        cluster_size: cluster_bytes as u32,
+    })
+}
+
+/// --format-after: create the filesystem once the erase has verified, and
+/// note in the report what happened
+pub fn format_filesystem(eraser: &mut SecureEraser, kind: FsKind, label: Option<&str>, report: &mut EraseReport) {
+    if report.verification_passed() == Some(false) {
+        report.warnings.push(format!("No {} filesystem created: verification failed", kind));
+        return;
+    }
+    match eraser.write_filesystem(&report.device, kind, label) {
+        Ok(filesystem) => {
+            info!("Created an empty {} filesystem", kind);
+            // Drop whatever partitions the kernel still remembers from before
+            // the wipe. EINVAL: the device never had partitions to drop.
+            let reread = File::open(&report.device).and_then(|file| reread_partition_table(&file));
+            match reread {
+                Err(e) if e.kind() != io::ErrorKind::InvalidInput => {
+                    report.warnings.push(format!("The kernel did not re-read the device ({}); replug it before use", e));
+                }
+                _ => {}
+            }
+            report.filesystem = Some(filesystem);
+        }
+        Err(e) => report.warnings.push(format!("Could not create the {} filesystem: {}", kind, e)),
+    }
+}
+
+/// --init-table: lay down the empty table once the erase has verified, and
+/// note in the report what happened
+pub fn init_partition_table(eraser: &mut SecureEraser, kind: TableKind, report: &mut EraseReport) {
+    if report.verification_passed() == Some(false) {
+        report.warnings.push(format!("No {} partition table written: verification failed", kind));
+        return;
+    }
+    match eraser.write_partition_table(&report.device, kind) {
+        Ok(table) => {
+            info!("Wrote an empty {} partition table", kind);
+            let reread = File::open(&report.device).and_then(|file| reread_partition_table(&file));
+            if let Err(e) = reread {
+                report.warnings.push(format!("The kernel did not re-read the new partition table ({}); replug the device or reboot", e));
+            }
+            report.partition_table = Some(table);
+        }
+        Err(e) => report.warnings.push(format!("Could not write the {} partition table: {}", kind, e)),
+    }
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPT.get().is_some_and(CancellationToken::is_cancelled)
+        || CANCELLED.with(|flag| flag.borrow().as_ref().is_some_and(CancellationToken::is_cancelled))
+}
+
+/// Error out once Ctrl-C has been pressed or this thread's erase cancelled
+pub(crate) fn check_interrupted() -> io::Result<()> {
+    if is_interrupted() {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "Interrupted by user"));
+    }
+    Ok(())
+}
+
+/// Everything under a directory that `shred -r` acts on
+#[derive(Default)]
+pub struct ShredTree {
+    pub files: Vec<PathBuf>,
+    pub links: Vec<PathBuf>,            // Removed, never followed
+    pub dirs: Vec<PathBuf>,             // Children before parents
+    pub errors: Vec<(PathBuf, String)>, // Entries that couldn't be read
+}
+
+/// Walk `root` without following symlinks. With `one_file_system`,
+/// directories on a different device than `root` (mount points) are skipped.
+pub fn collect_shred_tree(root: &Path, one_file_system: bool) -> ShredTree {
+    let mut tree = ShredTree::default();
+    let root_dev = std::fs::symlink_metadata(root).ok().map(|m| device_id(&m));
+    walk_shred_tree(root, one_file_system, root_dev, &mut tree);
+    tree
+}
+
+pub(crate) fn walk_shred_tree(dir: &Path, one_file_system: bool, root_dev: Option<u64>, tree: &mut ShredTree) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tree.errors.push((dir.to_path_buf(), e.to_string()));
+            return;
+        }
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                tree.errors.push((dir.to_path_buf(), e.to_string()));
+                continue;
+            }
+        };
+        let metadata = match std::fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                tree.errors.push((path, e.to_string()));
+                continue;
+            }
+        };
+
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            tree.links.push(path);
+        } else if file_type.is_dir() {
+            if one_file_system && root_dev.is_some() && Some(device_id(&metadata)) != root_dev {
+                tree.errors.push((path, "on another filesystem (--one-file-system)".to_string()));
+                continue;
+            }
+            walk_shred_tree(&path, one_file_system, root_dev, tree);
+        } else if file_type.is_file() {
+            tree.files.push(path);
+        } else {
+            tree.errors.push((path, "not a regular file".to_string()));
+        }
+    }
+    tree.dirs.push(dir.to_path_buf());
+}
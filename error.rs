@@ -0,0 +1,428 @@
+//! Exit statuses and the error types an erase can fail with
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{report::*, ui::format_duration, verify::*};
+
+pub const EXIT_FAILURE: i32 = 1; // Any failure without a more specific status
+pub const EXIT_USAGE: i32 = 2; // Bad arguments; clap uses this status too
+pub const EXIT_NOT_FOUND: i32 = 3; // Device or file doesn't exist
+pub const EXIT_IN_USE: i32 = 4; // Device mounted or held by another driver
+pub const EXIT_PERMISSION: i32 = 5; // Permission denied opening or writing the device
+pub const EXIT_IO: i32 = 6; // I/O error during the wipe
+pub const EXIT_VERIFY_FAILED: i32 = 7; // Wipe finished but verification found mismatches
+pub const EXIT_INTERRUPTED: i32 = 8; // Cancelled at a prompt or stopped by SIGINT/SIGTERM
+pub const EXIT_BATCH_PARTIAL: i32 = 9; // Some devices in a batch failed, others were erased
+pub const EXIT_INCOMPLETE: i32 = 10; // Unwritable sectors were skipped, the device was removed mid-wipe or ended early
+pub const EXIT_NOT_RESPONDING: i32 = 11; // A write never returned; the device stopped responding
+
+/// Everything a library call can fail with. Each variant keeps what a
+/// caller needs to act on it, and `source()` leads to the underlying
+/// `io::Error` where there is one.
+#[derive(Debug, thiserror::Error)]
+pub enum EraseError {
+    #[error("Device not found: {}", .path.display())]
+    NotFound { path: PathBuf },
+
+    #[error("{} is mounted on {mountpoint}", .device.display())]
+    Mounted { device: PathBuf, mountpoint: String },
+
+    /// Held by another driver or process (EBUSY on the exclusive open)
+    #[error("{} is in use: {source}", .device.display())]
+    InUse { device: PathBuf, source: io::Error },
+
+    #[error("Permission denied opening {}: {source}", .path.display())]
+    PermissionDenied { path: PathBuf, source: io::Error },
+
+    /// A read or write that failed, with where it was issued when known
+    #[error("{}", describe_io(*.offset, *.pass, .source))]
+    Io { offset: Option<u64>, pass: Option<usize>, source: io::Error },
+
+    /// An I/O step around the passes, e.g. saving a checkpoint, with what
+    /// was being done
+    #[error("{what}: {source}")]
+    Failed { what: String, source: io::Error },
+
+    #[error(transparent)]
+    VerificationFailed(#[from] VerificationError),
+
+    /// Stopped by a signal or a CancellationToken mid-erase, with how far
+    /// it got
+    #[error(transparent)]
+    Interrupted(#[from] InterruptedError),
+
+    /// Stopped by a signal or a CancellationToken outside an erase pass
+    /// (verifying an existing wipe, scanning, shredding)
+    #[error("Interrupted by user")]
+    Cancelled,
+
+    #[error(transparent)]
+    DeviceRemoved(#[from] DeviceRemovedError),
+
+    #[error(transparent)]
+    ShortDevice(#[from] ShortDeviceError),
+
+    /// A write outlasted `set_write_timeout`'s abort limit, and the erase
+    /// was cancelled
+    #[error(transparent)]
+    NotResponding(#[from] NotRespondingError),
+
+    /// The open device isn't the one that was confirmed
+    #[error("Device changed since confirmation: {detail}")]
+    DeviceChanged { device: PathBuf, detail: String },
+
+    /// Something this device, file or pattern can't do
+    #[error("{what}")]
+    Unsupported { what: String },
+
+    /// A setting or argument the operation can't work with
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl EraseError {
+    /// Classify a failed open of `path`, so a missing device, a refused
+    /// permission and a busy device stay apart
+    pub(crate) fn open(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            io::ErrorKind::NotFound => EraseError::NotFound { path },
+            io::ErrorKind::PermissionDenied => EraseError::PermissionDenied { path, source },
+            _ if is_busy(&source) => EraseError::InUse { device: path, source },
+            _ => EraseError::from(source),
+        }
+    }
+
+    /// Tag an I/O error with the pass it happened in
+    pub(crate) fn in_pass(self, number: usize) -> Self {
+        match self {
+            EraseError::Io { offset, pass: None, source } => EraseError::Io { offset, pass: Some(number), source },
+            other => other,
+        }
+    }
+
+    /// The OS error code behind the failure, if any
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            EraseError::DeviceRemoved(removed) => removed.errno,
+            EraseError::ShortDevice(short) => short.errno,
+            EraseError::InUse { source, .. }
+            | EraseError::PermissionDenied { source, .. }
+            | EraseError::Io { source, .. }
+            | EraseError::Failed { source, .. } => source.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// The exit status the command line ends with for this error, also
+    /// what the C API returns for it
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            EraseError::NotFound { .. } | EraseError::DeviceChanged { .. } => EXIT_NOT_FOUND,
+            EraseError::Mounted { .. } | EraseError::InUse { .. } => EXIT_IN_USE,
+            EraseError::PermissionDenied { .. } => EXIT_PERMISSION,
+            EraseError::Io { source, .. } | EraseError::Failed { source, .. } => io_exit_code(source),
+            EraseError::VerificationFailed(_) => EXIT_VERIFY_FAILED,
+            EraseError::Interrupted(_) | EraseError::Cancelled => EXIT_INTERRUPTED,
+            EraseError::DeviceRemoved(_) | EraseError::ShortDevice(_) => EXIT_INCOMPLETE,
+            EraseError::NotResponding(_) => EXIT_NOT_RESPONDING,
+            EraseError::InvalidArgument(_) => EXIT_USAGE,
+            EraseError::Unsupported { .. } | EraseError::Other(_) => EXIT_FAILURE,
+        }
+    }
+
+    /// The device offset the failure happened at, if any
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            EraseError::DeviceRemoved(removed) => removed.offset,
+            EraseError::ShortDevice(short) => Some(short.reached),
+            EraseError::NotResponding(stuck) => Some(stuck.offset),
+            EraseError::Io { offset, .. } => *offset,
+            EraseError::VerificationFailed(failed) => Some(failed.first_mismatch),
+            _ => None,
+        }
+    }
+}
+
+/// Unwraps the offset a write path attached with `at_offset`, and turns an
+/// interrupted read or write into `Cancelled`
+impl From<io::Error> for EraseError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::Interrupted {
+            return EraseError::Cancelled;
+        }
+        if !error.get_ref().is_some_and(|inner| inner.is::<OffsetError>()) {
+            return EraseError::Io { offset: None, pass: None, source: error };
+        }
+        match error.into_inner().map(|inner| inner.downcast::<OffsetError>()) {
+            Some(Ok(located)) => EraseError::Io { offset: Some(located.offset), pass: None, source: located.source },
+            _ => unreachable!("checked for an OffsetError above"),
+        }
+    }
+}
+
+fn describe_io(offset: Option<u64>, pass: Option<usize>, source: &io::Error) -> String {
+    match (offset, pass) {
+        (Some(offset), Some(pass)) => format!("write at offset {} failed in pass {}: {}", offset, pass, source),
+        (Some(offset), None) => format!("write at offset {} failed: {}", offset, source),
+        (None, Some(pass)) => format!("pass {} failed: {}", pass, source),
+        (None, None) => source.to_string(),
+    }
+}
+
+/// The exit status for an I/O error, by kind, so a device that vanished
+/// or refused access is told apart from a bad write
+pub fn io_exit_code(e: &io::Error) -> i32 {
+    match e.kind() {
+        io::ErrorKind::NotFound => EXIT_NOT_FOUND,
+        io::ErrorKind::PermissionDenied => EXIT_PERMISSION,
+        io::ErrorKind::Interrupted => EXIT_INTERRUPTED,
+        _ if is_busy(e) => EXIT_IN_USE,
+        _ => EXIT_IO,
+    }
+}
+
+/// EBUSY: the device is mounted or held open exclusively elsewhere
+pub(crate) fn is_busy(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    return error.raw_os_error() == Some(libc::EBUSY);
+    // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+    #[cfg(windows)]
+    return matches!(error.raw_os_error(), Some(32 | 33));
+}
+
+/// Returned when the final pass does not read back as written
+#[derive(Debug, Clone)]
+pub struct VerificationError {
+    pub pass: usize,
+    pub first_mismatch: u64,
+    pub bytes_verified: u64,
+    pub bad_blocks: Vec<BadBlock>,  // Including sectors skipped while writing
+}
+
+impl std::fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Verification of pass {} failed: first mismatch at offset {} ({} bytes verified)",
+            self.pass, self.first_mismatch, self.bytes_verified
+        )
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+/// Returned when a signal or a cancelled CancellationToken stopped the
+/// erase between blocks
+#[derive(Debug, Clone)]
+pub struct InterruptedError {
+    pub pass: usize,
+    pub passes: usize,
+    pub percent: f64,        // Of the whole run, all passes together
+    pub bytes_written: u64,  // Synced to the device before returning
+    pub verifying: bool,     // Stopped reading back, after every pass was written
+}
+
+impl std::fmt::Display for InterruptedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.verifying {
+            return write!(f, "Interrupted while verifying pass {}/{} ({} written)",
+                          self.pass, self.passes, format_size(self.bytes_written));
+        }
+        write!(
+            f,
+            "Interrupted at pass {}/{}, {:.0}% ({} written)",
+            self.pass, self.passes, self.percent, format_size(self.bytes_written)
+        )
+    }
+}
+
+impl std::error::Error for InterruptedError {}
+
+/// Returned when the device went away mid-erase, e.g. a USB stick pulled
+#[derive(Debug, Clone)]
+pub struct DeviceRemovedError {
+    pub pass: usize,
+    pub passes: usize,
+    pub offset: Option<u64>,  // Of the failed write; None when reading back failed
+    pub percent: f64,         // Of the whole run, all passes together
+    pub errno: Option<i32>,
+}
+
+impl std::fmt::Display for DeviceRemovedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            Some(offset) => write!(
+                f,
+                "Device was removed at pass {}/{}, offset {} ({:.0}% of the run): the wipe is INCOMPLETE",
+                self.pass, self.passes, format_size(offset), self.percent
+            ),
+            None => write!(
+                f,
+                "Device was removed while verifying pass {}/{}: every pass was written, but the wipe is UNVERIFIED",
+                self.pass, self.passes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DeviceRemovedError {}
+
+/// Returned when a pass ended short of the recorded device size: writes
+/// failed with ENOSPC or EFBIG, or the pass simply came up short. Card
+/// readers sometimes report more than the card holds.
+#[derive(Debug, Clone)]
+pub struct ShortDeviceError {
+    pub pass: usize,
+    pub passes: usize,
+    pub expected: u64,            // Where the pass should have ended
+    pub reached: u64,             // Where writing stopped
+    pub size_now: Option<u64>,    // The device size queried again after the failure
+    pub errno: Option<i32>,       // None when no write failed
+}
+
+impl std::fmt::Display for ShortDeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Device is smaller than reported: pass {}/{} ended at byte {} of {}",
+            self.pass, self.passes, self.reached, self.expected
+        )?;
+        if let Some(size) = self.size_now {
+            write!(f, " (the device now reports {} bytes)", size)?;
+        }
+        write!(f, ": the wipe is INCOMPLETE")
+    }
+}
+
+impl std::error::Error for ShortDeviceError {}
+
+/// Returned once the write watchdog gave up on a write that never came
+/// back. The device is not erased; the pass stopped at `offset`.
+#[derive(Debug, Clone)]
+pub struct NotRespondingError {
+    pub device: PathBuf,
+    pub offset: u64,
+    pub pass: usize,
+    pub passes: usize,
+    pub stuck: std::time::Duration,  // How long the write had been in flight
+}
+
+impl std::fmt::Display for NotRespondingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Device not responding: a write to {} at offset {} (pass {}/{}) has not returned after {}",
+            self.device.display(), self.offset, self.pass, self.passes, format_duration(self.stuck.as_secs_f64())
+        )
+    }
+}
+
+impl std::error::Error for NotRespondingError {}
+
+/// A device write that failed, with the offset it was issued at. Travels
+/// inside an `io::Error` so the write paths keep their signatures.
+#[derive(Debug)]
+pub struct OffsetError {
+    pub offset: u64,
+    pub source: io::Error,
+}
+
+impl std::fmt::Display for OffsetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "write at offset {} failed: {}", self.offset, self.source)
+    }
+}
+
+impl std::error::Error for OffsetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Tag `error` with the offset of the failed write, unless a lower layer
+/// already did
+pub(crate) fn at_offset(error: io::Error, offset: u64) -> io::Error {
+    if error.get_ref().is_some_and(|inner| inner.is::<OffsetError>()) {
+        return error;
+    }
+    io::Error::new(error.kind(), OffsetError { offset, source: error })
+}
+
+/// errno and device offset of a failed write, where the error carries them
+pub(crate) fn failure_details(error: &io::Error) -> (Option<i32>, Option<u64>) {
+    match error.get_ref().and_then(|inner| inner.downcast_ref::<OffsetError>()) {
+        Some(located) => (located.source.raw_os_error(), Some(located.offset)),
+        None => (error.raw_os_error(), None),
+    }
+}
+
+/// The OS error code of `error`, looking through an OffsetError
+pub(crate) fn os_error_code(error: &io::Error) -> Option<i32> {
+    match error.get_ref().and_then(|inner| inner.downcast_ref::<OffsetError>()) {
+        Some(located) => located.source.raw_os_error(),
+        None => error.raw_os_error(),
+    }
+}
+
+/// Whether a failed write points at the medium (a bad sector) rather than
+/// at the device being gone or the request being wrong
+pub(crate) fn is_media_error(error: &io::Error) -> bool {
+    let errno = os_error_code(error);
+    #[cfg(unix)]
+    return errno == Some(libc::EIO);
+    // ERROR_CRC, ERROR_SECTOR_NOT_FOUND, ERROR_WRITE_FAULT, ERROR_IO_DEVICE
+    #[cfg(windows)]
+    return matches!(errno, Some(23 | 27 | 29 | 1117));
+}
+
+/// Whether a failed write could be down to its size rather than the medium:
+/// some USB bridges fail large transfers with EIO or EINVAL but take
+/// smaller ones
+pub(crate) fn is_size_error(error: &io::Error) -> bool {
+    let errno = os_error_code(error);
+    #[cfg(unix)]
+    return matches!(errno, Some(libc::EIO | libc::EINVAL));
+    // ERROR_INVALID_PARAMETER, ERROR_IO_DEVICE
+    #[cfg(windows)]
+    return matches!(errno, Some(87 | 1117));
+}
+
+/// Whether a failed write or read means the device itself is gone:
+/// unplugged, or its card pulled from the reader
+pub(crate) fn is_device_gone(error: &io::Error) -> bool {
+    let errno = os_error_code(error);
+    #[cfg(target_os = "linux")]
+    return matches!(errno, Some(libc::ENODEV | libc::ENXIO | libc::ENOMEDIUM));
+    #[cfg(all(unix, not(target_os = "linux")))]
+    return matches!(errno, Some(libc::ENODEV | libc::ENXIO));
+    // ERROR_DEV_NOT_EXIST, ERROR_NO_SUCH_DEVICE, ERROR_DEVICE_NOT_CONNECTED
+    #[cfg(windows)]
+    return matches!(errno, Some(55 | 433 | 1167));
+}
+
+/// Whether `error` came from `path` having been removed. A pulled USB stick
+/// tends to fail with EIO before the kernel reports it gone, by which time
+/// its device node has already disappeared.
+pub(crate) fn device_removed(error: &io::Error, path: &Path) -> bool {
+    is_device_gone(error) || (is_media_error(error) && !path.exists())
+}
+
+/// Whether a write failed because the filesystem is full, or ran past the
+/// end of the device
+pub(crate) fn is_out_of_space(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        matches!(os_error_code(e), Some(libc::ENOSPC) | Some(libc::EFBIG))
+    }
+
+    #[cfg(windows)]
+    {
+        // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+        matches!(os_error_code(e), Some(39) | Some(112))
+    }
+}
@@ -0,0 +1,392 @@
+//! C API for programs that drive the eraser in-process, built with the
+//! `ffi` feature; build.rs writes the matching header, memerase.h, with
+//! cbindgen. An erase runs on a thread of its own: start it with
+//! `memerase_erase_start`, follow it through the progress callback or
+//! `memerase_job_progress`, and collect the status and JSON report once
+//! `memerase_job_wait` returns. Strings handed out are freed with
+//! `memerase_string_free`, jobs with `memerase_job_free`.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::{device::*, erase::*, error::*, patterns::*, provider::*, report::*, verify::*};
+use crate::*;
+
+// Statuses: 0 for success, otherwise the command line's exit status for the
+// same failure (EXIT_* in error.rs)
+pub const MEMERASE_OK: i32 = 0;
+pub const MEMERASE_ERR_FAILURE: i32 = 1;
+pub const MEMERASE_ERR_USAGE: i32 = 2;
+pub const MEMERASE_ERR_NOT_FOUND: i32 = 3;
+pub const MEMERASE_ERR_IN_USE: i32 = 4;
+pub const MEMERASE_ERR_PERMISSION: i32 = 5;
+pub const MEMERASE_ERR_IO: i32 = 6;
+pub const MEMERASE_ERR_VERIFY_FAILED: i32 = 7;
+pub const MEMERASE_ERR_INTERRUPTED: i32 = 8;
+pub const MEMERASE_ERR_INCOMPLETE: i32 = 10;
+pub const MEMERASE_ERR_NOT_RESPONDING: i32 = 11;
+
+// MemeraseOptions::pattern
+pub const MEMERASE_PATTERN_ZEROS: u32 = 0;
+pub const MEMERASE_PATTERN_ONES: u32 = 1;
+pub const MEMERASE_PATTERN_RANDOM: u32 = 2;
+pub const MEMERASE_PATTERN_DOD3PASS: u32 = 3;
+pub const MEMERASE_PATTERN_GUTMANN35: u32 = 4;
+
+// MemeraseOptions::verify
+pub const MEMERASE_VERIFY_NONE: u32 = 0;
+pub const MEMERASE_VERIFY_SAMPLE: u32 = 1;
+pub const MEMERASE_VERIFY_FULL: u32 = 2;
+pub const MEMERASE_VERIFY_HASH: u32 = 3;
+
+// MemeraseProgress::phase
+pub const MEMERASE_PHASE_WRITING: u32 = 0;
+pub const MEMERASE_PHASE_SYNCING: u32 = 1;
+pub const MEMERASE_PHASE_VERIFYING: u32 = 2;
+
+pub const MEMERASE_PATH_MAX: usize = 256;
+pub const MEMERASE_NAME_MAX: usize = 64;
+
+/// One entry of `memerase_list_devices`. The strings are NUL-terminated and
+/// cut short if they don't fit.
+#[repr(C)]
+pub struct MemeraseDevice {
+    pub path: [c_char; MEMERASE_PATH_MAX],
+    pub name: [c_char; MEMERASE_NAME_MAX],
+    pub size: u64,
+    pub removable: bool,
+    pub mounted: bool,
+}
+
+/// Where an erase stands
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MemeraseProgress {
+    pub phase: u32,           // MEMERASE_PHASE_*
+    pub pass: u32,            // 1-based
+    pub passes: u32,
+    pub bytes_done: u64,      // All passes together
+    pub total_bytes: u64,
+    pub rate: f64,            // Bytes per second over the last few seconds
+    pub eta_secs: f64,        // Negative while unknown
+}
+
+/// Called on the erase's thread at most ten times a second, on every phase
+/// change and when a pass finishes
+pub type MemeraseProgressFn = Option<extern "C" fn(progress: *const MemeraseProgress, user_data: *mut c_void)>;
+
+/// How to erase. A zeroed struct is a single pass of zeros without
+/// verification, regular files refused.
+#[repr(C)]
+pub struct MemeraseOptions {
+    pub pattern: u32,         // MEMERASE_PATTERN_*
+    pub verify: u32,          // MEMERASE_VERIFY_*
+    pub block_size: u64,      // Bytes per write; 0 for the default 1MB
+    pub allow_file: bool,     // Accept a regular file or disk image as the target
+    pub progress: MemeraseProgressFn,
+    pub user_data: *mut c_void,  // Passed back to `progress` untouched
+}
+
+/// An erase started by `memerase_erase_start`
+pub struct MemeraseJob {
+    shared: Arc<std::sync::Mutex<JobState>>,
+    cancel: CancellationToken,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+struct JobState {
+    progress: MemeraseProgress,
+    result: Option<Result<EraseReport, EraseError>>,
+}
+
+/// The caller's callback, moved to the erase thread. The caller answers for
+/// `user_data` being usable from there.
+struct Callback {
+    function: extern "C" fn(*const MemeraseProgress, *mut c_void),
+    user_data: *mut c_void,
+}
+
+unsafe impl Send for Callback {}
+
+impl MemeraseProgress {
+    fn from_update(update: &ProgressUpdate) -> Self {
+        MemeraseProgress {
+            phase: match update.phase {
+                ProgressPhase::Writing => MEMERASE_PHASE_WRITING,
+                ProgressPhase::Syncing => MEMERASE_PHASE_SYNCING,
+                ProgressPhase::Verifying => MEMERASE_PHASE_VERIFYING,
+            },
+            pass: update.pass as u32,
+            passes: update.passes as u32,
+            bytes_done: update.bytes_done,
+            total_bytes: update.total_bytes,
+            rate: update.rate,
+            eta_secs: update.eta_secs.unwrap_or(-1.0),
+        }
+    }
+}
+
+/// Copy `value` into `field`, truncated, always NUL-terminated
+fn copy_c_string(field: &mut [c_char], value: &str) {
+    let len = value.len().min(field.len() - 1);
+    for (slot, &byte) in field.iter_mut().zip(&value.as_bytes()[..len]) {
+        *slot = byte as c_char;
+    }
+    field[len] = 0;
+}
+
+fn into_c_string(value: String) -> *mut c_char {
+    // Nothing serialized or formatted here contains a NUL
+    CString::new(value).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+fn status(result: &Result<EraseReport, EraseError>) -> i32 {
+    match result {
+        Ok(_) => MEMERASE_OK,
+        Err(e) => e.exit_code(),
+    }
+}
+
+/// Fill `devices` (room for `capacity` entries, may be NULL when 0) with
+/// the disks that can be erased and set `*count` to how many there are,
+/// which may be more than `capacity`
+///
+/// # Safety
+/// `devices` must point to `capacity` writable entries and `count` to a
+/// writable size_t.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_list_devices(devices: *mut MemeraseDevice, capacity: usize, count: *mut usize) -> i32 {
+    if count.is_null() || (devices.is_null() && capacity > 0) {
+        return MEMERASE_ERR_USAGE;
+    }
+    let listed = match system_provider().list() {
+        Ok(listed) => listed,
+        Err(e) => return e.exit_code(),
+    };
+    for (i, device) in listed.iter().take(capacity).enumerate() {
+        let entry = &mut *devices.add(i);
+        copy_c_string(&mut entry.path, &device.path.to_string_lossy());
+        copy_c_string(&mut entry.name, &device.name);
+        entry.size = device.size;
+        entry.removable = device.is_removable;
+        entry.mounted = device.is_mounted;
+    }
+    *count = listed.len();
+    MEMERASE_OK
+}
+
+/// Check the target and the options and start erasing `device` on a new
+/// thread, storing the job in `*job`. Nothing is written when this returns
+/// an error: an unknown device, a regular file without `allow_file`, an
+/// unknown pattern or an invalid block size. `options` may be NULL for the
+/// defaults. A write stuck for DEFAULT_WRITE_ABORT seconds fails the job
+/// with MEMERASE_ERR_NOT_RESPONDING; the host process is never ended.
+///
+/// # Safety
+/// `device` must be a NUL-terminated string, `options` NULL or a valid
+/// MemeraseOptions, and `job` a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_erase_start(device: *const c_char, options: *const MemeraseOptions,
+                                              job: *mut *mut MemeraseJob) -> i32 {
+    if device.is_null() || job.is_null() {
+        return MEMERASE_ERR_USAGE;
+    }
+    *job = std::ptr::null_mut();
+    let Ok(device) = CStr::from_ptr(device).to_str() else {
+        return MEMERASE_ERR_USAGE;
+    };
+    let options = options.as_ref();
+
+    let pattern = match options.map_or(MEMERASE_PATTERN_ZEROS, |o| o.pattern) {
+        MEMERASE_PATTERN_ZEROS => WipePattern::Zeros,
+        MEMERASE_PATTERN_ONES => WipePattern::Ones,
+        MEMERASE_PATTERN_RANDOM => WipePattern::Random,
+        MEMERASE_PATTERN_DOD3PASS => WipePattern::Dod3Pass,
+        MEMERASE_PATTERN_GUTMANN35 => WipePattern::Gutmann35,
+        _ => return MEMERASE_ERR_USAGE,
+    };
+    let verify = match options.map_or(MEMERASE_VERIFY_NONE, |o| o.verify) {
+        MEMERASE_VERIFY_NONE => None,
+        MEMERASE_VERIFY_SAMPLE => Some(VerifyMode::Sample { count: DEFAULT_VERIFY_SAMPLES, strategy: SampleStrategy::Random }),
+        MEMERASE_VERIFY_FULL => Some(VerifyMode::Full),
+        MEMERASE_VERIFY_HASH => Some(VerifyMode::Hash),
+        _ => return MEMERASE_ERR_USAGE,
+    };
+
+    let mut eraser = SecureEraser::new();
+    // Set here rather than inherited, so the defaults can't change what a
+    // stuck device does to the host
+    eraser.set_write_timeout(Some(std::time::Duration::from_secs(DEFAULT_WRITE_TIMEOUT)),
+                             Some(std::time::Duration::from_secs(DEFAULT_WRITE_ABORT)));
+    if let Some(block_size) = options.map(|o| o.block_size).filter(|&size| size != 0) {
+        let Ok(block_size) = usize::try_from(block_size) else {
+            return MEMERASE_ERR_USAGE;
+        };
+        if eraser.set_block_size(block_size).is_err() {
+            return MEMERASE_ERR_USAGE;
+        }
+    }
+    let allow_file = options.is_some_and(|o| o.allow_file);
+    let target = eraser.device_provider().list()
+        .and_then(|devices| resolve_target(eraser.device_provider(), &PathBuf::from(device), &devices, allow_file));
+    let target = match target {
+        Ok(target) => target.path,
+        Err(e) => return e.exit_code(),
+    };
+
+    let callback = options.and_then(|o| o.progress.map(|function| Callback { function, user_data: o.user_data }));
+    let cancel = CancellationToken::new();
+    let shared = Arc::new(std::sync::Mutex::new(JobState {
+        progress: MemeraseProgress {
+            phase: MEMERASE_PHASE_WRITING,
+            pass: 0,
+            passes: pattern.pass_count() as u32,
+            bytes_done: 0,
+            total_bytes: 0,
+            rate: 0.0,
+            eta_secs: -1.0,
+        },
+        result: None,
+    }));
+
+    let worker = Arc::clone(&shared);
+    let token = cancel.clone();
+    let spawned = std::thread::Builder::new().name("memerase-ffi".to_string()).spawn(move || {
+        let progress = Arc::clone(&worker);
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            EraseJob::new(target)
+                .pattern(pattern)
+                .verify(verify)
+                .cancellation(token)
+                .on_progress(move |update| {
+                    let update = MemeraseProgress::from_update(update);
+                    progress.lock().unwrap().progress = update;
+                    if let Some(ref callback) = callback {
+                        (callback.function)(&update, callback.user_data);
+                    }
+                })
+                .build()
+                .and_then(|job| eraser.run(job))
+        }));
+        worker.lock().unwrap().result =
+            Some(outcome.unwrap_or_else(|_| Err(EraseError::Other("the erase thread panicked".to_string()))));
+    });
+    match spawned {
+        Ok(thread) => {
+            *job = Box::into_raw(Box::new(MemeraseJob { shared, cancel, thread: Some(thread) }));
+            MEMERASE_OK
+        }
+        Err(_) => MEMERASE_ERR_FAILURE,
+    }
+}
+
+/// Copy the latest progress into `*progress` (if not NULL). Returns true
+/// while the erase is still running.
+///
+/// # Safety
+/// `job` must come from `memerase_erase_start` and not have been freed;
+/// `progress` must be NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_job_progress(job: *const MemeraseJob, progress: *mut MemeraseProgress) -> bool {
+    let Some(job) = job.as_ref() else {
+        return false;
+    };
+    let state = job.shared.lock().unwrap();
+    if let Some(progress) = progress.as_mut() {
+        *progress = state.progress;
+    }
+    state.result.is_none()
+}
+
+/// Ask the erase to stop after the block in flight; `memerase_job_wait`
+/// then returns MEMERASE_ERR_INTERRUPTED
+///
+/// # Safety
+/// `job` must come from `memerase_erase_start` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_job_cancel(job: *const MemeraseJob) {
+    if let Some(job) = job.as_ref() {
+        job.cancel.cancel();
+    }
+}
+
+/// Block until the erase has finished and return its status
+///
+/// # Safety
+/// `job` must come from `memerase_erase_start` and not have been freed,
+/// and only one thread may wait on it at a time.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_job_wait(job: *mut MemeraseJob) -> i32 {
+    let Some(job) = job.as_mut() else {
+        return MEMERASE_ERR_USAGE;
+    };
+    if let Some(thread) = job.thread.take() {
+        let _ = thread.join();
+    }
+    job.shared.lock().unwrap().result.as_ref().map_or(MEMERASE_ERR_FAILURE, status)
+}
+
+/// The report of a finished, successful erase as JSON, the same document
+/// `--report` writes for a device under "report"; NULL while the erase is
+/// running or when it failed. Free it with `memerase_string_free`.
+///
+/// # Safety
+/// `job` must come from `memerase_erase_start` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_job_report(job: *const MemeraseJob) -> *mut c_char {
+    let Some(job) = job.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    match job.shared.lock().unwrap().result {
+        Some(Ok(ref report)) => serde_json::to_string(report).map_or(std::ptr::null_mut(), into_c_string),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Why a finished erase failed, as the command line would print it; NULL
+/// while it is running or when it succeeded. Free it with
+/// `memerase_string_free`.
+///
+/// # Safety
+/// `job` must come from `memerase_erase_start` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_job_error(job: *const MemeraseJob) -> *mut c_char {
+    let Some(job) = job.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    match job.shared.lock().unwrap().result {
+        Some(Err(ref e)) => into_c_string(e.to_string()),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Cancel the erase if it is still running, wait for it to stop and free
+/// the job. NULL is ignored.
+///
+/// # Safety
+/// `job` must be NULL or come from `memerase_erase_start`, and must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_job_free(job: *mut MemeraseJob) {
+    if job.is_null() {
+        return;
+    }
+    let mut job = Box::from_raw(job);
+    job.cancel.cancel();
+    if let Some(thread) = job.thread.take() {
+        let _ = thread.join();
+    }
+}
+
+/// Free a string returned by this API. NULL is ignored.
+///
+/// # Safety
+/// `string` must be NULL or come from this API, and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn memerase_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
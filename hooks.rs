@@ -0,0 +1,102 @@
+//! What runs around an erase and after it: the --pre-cmd and --post-cmd
+//! hooks, and the --notify-url webhook
+
+use std::io;
+use tracing::{debug, info, warn};
+
+use crate::{device::DeviceIdentity, patterns::WipePattern, report::RunRecord};
+
+/// What --pre-cmd and --post-cmd see about the device
+pub fn hook_env(device: &DeviceIdentity, method: WipePattern) -> Vec<(&'static str, String)> {
+    vec![
+        ("MEMERASE_DEVICE", device.path.display().to_string()),
+        ("MEMERASE_SERIAL", device.serial.clone().unwrap_or_default()),
+        ("MEMERASE_METHOD", format!("{:?}", method)),
+    ]
+}
+
+/// MEMERASE_RESULT for --post-cmd
+pub fn hook_result(record: &RunRecord) -> &'static str {
+    if record.succeeded {
+        "success"
+    } else if !record.outcome.attempted {
+        "not-attempted"
+    } else if record.outcome.verified == Some(false) {
+        "verify-failed"
+    } else {
+        "failed"
+    }
+}
+
+/// Run a hook command through the platform shell. Its output goes to
+/// stderr, so stdout stays clean for JSON.
+pub fn run_hook(command: &str, vars: &[(&str, String)]) -> io::Result<std::process::ExitStatus> {
+    debug!("Running hook: {}", command);
+    #[cfg(unix)]
+    let mut shell = std::process::Command::new("sh");
+    #[cfg(unix)]
+    shell.arg("-c");
+    #[cfg(windows)]
+    let mut shell = std::process::Command::new("cmd");
+    #[cfg(windows)]
+    shell.arg("/C");
+    shell.arg(command)
+        .envs(vars.iter().map(|(name, value)| (name, value)))
+        .stdin(std::process::Stdio::null())
+        .stdout(io::stderr())
+        .status()
+}
+
+/// Bearer token for --notify-url, kept out of the command line
+pub const NOTIFY_TOKEN_ENV: &str = "MEMERASE_NOTIFY_TOKEN";
+
+/// Scheme and host of a URL, for logging. Webhook paths are often the
+/// secret, so the rest is never printed.
+fn url_origin(url: &str) -> &str {
+    let start = url.find("://").map_or(0, |i| i + 3);
+    let end = url[start..].find(['/', '?', '#']).map_or(url.len(), |i| start + i);
+    &url[..end]
+}
+
+/// POST the run's records to --notify-url, retrying once. Delivery is best
+/// effort: the outcome is logged and never changes the exit status.
+pub fn send_notification(url: &str, host: &str, records: &[RunRecord], succeeded: bool, timeout: std::time::Duration) {
+    let erased = records.iter().filter(|r| r.succeeded).count();
+    let payload = serde_json::json!({
+        "event": "erase-finished",
+        // Chat webhooks (Slack, Mattermost) display this field
+        "text": format!("{}: {} of {} device(s) erased{}", host, erased, records.len(),
+                        if succeeded { "" } else { ", run FAILED" }),
+        "hostname": host,
+        "succeeded": succeeded,
+        "records": records,
+    })
+    .to_string();
+    let token = std::env::var(NOTIFY_TOKEN_ENV).ok().filter(|token| !token.is_empty());
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    for attempt in 1..=2 {
+        let mut request = agent.post(url).set("Content-Type", "application/json");
+        if let Some(token) = &token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        // ureq's own messages include the full URL
+        let result = request.send_string(&payload).map_err(|e| match e {
+            ureq::Error::Status(code, _) => format!("HTTP {}", code),
+            ureq::Error::Transport(t) => match std::error::Error::source(&t) {
+                Some(cause) => format!("{}: {}", t.kind(), cause),
+                None => t.kind().to_string(),
+            },
+        });
+        match result {
+            Ok(response) => {
+                info!("Notification delivered to {} (HTTP {})", url_origin(url), response.status());
+                return;
+            }
+            Err(e) if attempt == 1 => {
+                warn!("Warning: notification to {} failed ({}), retrying", url_origin(url), e);
+                std::thread::sleep(std::time::Duration::from_secs(2));
+            }
+            Err(e) => warn!("Warning: could not deliver notification to {}: {}", url_origin(url), e),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! --kiosk: erase removable media as it is plugged in
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use crate::{audit::*, config::*, device::*, erase::*, error::*, metrics::*, patterns::*, report::*, ui::*, verify::*};
+use crate::*;
+
+/// How --kiosk erases each device it picks up
+pub struct KioskOptions {
+    pub pattern: WipePattern,
+    pub verify: Option<VerifyMode>,
+    pub sync_mode: SyncMode,
+    pub direct_io: DirectIo,
+    pub auto_tune: bool,
+    pub keep_going: bool,
+    pub stamp_key: Option<Vec<u8>>,
+    pub settle: std::time::Duration,  // How long a new device must stay attached before it is erased
+    pub init_table: Option<TableKind>,
+    pub format_after: Option<(FsKind, Option<String>)>,
+    pub report_csv: Option<String>,  // Gets a row as each device finishes
+    pub metrics: Option<Arc<Metrics>>,
+}
+
+/// --kiosk: erase each removable device attached after startup, until
+/// interrupted. --kiosk together with --yes and --removable-only stands in
+/// for the per-device confirmation, so the filters (and the protected list)
+/// are the only guard; devices present at startup are never touched.
+/// Returns the run record of every device it erased, or tried to.
+pub fn run_kiosk(
+    eraser: &mut SecureEraser,
+    config: &Config,
+    filter: &DeviceFilter,
+    options: &KioskOptions,
+) -> Result<Vec<RunRecord>, EraseError> {
+    let KioskOptions { pattern, verify, sync_mode, direct_io, .. } = *options;
+    let operator = invoking_user();
+    let host = hostname();
+    let metrics = options.metrics.as_ref();
+
+    let mut known: HashSet<PathBuf> = eraser.list_devices()?.into_iter().map(|d| d.path).collect();
+    let mut pending: HashMap<PathBuf, std::time::Instant> = HashMap::new();
+    let mut records: Vec<RunRecord> = Vec::new();
+    human!("Kiosk mode: ignoring the {} device(s) already attached; insert media to erase (Ctrl-C to stop)",
+           known.len());
+    audit(serde_json::json!({ "event": "kiosk-start", "ignored": known }));
+
+    while !is_interrupted() {
+        std::thread::sleep(KIOSK_POLL);
+        let devices = eraser.list_devices()?;
+        // Unplugged devices are forgotten, so plugging one back in counts as new
+        known.retain(|path| devices.iter().any(|d| &d.path == path));
+        pending.retain(|path, _| devices.iter().any(|d| &d.path == path));
+        for device in devices.iter().filter(|d| !known.contains(&d.path)) {
+            pending.entry(device.path.clone()).or_insert_with(|| {
+                info!(target: "note", "{} attached; letting it settle for {}s", device.path.display(), options.settle.as_secs());
+                std::time::Instant::now()
+            });
+        }
+
+        let settled: Vec<PathBuf> = pending.iter()
+            .filter(|(_, since)| since.elapsed() >= options.settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            pending.remove(&path);
+            known.insert(path.clone());
+            // Size and the removable flag are only trustworthy once it settled
+            let Some(device) = eraser.list_devices()?.into_iter().find(|d| d.path == path) else {
+                continue;
+            };
+            if !filter.matches(&device) {
+                info!(target: "note", "Ignoring {}: excluded by the filters", path.display());
+                continue;
+            }
+            if let Some((rule, matched, file)) = config.protecting_rule(&device) {
+                warn!("Skipping {}: protected by '{}' ({}) in {}", path.display(), rule, matched, file.display());
+                continue;
+            }
+            let holders = eraser.device_provider().holders(&device.name);
+            if device.is_mounted || !holders.is_empty() {
+                warn!("Skipping {}: mounted or in use; disable automounting for kiosk use", path.display());
+                continue;
+            }
+
+            let _device_span = tracing::info_span!("erase", device = %path.display()).entered();
+            let identity = eraser.device_provider().identity(&device);
+            audit(serde_json::json!({ "event": "device", "identity": identity }));
+            let started_at = rfc3339(unix_time());
+            let started = std::time::Instant::now();
+            let mut job = EraseJob::new(&path)
+                .pattern(pattern)
+                .verify(verify)
+                .sync_mode(sync_mode)
+                .direct_io(direct_io)
+                .auto_tune(options.auto_tune)
+                .keep_going(options.keep_going)
+                .stamp_key(options.stamp_key.as_deref())
+                .expect_device(Some(ExpectedDevice { size: device.size, serial: identity.serial.clone() }));
+            if let Some(metrics) = metrics {
+                metrics.start(&path, identity.serial.as_deref());
+                let (metrics, device) = (Arc::clone(metrics), path.clone());
+                job = job.on_progress(move |update| metrics.progress(&device, update));
+            }
+            let outcome = job.build().and_then(|job| eraser.run(job));
+            let mut result = BatchResult {
+                device: path.clone(),
+                size: device.size,
+                attempted: true,
+                verified: None,
+                error: None,
+                errno: None,
+                offset: None,
+                unwritable: 0,
+                duration_secs: started.elapsed().as_secs_f64(),
+            };
+            let mut erase_report = None;
+            match outcome {
+                Ok(mut report) => {
+                    report.confirmation = Some(Confirmation { method: "kiosk (--kiosk --yes)".to_string(), user: operator.clone() });
+                    if let Some(kind) = options.init_table {
+                        init_partition_table(eraser, kind, &mut report);
+                    }
+                    if let Some((kind, ref label)) = options.format_after {
+                        format_filesystem(eraser, kind, label.as_deref(), &mut report);
+                    }
+                    emit(ProgressEvent::Result(&report));
+                    print_result_line(&report);
+                    result.verified = report.verification_passed();
+                    result.unwritable = report.unwritable.iter().map(|bad| bad.length).sum();
+                    erase_report = Some(report);
+                }
+                Err(e) => {
+                    emit(ProgressEvent::Error { device: &path, error: e.to_string() });
+                    error!("Error: {}: {}", path.display(), e);
+                    if matches!(e, EraseError::VerificationFailed(_)) {
+                        result.verified = Some(false);
+                    }
+                    (result.errno, result.offset) = (e.errno(), e.offset());
+                    result.error = Some(e.to_string());
+                }
+            }
+            let record = RunRecord {
+                tool_version: env!("CARGO_PKG_VERSION").to_string(),
+                hostname: host.clone(),
+                device: identity,
+                method: pattern,
+                passes: pattern.pass_count(),
+                operator: operator.clone(),
+                started_at,
+                finished_at: rfc3339(unix_time()),
+                succeeded: result.succeeded(),
+                outcome: result,
+                report: erase_report,
+            };
+            human!("{}: {}, safe to remove", path.display(), if record.succeeded { "done" } else { "FAILED" });
+            if let Some(metrics) = metrics {
+                let result = match &record.outcome {
+                    outcome if outcome.succeeded() => "succeeded",
+                    _ if is_interrupted() => "cancelled",
+                    _ => "failed",
+                };
+                metrics.finish(&path, result, record.report.as_ref());
+            }
+            if let Some(path) = &options.report_csv {
+                if let Err(e) = append_csv_report(path, std::slice::from_ref(&record)) {
+                    warn!("Warning: could not append to {}: {}", path, e);
+                }
+            }
+            if let Some(dir) = &config.report_dir {
+                let dest = dir.join(format!("{}-{}.json", device.name, unix_time()));
+                let written = std::fs::File::create(&dest).and_then(|mut file| write_run_report(&mut file, std::slice::from_ref(&record)));
+                if let Err(e) = written {
+                    warn!("Warning: could not write report {}: {}", dest.display(), e);
+                }
+            }
+            records.push(record);
+        }
+    }
+
+    let failed = records.iter().filter(|r| !r.succeeded).count();
+    human!("Kiosk stopped: {} erased, {} failed", records.len() - failed, failed);
+    Ok(records)
+}
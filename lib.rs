@@ -0,0 +1,96 @@
+//! Overwrite block devices and files so their contents can't be recovered,
+//! and read them back to prove it.
+//!
+//! `SecureEraser` does the work: `run` for whole devices (or a range of
+//! one) as an `EraseJob` describes, `quick_erase` for the partition tables and filesystem
+//! headers only, `shred_file` and `wipe_free_space` for files, and
+//! `verify_device` / `scan_device` to check a device without writing. The
+//! `secure-eraser` binary is a thin command line over this crate: `batch`,
+//! `kiosk` and `daemon` run its commands, and `config`, `report`,
+//! `certificate`, `hooks` and `metrics` hold what they share.
+//!
+//! The library prints nothing. Messages are `tracing` events (text meant
+//! for people uses the target "output") and progress is reported through a
+//! `ProgressCallback`; install a subscriber to see them.
+//!
+//! ```
+//! use memerase::{SecureEraser, SparseMode, VerifyMode, WipePattern};
+//!
+//! let path = std::env::temp_dir().join(format!("memerase-doc-shred-{}", std::process::id()));
+//! std::fs::write(&path, b"account numbers")?;
+//!
+//! let mut eraser = SecureEraser::new();
+//! let result = eraser.shred_file(&path, WipePattern::Dod3Pass, Some(VerifyMode::Full), false, SparseMode::Full)?;
+//! assert_eq!(result.passes, 3);
+//! assert_eq!(result.verified, Some(true));
+//! assert!(!path.exists());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#[macro_use]
+pub mod ui;
+pub mod audit;
+pub mod batch;
+pub mod certificate;
+pub mod config;
+#[cfg(unix)]
+pub mod daemon;
+#[cfg(all(unix, feature = "dbus"))]
+pub mod dbus;
+pub mod device;
+pub mod erase;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hooks;
+pub mod kiosk;
+pub mod metrics;
+pub mod patterns;
+pub mod provider;
+pub mod report;
+pub mod verify;
+
+pub use config::Config;
+pub use device::{DeviceInfo, DirectIo, SyncMode};
+pub use erase::{CancellationToken, EraseJob, EraseJobBuilder, ProgressCallback, ProgressUpdate, SecureEraser, SparseMode};
+pub use error::EraseError;
+pub use patterns::WipePattern;
+pub use provider::{DeviceProvider, FakeDevices, SysfsProvider};
+pub use report::EraseReport;
+pub use verify::{VerifyMode, VerifyResult};
+
+pub(crate) const BLOCK_SIZE: usize = 1024 * 1024; // 1MB blocks by default
+pub(crate) const MIN_BLOCK_SIZE: usize = 512;
+pub(crate) const MAX_BLOCK_SIZE: usize = 256 * 1024 * 1024;
+pub(crate) const AUTO_TUNE_BURST: u64 = 64 * 1024 * 1024;
+pub(crate) const AUTO_TUNE_CANDIDATES: [usize; 5] = [64 * 1024, 256 * 1024, 1024 * 1024, 4 * 1024 * 1024, 16 * 1024 * 1024];
+pub(crate) const PIPELINE_DEPTH: usize = 2; // Buffers cycling between generator and writer
+pub(crate) const DIRECT_IO_ALIGN: usize = 4096; // Satisfies any common logical block size
+pub(crate) const DEFAULT_VERIFY_SAMPLES: usize = 100;
+pub(crate) const SCAN_REGIONS: u64 = 16;
+pub(crate) const MAX_REPORTED_MISMATCHES: usize = 100;
+pub(crate) const MISMATCH_DUMP_LEN: usize = 16;
+pub(crate) const STAMP_SIZE: u64 = 512; // Erasure record lives in the final sector
+pub(crate) const STAMP_MAGIC: &str = "MEMERASE-STAMP-1";
+pub(crate) const EDGE_SPAN: u64 = 1024 * 1024 * 1024; // Dense sampling zone at each end
+pub(crate) const HASH_SEGMENT_SIZE: u64 = 64 * 1024 * 1024; // One digest per 64MB
+pub(crate) const DEFAULT_QUEUE_DEPTH: u32 = 32; // In-flight writes for the io_uring backend
+pub(crate) const THROUGHPUT_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+pub(crate) const KIOSK_POLL: std::time::Duration = std::time::Duration::from_secs(1); // /sys/block rescans in --kiosk mode
+pub(crate) const STATUS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2); // --status-file rewrites at most this often
+pub(crate) const PROGRESS_CALLBACK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100); // At most 10 updates a second, whatever the block size
+pub(crate) const DEFAULT_WRITEBACK_MB: u64 = 32; // Start writeback of buffered data every 32MB
+pub(crate) const DEFAULT_MAX_DIRTY_MB: u64 = 256; // Wait for writeback beyond this much outstanding
+pub(crate) const ESTIMATE_BURST: u64 = 256 * 1024 * 1024; // Timed write before the final confirmation
+pub(crate) const DEFAULT_WRITE_BATCH: usize = 8; // Fill-pattern blocks per vectored write
+pub(crate) const MAX_WRITE_BATCH: usize = 1024; // IOV_MAX on Linux
+pub(crate) const SCRAMBLE_RENAMES: usize = 6; // Renames applied to a shredded file's name
+pub(crate) const QUICK_EDGE: u64 = 16 * 1024 * 1024; // Overwritten at each end of the disk by --method quick
+pub(crate) const QUICK_PARTITION_HEAD: u64 = 4 * 1024 * 1024; // ...and at the start of every partition
+pub(crate) const TAIL_FIRST_BYTES: u64 = 1024 * 1024; // Written at both ends before the rest of pass 1 (backup GPT)
+pub const DEFAULT_WRITE_RETRIES: u32 = 3; // Attempts at a single failing sector before it is skipped
+pub const DEFAULT_WRITE_TIMEOUT: u64 = 180; // Seconds a single write may take before the stuck offset is logged
+pub const DEFAULT_WRITE_ABORT: u64 = 900; // ...and before the device is given up as not responding
+pub(crate) const PRE_HASH_REGIONS: u64 = 64; // Sample regions --pre-hash digests before the first write
+pub(crate) const PRE_HASH_REGION: u64 = 1024 * 1024; // ...each this long
+pub(crate) const PRE_HASH_DEADLINE: std::time::Duration = std::time::Duration::from_secs(120); // Sampling stops after this, e.g. on a dying drive
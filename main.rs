@@ -1,416 +1,1037 @@
 use std::fs::{File, OpenOptions};
-use std::io::{self, Read, Seek, SeekFrom, Write, BufRead, BufReader};
+use std::io::{self, Read, Write, BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
 use std::sync::Arc;
 use rand::{Rng, thread_rng};
-use clap::{Arg, Command};
-use indicatif::{ProgressBar, ProgressStyle};
+use clap::{Arg, ArgGroup, Command};
+use indicatif::ProgressStyle;
+use tracing::{error, info, warn};
 
-#[cfg(unix)]
-use std::os::unix::fs::OpenOptionsExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
 
-#[cfg(windows)]
-use winapi::um::{
-    fileapi::*,
-    handleapi::*,
-    winioctl::*,
-    errhandlingapi::GetLastError,
-};
+use memerase::{audit::*, batch::*, certificate::*, config::*, device::*, erase::*, error::*, hooks::*, kiosk::*, metrics::*, patterns::*, provider::*, report::*, ui::*, verify::*};
+use memerase::*;
+use memerase::human;
+#[cfg(unix)]
+use memerase::daemon::{run_daemon, JobDefaults};
 
-const BLOCK_SIZE: usize = 1024 * 1024; // 1MB blocks
+const DEFAULT_SOCKET: &str = "/run/memerase.sock"; // Where --daemon listens unless --socket says otherwise
+const EXIT_CODES_HELP: &str = "\
+Exit status:
+  0  success
+  1  other failure
+  2  bad arguments
+  3  device not found
+  4  device mounted or in use
+  5  permission denied
+  6  I/O error during the wipe
+  7  verification failed
+  8  cancelled by the user or by a signal
+  9  batch only partly erased
+ 10  incomplete: unwritable sectors skipped, the device was removed, or it was smaller than reported
+ 11  device stopped responding mid-write";
 
-#[derive(Debug, Clone, Copy)]
-pub enum WipePattern {
-    Zeros,
-    Ones,
-    Random,
-    Dod3Pass,    // DoD 5220.22-M (3 passes)
-    Gutmann35,   // Gutmann 35-pass method
+/// An error that ends the run with a specific exit status
+#[derive(Debug)]
+struct ExitError {
+    code: i32,
+    message: String,
 }
 
-impl std::str::FromStr for WipePattern {
-    type Err = String;
-    
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "zeros" => Ok(WipePattern::Zeros),
-            "ones" => Ok(WipePattern::Ones),
-            "random" => Ok(WipePattern::Random),
-            "dod3" => Ok(WipePattern::Dod3Pass),
-            "gutmann35" => Ok(WipePattern::Gutmann35),
-            _ => Err(format!("Invalid pattern: {}", s)),
-        }
+impl std::fmt::Display for ExitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DeviceInfo {
-    pub path: PathBuf,
-    pub name: String,
-    pub size: u64,
-    pub is_removable: bool,
-    pub is_mounted: bool,
+impl std::error::Error for ExitError {}
+
+fn exit_error(code: i32, message: impl std::fmt::Display) -> Box<dyn std::error::Error> {
+    Box::new(ExitError { code, message: message.to_string() })
 }
 
-pub struct SecureEraser {
-    rng: rand::rngs::ThreadRng,
+/// For `map_err` on argument parsing
+fn usage_error(message: impl std::fmt::Display) -> Box<dyn std::error::Error> {
+    exit_error(EXIT_USAGE, message)
 }
 
-impl SecureEraser {
-    pub fn new() -> Self {
-        Self {
-            rng: thread_rng(),
-        }
+/// The exit status an error maps to
+fn exit_code(e: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(e) = e.downcast_ref::<ExitError>() {
+        e.code
+    } else if let Some(e) = e.downcast_ref::<EraseError>() {
+        e.exit_code()
+    } else if let Some(e) = e.downcast_ref::<io::Error>() {
+        io_exit_code(e)
+    } else {
+        EXIT_FAILURE
     }
+}
 
-    /// List available storage devices
-    pub fn list_devices(&self) -> Result<Vec<DeviceInfo>, Box<dyn std::error::Error>> {
-        #[cfg(unix)]
-        return self.list_devices_unix();
-        
-        #[cfg(windows)]
-        return self.list_devices_windows();
-    }
+/// Record the status in the audit log and exit with it
+fn exit_with(code: i32, reason: Option<String>) -> ! {
+    audit(serde_json::json!({ "event": "exit", "status": code, "error": reason }));
+    std::process::exit(code)
+}
+
+/// Turn Ctrl-C and SIGTERM into the library's interrupt flag, so an erase
+/// unwinds instead of dying mid-write. A second signal exits immediately.
+fn install_interrupt_handler() {
+    enable_interrupt();
 
     #[cfg(unix)]
-    fn list_devices_unix(&self) -> Result<Vec<DeviceInfo>, Box<dyn std::error::Error>> {
-        let mut devices = Vec::new();
-        let sys_block = Path::new("/sys/block");
-        
-        if !sys_block.exists() {
-            return Ok(devices);
-        }
-
-        for entry in std::fs::read_dir(sys_block)? {
-            let entry = entry?;
-            let device_name = entry.file_name().to_string_lossy().to_string();
-            
-            // Skip loop devices and other virtual devices
-            if device_name.starts_with("loop") || device_name.starts_with("ram") {
-                continue;
+    {
+        extern "C" fn on_interrupt(_signal: libc::c_int) {
+            if interrupt() {
+                // tcsetattr is async-signal-safe; don't leave the terminal without echo
+                if let Some((fd, saved)) = SAVED_TERMINAL.get() {
+                    unsafe { libc::tcsetattr(*fd, libc::TCSANOW, saved) };
+                }
+                unsafe { libc::_exit(EXIT_INTERRUPTED) };
             }
+        }
+        let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGINT, handler);
+            libc::signal(libc::SIGTERM, handler);
+        }
+    }
 
-            let device_path = PathBuf::from(format!("/dev/{}", device_name));
-            
-            // Check if it's a block device
-            if let Ok(metadata) = std::fs::metadata(&device_path) {
-                if !is_block_device(&metadata) {
-                    continue;
-                }
+    #[cfg(windows)]
+    {
+        use winapi::shared::minwindef::{BOOL, DWORD, TRUE};
 
-                let mut info = DeviceInfo {
-                    path: device_path.clone(),
-                    name: device_name.clone(),
-                    size: 0,
-                    is_removable: false,
-                    is_mounted: false,
-                };
-
-                // Get device size
-                if let Ok(size) = self.get_device_size_unix(&device_path) {
-                    info.size = size;
-                }
+        unsafe extern "system" fn on_interrupt(_ctrl_type: DWORD) -> BOOL {
+            if interrupt() {
+                winapi::um::processthreadsapi::ExitProcess(EXIT_INTERRUPTED as u32);
+            }
+            TRUE
+        }
+        unsafe {
+            winapi::um::consoleapi::SetConsoleCtrlHandler(Some(on_interrupt), TRUE);
+        }
+    }
+}
 
-                // Check if removable
-                let removable_path = format!("/sys/block/{}/removable", device_name);
-                if let Ok(removable_str) = std::fs::read_to_string(removable_path) {
-                    info.is_removable = removable_str.trim() == "1";
-                }
+/// Which terminal stream an event goes to
+#[derive(Clone, Copy)]
+enum TerminalStream {
+    Human,    // stdout, or stderr under --progress json
+    Stderr,
+}
 
-                // Check if mounted
-                info.is_mounted = self.is_device_mounted_unix(&device_path)?;
+/// Buffers one formatted event and writes it in one piece, with the bars
+/// on screen suspended
+struct TerminalWriter {
+    stream: TerminalStream,
+    buf: Vec<u8>,
+}
 
-                devices.push(info);
-            }
-        }
+impl Write for TerminalWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
-        Ok(devices)
+impl Drop for TerminalWriter {
+    fn drop(&mut self) {
+        let to_stdout = matches!(self.stream, TerminalStream::Human) && !json_progress();
+        let write = || {
+            let _ = if to_stdout {
+                io::stdout().lock().write_all(&self.buf)
+            } else {
+                io::stderr().lock().write_all(&self.buf)
+            };
+        };
+        suspend_bars(write);
     }
+}
 
-    #[cfg(windows)]
-    fn list_devices_windows(&self) -> Result<Vec<DeviceInfo>, Box<dyn std::error::Error>> {
-        let mut devices = Vec::new();
-        
-        for drive_letter in b'A'..=b'Z' {
-            let drive_path = format!("{}:", drive_letter as char);
-            let device_path = format!("\\\\.\\{}", drive_path);
-            
-            // This is a simplified version - full Windows implementation would require
-            // more complex WinAPI calls to properly enumerate all storage devices
-            if Path::new(&format!("{}\\", drive_path)).exists() {
-                let info = DeviceInfo {
-                    path: PathBuf::from(device_path),
-                    name: drive_path,
-                    size: 0, // Would need WinAPI calls to get actual size
-                    is_removable: false, // Would need WinAPI calls to determine
-                    is_mounted: true,
-                };
-                devices.push(info);
-            }
-        }
-        
-        Ok(devices)
+struct Terminal;
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Terminal {
+    type Writer = TerminalWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        TerminalWriter { stream: TerminalStream::Human, buf: Vec::new() }
     }
 
-    #[cfg(unix)]
-    fn get_device_size_unix(&self, device_path: &Path) -> Result<u64, Box<dyn std::error::Error>> {
-        use std::os::unix::io::AsRawFd;
-        
-        let file = File::open(device_path)?;
-        let fd = file.as_raw_fd();
-        
-        let mut size: u64 = 0;
-        let result = unsafe {
-            libc::ioctl(fd, libc::BLKGETSIZE64, &mut size as *mut u64)
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+        // Warnings, progress notes and --log-target stderr stay off stdout,
+        // as they always have
+        let stream = if *meta.level() <= tracing::Level::WARN || matches!(meta.target(), "note" | "log") {
+            TerminalStream::Stderr
+        } else {
+            TerminalStream::Human
         };
-        
-        if result == -1 {
-            return Err("Failed to get device size".into());
+        TerminalWriter { stream, buf: Vec::new() }
+    }
+}
+
+/// Terminal format: just the message, as the prints it replaced looked.
+/// Progress notes get a timestamp when no bar is drawn to place them.
+struct BareMessage;
+
+impl<S, N> tracing_subscriber::fmt::FormatEvent<S, N> for BareMessage
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    N: for<'a> tracing_subscriber::fmt::FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &tracing_subscriber::fmt::FmtContext<'_, S, N>,
+        mut writer: tracing_subscriber::fmt::format::Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        use std::io::IsTerminal;
+
+        if event.metadata().target() == "note" && (json_progress() || !io::stderr().is_terminal()) {
+            write!(writer, "[{}] ", rfc3339(unix_time()))?;
         }
-        
-        Ok(size)
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
     }
+}
 
-    #[cfg(unix)]
-    fn is_device_mounted_unix(&self, device_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
-        let mounts_file = File::open("/proc/mounts")?;
-        let reader = BufReader::new(mounts_file);
-        
-        let device_str = device_path.to_string_lossy();
-        
-        for line in reader.lines() {
-            let line = line?;
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 && parts[0] == device_str {
-                return Ok(true);
-            }
+/// Route every message through `tracing`. The terminal shows what -q/-v
+/// (or RUST_LOG, when set) allow; a --log-file gets everything, with spans
+/// and timestamps.
+fn init_logging(verbosity: u8, log_file: Option<File>) {
+    use tracing_subscriber::filter::{filter_fn, EnvFilter, FilterExt};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::Layer;
+
+    let level = match verbosity {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    let terminal = tracing_subscriber::fmt::layer()
+        .event_format(BareMessage)
+        .with_ansi(false)           // Span fields are cached per formatter; keep them plain for the file
+        .with_writer(Terminal)
+        .with_filter(
+            filter_fn(|meta| matches!(meta.target(), "output" | "log"))
+                .or(env_filter)
+                .and(filter_fn(|meta| meta.target() != "transcript")),
+        );
+    let transcript = log_file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::sync::Mutex::new(file))
+            .with_ansi(false)
+            .with_target(false)
+            .with_filter(tracing_subscriber::filter::LevelFilter::DEBUG)
+    });
+    tracing_subscriber::registry().with(terminal).with(transcript).init();
+}
+
+/// Open the --log-file. Unlike the audit log this is fatal, so a run never
+/// goes ahead without the transcript it was asked for.
+fn open_log_file(path: &Path) -> Result<File, Box<dyn std::error::Error>> {
+    Ok(OpenOptions::new().append(true).create(true).open(path)
+        .map_err(|e| format!("Cannot open log file {}: {}", path.display(), e))?)
+}
+
+/// The --removable-only/--min-size/--max-size/--bus filters
+fn device_filter(matches: &clap::ArgMatches) -> Result<DeviceFilter, Box<dyn std::error::Error>> {
+    let size = |id: &str| matches.get_one::<String>(id)
+        .map(|s| parse_size(s).map_err(|e| usage_error(format!("--{}: {}", id, e))))
+        .transpose();
+    Ok(DeviceFilter {
+        removable_only: matches.get_flag("removable-only"),
+        min_size: size("min-size")?,
+        max_size: size("max-size")?,
+        bus: matches.get_one::<String>("bus").map(|b| b.parse()).transpose().map_err(usage_error)?,
+    })
+}
+
+/// Display scan results as a table of regions plus the overall verdict
+fn display_scan(report: &ScanReport) {
+    println!("\nScan of {} ({}, {:?})\n", report.device.display(), size_label(report.device_size), report.mode);
+    println!("{:<16} {:<12} {:<10} {:<10} {:<}",
+             "Offset (MB)", "Length (MB)", "Zeros %", "Entropy", "Signatures");
+    println!("{}", "-".repeat(70));
+
+    for region in &report.regions {
+        println!("{:<16} {:<12} {:<10.1} {:<10.3} {:<}",
+                 region.offset / (1024 * 1024),
+                 region.length / (1024 * 1024),
+                 region.zero_fraction * 100.0,
+                 region.entropy,
+                 region.signatures.join(", "));
+    }
+
+    println!("\nVerdict: {:?}", report.verdict);
+    println!();
+}
+
+/// Display device information in a formatted table
+fn display_devices(devices: &[DeviceInfo], is_protected: impl Fn(&DeviceInfo) -> bool) {
+    println!("\nAvailable storage devices:\n");
+    println!("{:<20} {:<15} {:<15} {:<24} {:<12} {:<10} {:<6} Protected",
+             "Device", "Name", "Size", "Filesystem", "Removable", "Mounted", "Bus");
+    println!("{}", "-".repeat(112));
+
+    for device in devices {
+        // A partitioned disk shows its filesystems on the partition rows
+        let contents = match device.fs_type {
+            None if !device.partitions.is_empty() => String::new(),
+            _ => contents_label(device.fs_type.as_deref(), device.label.as_deref()),
+        };
+        println!("{:<20} {:<15} {:<15} {:<24} {:<12} {:<10} {:<6} {}",
+                 device.path.display(),
+                 device.name,
+                 size_label(device.size),
+                 contents,
+                 if device.is_removable { "Yes" } else { "No" },
+                 if device.is_mounted { "Yes" } else { "No" },
+                 device.bus.map_or("-".to_string(), |bus| bus.to_string()),
+                 if is_protected(device) { "PROTECTED" } else { "" });
+        for partition in &device.partitions {
+            println!("{:<20} {:<15} {:<15} {}",
+                     format!("  {}", partition.path.display()),
+                     partition.name,
+                     size_label(partition.size),
+                     contents_label(partition.fs_type.as_deref(), partition.label.as_deref()));
         }
-        
-        Ok(false)
     }
+    println!();
+}
 
-    /// Generate wipe patterns based on the selected method
-    pub fn generate_patterns(&mut self, pattern: WipePattern) -> Vec<Vec<u8>> {
-        match pattern {
-            WipePattern::Zeros => {
-                vec![vec![0x00; BLOCK_SIZE]]
-            }
-            WipePattern::Ones => {
-                vec![vec![0xFF; BLOCK_SIZE]]
-            }
-            WipePattern::Random => {
-                let mut random_pattern = vec![0u8; BLOCK_SIZE];
-                self.rng.fill(&mut random_pattern[..]);
-                vec![random_pattern]
-            }
-            WipePattern::Dod3Pass => {
-                let mut patterns = Vec::new();
-                
-                // Pass 1: 0x00
-                patterns.push(vec![0x00; BLOCK_SIZE]);
-                
-                // Pass 2: 0xFF
-                patterns.push(vec![0xFF; BLOCK_SIZE]);
-                
-                // Pass 3: Random
-                let mut random_pattern = vec![0u8; BLOCK_SIZE];
-                self.rng.fill(&mut random_pattern[..]);
-                patterns.push(random_pattern);
-                
-                patterns
-            }
-            WipePattern::Gutmann35 => {
-                let mut patterns = Vec::new();
-                
-                // First 4 random passes
-                for _ in 0..4 {
-                    let mut random_pattern = vec![0u8; BLOCK_SIZE];
-                    self.rng.fill(&mut random_pattern[..]);
-                    patterns.push(random_pattern);
-                }
-                
-                // Some Gutmann patterns
-                let gutmann_bytes = [0x55, 0xAA, 0x92, 0x49, 0x24];
-                for &byte_pattern in &gutmann_bytes {
-                    patterns.push(vec![byte_pattern; BLOCK_SIZE]);
-                }
-                
-                patterns
-            }
+/// `vfat "BACKUPS"`, the type alone without a label, "-" when unrecognized
+fn contents_label(fs_type: Option<&str>, label: Option<&str>) -> String {
+    match (fs_type, label) {
+        (Some(fs_type), Some(label)) => format!("{} {:?}", fs_type, label),
+        (Some(fs_type), None) => fs_type.to_string(),
+        (None, _) => "-".to_string(),
+    }
+}
+
+/// --write-timeout and --write-abort, with 0 meaning off
+fn write_timeout_args(matches: &clap::ArgMatches) -> Result<(Option<std::time::Duration>, Option<std::time::Duration>), String> {
+    let seconds = |name: &str| match *matches.get_one::<u64>(name).unwrap() {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs)),
+    };
+    let (warn_after, abort_after) = (seconds("write-timeout"), seconds("write-abort"));
+    if let (Some(warn), Some(abort)) = (warn_after, abort_after) {
+        if abort < warn {
+            return Err(format!("--write-abort ({}s) can't be shorter than --write-timeout ({}s)", abort.as_secs(), warn.as_secs()));
         }
     }
+    Ok((warn_after, abort_after))
+}
 
-    /// Perform secure erase operation
-    pub fn secure_erase(
-        &mut self,
-        device_path: &Path,
-        pattern: WipePattern,
-        verify: bool,
-        progress_callback: Option<Box<dyn Fn(f64)>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        println!("Starting secure erase of: {}", device_path.display());
+/// SIGUSR1 pauses a running erase after the current block, SIGUSR2 resumes it
+#[cfg(unix)]
+fn install_pause_handler() {
+    extern "C" fn on_pause_signal(signal: libc::c_int) {
+        PAUSED.store(signal == libc::SIGUSR1, std::sync::atomic::Ordering::SeqCst);
+    }
+    let handler = on_pause_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler);
+        libc::signal(libc::SIGUSR2, handler);
+    }
+}
 
-        // Open device for direct access
-        let mut file = self.open_device_for_writing(device_path)?;
-        
-        // Get device size
-        let device_size = self.get_device_size(&file, device_path)?;
-        println!("Device size: {} MB", device_size / (1024 * 1024));
+/// Toggles the pause when `p` is pressed on the controlling terminal. The
+/// terminal is switched to unbuffered, no-echo input while this is alive.
+#[cfg(unix)]
+struct PauseKey {
+    tty: File,
+    saved: libc::termios,
+}
 
-        let patterns = self.generate_patterns(pattern);
-        let total_blocks = (device_size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
+#[cfg(unix)]
+impl PauseKey {
+    fn start() -> Option<Self> {
+        let tty = File::open("/dev/tty").ok()?;
+        let fd = tty.as_raw_fd();
+        // A background job touching the terminal would be stopped by SIGTTOU
+        if unsafe { libc::tcgetpgrp(fd) != libc::getpgrp() } {
+            return None;
+        }
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return None;
+        }
 
-        // Create progress bar
-        let pb = ProgressBar::new(patterns.len() as u64 * total_blocks);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} blocks ({percent}%) {msg}")
-                .unwrap()
-                .progress_chars("#>-"),
-        );
+        // ISIG stays on, so Ctrl-C still raises SIGINT
+        let mut unbuffered = saved;
+        unbuffered.c_lflag &= !(libc::ICANON | libc::ECHO);
+        unbuffered.c_cc[libc::VMIN] = 1;
+        unbuffered.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &unbuffered) } != 0 {
+            return None;
+        }
+        let _ = SAVED_TERMINAL.set((fd, saved));
 
-        for (pass_num, pattern_data) in patterns.iter().enumerate() {
-            pb.set_message(format!("Pass {}/{}", pass_num + 1, patterns.len()));
-            
-            // Reset to beginning of device
-            file.seek(SeekFrom::Start(0))?;
-            
-            let mut bytes_written = 0u64;
-            let mut block_count = 0u64;
-
-            while bytes_written < device_size {
-                let write_size = std::cmp::min(BLOCK_SIZE as u64, device_size - bytes_written) as usize;
-                
-                file.write_all(&pattern_data[..write_size])?;
-                file.flush()?; // Ensure data is written to device
-                
-                bytes_written += write_size as u64;
-                block_count += 1;
-                pb.inc(1);
-
-                if let Some(ref callback) = progress_callback {
-                    let progress = (pass_num as f64 * total_blocks as f64 + block_count as f64) 
-                                 / (patterns.len() as f64 * total_blocks as f64) * 100.0;
-                    callback(progress);
+        let mut input = tty.try_clone().ok()?;
+        std::thread::spawn(move || {
+            let mut key = [0u8];
+            while let Ok(1) = input.read(&mut key) {
+                if key[0].eq_ignore_ascii_case(&b'p') {
+                    PAUSED.fetch_xor(true, std::sync::atomic::Ordering::SeqCst);
                 }
             }
+        });
+        Some(PauseKey { tty, saved })
+    }
+}
 
-            pb.println(format!("Pass {} completed", pass_num + 1));
+#[cfg(unix)]
+impl Drop for PauseKey {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.saved) };
+    }
+}
 
-            // Verify final pass if requested
-            if verify && pass_num == patterns.len() - 1 {
-                pb.set_message("Verifying final pass...");
-                if !self.verify_erase(device_path, pattern_data)? {
-                    pb.println("Warning: Verification failed!");
-                } else {
-                    pb.println("Verification successful!");
+/// The terminal in raw mode for the interactive picker, restored on drop.
+/// ISIG is off too, so Ctrl-C arrives as a key and can't leave the terminal
+/// raw.
+#[cfg(unix)]
+struct RawTerminal {
+    tty: File,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawTerminal {
+    fn open() -> io::Result<Self> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let fd = tty.as_raw_fd();
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawTerminal { tty, saved })
+    }
+
+    fn key(&mut self) -> io::Result<Key> {
+        let mut byte = [0u8];
+        self.tty.read_exact(&mut byte)?;
+        Ok(match byte[0] {
+            b'\r' | b'\n' => Key::Enter,
+            3 | b'q' | b'Q' => Key::Quit,
+            b'k' => Key::Up,
+            b'j' => Key::Down,
+            27 => {
+                // Arrow keys are ESC [ A/B; a lone ESC has nothing after it
+                let mut pollfd = libc::pollfd { fd: self.tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+                if unsafe { libc::poll(&mut pollfd, 1, 50) } <= 0 {
+                    return Ok(Key::Quit);
+                }
+                let mut seq = [0u8; 2];
+                self.tty.read_exact(&mut seq)?;
+                match seq {
+                    [b'[', b'A'] => Key::Up,
+                    [b'[', b'B'] => Key::Down,
+                    _ => Key::Other(0),
                 }
             }
-        }
+            other => Key::Other(other),
+        })
+    }
+}
 
-        pb.finish_with_message("Secure erase completed successfully!");
-        Ok(())
+#[cfg(unix)]
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.saved) };
     }
+}
 
-    fn open_device_for_writing(&self, device_path: &Path) -> Result<File, Box<dyn std::error::Error>> {
-        #[cfg(unix)]
-        {
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .custom_flags(libc::O_SYNC) // Direct I/O
-                .open(device_path)?;
-            Ok(file)
+#[cfg(unix)]
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Quit,
+    Other(u8),
+}
+
+/// Arrow-key menu on the terminal; None if the user quits. `confirm`
+/// returns a question for rows that need a second keystroke ('Y') to pick.
+#[cfg(unix)]
+fn pick_from_menu(
+    title: &str,
+    rows: &[String],
+    initial: usize,
+    confirm: impl Fn(usize) -> Option<String>,
+) -> io::Result<Option<usize>> {
+    let mut term = RawTerminal::open()?;
+    let mut selected = initial.min(rows.len().saturating_sub(1));
+    let mut question: Option<String> = None;
+    loop {
+        let mut screen = format!("\x1b[2J\x1b[H{}\r\n\r\n", title);
+        for (index, row) in rows.iter().enumerate() {
+            let marker = if index == selected { "> " } else { "  " };
+            if index == selected && COLOR.load(std::sync::atomic::Ordering::Relaxed) {
+                screen.push_str(&format!("\x1b[7m{}{}\x1b[0m\r\n", marker, row));
+            } else {
+                screen.push_str(&format!("{}{}\r\n", marker, row));
+            }
         }
+        match &question {
+            Some(text) => screen.push_str(&format!("\r\n{} Press Y to select it anyway, any other key to go back.\r\n", text)),
+            None => screen.push_str("\r\nUp/Down or j/k to move, Enter to select, q to quit\r\n"),
+        }
+        term.tty.write_all(screen.as_bytes())?;
 
-        #[cfg(windows)]
-        {
-            // Windows implementation would require CreateFile with specific flags
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(device_path)?;
-            Ok(file)
+        let key = term.key()?;
+        if question.take().is_some() {
+            if let Key::Other(b'Y') = key {
+                break;
+            }
+            continue;
+        }
+        match key {
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down => selected = (selected + 1).min(rows.len().saturating_sub(1)),
+            Key::Enter if !rows.is_empty() => match confirm(selected) {
+                Some(text) => question = Some(text),
+                None => break,
+            },
+            Key::Quit => {
+                term.tty.write_all(b"\x1b[2J\x1b[H")?;
+                return Ok(None);
+            }
+            _ => {}
         }
     }
+    term.tty.write_all(b"\x1b[2J\x1b[H")?;
+    Ok(Some(selected))
+}
 
-    fn get_device_size(&self, file: &File, device_path: &Path) -> Result<u64, Box<dyn std::error::Error>> {
-        #[cfg(unix)]
-        {
-            self.get_device_size_unix(device_path)
+/// Choose the target interactively when no --device was given. Mounted and
+/// non-removable disks are flagged and take an extra 'Y' to choose.
+#[cfg(unix)]
+fn pick_device(devices: &[DeviceInfo]) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>> {
+    if devices.is_empty() {
+        return Err(exit_error(EXIT_NOT_FOUND, "No storage devices found"));
+    }
+    let color = COLOR.load(std::sync::atomic::Ordering::Relaxed);
+    let rows: Vec<String> = devices.iter()
+        .map(|device| {
+            let mut flags = Vec::new();
+            if device.is_mounted {
+                flags.push(if color { "\x1b[31mMOUNTED\x1b[0m" } else { "MOUNTED" });
+            }
+            if !device.is_removable {
+                flags.push(if color { "\x1b[33minternal\x1b[0m" } else { "internal" });
+            } else {
+                flags.push("removable");
+            }
+            format!("{:<16} {:>10}  {:<24} {:<20} {}",
+                    device.path.display(),
+                    format_size(device.size),
+                    device_model(&device.name).unwrap_or_else(|| "-".to_string()),
+                    device_serial(&device.name).unwrap_or_else(|| "-".to_string()),
+                    flags.join(" "))
+        })
+        .collect();
+    let picked = pick_from_menu("Select the device to erase:", &rows, 0, |index| {
+        let device = &devices[index];
+        if device.is_mounted {
+            Some(format!("{} is MOUNTED and will be refused unless unmounted.", device.path.display()))
+        } else if !device.is_removable {
+            Some(format!("{} is an internal (non-removable) disk.", device.path.display()))
+        } else {
+            None
         }
+    })?;
+    Ok(picked.map(|index| devices[index].clone()))
+}
 
-        #[cfg(windows)]
-        {
-            // For Windows, we'd need to use GetFileSizeEx or DeviceIoControl
-            // This is a simplified version
-            let metadata = file.metadata()?;
-            Ok(metadata.len())
-        }
+#[cfg(unix)]
+fn pick_pattern(default: WipePattern) -> Result<Option<WipePattern>, Box<dyn std::error::Error>> {
+    let patterns = [WipePattern::Zeros, WipePattern::Ones, WipePattern::Random, WipePattern::Dod3Pass, WipePattern::Gutmann35];
+    let rows: Vec<String> = patterns.iter()
+        .map(|pattern| format!("{:<12} {} pass(es)", format!("{:?}", pattern), pattern.pass_count()))
+        .collect();
+    let initial = patterns.iter().position(|&p| p == default).unwrap_or(0);
+    Ok(pick_from_menu("Select the wipe pattern:", &rows, initial, |_| None)?.map(|index| patterns[index]))
+}
+
+#[cfg(not(unix))]
+fn pick_device(_devices: &[DeviceInfo]) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>> {
+    Err(usage_error("The interactive device picker needs a Unix terminal; pass --device"))
+}
+
+#[cfg(not(unix))]
+fn pick_pattern(default: WipePattern) -> Result<Option<WipePattern>, Box<dyn std::error::Error>> {
+    Ok(Some(default))
+}
+
+/// The table --init-table asks for; "none" (the default) is None
+fn init_table_arg(matches: &clap::ArgMatches) -> Option<TableKind> {
+    match matches.get_one::<String>("init-table").map(String::as_str) {
+        Some("gpt") => Some(TableKind::Gpt),
+        Some("mbr") => Some(TableKind::Mbr),
+        _ => None,
     }
+}
 
-    /// Verify erase by reading back data (sample verification)
-    fn verify_erase(&self, device_path: &Path, expected_pattern: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
-        let mut file = File::open(device_path)?;
-        let mut read_buffer = vec![0u8; BLOCK_SIZE];
+/// The filesystem --format-after asks for, with its checked --label
+fn format_after_arg(matches: &clap::ArgMatches) -> Result<Option<(FsKind, Option<String>)>, String> {
+    let kind = match matches.get_one::<String>("format-after").map(String::as_str) {
+        Some("fat32") => FsKind::Fat32,
+        Some("exfat") => FsKind::Exfat,
+        _ => return Ok(None),
+    };
+    let label = matches.get_one::<String>("label")
+        .map(|label| filesystem_label(kind, label))
+        .transpose()?;
+    Ok(Some((kind, label)))
+}
 
-        // Sample verification - check first 10 blocks
-        for _ in 0..10 {
-            match file.read_exact(&mut read_buffer) {
-                Ok(_) => {
-                    if read_buffer != expected_pattern {
-                        return Ok(false);
-                    }
-                }
-                Err(_) => return Ok(false),
-            }
+fn describe_verify(verify: Option<VerifyMode>) -> String {
+    match verify {
+        None => "none".to_string(),
+        Some(VerifyMode::Sample { count, strategy }) => format!("{} sampled blocks ({:?})", count, strategy),
+        Some(VerifyMode::Percent { percent, .. }) => format!("{}% of blocks, seeded", percent),
+        Some(VerifyMode::Full) => "read back everything written".to_string(),
+        Some(VerifyMode::Hash) => "re-read and compare BLAKE3 digests".to_string(),
+    }
+}
+
+/// Everything about the target in one box right above the y/N prompt, so
+/// a wrong disk is caught by its model, bus or mount state
+fn print_preflight_summary(
+    device: &DeviceInfo,
+    named_as: &str,
+    method: &str,
+    sync_mode: SyncMode,
+    verify: Option<VerifyMode>,
+    estimate: &DurationEstimate,
+    devices: &dyn DeviceProvider,
+) {
+    if verbosity() == 0 {
+        return;
+    }
+    let identity = devices.identity(device);
+    let holders = devices.holders(&device.name);
+    let system = system_disks();
+    let unknown = || "unknown".to_string();
+    let mut path = device.path.display().to_string();
+    if Path::new(named_as) != device.path {
+        path = format!("{} (given as {})", path, named_as);
+    }
+    let system_disk = if system.is_empty() {
+        "unknown (could not resolve /)".to_string()
+    } else if system.contains(&device.name) {
+        "YES - this disk holds the running system".to_string()
+    } else {
+        format!("NOT the system disk ({})", system.join(", "))
+    };
+    let rows = [
+        ("Device", path),
+        ("Model", identity.model.unwrap_or_else(unknown)),
+        ("Serial", identity.serial.unwrap_or_else(unknown)),
+        ("Capacity", format!("{} ({} bytes)", format_size(device.size), device.size)),
+        ("Sectors", format!("{} logical, {} physical", identity.logical_sector_size, identity.physical_sector_size)),
+        ("Attached", format!("{}, {}", device.bus.map_or_else(unknown, |bus| bus.to_string()),
+                             if device.is_removable { "removable" } else { "non-removable" })),
+        ("System disk", system_disk),
+        ("Mounted", if device.is_mounted { "YES".to_string() } else { "no".to_string() }),
+        ("Holders", if holders.is_empty() { "none".to_string() } else { holders.join(", ") }),
+        ("Method", method.to_string()),
+        ("Sync", describe_sync_strategy(sync_mode, None)),
+        ("Verify", describe_verify(verify)),
+        ("Estimate", format!("~{} at {:.1} MB/s ({})", format_duration(estimate.total_secs), estimate.mb_per_s, estimate.source)),
+    ];
+    let width = rows.iter().map(|(_, value)| value.chars().count()).max().unwrap_or(0) + 14;
+    human!("+{}+", "-".repeat(width + 2));
+    for (label, value) in &rows {
+        human!("| {:<12}  {:<w$} |", label, value, w = width - 14);
+    }
+    human!("+{}+", "-".repeat(width + 2));
+}
+
+/// Per-file table printed at the end of `shred`
+fn print_shred_results(results: &[ShredResult]) {
+    println!("\n{:<48} {:>12} {:>12} {:>7} {:<10} Result", "File", "Size", "Overwritten", "Passes", "Verified");
+    println!("{}", "-".repeat(103));
+    for result in results {
+        let verified = match result.verified {
+            Some(true) => "yes",
+            Some(false) => "FAILED",
+            None => "-",
+        };
+        let outcome = match (&result.error, result.kind) {
+            (Some(error), _) => error.as_str(),
+            (None, "file") => "shredded",
+            (None, _) => "removed",
+        };
+        println!("{:<48} {:>12} {:>12} {:>7} {:<10} {}",
+                 result.path.display(), result.size, result.bytes_overwritten, result.passes, verified, outcome);
+    }
+}
+
+/// Print a dry-run plan
+fn print_plan(plan: &ErasePlan) {
+    println!("Dry run for {} (nothing will be written)", plan.device.display());
+    println!("  Device size:   {} ({} bytes)", format_size(plan.device_size), plan.device_size);
+    println!("  Sector size:   {} bytes", plan.sector_size);
+    println!("  Block size:    {} bytes", plan.block_size);
+    println!("  Sync mode:     {:?}", plan.sync_mode);
+    println!("  Direct I/O:    {}", if plan.direct_io { "yes" } else { "no" });
+    println!("  FUA writes:    {}", if plan.fua { "yes" } else { "no" });
+    if let Some(range) = plan.range {
+        println!("  Range:         {} only (PARTIAL)", describe_range(range));
+    }
+    println!("  Method:        {:?}, {} pass(es), {} bytes in total", plan.method, plan.passes.len(), plan.bytes_total);
+    for pass in &plan.passes {
+        println!("    Pass {:<3} {:<12} {} bytes", pass.pass, pass.pattern, pass.bytes);
+    }
+    match plan.verification {
+        Some(mode) => println!("  Verification:  {:?}", mode),
+        None => println!("  Verification:  none"),
+    }
+    println!("  Estimate:      ~{} at {:.1} MB/s ({})",
+             format_duration(plan.estimate.total_secs), plan.estimate.mb_per_s, plan.estimate.source);
+    if plan.blockers.is_empty() {
+        println!("Result: the erase would proceed (after confirmation)");
+    } else {
+        for blocker in &plan.blockers {
+            println!("  Blocked:       {}", blocker);
         }
+        println!("Result: the erase would be refused");
+    }
+}
+
+fn print_batch_results(results: &[BatchResult]) {
+    if verbosity() == 0 {
+        let succeeded = results.iter().filter(|r| r.succeeded()).count();
+        human!("{} of {} devices erased successfully", succeeded, results.len());
+        return;
+    }
+    human!("\n{:<32} {:>10} {:>10} {:<10} {}", "Device", "Size", "Duration", "Verified", "Result");
+    human!("{}", "-".repeat(80));
+    for result in results {
+        let verified = match result.verified {
+            Some(true) => "yes",
+            Some(false) => "FAILED",
+            None => "-",
+        };
+        let outcome = match (&result.error, result.attempted) {
+            (_, false) => "not attempted".to_string(),
+            (Some(error), _) => error.clone(),
+            (None, _) if result.verified == Some(false) => "verification failed".to_string(),
+            (None, _) => "erased".to_string(),
+        };
+        human!("{:<32} {:>10} {:>10} {:<10} {}",
+                 result.device.display(),
+                 size_label(result.size),
+                 format_duration(result.duration_secs),
+                 verified,
+                 outcome);
+    }
+
+    let succeeded = results.iter().filter(|r| r.succeeded()).count();
+    human!("\n{} of {} devices erased successfully", succeeded, results.len());
+}
 
-        Ok(true)
+/// Write the `--report` file ("-" for stdout). Failing here must not hide
+/// how the erase went, so the caller only warns.
+fn write_run_report_to(dest: &str, records: &[RunRecord]) -> io::Result<()> {
+    if dest == "-" {
+        write_run_report(&mut io::stdout().lock(), records)
+    } else {
+        write_run_report(&mut File::create(dest)?, records)
     }
+}
 
-    /// Display device information in a formatted table
-    pub fn display_devices(&self, devices: &[DeviceInfo]) {
-        println!("\nAvailable storage devices:\n");
-        println!("{:<20} {:<15} {:<15} {:<12} {:<10}", 
-                 "Device", "Name", "Size (MB)", "Removable", "Mounted");
-        println!("{}", "-".repeat(70));
+/// --kiosk: the per-device settings from the command line, then the
+/// --report and the exit status once it is stopped
+fn start_kiosk(
+    eraser: &mut SecureEraser,
+    matches: &clap::ArgMatches,
+    config: &Config,
+    filter: &DeviceFilter,
+    pattern: WipePattern,
+    verify: Option<VerifyMode>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let options = KioskOptions {
+        pattern,
+        verify,
+        sync_mode: matches.get_one::<String>("sync-mode").unwrap().parse().map_err(usage_error)?,
+        direct_io: matches.get_one::<String>("direct-io").unwrap().parse().map_err(usage_error)?,
+        auto_tune: matches.get_flag("auto-tune"),
+        keep_going: matches.get_flag("keep-going"),
+        stamp_key: matches.get_one::<String>("stamp").map(|key| key.as_bytes().to_vec()),
+        settle: std::time::Duration::from_secs(*matches.get_one::<u64>("settle-delay").unwrap()),
+        init_table: init_table_arg(matches),
+        format_after: format_after_arg(matches).map_err(usage_error)?,
+        report_csv: matches.get_one::<String>("report-csv").cloned(),
+        metrics: matches.get_one::<String>("metrics-listen").map(|addr| Metrics::listen(addr)).transpose()?,
+    };
+    install_interrupt_handler();
+    let records = run_kiosk(eraser, config, filter, &options)?;
 
-        for device in devices {
-            println!("{:<20} {:<15} {:<15} {:<12} {:<10}",
-                     device.path.display(),
-                     device.name,
-                     device.size / (1024 * 1024),
-                     if device.is_removable { "Yes" } else { "No" },
-                     if device.is_mounted { "Yes" } else { "No" });
+    if let Some(dest) = matches.get_one::<String>("report") {
+        if let Err(e) = write_run_report_to(dest, &records) {
+            warn!("Warning: could not write report {}: {}", dest, e);
         }
-        println!();
     }
+    let failed = records.iter().filter(|r| !r.succeeded).count();
+    if failed > 0 {
+        return Err(exit_error(EXIT_BATCH_PARTIAL, format!("{} of {} devices failed", failed, records.len())));
+    }
+    Ok(())
 }
 
+/// --daemon: the job defaults and the socket from the command line
 #[cfg(unix)]
-fn is_block_device(metadata: &std::fs::Metadata) -> bool {
-    use std::os::unix::fs::MetadataExt;
-    (metadata.mode() & libc::S_IFMT) == libc::S_IFBLK
+fn start_daemon(
+    matches: &clap::ArgMatches,
+    config: Config,
+    pattern: WipePattern,
+    verify: Option<VerifyMode>,
+    block_size: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let socket = PathBuf::from(matches.get_one::<String>("socket").unwrap());
+    let defaults = JobDefaults {
+        pattern,
+        verify,
+        sync_mode: matches.get_one::<String>("sync-mode").unwrap().parse().map_err(usage_error)?,
+        direct_io: matches.get_one::<String>("direct-io").unwrap().parse().map_err(usage_error)?,
+        block_size,
+        write_timeout: write_timeout_args(matches).map_err(usage_error)?.0,
+    };
+    let group = matches.get_one::<String>("socket-group").map(|name| group_id(name)).transpose()?;
+    let metrics = matches.get_one::<String>("metrics-listen").map(|addr| Metrics::listen(addr)).transpose()?;
+    install_interrupt_handler();
+    Ok(run_daemon(&socket, group, matches.get_flag("dbus"), config, defaults, metrics)?)
 }
 
-#[cfg(windows)]
-fn is_block_device(_metadata: &std::fs::Metadata) -> bool {
-    // On Windows, we'd need different logic to determine if it's a block device
-    true
+#[cfg(not(unix))]
+fn start_daemon(
+    _matches: &clap::ArgMatches,
+    _config: Config,
+    _pattern: WipePattern,
+    _verify: Option<VerifyMode>,
+    _block_size: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err(usage_error("--daemon needs Unix domain sockets and is only available on Unix"))
+}
+
+/// Numeric id of a group given by name or number
+#[cfg(unix)]
+fn group_id(name: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    let c_name = std::ffi::CString::new(name).map_err(|_| usage_error(format!("Invalid group name: {:?}", name)))?;
+    let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(usage_error(format!("No such group: {}", name)));
+    }
+    Ok(unsafe { (*entry).gr_gid })
 }
 
 fn confirm_action(message: &str) -> bool {
-    println!("{} [y/N]: ", message);
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap_or(0);
+    human!("{} [y/N]: ", message);
+    let input = read_confirmation_line();
     matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let matches = Command::new("secure-eraser")
+/// Ask the user to type one of `accepted` (the device's base name or its
+/// serial) exactly; anything else cancels
+fn confirm_by_name(message: &str, accepted: &[String]) -> bool {
+    human!("{}", message);
+    human!("Type '{}' to confirm: ", accepted.join("' or '"));
+    let input = read_confirmation_line();
+    let input = input.trim();
+    if accepted.iter().any(|name| name == input) {
+        return true;
+    }
+    human!("'{}' does not match.", input);
+    false
+}
+
+/// One line from the controlling terminal, so a redirected stdin (`yes |`,
+/// a script's heredoc) can't answer a destructive prompt. Falls back to
+/// stdin when there is no terminal.
+fn read_confirmation_line() -> String {
+    #[cfg(unix)]
+    let tty = File::open("/dev/tty");
+    #[cfg(windows)]
+    let tty = File::open("CONIN$");
+
+    let mut input = String::new();
+    match tty {
+        Ok(tty) => BufReader::new(tty).read_line(&mut input).unwrap_or(0),
+        Err(_) => io::stdin().read_line(&mut input).unwrap_or(0),
+    };
+    input
+}
+
+/// Whether a controlling terminal is available to answer prompts
+fn has_terminal() -> bool {
+    #[cfg(unix)]
+    return File::open("/dev/tty").is_ok();
+    #[cfg(windows)]
+    return File::open("CONIN$").is_ok();
+}
+
+/// Device entries from a manifest file or stdin (`-`): one per line, blank
+/// lines ignored, `#` starts a comment
+fn read_device_list(source: &str) -> io::Result<Vec<String>> {
+    let reader: Box<dyn BufRead> = if source == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(source)?))
+    };
+
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let entry = line.split('#').next().unwrap_or("").trim();
+        if !entry.is_empty() {
+            entries.push(entry.to_string());
+        }
+    }
+    Ok(entries)
+}
+
+/// --config (or MEMERASE_CONFIG) is needed before the real parse, since the
+/// file supplies that parse's defaults
+fn config_arg() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|a| a.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    std::env::var_os("MEMERASE_CONFIG").filter(|path| !path.is_empty()).map(PathBuf::from)
+}
+
+/// Install the config file's values as the command line's defaults
+fn apply_config(config: &Config, mut cli: Command) -> Command {
+    for (id, value) in [
+        ("pattern", &config.pattern),
+        ("verify", &config.verify),
+        ("block-size", &config.block_size),
+        ("sync-mode", &config.sync_mode),
+    ] {
+        if let Some(value) = value {
+            cli = cli.mut_arg(id, |arg| arg.default_value(value.clone()));
+        }
+    }
+    cli
+}
+
+/// --dump-config: the settings a run would use, and where each came from
+fn dump_config(config: &Config, matches: &clap::ArgMatches) {
+    use clap::parser::ValueSource;
+
+    let cli = build_cli();
+    let source = |id: &str, key: &str| match matches.value_source(id) {
+        Some(ValueSource::CommandLine) => "command line".to_string(),
+        Some(ValueSource::EnvVariable) => {
+            let var = cli.get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .and_then(|arg| arg.get_env())
+                .map(|var| var.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            format!("environment {}", var)
+        }
+        _ => config.source(key),
+    };
+    let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+    let arg = |id: &str, key: &str| {
+        let value = matches.get_one::<String>(id).map_or("none".to_string(), |v| quote(v));
+        (format!("{} = {}", key, value), source(id, key))
+    };
+    let mut lines = vec![arg("pattern", "pattern"), arg("block-size", "block_size"), arg("sync-mode", "sync_mode")];
+    if matches.get_flag("no-verify") {
+        lines.push(("verify = \"none\"".to_string(), source("no-verify", "verify")));
+    } else {
+        lines.push(arg("verify", "verify"));
+    }
+    lines.push(arg("report", "report"));
+    lines.push(arg("report-csv", "report_csv"));
+    lines.push((format!("assume_yes = {}", matches.get_flag("yes")), source("yes", "assume_yes")));
+    let report_dir = config.report_dir.as_ref().map_or("none".to_string(), |dir| quote(&dir.display().to_string()));
+    lines.push((format!("report_dir = {}", report_dir), config.source("report_dir")));
+    let protected: Vec<String> = config.protected.iter().map(|entry| quote(entry)).collect();
+    lines.push((format!("protected = [{}]", protected.join(", ")), config.source("protected")));
+
+    let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+    for (line, source) in lines {
+        println!("{:<width$}  # {}", line, source, width = width);
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(()) => exit_with(0, None),
+        Err(e) => {
+            error!("Error: {}", e);
+            exit_with(exit_code(e.as_ref()), Some(e.to_string()))
+        }
+    }
+}
+
+/// Bash and fish can call back into the tool while completing, so there
+/// --device offers the block devices list_devices finds; other shells
+/// complete it as a path.
+const BASH_DEVICE_COMPLETION: &str = r#"
+_secure_eraser_devices() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [[ "$prev" == "-d" || "$prev" == "--device" ]]; then
+        COMPREPLY=($(compgen -W "$(secure-eraser complete-devices 2>/dev/null)" -- "$cur"))
+        return 0
+    fi
+    _secure__eraser "$@"
+}
+complete -F _secure_eraser_devices -o nosort -o bashdefault -o default secure-eraser
+"#;
+
+const FISH_DEVICE_COMPLETION: &str = r#"
+complete -c secure-eraser -s d -l device -f -a "(secure-eraser complete-devices 2>/dev/null)"
+"#;
+
+fn print_completions(shell: clap_complete::Shell) {
+    let mut stdout = io::stdout();
+    clap_complete::generate(shell, &mut build_cli(), "secure-eraser", &mut stdout);
+    match shell {
+        clap_complete::Shell::Bash => print!("{}", BASH_DEVICE_COMPLETION),
+        clap_complete::Shell::Fish => print!("{}", FISH_DEVICE_COMPLETION),
+        _ => {}
+    }
+}
+
+/// The whole command line. Built in one place so parsing and `completions`
+/// see the same flags.
+fn build_cli() -> Command {
+    Command::new("secure-eraser")
         .version("1.0.0")
+        .after_help(EXIT_CODES_HELP)
         .author("Rust Implementation")
         .about("Secure disk eraser with multiple wipe patterns")
         .arg(Arg::new("list")
@@ -418,86 +1039,1620 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .long("list")
             .help("List available devices")
             .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("kiosk")
+            .long("kiosk")
+            .help("Erase every removable device attached from now on, without prompting, until interrupted")
+            .requires_all(["yes", "removable-only"])
+            .conflicts_with("targets")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("daemon")
+            .long("daemon")
+            .help("Serve wipe jobs over a Unix socket (line-delimited JSON) until SIGTERM")
+            .conflicts_with_all(["targets", "kiosk"])
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("socket")
+            .long("socket")
+            .value_name("PATH")
+            .help("Socket the --daemon listens on")
+            .value_hint(clap::ValueHint::FilePath)
+            .default_value(DEFAULT_SOCKET))
+        .arg(Arg::new("dbus")
+            .long("dbus")
+            .help("Also offer the --daemon's jobs on the system bus as org.memerase.Eraser1")
+            .requires("daemon")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("socket-group")
+            .long("socket-group")
+            .value_name("GROUP")
+            .help("Let members of GROUP use the --daemon socket as well as root")
+            .requires("daemon"))
+        .arg(Arg::new("metrics-listen")
+            .long("metrics-listen")
+            .value_name("ADDR:PORT")
+            .help("In --kiosk or --daemon mode, serve Prometheus metrics over HTTP at /metrics on ADDR:PORT"))
+        .arg(Arg::new("settle-delay")
+            .long("settle-delay")
+            .value_name("SECS")
+            .help("In --kiosk mode, wait SECS after a device appears before erasing it")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("5"))
+        .arg(Arg::new("removable-only")
+            .long("removable-only")
+            .help("Only list (or offer in the picker) removable devices")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("min-size")
+            .long("min-size")
+            .value_name("SIZE")
+            .help("Only list devices of at least SIZE, e.g. 4G"))
+        .arg(Arg::new("max-size")
+            .long("max-size")
+            .value_name("SIZE")
+            .help("Only list devices of at most SIZE, e.g. 256G"))
+        .arg(Arg::new("bus")
+            .long("bus")
+            .value_name("BUS")
+            .help("Only list devices on this bus")
+            .value_parser(["usb", "sata", "nvme"])
+            .ignore_case(true))
+        .arg(Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .help("Only print errors and a one-line result; no progress bar")
+            .conflicts_with("verbose")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("config")
+            .long("config")
+            .env("MEMERASE_CONFIG")
+            .value_name("PATH")
+            .value_hint(clap::ValueHint::FilePath)
+            .help(format!("Read defaults from PATH instead of {} and ~/.config/memerase/config.toml", SYSTEM_CONFIG)))
+        .arg(Arg::new("dump-config")
+            .long("dump-config")
+            .help("Print the effective settings and where each came from, then exit")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("report")
+            .long("report")
+            .env("MEMERASE_REPORT")
+            .value_name("PATH")
+            .help("Write a JSON record of the run to PATH ('-' for stdout), even if it fails"))
+        .arg(Arg::new("report-csv")
+            .long("report-csv")
+            .env("MEMERASE_REPORT_CSV")
+            .value_name("PATH")
+            .help("Append one CSV row per device to PATH, writing a header if the file is new"))
+        .arg(Arg::new("status-file")
+            .long("status-file")
+            .value_name("PATH")
+            .help("Keep PATH updated with the erase's progress as JSON, every few seconds, for monitors that poll it")
+            .conflicts_with_all(["kiosk", "daemon"]))
+        .arg(Arg::new("certificate")
+            .long("certificate")
+            .value_name("PATH")
+            .help("Write a signed certificate of erasure to PATH once the run completes")
+            .requires("signing-key"))
+        .arg(Arg::new("signing-key")
+            .long("signing-key")
+            .value_name("PATH")
+            .help("Ed25519 secret key for --certificate (see generate-key)"))
+        .arg(Arg::new("cert-field")
+            .long("cert-field")
+            .value_name("KEY=VALUE")
+            .help("Add an operator field to the certificate (repeatable)")
+            .action(clap::ArgAction::Append))
+        .arg(Arg::new("certify-failures")
+            .long("certify-failures")
+            .help("Sign a certificate even when the erase failed")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("pre-cmd")
+            .long("pre-cmd")
+            .value_name("CMD")
+            .help("Run CMD through the shell before erasing each device; a non-zero exit skips the device")
+            .value_hint(clap::ValueHint::CommandString))
+        .arg(Arg::new("post-cmd")
+            .long("post-cmd")
+            .value_name("CMD")
+            .help("Run CMD through the shell after each device, with MEMERASE_RESULT set")
+            .value_hint(clap::ValueHint::CommandString))
+        .arg(Arg::new("notify-url")
+            .long("notify-url")
+            .value_name("URL")
+            .help(format!("POST a JSON summary of the run to URL when it ends (bearer token from {})", NOTIFY_TOKEN_ENV))
+            .value_parser(|url: &str| match url.starts_with("http://") || url.starts_with("https://") {
+                true => Ok(url.to_string()),
+                false => Err("expected an http:// or https:// URL".to_string()),
+            }))
+        .arg(Arg::new("notify-on")
+            .long("notify-on")
+            .value_name("WHEN")
+            .help("Which runs to notify about")
+            .value_parser(["success", "failure", "always"])
+            .default_value("always")
+            .requires("notify-url"))
+        .arg(Arg::new("notify-timeout")
+            .long("notify-timeout")
+            .value_name("SECS")
+            .help("Give up on a notification attempt after SECS")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .default_value("10")
+            .requires("notify-url"))
+        .arg(Arg::new("log-file")
+            .long("log-file")
+            .env("MEMERASE_LOG_FILE")
+            .value_name("PATH")
+            .help("Append a timestamped transcript of the run to PATH"))
+        .arg(Arg::new("log-target")
+            .long("log-target")
+            .env("MEMERASE_LOG_TARGET")
+            .value_name("TARGET")
+            .help("Also log erase start, pass completion, verification and errors to stderr, syslog or journald")
+            .value_parser(clap::value_parser!(LogTarget)))
+        .arg(Arg::new("audit-log")
+            .long("audit-log")
+            .env("MEMERASE_AUDIT_LOG")
+            .value_name("PATH")
+            .help("Append hash-chained audit entries to PATH [default for root: /var/log/secure-eraser/audit.log]"))
+        .arg(Arg::new("progress")
+            .long("progress")
+            .env("MEMERASE_PROGRESS")
+            .value_name("MODE")
+            .help("Progress output: bar, or json for newline-delimited events on stdout")
+            .value_parser(["bar", "json"])
+            .default_value("bar"))
+        .arg(Arg::new("no-color")
+            .long("no-color")
+            .help("Plain progress bar without color (also set by a non-empty NO_COLOR)")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("verbose")
+            .long("verbose")
+            .help("Show open flags, sector sizes, sync strategy and retries (repeat for more)")
+            .action(clap::ArgAction::Count))
         .arg(Arg::new("device")
             .short('d')
             .long("device")
             .value_name("PATH")
-            .help("Device to erase")
-            .required_unless_present("list"))
+            .help("Device to erase (repeat to erase several in turn)")
+            .value_hint(clap::ValueHint::FilePath)
+            .action(clap::ArgAction::Append))
+        .arg(Arg::new("devices-from")
+            .long("devices-from")
+            .value_name("FILE")
+            .help("Read device paths or serials, one per line, from FILE ('-' for stdin)"))
+        .arg(Arg::new("serial")
+            .long("serial")
+            .value_name("SERIAL")
+            .help("Erase the one device with this serial number; with --device, both must name the same disk")
+            .conflicts_with("devices-from"))
+        .group(ArgGroup::new("targets")
+            .args(["device", "devices-from", "serial"])
+            .multiple(true))
+        .arg(Arg::new("yes")
+            .short('y')
+            .long("yes")
+            .env("MEMERASE_ASSUME_YES")
+            .visible_alias("assume-yes")
+            .help("Skip the confirmation prompts (requires an explicit --device or --devices-from)")
+            .value_parser(clap::builder::BoolishValueParser::new())
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("allow-file")
+            .long("allow-file")
+            .help("Allow a regular file or disk image as the target")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("dry-run")
+            .long("dry-run")
+            .help("Run every preflight check and show the plan without writing")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("strict-confirm")
+            .long("strict-confirm")
+            .value_name("BOOL")
+            .help("Confirm by typing the device name or serial instead of y/N")
+            .num_args(0..=1)
+            .default_value("true")
+            .default_missing_value("true")
+            .value_parser(clap::value_parser!(bool)))
+        .arg(Arg::new("method")
+            .long("method")
+            .value_name("METHOD")
+            .help("full overwrites every byte; quick only destroys partition tables and filesystem headers, leaving the data recoverable")
+            .value_parser(["full", "quick"])
+            .conflicts_with_all(["kiosk", "daemon"])
+            .default_value("full"))
         .arg(Arg::new("pattern")
             .short('p')
             .long("pattern")
+            .env("MEMERASE_PATTERN")
             .value_name("TYPE")
-            .help("Wipe pattern: zeros, ones, random, dod3, gutmann35")
+            .help("Wipe pattern")
+            .value_parser(PATTERN_NAMES)
+            .ignore_case(true)
             .default_value("zeros"))
         .arg(Arg::new("verify")
             .short('v')
             .long("verify")
-            .help("Verify final pass")
+            .env("MEMERASE_VERIFY")
+            .value_name("MODE")
+            .help("Verify final pass: sample (default), full, hash")
+            .num_args(0..=1)
+            .default_missing_value("sample"))
+        .arg(Arg::new("no-verify")
+            .long("no-verify")
+            .env("MEMERASE_NO_VERIFY")
+            .help("Skip verification, even if the config file turns it on")
+            .conflicts_with("verify")
+            .value_parser(clap::builder::BoolishValueParser::new())
             .action(clap::ArgAction::SetTrue))
-        .get_matches();
+        .arg(Arg::new("verify-samples")
+            .long("verify-samples")
+            .value_name("N")
+            .help("Number of blocks to read back in sample verification")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("100"))
+        .arg(Arg::new("verify-strategy")
+            .long("verify-strategy")
+            .value_name("STRATEGY")
+            .help("Where samples are read: random, head, spread, edges")
+            .default_value("random"))
+        .arg(Arg::new("scan")
+            .long("scan")
+            .value_name("MODE")
+            .help("Read-only scan for residual data: sample (default), full")
+            .num_args(0..=1)
+            .default_missing_value("sample"))
+        .arg(Arg::new("json")
+            .long("json")
+            .help("Print results as JSON")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("verify-percent")
+            .long("verify-percent")
+            .value_name("0-100")
+            .help("Verify randomly chosen blocks covering at least this percentage (100 = full, 0 = skip)")
+            .value_parser(clap::value_parser!(u8).range(0..=100)))
+        .arg(Arg::new("verify-seed")
+            .long("verify-seed")
+            .value_name("SEED")
+            .help("Seed for --verify-percent block selection (random if omitted)")
+            .value_parser(clap::value_parser!(u64)))
+        .arg(Arg::new("sync-mode")
+            .long("sync-mode")
+            .env("MEMERASE_SYNC_MODE")
+            .value_name("MODE")
+            .help("When to flush writes to the device: o_sync, per-pass, periodic:<MB>, none")
+            .default_value("per-pass"))
+        .arg(Arg::new("direct-io")
+            .long("direct-io")
+            .value_name("MODE")
+            .help("Bypass the page cache with O_DIRECT: auto (block devices), on, off")
+            .default_value("auto"))
+        .arg(Arg::new("block-size")
+            .long("block-size")
+            .env("MEMERASE_BLOCK_SIZE")
+            .value_name("SIZE")
+            .help("Size of each write, e.g. 512K, 4M, 16M (power of two)")
+            .default_value("1M"))
+        .arg(Arg::new("seed")
+            .long("seed")
+            .value_name("SEED")
+            .help("Seed the random data with the seed a report recorded (for tests)")
+            .value_parser(clap::value_parser!(u64))
+            .hide(true))
+        .arg(Arg::new("auto-tune")
+            .long("auto-tune")
+            .help("Benchmark several block sizes at the start of the device and use the fastest")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("io-backend")
+            .long("io-backend")
+            .value_name("BACKEND")
+            .help("Write backend: sync (default) or uring (Linux io_uring)")
+            .default_value("sync")
+            .value_parser(clap::value_parser!(IoBackend)))
+        .arg(Arg::new("queue-depth")
+            .long("queue-depth")
+            .value_name("N")
+            .help("Writes kept in flight by the uring backend")
+            .default_value("32")
+            .value_parser(clap::value_parser!(u32)))
+        .arg(Arg::new("max-rate")
+            .long("max-rate")
+            .value_name("MB/s")
+            .help("Throttle writes to this rate, per device")
+            .value_parser(clap::value_parser!(f64)))
+        .arg(Arg::new("idle-io")
+            .long("idle-io")
+            .help("Run in the background: idle I/O class and lowest CPU priority")
+            .action(clap::ArgAction::SetTrue)
+            .conflicts_with("io-class"))
+        .arg(Arg::new("io-class")
+            .long("io-class")
+            .value_name("CLASS")
+            .help("I/O scheduling class: idle, best-effort, realtime")
+            .value_parser(clap::value_parser!(IoClass)))
+        .arg(Arg::new("io-level")
+            .long("io-level")
+            .value_name("0-7")
+            .help("Priority level within the best-effort or realtime class")
+            .default_value("4")
+            .value_parser(clap::value_parser!(u8).range(0..=7)))
+        .arg(Arg::new("writeback")
+            .long("writeback")
+            .value_name("MB")
+            .help("Start writeback of buffered data every MB written, 0 to disable")
+            .default_value("32")
+            .value_parser(clap::value_parser!(u64)))
+        .arg(Arg::new("max-dirty")
+            .long("max-dirty")
+            .value_name("MB")
+            .help("Wait for writeback when more than MB is outstanding")
+            .default_value("256")
+            .value_parser(clap::value_parser!(u64)))
+        .arg(Arg::new("no-estimate-burst")
+            .long("no-estimate-burst")
+            .help("Estimate the duration from the device class instead of a timed write")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("write-batch")
+            .long("write-batch")
+            .value_name("N")
+            .help("Blocks per vectored write for fill patterns (1-1024)")
+            .default_value("8")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("offset")
+            .long("offset")
+            .value_name("SIZE")
+            .help("Erase (and verify) only from SIZE into the device, e.g. 1G")
+            .conflicts_with_all(["checkpoint", "resume", "auto-tune", "stamp", "scan", "verify-only", "kiosk", "daemon"]))
+        .arg(Arg::new("length")
+            .long("length")
+            .value_name("SIZE")
+            .help("Erase (and verify) only SIZE bytes, from --offset (default 0) on")
+            .conflicts_with_all(["checkpoint", "resume", "auto-tune", "stamp", "scan", "verify-only", "kiosk", "daemon"]))
+        .arg(Arg::new("init-table")
+            .long("init-table")
+            .value_name("TYPE")
+            .help("After a verified erase, write an empty partition table")
+            .value_parser(["gpt", "mbr", "none"])
+            .conflicts_with_all(["stamp", "offset", "length"])
+            .default_value("none"))
+        .arg(Arg::new("format-after")
+            .long("format-after")
+            .value_name("FS")
+            .help("After a verified erase of removable media, create an empty filesystem over the whole device")
+            .value_parser(["fat32", "exfat"])
+            .conflicts_with_all(["init-table", "stamp", "offset", "length", "daemon"]))
+        .arg(Arg::new("label")
+            .long("label")
+            .value_name("LABEL")
+            .help("Volume label for --format-after")
+            .requires("format-after"))
+        .arg(Arg::new("badblocks-out")
+            .long("badblocks-out")
+            .value_name("PATH")
+            .help("Write the ranges that failed to write, read back or verify to PATH, one 'offset length kind' per line")
+            .conflicts_with_all(["kiosk", "daemon"]))
+        .arg(Arg::new("write-retries")
+            .long("write-retries")
+            .value_name("N")
+            .help("Attempts at each sector of a block that fails to write before it is skipped as bad; 0 stops at the first write error")
+            .value_parser(clap::value_parser!(u32))
+            .default_value(DEFAULT_WRITE_RETRIES.to_string()))
+        .arg(Arg::new("write-timeout")
+            .long("write-timeout")
+            .value_name("SECS")
+            .help("Warn with the stuck offset when a single write has not returned after SECS seconds; 0 disables the watchdog")
+            .value_parser(clap::value_parser!(u64))
+            .default_value(DEFAULT_WRITE_TIMEOUT.to_string()))
+        .arg(Arg::new("write-abort")
+            .long("write-abort")
+            .value_name("SECS")
+            .help("Give the device up as not responding (exit status 11) once a write has been stuck for SECS seconds; 0 waits forever")
+            .value_parser(clap::value_parser!(u64))
+            .default_value(DEFAULT_WRITE_ABORT.to_string()))
+        .arg(Arg::new("pre-hash")
+            .long("pre-hash")
+            .help("Before the first write, record SHA-256 digests of 64 scattered 1 MiB regions in the report")
+            .conflicts_with_all(["resume", "verify-only"])
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("no-tail-first")
+            .long("no-tail-first")
+            .help("Write pass 1 strictly in order, instead of overwriting the last 1 MiB (backup GPT) right after the first")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("skip-matching")
+            .long("skip-matching")
+            .help("Read fill-pattern blocks first and skip writing those that already match")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("fua")
+            .long("fua")
+            .help("Force each write to the medium (FUA / write-through); much slower")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("disable-write-cache")
+            .long("disable-write-cache")
+            .help("Disable the drive's volatile write cache during the erase (Linux)")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("gen-threads")
+            .long("gen-threads")
+            .value_name("N")
+            .help("Threads generating random data (default: min(4, cores))")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("fail-fast")
+            .long("fail-fast")
+            .help("Stop a multi-device batch at the first device that fails")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("keep-going")
+            .long("keep-going")
+            .help("Only warn when verification fails instead of exiting with an error")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("checkpoint")
+            .long("checkpoint")
+            .value_name("PATH")
+            .help("Periodically save progress to PATH so an interrupted erase can be resumed"))
+        .arg(Arg::new("checkpoint-interval")
+            .long("checkpoint-interval")
+            .value_name("SECS")
+            .help("Seconds between checkpoint saves")
+            .default_value("60")
+            .value_parser(clap::value_parser!(u64).range(1..)))
+        .arg(Arg::new("resume")
+            .long("resume")
+            .value_name("PATH")
+            .help("Continue the erase recorded in checkpoint PATH on the same device"))
+        .arg(Arg::new("stamp")
+            .long("stamp")
+            .value_name("KEY")
+            .help("Write an authenticated erase record to the final sector, keyed with KEY"))
+        .arg(Arg::new("verify-only")
+            .long("verify-only")
+            .help("Only verify the device against --pattern; never writes")
+            .action(clap::ArgAction::SetTrue))
+        .subcommand_negates_reqs(true)
+        .subcommand(Command::new("check-stamp")
+            .about("Read and validate the erase record written by --stamp")
+            .arg(Arg::new("device")
+                .short('d')
+                .long("device")
+                .value_name("PATH")
+                .help("Device to check")
+                .required(true))
+            .arg(Arg::new("key")
+                .long("key")
+                .value_name("KEY")
+                .help("Key the stamp was written with")
+                .required(true)))
+        .subcommand(Command::new("completions")
+            .about("Print a shell completion script")
+            .arg(Arg::new("shell")
+                .value_name("SHELL")
+                .help("bash, zsh, fish, powershell or elvish")
+                .required(true)
+                .value_parser(clap::value_parser!(clap_complete::Shell))))
+        .subcommand(Command::new("complete-devices")
+            .about("List device paths for shell completion")
+            .hide(true))
+        .subcommand(Command::new("generate-key")
+            .about("Create an Ed25519 key pair for signing certificates")
+            .arg(Arg::new("path")
+                .value_name("PATH")
+                .help("Where to write the secret key; the public key goes to PATH.pub")
+                .required(true)))
+        .subcommand(Command::new("verify-certificate")
+            .about("Check the signature of a certificate written by --certificate")
+            .arg(Arg::new("path")
+                .value_name("PATH")
+                .help("Certificate to check")
+                .required(true))
+            .arg(Arg::new("public-key")
+                .long("public-key")
+                .value_name("PATH")
+                .help("Require the certificate to be signed by this public key")))
+        .subcommand(Command::new("audit")
+            .about("Inspect an audit log written by --audit-log")
+            .subcommand_required(true)
+            .subcommand(Command::new("verify")
+                .about("Check that the hash chain of an audit log is intact")
+                .arg(Arg::new("path")
+                    .value_name("PATH")
+                    .help("Audit log to check")
+                    .required(true))))
+        .subcommand(Command::new("wipe-free")
+            .about("Overwrite the free space of a mounted filesystem")
+            .arg(Arg::new("path")
+                .long("path")
+                .value_name("DIR")
+                .help("Directory on the filesystem to scrub")
+                .required(true))
+            .arg(Arg::new("pattern")
+                .short('p')
+                .long("pattern")
+                .value_name("TYPE")
+                .help("Wipe pattern")
+                .value_parser(PATTERN_NAMES)
+                .ignore_case(true)
+                .default_value("zeros"))
+            .arg(Arg::new("headroom")
+                .long("headroom")
+                .value_name("MB")
+                .help("Space released as soon as the filesystem is full")
+                .default_value("64")
+                .value_parser(clap::value_parser!(u64)))
+            .arg(Arg::new("file-size")
+                .long("file-size")
+                .value_name("MB")
+                .help("Size of each filler file")
+                .default_value("1024")
+                .value_parser(clap::value_parser!(u64).range(1..))))
+        .subcommand(Command::new("shred")
+            .about("Overwrite files in place, then truncate and delete them")
+            .arg(Arg::new("paths")
+                .value_name("PATH")
+                .help("Files to shred")
+                .num_args(1..)
+                .required(true))
+            .arg(Arg::new("pattern")
+                .short('p')
+                .long("pattern")
+                .value_name("TYPE")
+                .help("Wipe pattern")
+                .value_parser(PATTERN_NAMES)
+                .ignore_case(true)
+                .default_value("zeros"))
+            .arg(Arg::new("verify")
+                .short('v')
+                .long("verify")
+                .value_name("MODE")
+                .help("Read back the final pass before deleting: sample, full, hash")
+                .num_args(0..=1)
+                .default_missing_value("sample"))
+            .arg(Arg::new("recursive")
+                .short('r')
+                .long("recursive")
+                .help("Shred directories: every file below them, then the directories")
+                .action(clap::ArgAction::SetTrue))
+            .arg(Arg::new("sparse-mode")
+                .long("sparse-mode")
+                .value_name("MODE")
+                .help("Sparse files: allocated-only (mapped extents) or full (logical length)")
+                .default_value("full")
+                .value_parser(clap::value_parser!(SparseMode)))
+            .arg(Arg::new("no-rename")
+                .long("no-rename")
+                .help("Keep the file name and timestamps; only overwrite and delete")
+                .action(clap::ArgAction::SetTrue))
+            .arg(Arg::new("one-file-system")
+                .long("one-file-system")
+                .help("With -r, don't descend into other mounted filesystems")
+                .action(clap::ArgAction::SetTrue))
+            .arg(Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Don't ask for confirmation")
+                .action(clap::ArgAction::SetTrue)))
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    // A broken config is reported once logging is up; completion ignores it
+    let config = Config::load(config_arg().as_deref());
+    let matches = match &config {
+        Ok(config) => apply_config(config, build_cli()),
+        Err(_) => build_cli(),
+    }.get_matches();
+
+    // Completion runs from shell startup and tab presses: no logging, no audit
+    if let Some(("completions", sub)) = matches.subcommand() {
+        let shell = *sub.get_one::<clap_complete::Shell>("shell").unwrap();
+        print_completions(shell);
+        return Ok(());
+    }
+    if let Some(("complete-devices", _)) = matches.subcommand() {
+        for device in SecureEraser::new().list_devices()? {
+            println!("{}", device.path.display());
+        }
+        return Ok(());
+    }
+
+    let level = if matches.get_flag("quiet") { 0 } else { 1 + matches.get_count("verbose") };
+    VERBOSITY.store(level, std::sync::atomic::Ordering::Relaxed);
+    let no_color = matches.get_flag("no-color") || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    COLOR.store(!no_color, std::sync::atomic::Ordering::Relaxed);
+    JSON_PROGRESS.store(matches.get_one::<String>("progress").unwrap() == "json", std::sync::atomic::Ordering::Relaxed);
+    let log_file = matches.get_one::<String>("log-file").map(|path| open_log_file(Path::new(path))).transpose()?;
+    init_logging(level, log_file);
+    let config = config.map_err(usage_error)?;
+    for warning in config.warnings() {
+        warn!("{}", warning);
+    }
+    if matches.get_flag("dump-config") {
+        dump_config(&config, &matches);
+        return Ok(());
+    }
+    tracing::info!(
+        target: "transcript",
+        "secure-eraser {} started: {}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::args().collect::<Vec<_>>().join(" ")
+    );
+
+    // Every invocation is audited, except checking the audit log itself
+    if !matches!(matches.subcommand(), Some(("audit", _))) {
+        let path = matches.get_one::<String>("audit-log").map(PathBuf::from);
+        #[cfg(unix)]
+        let path = path.or_else(|| (unsafe { libc::geteuid() } == 0).then(|| PathBuf::from(DEFAULT_AUDIT_LOG)));
+        if let Some(path) = path {
+            open_audit_log(&path);
+        }
+        audit(serde_json::json!({
+            "event": "invocation",
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "user": invoking_user(),
+            "args": std::env::args().collect::<Vec<_>>(),
+        }));
+    }
+
+    if let Some(&target) = matches.get_one::<LogTarget>("log-target") {
+        set_log_target(target);
+    }
+
+    let mut eraser = match matches.get_one::<u64>("seed") {
+        Some(&seed) => SecureEraser::with_seed(seed),
+        None => SecureEraser::new(),
+    };
+    let block_size = parse_size(matches.get_one::<String>("block-size").unwrap()).map_err(usage_error)?;
+    eraser.set_block_size(usize::try_from(block_size).map_err(|_| usage_error("Block size too large"))?)
+        .map_err(usage_error)?;
+    if let Some(&threads) = matches.get_one::<usize>("gen-threads") {
+        eraser.set_gen_threads(threads);
+    }
+    eraser.set_writeback(
+        *matches.get_one::<u64>("writeback").unwrap(),
+        *matches.get_one::<u64>("max-dirty").unwrap(),
+    );
+    eraser.set_disable_write_cache(matches.get_flag("disable-write-cache"));
+    eraser.set_fua(matches.get_flag("fua"));
+    eraser.set_skip_matching(matches.get_flag("skip-matching"));
+    eraser.set_tail_first(!matches.get_flag("no-tail-first"));
+    eraser.set_pre_hash(matches.get_flag("pre-hash"));
+    eraser.set_write_retries(*matches.get_one::<u32>("write-retries").unwrap());
+    let (warn_after, abort_after) = write_timeout_args(&matches).map_err(usage_error)?;
+    eraser.set_write_timeout(warn_after, abort_after);
+    // The stuck write may never return to fail the erase, so give up here
+    eraser.set_not_responding_hook(Some(Arc::new(|stuck: &NotRespondingError| {
+        #[cfg(unix)]
+        if let Some((fd, saved)) = SAVED_TERMINAL.get() {
+            unsafe { libc::tcsetattr(*fd, libc::TCSANOW, saved) };
+        }
+        exit_with(EXIT_NOT_RESPONDING, Some(stuck.to_string()))
+    })));
+    eraser.set_write_batch(*matches.get_one::<usize>("write-batch").unwrap());
+    eraser.set_max_rate(matches.get_one::<f64>("max-rate").copied());
+    eraser.set_io_backend(
+        *matches.get_one::<IoBackend>("io-backend").unwrap(),
+        *matches.get_one::<u32>("queue-depth").unwrap(),
+    );
+
+    if let Some(("check-stamp", sub)) = matches.subcommand() {
+        let device_path = Path::new(sub.get_one::<String>("device").unwrap());
+        let key = sub.get_one::<String>("key").unwrap();
+
+        let stamp = eraser.read_stamp(device_path)?
+            .ok_or_else(|| format!("No erase stamp found on {}", device_path.display()))?;
+
+        println!("Erase stamp on {}:", device_path.display());
+        println!("  Tool version: {}", stamp.tool_version);
+        println!("  Method:       {}", stamp.method);
+        println!("  Timestamp:    {} (Unix seconds, UTC)", stamp.timestamp);
+        println!("  Passes:       {}", stamp.passes);
+        println!("  Verification: {}", stamp.verification);
+
+        if !stamp.is_authentic(key.as_bytes()) {
+            return Err("Erase stamp MAC does not match: wrong key or tampered record".into());
+        }
+        println!("Stamp is authentic.");
+        return Ok(());
+    }
+
+    if let Some(("audit", sub)) = matches.subcommand() {
+        if let Some(("verify", sub)) = sub.subcommand() {
+            let path = Path::new(sub.get_one::<String>("path").unwrap());
+            let (count, last) = verify_audit_log(path)
+                .map_err(|e| format!("Audit log {} is broken: {}", path.display(), e))?;
+            println!("Audit log {}: {} entries, chain intact", path.display(), count);
+            println!("Last hash: {}", last);
+        }
+        return Ok(());
+    }
+
+    if let Some(("generate-key", sub)) = matches.subcommand() {
+        let path = Path::new(sub.get_one::<String>("path").unwrap());
+        let public = generate_signing_key(path)?;
+        println!("Secret key:  {}", path.display());
+        println!("Public key:  {}.pub", path.display());
+        println!("Fingerprint: {}", key_fingerprint(&public));
+        return Ok(());
+    }
+
+    if let Some(("verify-certificate", sub)) = matches.subcommand() {
+        let path = sub.get_one::<String>("path").unwrap();
+        let signed: SignedCertificate = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| format!("{} is not a certificate: {}", path, e))?;
+        let trusted = match sub.get_one::<String>("public-key") {
+            Some(key_path) => Some(parse_public_key(&std::fs::read_to_string(key_path)?)?),
+            None => None,
+        };
+        verify_certificate(&signed, trusted.as_ref())?;
+
+        println!("Certificate {}:", path);
+        println!("  Issued:      {}", signed.certificate["issued_at"].as_str().unwrap_or("unknown"));
+        println!("  Signed by:   {}", signed.fingerprint);
+        if let Some(fields) = signed.certificate["fields"].as_object() {
+            for (name, value) in fields {
+                println!("  {}: {}", name, value.as_str().unwrap_or_default());
+            }
+        }
+        for device in signed.certificate["devices"].as_array().into_iter().flatten() {
+            println!(
+                "  Device:      {} ({})",
+                device["device"]["path"].as_str().unwrap_or("unknown"),
+                if device["succeeded"].as_bool() == Some(true) { "erased" } else { "FAILED" },
+            );
+        }
+        println!("Signature is valid.");
+        if trusted.is_none() {
+            println!("Note: signer not checked; pass --public-key to require a known key.");
+        }
+        return Ok(());
+    }
+
+    if let Some(("wipe-free", sub)) = matches.subcommand() {
+        let pattern: WipePattern = sub.get_one::<String>("pattern")
+            .unwrap()
+            .parse()
+            .map_err(|e| usage_error(format!("Invalid pattern: {}", e)))?;
+        let dir = Path::new(sub.get_one::<String>("path").unwrap());
+        let headroom = *sub.get_one::<u64>("headroom").unwrap() * 1024 * 1024;
+        let file_size = *sub.get_one::<u64>("file-size").unwrap() * 1024 * 1024;
+
+        install_interrupt_handler();
+        info!("Filling free space under {} with {:?}", dir.display(), pattern);
+        let report = eraser.wipe_free_space(dir, pattern, headroom, file_size)?;
+
+        println!("\nFree-space wipe of {}", report.path.display());
+        if let Some(free) = report.free_before {
+            println!("  Free before:   {}", size_label(free));
+        }
+        println!("  Passes:        {}", report.passes);
+        println!("  Filler files:  {}", report.files_created);
+        println!("  Overwritten:   {} ({} bytes)", format_size(report.bytes_overwritten), report.bytes_overwritten);
+        for error in &report.cleanup_errors {
+            println!("  Warning: could not remove {}", error);
+        }
+        return Ok(());
+    }
+
+    if let Some(("shred", sub)) = matches.subcommand() {
+        let pattern: WipePattern = sub.get_one::<String>("pattern")
+            .unwrap()
+            .parse()
+            .map_err(|e| usage_error(format!("Invalid pattern: {}", e)))?;
+        let verify: Option<VerifyMode> = sub.get_one::<String>("verify").map(|m| m.parse()).transpose().map_err(usage_error)?;
+        let paths: Vec<PathBuf> = sub.get_many::<String>("paths").unwrap().map(PathBuf::from).collect();
+        let sparse_mode = *sub.get_one::<SparseMode>("sparse-mode").unwrap();
+
+        // Expand directories up front so progress can show a total
+        let mut tree = ShredTree::default();
+        for path in &paths {
+            let is_dir = std::fs::symlink_metadata(path).map(|m| m.is_dir()).unwrap_or(false);
+            if is_dir && sub.get_flag("recursive") {
+                let found = collect_shred_tree(path, sub.get_flag("one-file-system"));
+                tree.files.extend(found.files);
+                tree.links.extend(found.links);
+                tree.dirs.extend(found.dirs);
+                tree.errors.extend(found.errors);
+            } else {
+                tree.files.push(path.clone());
+            }
+        }
+
+        let summary = if tree.dirs.is_empty() {
+            format!("{} file(s)", tree.files.len())
+        } else {
+            format!("{} file(s) and {} symlink(s) in {} directories", tree.files.len(), tree.links.len(), tree.dirs.len())
+        };
+        if !sub.get_flag("yes") && !confirm_action(&format!(
+            "Shred {} with {:?}? They will be deleted and cannot be recovered.", summary, pattern
+        )) {
+            println!("Operation cancelled.");
+            return Ok(());
+        }
+
+        let failure = |path: &Path, kind, error: String| ShredResult {
+            path: path.to_path_buf(),
+            kind,
+            size: 0,
+            bytes_overwritten: 0,
+            sparse_mode: None,
+            passes: 0,
+            verified: None,
+            error: Some(error),
+        };
+        let mut results: Vec<ShredResult> = tree.errors.iter()
+            .map(|(path, error)| failure(path, "file", error.clone()))
+            .collect();
+
+        let pb = progress_bar(tree.files.len() as u64, "shred");
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&bar_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files {msg}"))
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        let mut warned_cow = std::collections::HashSet::new();
+        for path in &tree.files {
+            if let Some(fs) = is_copy_on_write_fs(path) {
+                if warned_cow.insert(fs) {
+                    progress_note(format!("Warning: {} is on {}, a copy-on-write filesystem; overwriting in place \
+                                                may leave the original blocks intact", path.display(), fs));
+                }
+            }
+            pb.set_message(path.display().to_string());
+            let result = eraser.shred_file(path, pattern, verify, !sub.get_flag("no-rename"), sparse_mode)
+                .unwrap_or_else(|e| failure(path, "file", e.to_string()));
+            results.push(result);
+            pb.inc(1);
+        }
+        pb.finish_and_clear();
+
+        // Links are removed, never followed; directories go bottom-up last
+        for link in &tree.links {
+            results.push(match std::fs::remove_file(link) {
+                Ok(()) => ShredResult { path: link.clone(), kind: "symlink", size: 0, bytes_overwritten: 0, sparse_mode: None, passes: 0, verified: None, error: None },
+                Err(e) => failure(link, "symlink", e.to_string()),
+            });
+        }
+        for dir in &tree.dirs {
+            results.push(match std::fs::remove_dir(dir) {
+                Ok(()) => ShredResult { path: dir.clone(), kind: "directory", size: 0, bytes_overwritten: 0, sparse_mode: None, passes: 0, verified: None, error: None },
+                Err(e) => failure(dir, "directory", e.to_string()),
+            });
+        }
+
+        print_shred_results(&results);
+        info!("Sparse mode: {:?}", sparse_mode);
+        let failed = results.iter().filter(|r| r.error.is_some()).count();
+        if failed > 0 {
+            return Err(format!("{} of {} file(s) could not be shredded", failed, results.len()).into());
+        }
+        return Ok(());
+    }
 
-    let mut eraser = SecureEraser::new();
     let devices = eraser.list_devices()?;
 
+    // Filters narrow what is offered, never what an explicit --device may name
+    let filter = device_filter(&matches)?;
+    let (shown, hidden): (Vec<DeviceInfo>, Vec<DeviceInfo>) = devices.iter().cloned().partition(|d| filter.matches(d));
     if matches.get_flag("list") {
-        eraser.display_devices(&devices);
+        let is_protected = |device: &DeviceInfo| config.protecting_rule(device).is_some();
+        if matches.get_flag("json") {
+            let listed: Vec<serde_json::Value> = shown.iter()
+                .map(|device| {
+                    let mut entry = serde_json::to_value(device)?;
+                    entry["protected"] = is_protected(device).into();
+                    Ok(entry)
+                })
+                .collect::<Result<_, serde_json::Error>>()?;
+            println!("{}", serde_json::to_string_pretty(&listed)?);
+        } else {
+            display_devices(&shown, is_protected);
+        }
+        if !hidden.is_empty() {
+            info!(target: "note", "{} device(s) hidden by filters", hidden.len());
+        }
         return Ok(());
     }
 
-    let device_path = matches.get_one::<String>("device").unwrap();
-    let device_path = Path::new(device_path);
+    let mut device_args: Vec<String> = matches.get_many::<String>("device")
+        .map(|paths| paths.cloned().collect())
+        .unwrap_or_default();
+    let devices_from = matches.get_one::<String>("devices-from");
+    if let Some(source) = devices_from {
+        device_args.extend(read_device_list(source)
+            .map_err(|e| format!("Cannot read device list {}: {}", source, e))?);
+        if device_args.is_empty() {
+            return Err(format!("Device list {} names no devices", source).into());
+        }
+    }
     
     let pattern: WipePattern = matches.get_one::<String>("pattern")
         .unwrap()
         .parse()
-        .map_err(|e| format!("Invalid pattern: {}", e))?;
+        .map_err(|e| usage_error(format!("Invalid pattern: {}", e)))?;
+
+    // --serial pins the physical drive; a --device given alongside it is a
+    // cross-check, not a second target
+    if let Some(serial) = matches.get_one::<String>("serial") {
+        let owners: Vec<&DeviceInfo> = devices.iter()
+            .filter(|d| device_serial(&d.name).as_deref() == Some(serial.as_str()))
+            .collect();
+        let owner = match owners.as_slice() {
+            [] => return Err(exit_error(EXIT_NOT_FOUND, format!("No device has serial {}", serial))),
+            [owner] => *owner,
+            _ => {
+                let paths: Vec<String> = owners.iter().map(|d| d.path.display().to_string()).collect();
+                return Err(usage_error(format!("Serial {} matches {} devices ({}); name one with --device",
+                                               serial, owners.len(), paths.join(", "))));
+            }
+        };
+        match device_args.as_slice() {
+            [] => {}
+            [path] => {
+                let named = resolve_target(eraser.device_provider(), Path::new(path), &devices, false)?;
+                if named.path != owner.path {
+                    return Err(usage_error(format!(
+                        "--device {} is {}, but serial {} is {}; refusing to guess which was meant",
+                        path, named.path.display(), serial, owner.path.display())));
+                }
+                device_args.clear();
+            }
+            _ => return Err(usage_error("--serial names one device; pass at most one --device to check it against")),
+        }
+        // Resolved below like a serial from a --devices-from manifest
+        device_args.push(serial.clone());
+    }
+
+    if matches.get_one::<String>("metrics-listen").is_some() && !matches.get_flag("kiosk") && !matches.get_flag("daemon") {
+        return Err(usage_error("--metrics-listen needs --kiosk or --daemon"));
+    }
+
+    // Nothing named on the command line: pick from a list on the terminal
+    let interactive = device_args.is_empty() && !matches.get_flag("kiosk") && !matches.get_flag("daemon");
+    // Checked here rather than by clap, so MEMERASE_ASSUME_YES doesn't break
+    // --list; from the environment it just leaves the picker's prompt alone
+    if interactive && matches.value_source("yes") == Some(clap::parser::ValueSource::CommandLine) {
+        return Err(usage_error("--yes requires an explicit --device or --devices-from"));
+    }
+    let pattern = if interactive {
+        use std::io::IsTerminal;
+        if !io::stdin().is_terminal() {
+            return Err(usage_error("No --device given; pass one, or run from a terminal to pick from a list"));
+        }
+        let Some(device) = pick_device(&shown)? else {
+            human!("Operation cancelled.");
+            exit_with(EXIT_INTERRUPTED, Some("cancelled in the device picker".to_string()));
+        };
+        device_args.push(device.path.display().to_string());
+        if matches!(matches.value_source("pattern"), Some(clap::parser::ValueSource::CommandLine | clap::parser::ValueSource::EnvVariable)) {
+            pattern
+        } else {
+            match pick_pattern(pattern)? {
+                Some(picked) => picked,
+                None => {
+                    human!("Operation cancelled.");
+                    exit_with(EXIT_INTERRUPTED, Some("cancelled in the pattern picker".to_string()));
+                }
+            }
+        }
+    } else {
+        pattern
+    };
     
-    let verify = matches.get_flag("verify");
+    let sample_count = *matches.get_one::<usize>("verify-samples").unwrap();
+    let sample_strategy: SampleStrategy = matches.get_one::<String>("verify-strategy")
+        .unwrap()
+        .parse()
+        .map_err(usage_error)?;
+    let with_sampling = |mode: VerifyMode| match mode {
+        VerifyMode::Sample { .. } => VerifyMode::Sample { count: sample_count, strategy: sample_strategy },
+        other => other,
+    };
 
-    // Find device info
-    let target_device = devices.iter()
-        .find(|d| d.path == device_path)
-        .ok_or_else(|| format!("Device not found: {}", device_path.display()))?;
+    let mut verify: Option<VerifyMode> = matches.get_one::<String>("verify")
+        .map(|m| m.parse())
+        .transpose()?
+        .map(with_sampling)
+        .filter(|_| !matches.get_flag("no-verify"));
 
-    // Safety checks
-    if target_device.is_mounted {
-        return Err("Device is mounted. Please unmount before erasing.".into());
+    if let Some(&percent) = matches.get_one::<u8>("verify-percent") {
+        let seed = matches.get_one::<u64>("verify-seed")
+            .or(matches.get_one::<u64>("seed"))
+            .copied()
+            .unwrap_or_else(|| thread_rng().gen());
+        verify = match percent {
+            0 => None,
+            100 => Some(VerifyMode::Full),
+            _ => Some(VerifyMode::Percent { percent, seed }),
+        };
     }
 
-    // Final confirmation
-    let confirm_msg = format!(
-        "WARNING: This will permanently destroy all data on {} ({} MB). Continue?",
-        device_path.display(),
-        target_device.size / (1024 * 1024)
-    );
+    // A quick wipe writes zeros over a few regions; settings that describe
+    // a full erase would only suggest more was done
+    let quick = matches.get_one::<String>("method").map(String::as_str) == Some("quick");
+    let pattern = if quick {
+        const FULL_ONLY: [&str; 12] = ["pattern", "verify", "verify-percent", "dry-run", "resume", "checkpoint",
+                                        "auto-tune", "stamp", "certificate", "skip-matching", "offset", "length"];
+        if let Some(arg) = FULL_ONLY.iter().find(|arg| matches!(matches.value_source(arg),
+                Some(clap::parser::ValueSource::CommandLine | clap::parser::ValueSource::EnvVariable))) {
+            return Err(usage_error(format!("--{} does not apply to --method quick", arg)));
+        }
+        verify = None;
+        WipePattern::Zeros
+    } else {
+        pattern
+    };
 
-    if !confirm_action(&confirm_msg) {
-        println!("Operation cancelled.");
+    if matches.get_flag("kiosk") {
+        return start_kiosk(&mut eraser, &matches, &config, &filter, pattern, verify);
+    }
+    if matches.get_flag("daemon") {
+        return start_daemon(&matches, config, pattern, verify, eraser.block_size());
+    }
+
+    // Resolve every target before touching any of them, so a typo in the
+    // third path is reported before the first disk is wiped
+    let mut targets = Vec::new();
+    let mut named_as = Vec::new();  // What each target was called on the command line
+    let mut unknown = Vec::new();
+    for arg in &device_args {
+        // Manifests may name a disk by serial instead of by path
+        let by_serial = (!arg.contains('/'))
+            .then(|| devices.iter().find(|d| device_serial(&d.name).as_deref() == Some(arg.as_str())))
+            .flatten();
+        let resolved = match by_serial {
+            Some(device) => Ok(device.clone()),
+            None => resolve_target(eraser.device_provider(), Path::new(arg), &devices, matches.get_flag("allow-file")),
+        };
+        match resolved {
+            Ok(device) => {
+                targets.push(device);
+                named_as.push(arg.clone());
+            }
+            Err(e) => unknown.push(e),
+        }
+    }
+    match unknown.len() {
+        0 => {}
+        1 => return Err(Box::new(unknown.remove(0))),
+        _ => {
+            for entry in &unknown {
+                warn!("  {}", entry);
+            }
+            return Err(exit_error(unknown[0].exit_code(), format!("{} of {} targets could not be resolved; nothing was erased",
+                                                            unknown.len(), device_args.len())));
+        }
+    }
+
+    if targets.len() > 1 && (matches.contains_id("scan") || matches.get_flag("verify-only")) {
+        return Err(usage_error("--scan and --verify-only take a single --device"));
+    }
+    let device_path = targets[0].path.as_path();
+
+    // Scanning never writes, so it is allowed on mounted devices
+    if let Some(mode) = matches.get_one::<String>("scan") {
+        let mode = match with_sampling(mode.parse()?) {
+            VerifyMode::Hash => return Err(usage_error("Hash mode is only available while erasing")),
+            other => other,
+        };
+
+        let report = eraser.scan_device(device_path, mode)?;
+        if matches.get_flag("json") {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            display_scan(&report);
+        }
+        return Ok(());
+    }
+
+    // Read-only verification needs neither the mount check nor confirmation
+    if matches.get_flag("verify-only") {
+        let mode = verify.unwrap_or(VerifyMode::Sample { count: sample_count, strategy: sample_strategy });
+        if mode == VerifyMode::Hash {
+            return Err(usage_error("Hash verification needs digests recorded during the erase"));
+        }
+        if !matches.get_flag("json") {
+            info!("Verifying {} against pattern {:?} ({:?})", device_path.display(), pattern, mode);
+        }
+
+        let result = eraser.verify_device(device_path, pattern, mode)?;
+        if matches.get_flag("json") {
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        } else {
+            for line in describe_verification(&result) {
+                println!("{}", line);
+            }
+        }
+
+        if !result.passed() {
+            return Err(exit_error(EXIT_VERIFY_FAILED, "Verification failed"));
+        }
+        return Ok(());
+    }
+
+    let sync_mode: SyncMode = matches.get_one::<String>("sync-mode").unwrap().parse().map_err(usage_error)?;
+    let direct_io: DirectIo = matches.get_one::<String>("direct-io").unwrap().parse().map_err(usage_error)?;
+
+    let checkpoint_path = matches.get_one::<String>("checkpoint").or(matches.get_one::<String>("resume"));
+    if targets.len() > 1 && checkpoint_path.is_some() {
+        return Err(usage_error("--checkpoint and --resume take a single --device"));
+    }
+    if targets.len() > 1 && matches.contains_id("badblocks-out") {
+        return Err(usage_error("--badblocks-out takes a single --device"));
+    }
+    let resume = match matches.get_one::<String>("resume") {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(Path::new(path))
+                .map_err(|e| format!("Cannot resume from {}: {}", path, e))?;
+            checkpoint.check_target(&targets[0])
+                .map_err(|e| format!("Refusing to resume: {}", e))?;
+            Some(checkpoint)
+        }
+        None => None,
+    };
+    // A resumed erase continues the checkpoint's method, whatever the default
+    let pattern = match resume {
+        Some(ref checkpoint) => {
+            let explicit = matches!(matches.value_source("pattern"), Some(clap::parser::ValueSource::CommandLine | clap::parser::ValueSource::EnvVariable));
+            if explicit && checkpoint.method != pattern {
+                return Err(usage_error(format!("Checkpoint was taken during a {:?} erase, not {:?}", checkpoint.method, pattern)));
+            }
+            checkpoint.method
+        }
+        None => pattern,
+    };
+
+    for target in &targets {
+        // Deliberately no flag to get past this; the config has to change
+        if let Some((rule, matched, file)) = config.protecting_rule(target) {
+            return Err(exit_error(EXIT_PERMISSION, format!(
+                "{} is protected: entry '{}' in {} matches its {}. Remove it from the config to erase this device",
+                target.path.display(), rule, file.display(), matched)));
+        }
+    }
+
+    // --offset/--length apply to every target, and must fit in each of them
+    let ranges: Vec<Option<ByteRange>> = match (matches.get_one::<String>("offset"), matches.get_one::<String>("length")) {
+        (None, None) => vec![None; targets.len()],
+        (offset, length) => {
+            let offset = offset.map(|size| parse_size(size)).transpose().map_err(usage_error)?.unwrap_or(0);
+            let length = length.map(|size| parse_size(size)).transpose().map_err(usage_error)?;
+            targets.iter()
+                .map(|target| {
+                    let range = ByteRange { offset, length: length.unwrap_or(target.size.saturating_sub(offset)) };
+                    if range.length == 0 || offset.checked_add(range.length).is_none_or(|end| end > target.size) {
+                        return Err(usage_error(format!("{} at offset {} does not fit in {} ({})",
+                                                       format_size(range.length), offset, target.path.display(),
+                                                       size_label(target.size))));
+                    }
+                    Ok(Some(range))
+                })
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    // --format-after hands media back to users; internal disks don't qualify
+    let format_after = format_after_arg(&matches).map_err(usage_error)?;
+    if let Some((kind, ref label)) = format_after {
+        for target in &targets {
+            if !target.is_removable {
+                return Err(usage_error(format!("--format-after is for removable media; {} is not removable", target.path.display())));
+            }
+            filesystem_image(kind, target.size, 0, label.as_deref())
+                .map_err(|e| usage_error(format!("{}: {}", target.path.display(), e)))?;
+        }
+    }
+
+    // Every preflight step, nothing opened for writing; the exit status says
+    // whether the real run would have been allowed
+    if matches.get_flag("dry-run") {
+        let mut plans = Vec::new();
+        for (target, &range) in targets.iter().zip(&ranges) {
+            eraser.set_range(range);
+            plans.push(eraser.plan_erase(target, pattern, sync_mode, direct_io, verify)?);
+        }
+        if matches.get_flag("json") {
+            if plans.len() == 1 {
+                println!("{}", serde_json::to_string_pretty(&plans[0])?);
+            } else {
+                println!("{}", serde_json::to_string_pretty(&plans)?);
+            }
+        } else {
+            for plan in &plans {
+                print_plan(plan);
+            }
+        }
+        let blockers: Vec<String> = plans.iter()
+            .flat_map(|plan| plan.blockers.iter().map(move |b| format!("{}: {}", plan.device.display(), b)))
+            .collect();
+        if !blockers.is_empty() {
+            return Err(format!("Dry run: erase would be refused ({})", blockers.join("; ")).into());
+        }
         return Ok(());
     }
 
-    // Progress callback (can be used for GUI integration)
-    let progress_callback: Option<Box<dyn Fn(f64)>> = Some(Box::new(|_progress| {
-        // Custom progress handling can be implemented here
+    // Safety checks, all targets up front so one busy disk doesn't stop the
+    // batch halfway through
+    let mut problems = Vec::new();
+    for target in &targets {
+        if target.is_mounted {
+            problems.push(format!("{} is mounted. Please unmount before erasing.", target.path.display()));
+        }
+        let holders = eraser.device_provider().holders(&target.name);
+        if !holders.is_empty() {
+            problems.push(format!("{} is in use by {}. Release it before erasing.",
+                                  target.path.display(), holders.join(", ")));
+        }
+    }
+    match problems.len() {
+        0 => {}
+        1 => return Err(exit_error(EXIT_IN_USE, problems.remove(0))),
+        _ => {
+            for problem in &problems {
+                warn!("  {}", problem);
+            }
+            return Err(exit_error(EXIT_IN_USE, format!("{} of {} targets failed the safety checks; nothing was erased",
+                                                         problems.len(), targets.len())));
+        }
+    }
+
+    // Final confirmation, one prompt for the whole batch
+    let confirm_msg = if quick {
+        let names: Vec<String> = targets.iter().map(|t| format!("{} ({})", t.path.display(), size_label(t.size))).collect();
+        format!(
+            "WARNING: Quick wipe of {}: partition tables and filesystem headers will be destroyed, \
+             but the rest of the data stays RECOVERABLE. Continue?",
+            names.join(", ")
+        )
+    } else if let [Some(range)] = ranges.as_slice() {
+        format!(
+            "WARNING: This will permanently destroy {} of {} ({}); \
+             data outside that range is NOT erased. Continue?",
+            describe_range(*range),
+            device_path.display(),
+            size_label(targets[0].size)
+        )
+    } else if targets.len() == 1 {
+        format!(
+            "WARNING: This will permanently destroy all data on {} ({}). Continue?",
+            device_path.display(),
+            size_label(targets[0].size)
+        )
+    } else {
+        let mut msg = format!("WARNING: This will permanently destroy all data on {} devices:", targets.len());
+        for (target, range) in targets.iter().zip(&ranges) {
+            msg.push_str(&format!("\n  {} ({})", target.path.display(), size_label(target.size)));
+            if let Some(range) = range {
+                msg.push_str(&format!(", ONLY {}", describe_range(*range)));
+            }
+        }
+        msg.push_str("\nContinue?");
+        msg
+    };
+
+    // Load the key before anything is erased, so a bad path can't leave a
+    // wiped disk without its certificate
+    let certificate = match matches.get_one::<String>("certificate") {
+        Some(path) => {
+            let key = load_signing_key(Path::new(matches.get_one::<String>("signing-key").unwrap()))?;
+            let mut fields = std::collections::BTreeMap::new();
+            for field in matches.get_many::<String>("cert-field").into_iter().flatten() {
+                let (name, value) = field.split_once('=')
+                    .ok_or_else(|| usage_error(format!("--cert-field expects KEY=VALUE, got '{}'", field)))?;
+                fields.insert(name.to_string(), value.to_string());
+            }
+            Some((path, key, fields))
+        }
+        None => None,
+    };
+
+    let assume_yes = matches.get_flag("yes") && !interactive;
+    // The list came in on stdin, so only a terminal can answer the prompt
+    if devices_from.map(String::as_str) == Some("-") && !assume_yes && !has_terminal() {
+        return Err(usage_error("--devices-from - consumes stdin; run from a terminal or pass --yes"));
+    }
+    let confirmation = Confirmation {
+        method: if assume_yes { "skipped (--yes)" } else { "interactive" }.to_string(),
+        user: invoking_user(),
+    };
+    for ((target, named_as), &range) in targets.iter().zip(&named_as).zip(&ranges) {
+        eraser.set_range(range);
+        let mut estimate = eraser.estimate_duration(target, pattern, sync_mode, direct_io, false);
+        let method = if let Some(range) = range {
+            format!("{:?}, {} pass(es), ONLY {}", pattern, pattern.pass_count(), describe_range(range))
+        } else if quick {
+            // Only the quick regions are written, so the estimate shrinks with them
+            let bytes: u64 = quick_regions(&target.name, target.size).iter().map(|region| region.length).sum();
+            estimate.total_secs *= bytes as f64 / target.size.max(1) as f64;
+            format!("QUICK - {} at the ends and partition starts only", format_size(bytes))
+        } else {
+            format!("{:?}, {} pass(es)", pattern, pattern.pass_count())
+        };
+        print_preflight_summary(target, named_as, &method, sync_mode, verify, &estimate, eraser.device_provider());
+    }
+    // What the operator is shown is what each open device must still be
+    let confirmed_as: Vec<ExpectedDevice> = targets.iter()
+        .map(|target| ExpectedDevice { size: target.size, serial: device_serial(&target.name) })
+        .collect();
+    let confirmed = if assume_yes {
+        info!("{}", confirm_msg);
+        info!("Confirmation skipped (--yes) by {}", confirmation.user);
+        true
+    } else if interactive || *matches.get_one::<bool>("strict-confirm").unwrap() {
+        // A batch is confirmed by typing every name, in order
+        let accepted = if targets.len() == 1 {
+            let mut accepted = vec![targets[0].name.clone()];
+            accepted.extend(device_serial(&targets[0].name));
+            accepted
+        } else {
+            vec![targets.iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join(" ")]
+        };
+        confirm_by_name(confirm_msg.trim_end_matches("Continue?").trim_end(), &accepted)
+    } else {
+        confirm_action(&confirm_msg)
+    };
+    audit(serde_json::json!({
+        "event": "confirmation",
+        "prompt": "destroy",
+        "method": confirmation.method,
+        "user": confirmation.user,
+        "devices": targets.iter().map(|t| &t.path).collect::<Vec<_>>(),
+        "accepted": confirmed,
     }));
+    if !confirmed {
+        human!("Operation cancelled.");
+        exit_with(EXIT_INTERRUPTED, Some("cancelled at the confirmation prompt".to_string()));
+    }
+
+    if let Some(ref checkpoint) = resume {
+        info!("Resuming {:?} erase at pass {}/{}, offset {} (checkpoint saved {} ago)",
+                 checkpoint.method, checkpoint.pass + 1, checkpoint.passes.len(), checkpoint.offset,
+                 format_duration(unix_time().saturating_sub(checkpoint.updated_at) as f64));
+    }
+
+    // Priority must be in place before the first write is issued
+    let io_class = if matches.get_flag("idle-io") {
+        Some(IoClass::Idle)
+    } else {
+        matches.get_one::<IoClass>("io-class").copied()
+    };
+    if let Some(class) = io_class {
+        let (applied, warnings) = apply_scheduling_priority(class, *matches.get_one::<u8>("io-level").unwrap());
+        if !applied.is_empty() {
+            info!("Scheduling priority: {}", applied.join(", "));
+        }
+        for warning in warnings {
+            info!("Warning: {}", warning);
+        }
+    }
+
+    // A quick wipe is over in seconds, so there is no long job to back out of
+    if !quick {
+        // The data is already forfeit, so a timed burst gives a real estimate;
+        // the second prompt is the last chance to back out of a multi-day job
+        // A timed burst would overwrite part of what a resumed erase already wrote
+        let measure = !matches.get_flag("no-estimate-burst") && resume.is_none();
+        if measure {
+            info!("Timing a short write burst to estimate the duration...");
+        }
+        let estimates: Vec<DurationEstimate> = targets.iter().zip(&ranges)
+            .map(|(target, &range)| {
+                eraser.set_range(range);
+                eraser.estimate_duration(target, pattern, sync_mode, direct_io, measure)
+            })
+            .collect();
+        let estimate_msg = if let [estimate] = estimates.as_slice() {
+            format!(
+                "Estimated duration: ~{} for {} pass(es) at {:.1} MB/s ({}). Continue?",
+                format_duration(estimate.total_secs), estimate.passes, estimate.mb_per_s, estimate.source
+            )
+        } else {
+            let mut msg = String::new();
+            for (target, estimate) in targets.iter().zip(&estimates) {
+                msg.push_str(&format!("  {}: ~{} at {:.1} MB/s ({})\n", target.path.display(),
+                                      format_duration(estimate.total_secs), estimate.mb_per_s, estimate.source));
+            }
+            let total: f64 = estimates.iter().map(|e| e.total_secs).sum();
+            msg.push_str(&format!("Estimated duration: ~{} for the whole batch. Continue?", format_duration(total)));
+            msg
+        };
+        if assume_yes {
+            info!("{}", estimate_msg.trim_end_matches(" Continue?"));
+        } else {
+            let accepted = confirm_action(&estimate_msg);
+            audit(serde_json::json!({
+                "event": "confirmation",
+                "prompt": "duration",
+                "method": confirmation.method,
+                "user": confirmation.user,
+                "accepted": accepted,
+            }));
+            if !accepted {
+                human!("Operation cancelled.");
+                exit_with(EXIT_INTERRUPTED, Some("cancelled at the duration prompt".to_string()));
+            }
+        }
+    }
+
+    let batch = targets.len() > 1;
+    if let Some(path) = checkpoint_path {
+        eraser.set_checkpoint(Path::new(path),
+                              std::time::Duration::from_secs(*matches.get_one::<u64>("checkpoint-interval").unwrap()));
+    }
+    if let Some(checkpoint) = resume {
+        eraser.set_resume(checkpoint);
+    }
+    install_interrupt_handler();
+    #[cfg(unix)]
+    install_pause_handler();
+    #[cfg(unix)]
+    let pause_key = PauseKey::start();
+    // Without --report, a configured report_dir gets one file per run
+    let report_dest = matches.get_one::<String>("report").cloned().or_else(|| {
+        config.report_dir.as_ref().map(|dir| {
+            let name = if targets.len() == 1 { targets[0].name.as_str() } else { "batch" };
+            dir.join(format!("{}-{}.json", name, unix_time())).display().to_string()
+        })
+    });
+    let options = BatchOptions {
+        pattern,
+        verify,
+        sync_mode,
+        direct_io,
+        quick,
+        auto_tune: matches.get_flag("auto-tune"),
+        keep_going: matches.get_flag("keep-going"),
+        fail_fast: matches.get_flag("fail-fast"),
+        stamp_key: matches.get_one::<String>("stamp").map(|key| key.as_bytes().to_vec()),
+        init_table: init_table_arg(&matches),
+        format_after,
+        pre_cmd: matches.get_one::<String>("pre-cmd").cloned(),
+        status_file: matches.get_one::<String>("status-file").cloned(),
+        confirmation,
+    };
+    let BatchOutcome { results, records, error: single_error, bad_blocks } =
+        run_batch(&mut eraser, &targets, &ranges, &confirmed_as, &options);
+    // The exits below skip destructors, and the terminal must be restored
+    #[cfg(unix)]
+    drop(pause_key);
+
+    if let Some(dest) = &report_dest {
+        if let Err(e) = write_run_report_to(dest, &records) {
+            warn!("Warning: could not write report {}: {}", dest, e);
+        }
+    }
+    if let Some(path) = matches.get_one::<String>("report-csv") {
+        if let Err(e) = append_csv_report(path, &records) {
+            warn!("Warning: could not append to {}: {}", path, e);
+        }
+    }
+    // Written even when empty, so a clean run is on record too
+    if let (Some(path), Some(map)) = (matches.get_one::<String>("badblocks-out"), &bad_blocks) {
+        match write_bad_block_map(path, &targets[0].path, map) {
+            Ok(()) => info!("Bad-block map ({} range(s)) written to {}", map.len(), path),
+            Err(e) => warn!("Warning: could not write bad-block map {}: {}", path, e),
+        }
+    }
+    if let Some((path, key, fields)) = &certificate {
+        // Only a completed run gets a certificate, unless failures are
+        // explicitly certified too
+        let all_succeeded = records.iter().all(|r| r.succeeded);
+        if is_interrupted() {
+            warn!("Not signing {}: the run was interrupted", path);
+        } else if !all_succeeded && !matches.get_flag("certify-failures") {
+            warn!("Not signing {}: the erase failed (use --certify-failures to certify it anyway)", path);
+        } else {
+            let signed = sign_certificate(key, fields, &records);
+            match serde_json::to_string_pretty(&signed)
+                .map_err(io::Error::from)
+                .and_then(|json| std::fs::write(path, json + "\n"))
+            {
+                Ok(()) => info!("Signed certificate written to {} (key {})", path, key_fingerprint(&key.verifying_key())),
+                Err(e) => warn!("Warning: could not write certificate {}: {}", path, e),
+            }
+        }
+    }
+    if let Some(command) = matches.get_one::<String>("post-cmd") {
+        // Failures here are reported, but the erase result stands
+        let report_path = report_dest.as_deref().filter(|dest| *dest != "-").unwrap_or("");
+        for record in &records {
+            let mut vars = hook_env(&record.device, record.method);
+            vars.push(("MEMERASE_RESULT", hook_result(record).to_string()));
+            vars.push(("MEMERASE_REPORT_PATH", report_path.to_string()));
+            match run_hook(command, &vars) {
+                Ok(status) if status.success() => {}
+                Ok(status) => warn!("Warning: --post-cmd for {} failed ({})", record.device.path.display(), status),
+                Err(e) => warn!("Warning: cannot run --post-cmd: {}", e),
+            }
+        }
+    }
+    if let Some(url) = matches.get_one::<String>("notify-url") {
+        let succeeded = !is_interrupted() && records.iter().all(|r| r.succeeded);
+        let wanted = match matches.get_one::<String>("notify-on").unwrap().as_str() {
+            "success" => succeeded,
+            "failure" => !succeeded,
+            _ => true,
+        };
+        if wanted {
+            let timeout = std::time::Duration::from_secs(*matches.get_one::<u64>("notify-timeout").unwrap());
+            send_notification(url, &hostname(), &records, succeeded, timeout);
+        }
+    }
+    if let Some(e) = single_error {
+        return Err(e.into());
+    }
 
-    // Perform the erase
-    eraser.secure_erase(device_path, pattern, verify, progress_callback)?;
+    if is_interrupted() {
+        if batch {
+            print_batch_results(&results);
+        }
+        exit_with(EXIT_INTERRUPTED, Some("interrupted".to_string()));
+    }
+
+    if batch {
+        if matches.get_flag("json") {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        } else {
+            print_batch_results(&results);
+        }
+        // 0 when everything succeeded, EXIT_BATCH_PARTIAL for a mixed
+        // result, and an I/O or verification status when nothing did
+        match results.iter().filter(|r| r.succeeded()).count() {
+            n if n == results.len() => {}
+            0 => {
+                let code = if results.iter().all(|r| r.verified == Some(false)) { EXIT_VERIFY_FAILED } else { EXIT_IO };
+                return Err(exit_error(code, format!("None of the {} devices was erased", results.len())));
+            }
+            n => {
+                error!("Error: {} of {} devices failed", results.len() - n, results.len());
+                exit_with(EXIT_BATCH_PARTIAL, Some("some devices failed".to_string()));
+            }
+        }
+    }
+    // Erased, apart from sectors that are still as they were
+    if results.iter().any(|r| r.unwritable > 0) {
+        exit_with(EXIT_INCOMPLETE, Some("unwritable sectors skipped".to_string()));
+    }
 
     Ok(())
 }
 
 // Cargo.toml dependencies needed:
 /*
+[package]
+name = "memerase"
+version = "1.0.0"
+edition = "2021"
+
+[lib]
+path = "lib.rs"
+crate-type = ["rlib", "cdylib"]  # cdylib: libmemerase.so for the C API (--features ffi)
+
+[[bin]]
+name = "secure-eraser"
+path = "main.rs"
+
 [dependencies]
 rand = "0.8"
-clap = { version = "4.0", features = ["derive"] }
+clap = { version = "4.0", features = ["derive", "string", "env"] }
 indicatif = "0.17"
+serde = { version = "1.0", features = ["derive"] }
+serde_json = "1.0"
+blake3 = "1.5"
+sha2 = "0.10"
+rand_chacha = "0.3"
+ed25519-dalek = { version = "2", features = ["rand_core"] }
+tracing = "0.1"
+tracing-subscriber = { version = "0.3", features = ["env-filter"] }
+clap_complete = "4"
+toml = "0.8"
+ureq = "2"
+thiserror = "1"
+
+[features]
+ffi = ["dep:cbindgen"]  # extern "C" API in ffi.rs; build.rs writes target/include/memerase.h
+dbus = ["dep:zbus"]     # --dbus: the daemon's jobs on the system bus
+
+[build-dependencies]
+cbindgen = { version = "0.26", optional = true }
 
 [target.'cfg(unix)'.dependencies]
 libc = "0.2"
+zbus = { version = "4", optional = true }
+
+[target.'cfg(target_os = "linux")'.dependencies]
+io-uring = "0.6"
 
 [target.'cfg(windows)'.dependencies]
-winapi = { version = "0.3", features = ["fileapi", "handleapi", "winioctl", "errhandlingapi"] }
+winapi = { version = "0.3", features = ["fileapi", "handleapi", "winioctl", "errhandlingapi", "processthreadsapi", "winbase", "consoleapi", "minwindef"] }
 */
@@ -0,0 +1,147 @@
+//! --metrics-listen: erase progress and results for Prometheus
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::{erase::ProgressUpdate, error::EraseError, report::EraseReport};
+
+/// Upper bounds of the job throughput histogram, in MB/s
+const THROUGHPUT_BUCKETS: [f64; 10] = [5.0, 10.0, 25.0, 50.0, 100.0, 200.0, 400.0, 800.0, 1600.0, 3200.0];
+
+/// --metrics-listen: what a Prometheus scrape of /metrics sees in --kiosk
+/// and --daemon mode. Fed from the jobs' progress callbacks, the updates
+/// the bars and the daemon's job status are drawn from, and from their
+/// reports' RunStats once they finish.
+#[derive(Default)]
+pub struct Metrics {
+    state: std::sync::Mutex<MetricsState>,
+}
+
+#[derive(Default)]
+pub struct MetricsState {
+    active: HashMap<PathBuf, ActiveJob>,
+    finished: std::collections::BTreeMap<&'static str, u64>,  // By result: succeeded, failed, cancelled
+    throughput: [u64; THROUGHPUT_BUCKETS.len()],  // Jobs at or below each bucket, not cumulative
+    throughput_count: u64,
+    throughput_sum: f64,  // Bytes per second
+}
+
+struct ActiveJob {
+    serial: String,
+    bytes_written: u64,
+    bytes_total: u64,
+    rate: f64,  // Bytes per second over the last few seconds
+}
+
+impl Metrics {
+    /// Serve /metrics on `addr` from a thread of its own
+    pub fn listen(addr: &str) -> Result<Arc<Self>, EraseError> {
+        let listener = std::net::TcpListener::bind(addr)
+            .map_err(|source| EraseError::Failed { what: format!("Cannot listen on {}", addr), source })?;
+        let metrics = Arc::new(Metrics::default());
+        let serving = Arc::clone(&metrics);
+        std::thread::Builder::new().name("metrics".to_string()).spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = serving.answer(stream) {
+                    debug!("metrics client: {}", e);
+                }
+            }
+        })?;
+        human!("Serving metrics on http://{}/metrics", addr);
+        Ok(metrics)
+    }
+
+    /// One request per connection; anything but GET /metrics is a 404
+    fn answer(&self, mut stream: std::net::TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+        let path = request_line.split_whitespace().nth(1).unwrap_or("");
+        let (status, body) = match request_line.split_whitespace().next() {
+            Some("GET") if path == "/metrics" || path.starts_with("/metrics?") => ("200 OK", self.render()),
+            _ => ("404 Not Found", "Not found; metrics are at /metrics\n".to_string()),
+        };
+        write!(stream, "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               status, body.len(), body)
+    }
+
+    pub fn start(&self, device: &Path, serial: Option<&str>) {
+        self.state.lock().unwrap().active.insert(device.to_path_buf(), ActiveJob {
+            serial: serial.unwrap_or_default().to_string(),
+            bytes_written: 0,
+            bytes_total: 0,
+            rate: 0.0,
+        });
+    }
+
+    pub fn progress(&self, device: &Path, update: &ProgressUpdate) {
+        if let Some(job) = self.state.lock().unwrap().active.get_mut(device) {
+            job.bytes_written = update.bytes_done;
+            job.bytes_total = update.total_bytes;
+            job.rate = update.rate;
+        }
+    }
+
+    /// `result` is one of the daemon's job states; the report, when there
+    /// is one, gives the throughput
+    pub fn finish(&self, device: &Path, result: &'static str, report: Option<&EraseReport>) {
+        let mut state = self.state.lock().unwrap();
+        state.active.remove(device);
+        *state.finished.entry(result).or_default() += 1;
+        if let Some(report) = report.filter(|report| report.stats.write_secs > 0.0) {
+            let mb_s = report.stats.average_mb_s;
+            if let Some(bucket) = THROUGHPUT_BUCKETS.iter().position(|&bound| mb_s <= bound) {
+                state.throughput[bucket] += 1;
+            }
+            state.throughput_count += 1;
+            state.throughput_sum += mb_s * 1024.0 * 1024.0;
+        }
+    }
+
+    /// The Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# HELP memerase_active_jobs Erases in progress.\n# TYPE memerase_active_jobs gauge\n");
+        out.push_str(&format!("memerase_active_jobs {}\n", state.active.len()));
+
+        let mut active: Vec<(&PathBuf, &ActiveJob)> = state.active.iter().collect();
+        active.sort_by(|a, b| a.0.cmp(b.0));
+        let mut gauge = |name: &str, help: &str, value: &dyn Fn(&ActiveJob) -> f64| {
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name));
+            for (device, job) in &active {
+                out.push_str(&format!("{}{{device=\"{}\",serial=\"{}\"}} {}\n", name,
+                                      label_value(&device.display().to_string()), label_value(&job.serial), value(job)));
+            }
+        };
+        gauge("memerase_job_bytes_written", "Bytes written so far by an active erase, all passes together.",
+              &|job| job.bytes_written as f64);
+        gauge("memerase_job_bytes_total", "Bytes an active erase writes in all.", &|job| job.bytes_total as f64);
+        gauge("memerase_job_write_rate_bytes_per_second", "Write rate of an active erase over the last few seconds.",
+              &|job| job.rate);
+
+        out.push_str("# HELP memerase_jobs_total Finished erases by result.\n# TYPE memerase_jobs_total counter\n");
+        for result in ["succeeded", "failed", "cancelled"] {
+            out.push_str(&format!("memerase_jobs_total{{result=\"{}\"}} {}\n", result, state.finished.get(result).copied().unwrap_or(0)));
+        }
+
+        let name = "memerase_job_throughput_bytes_per_second";
+        out.push_str(&format!("# HELP {} Average write rate of finished erases.\n# TYPE {} histogram\n", name, name));
+        let mut cumulative = 0;
+        for (bound, count) in THROUGHPUT_BUCKETS.iter().zip(state.throughput) {
+            cumulative += count;
+            out.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, bound * 1024.0 * 1024.0, cumulative));
+        }
+        out.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, state.throughput_count));
+        out.push_str(&format!("{}_sum {}\n{}_count {}\n", name, state.throughput_sum, name, state.throughput_count));
+        out
+    }
+}
+
+/// Escape a Prometheus label value
+fn label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,149 @@
+//! Wipe patterns and the per-pass fill they expand to
+
+use serde::{Deserialize, Serialize};
+
+/// What --pattern accepts, also offered by shell completion
+pub const PATTERN_NAMES: [&str; 5] = ["zeros", "ones", "random", "dod3", "gutmann35"];
+
+/// The overwrite scheme for an erase; `SecureEraser::pass_plan` expands it
+/// into the fill of each pass
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WipePattern {
+    Zeros,
+    Ones,
+    Random,
+    Dod3Pass,    // DoD 5220.22-M (3 passes)
+    Gutmann35,   // Gutmann 35-pass method
+}
+
+impl std::str::FromStr for WipePattern {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "zeros" => Ok(WipePattern::Zeros),
+            "ones" => Ok(WipePattern::Ones),
+            "random" => Ok(WipePattern::Random),
+            "dod3" => Ok(WipePattern::Dod3Pass),
+            "gutmann35" => Ok(WipePattern::Gutmann35),
+            _ => Err(format!("Invalid pattern: {}", s)),
+        }
+    }
+}
+
+impl WipePattern {
+    /// Whether the last pass writes random data, which cannot be re-checked
+    /// later without the key it was generated from
+    pub fn final_pass_is_random(&self) -> bool {
+        matches!(self, WipePattern::Random | WipePattern::Dod3Pass)
+    }
+
+    /// Number of passes the method writes
+    pub fn pass_count(&self) -> usize {
+        match self {
+            WipePattern::Zeros | WipePattern::Ones | WipePattern::Random => 1,
+            WipePattern::Dod3Pass => 3,
+            WipePattern::Gutmann35 => 9,
+        }
+    }
+}
+
+/// Space-separated lowercase hex, e.g. "00 ff 3a"
+pub(crate) fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What one pass writes. Constant fills are compared against the byte
+/// directly; random passes are a ChaCha keystream addressed by device offset,
+/// so any block can be regenerated (by any thread, for verification) from
+/// the key alone.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum PassFill {
+    Fill(u8),
+    Random { key: [u8; 32] },
+}
+
+impl PassFill {
+    pub fn is_random(&self) -> bool {
+        matches!(self, PassFill::Random { .. })
+    }
+
+    /// Short description, e.g. "0xff fill"
+    pub fn describe(&self) -> String {
+        match *self {
+            PassFill::Fill(byte) => format!("0x{:02x} fill", byte),
+            PassFill::Random { .. } => "random".to_string(),
+        }
+    }
+
+    /// Seed of a random pass as hex, recorded so the data can be re-derived
+    pub fn seed_hex(&self) -> Option<String> {
+        match self {
+            PassFill::Fill(_) => None,
+            PassFill::Random { key } => Some(key.iter().map(|b| format!("{:02x}", b)).collect()),
+        }
+    }
+
+    /// Fill `buffer` with the bytes this pass writes at device `offset`
+    pub fn fill(&self, offset: u64, buffer: &mut [u8]) {
+        match *self {
+            PassFill::Fill(byte) => buffer.fill(byte),
+            PassFill::Random { key } => {
+                use rand::{RngCore, SeedableRng};
+
+                let mut rng = rand_chacha::ChaCha8Rng::from_seed(key);
+                rng.set_word_pos((offset / 4) as u128);
+                let skip = (offset % 4) as usize;
+                if skip == 0 {
+                    rng.fill_bytes(buffer);
+                } else {
+                    let mut scratch = vec![0u8; buffer.len() + skip];
+                    rng.fill_bytes(&mut scratch);
+                    buffer.copy_from_slice(&scratch[skip..]);
+                }
+            }
+        }
+    }
+
+    /// `len` expected bytes at device `offset`
+    pub(crate) fn bytes_at(&self, offset: u64, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        self.fill(offset, &mut bytes);
+        bytes
+    }
+
+    /// Index of the first byte of `actual` (read from device `offset`) that
+    /// differs from what this pass wrote
+    pub(crate) fn first_mismatch(&self, actual: &[u8], offset: u64) -> Option<usize> {
+        match *self {
+            PassFill::Fill(byte) => first_non_matching_byte(actual, byte),
+            PassFill::Random { .. } => first_difference(actual, &self.bytes_at(offset, actual.len())),
+        }
+    }
+}
+
+/// Bytes compared at once; slice equality on chunks this size is a SIMD
+/// memcmp, and only a differing chunk is scanned byte by byte
+const COMPARE_CHUNK: usize = 4096;
+
+/// Index of the first byte in `data` that is not `byte`, compared a chunk at
+/// a time against a stack buffer of `byte`
+pub fn first_non_matching_byte(data: &[u8], byte: u8) -> Option<usize> {
+    let splat = [byte; COMPARE_CHUNK];
+    data.chunks(COMPARE_CHUNK).enumerate()
+        .find(|(_, chunk)| *chunk != &splat[..chunk.len()])
+        .and_then(|(i, chunk)| chunk.iter().position(|&b| b != byte).map(|p| i * COMPARE_CHUNK + p))
+}
+
+/// Index of the first byte where `actual` differs from `expected`, compared
+/// a chunk at a time over their common length
+pub fn first_difference(actual: &[u8], expected: &[u8]) -> Option<usize> {
+    actual.chunks(COMPARE_CHUNK).zip(expected.chunks(COMPARE_CHUNK)).enumerate()
+        .find_map(|(i, (a, e))| {
+            let len = std::cmp::min(a.len(), e.len());
+            (a[..len] != e[..len]).then(|| i * COMPARE_CHUNK + (0..len).find(|&j| a[j] != e[j]).unwrap())
+        })
+}
@@ -0,0 +1,436 @@
+//! Where the facts about block devices come from: the device list, sizes,
+//! mounts, stacked holders and identities. `SecureEraser` and the command
+//! line ask a `DeviceProvider` instead of reading /sys and /proc
+//! themselves, so the safety checks can run against a fixture tree
+//! (`SysfsProvider::with_root`) or canned answers (`FakeDevices`).
+
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::{device::*, error::*};
+
+/// Device enumeration and the checks made before a device is written
+pub trait DeviceProvider: Send + Sync {
+    /// Whole disks that could be erased
+    fn list(&self) -> Result<Vec<DeviceInfo>, EraseError>;
+
+    /// Size in bytes of a device or regular file
+    fn size(&self, path: &Path) -> Result<u64, EraseError>;
+
+    /// Where the device, or any partition on it, is mounted
+    fn mount_point(&self, path: &Path) -> Result<Option<String>, EraseError>;
+
+    fn is_mounted(&self, path: &Path) -> Result<bool, EraseError> {
+        Ok(self.mount_point(path)?.is_some())
+    }
+
+    /// Kernel devices (device-mapper, md, ...) stacked on top of a disk or
+    /// one of its partitions
+    fn holders(&self, name: &str) -> Vec<String>;
+
+    /// Model, serial and sector sizes of a target, read before it is erased
+    fn identity(&self, device: &DeviceInfo) -> DeviceIdentity;
+
+    /// The node `path` names once /dev/disk/by-id and other links are
+    /// followed; None if there is nothing there
+    fn resolve(&self, path: &Path) -> Option<PathBuf>;
+}
+
+/// The provider for the running system: sysfs and /proc/mounts on Unix,
+/// drive letters on Windows
+pub fn system_provider() -> Box<dyn DeviceProvider> {
+    #[cfg(unix)]
+    return Box::new(SysfsProvider::new());
+
+    #[cfg(windows)]
+    return Box::new(WindowsProvider);
+}
+
+/// Reads /sys/block, /proc/mounts and /dev under `root`, which is `/` for
+/// the running system. In a fixture tree plain files stand in for the
+/// device nodes.
+#[derive(Debug, Clone)]
+pub struct SysfsProvider {
+    root: PathBuf,
+}
+
+impl SysfsProvider {
+    pub fn new() -> Self {
+        Self::with_root("/")
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        SysfsProvider { root: root.into() }
+    }
+
+    fn sys_block(&self, name: &str) -> PathBuf {
+        self.root.join("sys/block").join(name)
+    }
+
+    /// The node for a kernel name, /dev/<name> under the root
+    fn node(&self, name: &str) -> PathBuf {
+        self.root.join("dev").join(name)
+    }
+
+    fn is_fixture(&self) -> bool {
+        self.root != Path::new("/")
+    }
+
+    /// Partitions the kernel found on a disk, by kernel name
+    pub fn partitions(&self, name: &str) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(self.sys_block(name)) else {
+            return Vec::new();
+        };
+        let mut partitions: Vec<String> = entries.flatten()
+            .filter(|entry| entry.path().join("partition").exists())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        partitions.sort();
+        partitions
+    }
+
+    /// A partition of `disk`, sized from sysfs so it needs no permission
+    /// on the node, and the filesystem found on it
+    fn partition_info(&self, disk: &str, name: String) -> PartitionInfo {
+        let path = self.node(&name);
+        let size = std::fs::read_to_string(self.sys_block(disk).join(&name).join("size")).ok()
+            .and_then(|sectors| sectors.trim().parse::<u64>().ok())
+            .map(|sectors| sectors * 512)
+            .unwrap_or_else(|| self.size(&path).unwrap_or(0));
+        let (fs_type, label) = probe_filesystem(&path);
+        PartitionInfo { path, name, size, fs_type, label }
+    }
+
+    /// The bus a disk hangs off, from where its sysfs node sits. USB comes
+    /// first so a SATA or NVMe disk in a USB enclosure counts as USB.
+    pub fn bus(&self, name: &str) -> Option<BusType> {
+        let node = std::fs::canonicalize(self.sys_block(name)).ok()?;
+        let node = node.strip_prefix(&self.root).unwrap_or(&node).to_string_lossy();
+        if node.contains("/usb") || node.starts_with("usb") {
+            Some(BusType::Usb)
+        } else if name.starts_with("nvme") || node.contains("/nvme") {
+            Some(BusType::Nvme)
+        } else if node.contains("/ata") {
+            Some(BusType::Sata)
+        } else {
+            None
+        }
+    }
+
+    pub fn model(&self, name: &str) -> Option<String> {
+        std::fs::read_to_string(self.sys_block(name).join("device/model"))
+            .ok()
+            .map(|model| model.trim().to_string())
+            .filter(|model| !model.is_empty())
+    }
+
+    /// Serial number the kernel reports for a disk, if any (virtio disks
+    /// keep theirs outside device/)
+    pub fn serial(&self, name: &str) -> Option<String> {
+        ["device/serial", "device/vpd_pg80", "serial"].iter()
+            .filter_map(|attr| std::fs::read(self.sys_block(name).join(attr)).ok())
+            .map(|raw| String::from_utf8_lossy(&raw).chars().filter(|c| c.is_ascii_graphic()).collect::<String>())
+            .find(|serial| !serial.is_empty())
+    }
+}
+
+impl Default for SysfsProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceProvider for SysfsProvider {
+    fn list(&self) -> Result<Vec<DeviceInfo>, EraseError> {
+        let mut devices = Vec::new();
+        let sys_block = self.root.join("sys/block");
+
+        if !sys_block.exists() {
+            return Ok(devices);
+        }
+
+        for entry in std::fs::read_dir(sys_block)? {
+            let entry = entry?;
+            let device_name = entry.file_name().to_string_lossy().to_string();
+
+            // Skip loop devices and other virtual devices
+            if device_name.starts_with("loop") || device_name.starts_with("ram") {
+                continue;
+            }
+
+            let device_path = self.node(&device_name);
+
+            // Check if it's a block device
+            let Ok(metadata) = std::fs::metadata(&device_path) else {
+                continue;
+            };
+            let is_node = is_block_device(&metadata) || (self.is_fixture() && metadata.is_file());
+            if !is_node {
+                continue;
+            }
+
+            let removable = std::fs::read_to_string(self.sys_block(&device_name).join("removable"));
+            let (fs_type, label) = probe_filesystem(&device_path);
+            devices.push(DeviceInfo {
+                size: self.size(&device_path).unwrap_or(0),
+                is_removable: removable.is_ok_and(|removable| removable.trim() == "1"),
+                is_mounted: self.is_mounted(&device_path)?,
+                bus: self.bus(&device_name),
+                fs_type,
+                label,
+                partitions: self.partitions(&device_name).into_iter()
+                    .map(|partition| self.partition_info(&device_name, partition))
+                    .collect(),
+                path: device_path,
+                name: device_name,
+            });
+        }
+
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    fn size(&self, path: &Path) -> Result<u64, EraseError> {
+        let mut file = File::open(path).map_err(|e| EraseError::open(path, e))?;
+
+        // Disk images and other regular files report their size directly
+        let metadata = file.metadata()?;
+        if metadata.is_file() {
+            return Ok(metadata.len());
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            // _IOR(0x12, 114, size_t); not exported by the libc crate
+            const BLKGETSIZE64: libc::c_ulong = 0x8008_1272;
+
+            let mut size: u64 = 0;
+            let result = unsafe {
+                libc::ioctl(file.as_raw_fd(), BLKGETSIZE64, &mut size as *mut u64)
+            };
+            if result == 0 {
+                return Ok(size);
+            }
+        }
+
+        // Other block devices can be sized by seeking to their end
+        match file.seek(SeekFrom::End(0)) {
+            Ok(size) if size > 0 => Ok(size),
+            Ok(_) => Err(EraseError::Unsupported { what: format!("{} reports a size of 0 bytes", path.display()) }),
+            Err(e) => Err(EraseError::Failed { what: format!("Failed to get the size of {}", path.display()), source: e }),
+        }
+    }
+
+    fn mount_point(&self, path: &Path) -> Result<Option<String>, EraseError> {
+        let mounts = std::fs::read_to_string(self.root.join("proc/mounts"))?;
+
+        // The disk itself and each of its partitions, as the nodes they
+        // resolve to, so by-id links and /dev/mapper names match too
+        let resolved = |node: &Path| std::fs::canonicalize(node).unwrap_or_else(|_| node.to_path_buf());
+        let mut nodes = vec![resolved(path)];
+        if let Some(name) = resolved(path).file_name().and_then(|name| name.to_str()) {
+            nodes.extend(self.partitions(name).iter().map(|partition| resolved(&self.node(partition))));
+        }
+
+        for line in mounts.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(source), Some(mountpoint)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            if !source.starts_with('/') {
+                continue;
+            }
+            if nodes.contains(&resolved(&self.root.join(source.trim_start_matches('/')))) {
+                return Ok(Some(mountpoint.to_string()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn holders(&self, name: &str) -> Vec<String> {
+        let disk = self.sys_block(name);
+        std::iter::once(disk.clone())
+            .chain(self.partitions(name).into_iter().map(|partition| disk.join(partition)))
+            .flat_map(|node| std::fs::read_dir(node.join("holders")).into_iter().flatten().flatten())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn identity(&self, device: &DeviceInfo) -> DeviceIdentity {
+        let (logical, physical) = match File::open(&device.path) {
+            Ok(file) => (logical_block_size(&file), physical_block_size(&file)),
+            Err(_) => (512, 512),
+        };
+        DeviceIdentity {
+            path: device.path.clone(),
+            model: self.model(&device.name),
+            serial: self.serial(&device.name),
+            size: device.size,
+            logical_sector_size: logical,
+            physical_sector_size: physical,
+        }
+    }
+
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        std::fs::canonicalize(path).ok()
+    }
+}
+
+/// Drive letters, without the WinAPI calls a full enumeration would need
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowsProvider;
+
+#[cfg(windows)]
+impl DeviceProvider for WindowsProvider {
+    fn list(&self) -> Result<Vec<DeviceInfo>, EraseError> {
+        let mut devices = Vec::new();
+
+        for drive_letter in b'A'..=b'Z' {
+            let drive_path = format!("{}:", drive_letter as char);
+            let device_path = format!("\\\\.\\{}", drive_path);
+
+            // This is a simplified version - full Windows implementation would require
+            // more complex WinAPI calls to properly enumerate all storage devices
+            if Path::new(&format!("{}\\", drive_path)).exists() {
+                let info = DeviceInfo {
+                    path: PathBuf::from(device_path),
+                    name: drive_path,
+                    size: 0, // Would need WinAPI calls to get actual size
+                    is_removable: false, // Would need WinAPI calls to determine
+                    is_mounted: true,
+                    bus: None,
+                    fs_type: None,
+                    label: None,
+                    partitions: Vec::new(),
+                };
+                devices.push(info);
+            }
+        }
+
+        Ok(devices)
+    }
+
+    fn size(&self, path: &Path) -> Result<u64, EraseError> {
+        // For Windows, we'd need to use GetFileSizeEx or DeviceIoControl
+        // This is a simplified version
+        let file = File::open(path).map_err(|e| EraseError::open(path, e))?;
+        Ok(file.metadata()?.len())
+    }
+
+    fn mount_point(&self, _path: &Path) -> Result<Option<String>, EraseError> {
+        Ok(None)
+    }
+
+    fn holders(&self, _name: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn identity(&self, device: &DeviceInfo) -> DeviceIdentity {
+        DeviceIdentity {
+            path: device.path.clone(),
+            model: None,
+            serial: None,
+            size: device.size,
+            logical_sector_size: 512,
+            physical_sector_size: 512,
+        }
+    }
+
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        std::fs::canonicalize(path).ok()
+    }
+}
+
+/// Canned answers for tests: the devices it is given, plus whatever
+/// mounts, holders, serials and links are set up on it. Nothing is read
+/// from the system.
+#[derive(Debug, Clone, Default)]
+pub struct FakeDevices {
+    devices: Vec<DeviceInfo>,
+    mounts: Vec<(PathBuf, String)>,
+    holders: Vec<(String, String)>,
+    serials: Vec<(String, String)>,
+    links: Vec<(PathBuf, PathBuf)>,
+}
+
+impl FakeDevices {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn device(mut self, device: DeviceInfo) -> Self {
+        self.devices.push(device);
+        self
+    }
+
+    /// The disk at `path`, or a partition on it, is mounted on `mountpoint`
+    pub fn mounted(mut self, path: impl Into<PathBuf>, mountpoint: &str) -> Self {
+        self.mounts.push((path.into(), mountpoint.to_string()));
+        self
+    }
+
+    pub fn held_by(mut self, name: &str, holder: &str) -> Self {
+        self.holders.push((name.to_string(), holder.to_string()));
+        self
+    }
+
+    pub fn serial(mut self, name: &str, serial: &str) -> Self {
+        self.serials.push((name.to_string(), serial.to_string()));
+        self
+    }
+
+    /// A /dev/disk/by-id style link to `target`
+    pub fn link(mut self, link: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        self.links.push((link.into(), target.into()));
+        self
+    }
+
+    fn find(&self, path: &Path) -> Option<&DeviceInfo> {
+        let path = self.resolve(path)?;
+        self.devices.iter().find(|device| device.path == path)
+    }
+}
+
+impl DeviceProvider for FakeDevices {
+    fn list(&self) -> Result<Vec<DeviceInfo>, EraseError> {
+        self.devices.iter()
+            .map(|device| Ok(DeviceInfo { is_mounted: self.is_mounted(&device.path)?, ..device.clone() }))
+            .collect()
+    }
+
+    fn size(&self, path: &Path) -> Result<u64, EraseError> {
+        self.find(path).map(|device| device.size).ok_or_else(|| EraseError::NotFound { path: path.to_path_buf() })
+    }
+
+    fn mount_point(&self, path: &Path) -> Result<Option<String>, EraseError> {
+        let Some(device) = self.find(path) else {
+            return Ok(None);
+        };
+        Ok(self.mounts.iter().find(|(mounted, _)| *mounted == device.path).map(|(_, mountpoint)| mountpoint.clone()))
+    }
+
+    fn holders(&self, name: &str) -> Vec<String> {
+        self.holders.iter().filter(|(held, _)| held == name).map(|(_, holder)| holder.clone()).collect()
+    }
+
+    fn identity(&self, device: &DeviceInfo) -> DeviceIdentity {
+        DeviceIdentity {
+            path: device.path.clone(),
+            model: None,
+            serial: self.serials.iter().find(|(name, _)| *name == device.name).map(|(_, serial)| serial.clone()),
+            size: device.size,
+            logical_sector_size: 512,
+            physical_sector_size: 512,
+        }
+    }
+
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        let target = self.links.iter().find(|(link, _)| link == path).map_or(path, |(_, target)| target.as_path());
+        self.devices.iter().any(|device| device.path == target).then(|| target.to_path_buf())
+    }
+}
@@ -0,0 +1,562 @@
+//! Erase reports, the files they are written to, and the small formatting
+//! helpers they use
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{device::*, erase::*, patterns::*, ui::*, verify::*};
+use crate::*;
+
+/// --badblocks-out: one "offset length kind" line per range, in bytes, so
+/// any line can be handed straight to --offset and --length
+pub fn write_bad_block_map(path: &str, device: &Path, map: &[BadBlock]) -> io::Result<()> {
+    let mut out = format!("# memerase bad-block map for {}\n# offset length kind (write, unreadable or mismatch), in bytes\n",
+                          device.display());
+    for bad in map {
+        out.push_str(&format!("{} {} {}\n", bad.offset, bad.length, bad.kind));
+    }
+    std::fs::write(path, out)
+}
+
+/// The `--report` JSON: one record, or an array for a batch
+pub fn write_run_report(out: &mut dyn Write, records: &[RunRecord]) -> io::Result<()> {
+    match records {
+        [record] => serde_json::to_writer_pretty(&mut *out, record)?,
+        _ => serde_json::to_writer_pretty(&mut *out, records)?,
+    }
+    writeln!(out)
+}
+
+const CSV_HEADER: &str = "device,serial,model,capacity_bytes,method,passes,verification,started_at,finished_at,operator,result";
+
+/// Quote a CSV field when it holds a comma, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(record: &RunRecord) -> String {
+    let verification = match record.outcome.verified {
+        Some(true) => "passed",
+        Some(false) => "failed",
+        None => "not verified",
+    };
+    let result = if record.succeeded {
+        "success".to_string()
+    } else if !record.outcome.attempted {
+        "not attempted".to_string()
+    } else {
+        format!("failed: {}", record.outcome.error.as_deref().unwrap_or("unknown error"))
+    };
+    [
+        record.device.path.display().to_string(),
+        record.device.serial.clone().unwrap_or_default(),
+        record.device.model.clone().unwrap_or_default(),
+        record.device.size.to_string(),
+        match record.report.as_ref().map(|report| report.scope) {
+            Some(EraseScope::Quick) => format!("{:?} (quick)", record.method),
+            Some(EraseScope::Partial) => format!("{:?} (partial)", record.method),
+            _ => format!("{:?}", record.method),
+        },
+        record.passes.to_string(),
+        verification.to_string(),
+        record.started_at.clone(),
+        record.finished_at.clone(),
+        record.operator.clone(),
+        result,
+    ]
+    .iter()
+    .map(|field| csv_field(field))
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+/// Append one row per device to the `--report-csv` file. Each row goes out
+/// in a single O_APPEND write so concurrent runs can share the file.
+pub fn append_csv_report(path: &str, records: &[RunRecord]) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    // Only the run that finds the file empty writes the header
+    if file.metadata()?.len() == 0 {
+        file.write_all(format!("{}\n", CSV_HEADER).as_bytes())?;
+    }
+    for record in records {
+        file.write_all(format!("{}\n", csv_row(record)).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// --status-file: a small JSON document for monitors that poll a file
+/// rather than talk to a daemon. It is replaced whole (temporary file, then
+/// rename), so a reader never sees half of one, and `updated_at` tells a
+/// live run from a stalled or dead one.
+#[derive(Clone)]
+pub struct StatusFile {
+    path: PathBuf,
+    device: PathBuf,
+    target: (usize, usize),  // 1-based position in the batch, and its size
+    written: Option<(std::time::Instant, ProgressPhase, usize)>,  // When, in which phase and pass
+    warned: bool,  // A failed write is reported once, not every few seconds
+}
+
+impl StatusFile {
+    pub fn new(path: &str) -> Self {
+        StatusFile { path: PathBuf::from(path), device: PathBuf::new(), target: (0, 0), written: None, warned: false }
+    }
+
+    /// The same file, about the `index`th of `count` targets
+    pub fn for_device(&self, device: &Path, index: usize, count: usize) -> Self {
+        StatusFile { device: device.to_path_buf(), target: (index + 1, count), written: None, ..self.clone() }
+    }
+
+    /// Rewritten when STATUS_INTERVAL has passed, and at once whenever the
+    /// phase or the pass changes
+    pub fn progress(&mut self, update: &ProgressUpdate) {
+        let due = self.written.is_none_or(|(at, phase, pass)| {
+            at.elapsed() >= STATUS_INTERVAL || phase != update.phase || pass != update.pass
+        });
+        if !due {
+            return;
+        }
+        self.written = Some((std::time::Instant::now(), update.phase, update.pass));
+        self.write(serde_json::json!({
+            "state": "running",
+            "phase": update.phase,
+            "pass": update.pass,
+            "passes": update.passes,
+            "bytes_done": update.bytes_done,
+            "total_bytes": update.total_bytes,
+            "percent": update.percent(),
+            "rate_mb_s": update.rate / (1024.0 * 1024.0),
+            "eta_secs": update.eta_secs,
+        }));
+    }
+
+    /// The device is done with: "finished", or "failed" with why
+    pub fn finish(&mut self, error: Option<&str>) {
+        self.write(match error {
+            None => serde_json::json!({ "state": "finished" }),
+            Some(error) => serde_json::json!({ "state": "failed", "error": error }),
+        });
+    }
+
+    fn write(&mut self, mut status: serde_json::Value) {
+        status["device"] = self.device.display().to_string().into();
+        status["target"] = self.target.0.into();
+        status["targets"] = self.target.1.into();
+        status["pid"] = std::process::id().into();
+        status["updated_at"] = unix_time().into();
+
+        // Not synced: a monitor only ever needs the latest state, and a
+        // flush every few seconds would compete with the erase
+        let mut temp = self.path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let written = std::fs::write(&temp, status.to_string() + "\n").and_then(|()| std::fs::rename(&temp, &self.path));
+        if let Err(e) = written {
+            if !std::mem::replace(&mut self.warned, true) {
+                warn!("Warning: could not update status file {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Timing and volume of one completed pass
+#[derive(Debug, Clone, Serialize)]
+pub struct PassReport {
+    pub pass: usize,
+    pub pattern: String,
+    pub seed: Option<String>,
+    pub bytes_written: u64,
+    pub blocks_written: u64,
+    pub blocks_skipped: u64,  // Already held the pattern (--skip-matching)
+    pub duration_secs: f64,
+    pub throughput_mb_s: f64,
+    pub min_mb_s: Option<f64>,  // Rolling-window extremes; None for passes shorter than the window
+    pub max_mb_s: Option<f64>,
+}
+
+/// What `--report` writes for each device. It is produced for failed runs
+/// too, in which case `report` is missing and `outcome` says why.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub tool_version: String,
+    pub hostname: String,
+    pub device: DeviceIdentity,
+    pub method: WipePattern,
+    pub passes: usize,
+    pub operator: String,
+    pub started_at: String,           // RFC 3339, UTC
+    pub finished_at: String,
+    pub succeeded: bool,
+    pub outcome: BatchResult,
+    pub report: Option<EraseReport>,
+}
+
+/// Everything that happened during one `secure_erase` run
+#[derive(Debug, Clone, Serialize)]
+pub struct EraseReport {
+    pub device: PathBuf,
+    pub device_size: u64,
+    pub method: WipePattern,
+    pub sync_mode: SyncMode,
+    pub direct_io: bool,
+    pub fua: bool,  // Writes bypassed the drive's volatile cache
+    pub io_backend: IoBackend,
+    pub block_size: usize,
+    pub write_size: Option<usize>,  // Cap on each write after the device failed larger ones
+    pub gen_threads: usize,
+    pub auto_tune: Option<AutoTuneResult>,
+    pub seed: u64,  // Of the eraser's RNG; --seed with this value repeats the random data
+    pub passes: Vec<PassReport>,
+    pub bytes_written: u64,
+    pub verification: Option<VerifyResult>,
+    pub stamped: bool,
+    pub partition_table: Option<NewPartitionTable>,  // Written by --init-table
+    pub filesystem: Option<NewFilesystem>,           // Written by --format-after
+    pub pre_wipe: Option<PreWipeFingerprint>,        // --pre-hash, read before the first write
+    pub unwritable: Vec<ByteRange>,  // Sectors that kept failing to write and were skipped, in any pass
+    pub bad_blocks: Vec<BadBlock>,   // `unwritable` plus what verification could not read or found changed
+    pub write_cache: Option<WriteCacheState>,  // Only when --disable-write-cache was used
+    pub confirmation: Option<Confirmation>,    // Filled in by the caller that asked
+
+    pub started_at: u64,      // Seconds since the Unix epoch, UTC
+    pub finished_at: u64,
+    pub paused_secs: f64,     // Time spent paused, not counted in pass durations
+    pub warnings: Vec<String>,
+    pub stats: RunStats,
+    pub scope: EraseScope,
+    pub regions: Vec<ByteRange>,  // What a quick or range wipe covered; empty for a full erase
+}
+
+/// Totals over the whole run. Computed once, so the console summary and the
+/// JSON report can't disagree.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunStats {
+    pub wall_secs: f64,
+    pub write_secs: f64,               // Sum of the pass durations
+    pub average_mb_s: f64,
+    pub worst_mb_s: Option<f64>,       // Slowest rolling window (or pass) of any pass
+    pub expected_bytes: u64,           // Device size times passes
+    pub verified_percent: Option<f64>,
+    pub retries: u64,
+    pub warnings: usize,
+}
+
+impl RunStats {
+    pub(crate) fn from_report(report: &EraseReport, wall_secs: f64, retries: u64) -> Self {
+        let write_secs: f64 = report.passes.iter().map(|pass| pass.duration_secs).sum();
+        let average_mb_s = if write_secs > 0.0 {
+            report.bytes_written as f64 / write_secs / (1024.0 * 1024.0)
+        } else {
+            0.0
+        };
+        let worst_mb_s = report.passes.iter()
+            .map(|pass| pass.min_mb_s.unwrap_or(pass.throughput_mb_s))
+            .reduce(f64::min);
+        RunStats {
+            wall_secs,
+            write_secs,
+            average_mb_s,
+            worst_mb_s,
+            expected_bytes: match report.scope {
+                EraseScope::Sanitized => report.device_size * report.passes.len() as u64,
+                EraseScope::Quick => report.regions.iter().map(|region| region.length).sum(),
+                EraseScope::Partial => report.regions.iter().map(|region| region.length).sum::<u64>() * report.passes.len() as u64,
+            },
+            verified_percent: report.verification.as_ref().map(|v| v.coverage_percent),
+            retries,
+            warnings: report.warnings.len(),
+        }
+    }
+}
+
+impl EraseReport {
+    pub fn verification_passed(&self) -> Option<bool> {
+        self.verification.as_ref().map(|v| v.passed())
+    }
+}
+
+/// Outcome of one target in a multi-device run
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub device: PathBuf,
+    pub size: u64,
+    pub attempted: bool,         // False when --fail-fast or an interrupt stopped the batch first
+    pub verified: Option<bool>,  // None when verification wasn't requested or didn't run
+    pub error: Option<String>,
+    pub errno: Option<i32>,
+    pub offset: Option<u64>,     // Device offset of the failed write, if known
+    pub unwritable: u64,         // Bytes skipped after repeated write failures
+    pub duration_secs: f64,
+}
+
+impl BatchResult {
+    pub fn succeeded(&self) -> bool {
+        self.attempted && self.error.is_none() && self.verified != Some(false)
+    }
+}
+
+/// Printed after every quick wipe, so nobody mistakes it for sanitization
+const QUICK_NOTICE: &str = "\
+*** QUICK WIPE ONLY - THIS DISK IS NOT SANITIZED ***
+Partition tables and filesystem headers were overwritten, so the disk looks
+blank, but everything else on it is still there and can be recovered with
+ordinary forensic tools. Use --method full before the disk leaves your control.";
+
+/// Console summary of a finished erase
+pub fn print_report(report: &EraseReport) {
+    if verbosity() == 0 {
+        print_result_line(report);
+        return;
+    }
+    human!("\nErase summary for {}", report.device.display());
+    human!("  Method:        {:?}", report.method);
+    match report.scope {
+        EraseScope::Sanitized => {}
+        EraseScope::Quick => human!("  Scope:         QUICK - {} regions, {} of {}", report.regions.len(),
+                                      format_size(report.bytes_written), format_size(report.device_size)),
+        EraseScope::Partial => {
+            let range = report.regions[0];
+            human!("  Scope:         PARTIAL - {}, the rest of the device was not touched", describe_range(range));
+        }
+    }
+    human!("  Sync mode:     {:?}", report.sync_mode);
+    human!("  Direct I/O:    {}", if report.direct_io { "yes" } else { "no" });
+    human!("  I/O backend:   {:?}", report.io_backend);
+    human!("  FUA writes:    {}", if report.fua { "yes" } else { "no" });
+    if let Some(ref confirmation) = report.confirmation {
+        human!("  Confirmation:  {} by {}", confirmation.method, confirmation.user);
+    }
+    if let Some(cache) = report.write_cache {
+        human!("  Write cache:   {} before, disabled during, {}",
+                 if cache.before { "enabled" } else { "disabled" },
+                 if cache.restored { "restored" } else { "NOT restored" });
+    }
+    human!("  Block size:    {} bytes{}", report.block_size,
+             if report.auto_tune.is_some() { " (auto-tuned)" } else { "" });
+    if let Some(size) = report.write_size {
+        human!("  Write size:    cut to {} after the device failed larger writes, so throughput is lower", format_size(size as u64));
+    }
+    human!("  Device size:   {}", size_label(report.device_size));
+    if !report.unwritable.is_empty() {
+        let bytes: u64 = report.unwritable.iter().map(|bad| bad.length).sum();
+        human!("  Unwritable:    {} in {} range(s), skipped and NOT erased", format_size(bytes), report.unwritable.len());
+        for bad in report.unwritable.iter().take(10) {
+            human!("                 {} bytes at offset {}", bad.length, bad.offset);
+        }
+        if report.unwritable.len() > 10 {
+            human!("                 ... and {} more (see the JSON report)", report.unwritable.len() - 10);
+        }
+    }
+    if let Some(ref pre_wipe) = report.pre_wipe {
+        let unreadable = pre_wipe.regions.iter().filter(|region| region.sha256.is_none()).count();
+        human!("  Pre-wipe:      {} regions of {} hashed before the first write, seed {}{}{}",
+                 pre_wipe.regions.len(), format_size(pre_wipe.region_size), pre_wipe.seed,
+                 if unreadable > 0 { format!(", {} unreadable", unreadable) } else { String::new() },
+                 if pre_wipe.complete { "" } else { " (stopped at the time limit)" });
+    }
+    let stats = &report.stats;
+    human!("  Bytes written: {} of {} expected", size_label(report.bytes_written), size_label(stats.expected_bytes));
+    human!("  Wall time:     {} ({} writing)", format_duration(stats.wall_secs), format_duration(stats.write_secs));
+    human!("  Throughput:    {:.1} MB/s average{}", stats.average_mb_s,
+             stats.worst_mb_s.map_or(String::new(), |worst| format!(", {:.1} MB/s worst", worst)));
+    if stats.retries > 0 {
+        human!("  Retries:       {}", stats.retries);
+    }
+    if report.paused_secs > 0.0 {
+        human!("  Paused:        {}", format_duration(report.paused_secs));
+    }
+
+    human!("  Generators:    {} threads for random passes", report.gen_threads);
+    for pass in &report.passes {
+        let range = match (pass.min_mb_s, pass.max_mb_s) {
+            (Some(min), Some(max)) => format!("  (min {:.1} / max {:.1})", min, max),
+            _ => String::new(),
+        };
+        human!("  Pass {:<3} {:<12} {:>10.1} s {:>10.1} MB/s avg{}",
+                 pass.pass, pass.pattern, pass.duration_secs, pass.throughput_mb_s, range);
+        if pass.blocks_skipped > 0 {
+            human!("           {} blocks written, {} skipped (already matching)",
+                     pass.blocks_written, pass.blocks_skipped);
+        }
+    }
+
+    let span = if report.scope == EraseScope::Partial { "range" } else { "device" };
+    let coverage = stats.verified_percent.map_or(String::new(), |percent| format!(", {:.1}% of the {} read back", percent, span));
+    match report.verification_passed() {
+        Some(true) => human!("  Verification:  passed{}", coverage),
+        Some(false) => human!("  Verification:  FAILED{}", coverage),
+        None => human!("  Verification:  not run"),
+    }
+    if report.stamped {
+        human!("  Erase stamp:   written");
+    }
+    if let Some(ref table) = report.partition_table {
+        let id = table.disk_guid.as_ref().map(|guid| format!("disk GUID {}", guid))
+            .or_else(|| table.disk_signature.as_ref().map(|signature| format!("disk signature 0x{}", signature)))
+            .unwrap_or_default();
+        human!("  New table:     empty {}, {}", table.kind, id);
+    }
+    if let Some(ref filesystem) = report.filesystem {
+        let label = filesystem.label.as_ref().map_or(String::new(), |label| format!(", label {}", label));
+        human!("  Filesystem:    {}{}, volume ID {}", filesystem.kind, label, filesystem.volume_id);
+    }
+    for warning in &report.warnings {
+        human!("  Warning: {}", warning);
+    }
+    if report.scope == EraseScope::Quick {
+        human!("\n{}", QUICK_NOTICE);
+    }
+    human!();
+}
+
+/// The one line `--quiet` keeps: what was erased and how it went
+pub fn print_result_line(report: &EraseReport) {
+    if report.scope == EraseScope::Quick {
+        human!("{}: QUICK wipe, {} of {} overwritten in {} s; NOT sanitized, the data is still recoverable",
+                 report.device.display(), format_size(report.bytes_written), format_size(report.device_size),
+                 report.finished_at.saturating_sub(report.started_at));
+        return;
+    }
+    let verification = match report.verification_passed() {
+        Some(true) => "verification passed",
+        Some(false) => "verification FAILED",
+        None => "not verified",
+    };
+    let scope = match report.regions.first() {
+        Some(range) if report.scope == EraseScope::Partial => format!(" (PARTIAL: bytes {}..{} only)", range.offset, range.end()),
+        _ => String::new(),
+    };
+    let unwritable = match report.unwritable.iter().map(|bad| bad.length).sum::<u64>() {
+        0 => String::new(),
+        bytes => format!(", completed with {} of unwritable sectors skipped", format_size(bytes)),
+    };
+    human!("{}: erased with {:?}, {} pass(es), {} bytes in {} s, {}{}{}",
+             report.device.display(), report.method, report.passes.len(), report.bytes_written,
+             report.finished_at.saturating_sub(report.started_at), verification, scope, unwritable);
+}
+
+/// How the destructive operation was approved, kept for the audit trail
+#[derive(Debug, Clone, Serialize)]
+pub struct Confirmation {
+    pub method: String,   // "interactive" or "skipped (--yes)"
+    pub user: String,     // Account that invoked the erase
+}
+
+/// UTC time in RFC 3339 form, e.g. "2024-05-01T12:00:00Z"
+pub fn rfc3339(secs: u64) -> String {
+    // Days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let time = secs % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+/// Parse a byte count with an optional binary suffix: "4096", "512K", "4M", "1.5T"
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let multiplier: u64 = match suffix.trim().to_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        "P" => 1 << 50,
+        _ => return Err(format!("Invalid size suffix: {}", s)),
+    };
+    // Whole and fractional parts kept as integers so "1.5T" is exact
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if (whole.is_empty() && fraction.is_empty()) || fraction.contains('.') {
+        return Err(format!("Invalid size: {}", s));
+    }
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| format!("Invalid size: {}", s))? };
+    let too_large = || format!("Size too large: {}", s);
+    let mut bytes = whole.checked_mul(multiplier).ok_or_else(too_large)?;
+    if !fraction.is_empty() {
+        let scale = 10u128.checked_pow(fraction.len() as u32).ok_or_else(|| format!("Invalid size: {}", s))?;
+        let numerator = fraction.parse::<u128>().map_err(|_| format!("Invalid size: {}", s))?
+            .checked_mul(multiplier as u128).ok_or_else(|| format!("Invalid size: {}", s))?;
+        if !numerator.is_multiple_of(scale) {
+            return Err(format!("Size is not a whole number of bytes: {}", s));
+        }
+        bytes = bytes.checked_add(u64::try_from(numerator / scale).map_err(|_| too_large())?).ok_or_else(too_large)?;
+    }
+    Ok(bytes)
+}
+
+/// Binary units to one decimal place: "512 B", "4.0 KiB", "465.8 GiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Step up before rounding would print "1024.0"
+    while value >= 1023.95 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } == 0 {
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            return String::from_utf8_lossy(&name[..len]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")).unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Name of the account running the tool, for audit records
+pub fn invoking_user() -> String {
+    #[cfg(unix)]
+    {
+        // Prefer the real user behind sudo over "root"
+        if let Ok(user) = std::env::var("SUDO_USER") {
+            return format!("{} (via sudo, uid {})", user, unsafe { libc::getuid() });
+        }
+        let uid = unsafe { libc::getuid() };
+        let passwd = unsafe { libc::getpwuid(uid) };
+        let name = if passwd.is_null() {
+            std::env::var("USER").unwrap_or_else(|_| "unknown".to_string())
+        } else {
+            unsafe { std::ffi::CStr::from_ptr((*passwd).pw_name) }.to_string_lossy().into_owned()
+        };
+        format!("{} (uid {})", name, uid)
+    }
+
+    #[cfg(windows)]
+    {
+        std::env::var("USERNAME").unwrap_or_else(|_| "unknown".to_string())
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
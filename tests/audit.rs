@@ -0,0 +1,41 @@
+//! The audit log's hash chain, and what happens to it when the last record
+//! was cut short or garbled
+
+mod common;
+
+use common::TempFile;
+use memerase::audit::{audit, open_audit_log, verify_audit_log};
+
+#[test]
+fn a_broken_last_record_stops_further_appends() {
+    // The log is opened once per process, so this is all one test
+    let log = TempFile::new("audit.log", b"");
+    let path = &log.path;
+    open_audit_log(path);
+    for device in ["/dev/sdb", "/dev/sdc"] {
+        audit(serde_json::json!({ "event": "device", "device": device }));
+    }
+    let (count, _) = verify_audit_log(path).unwrap();
+    assert_eq!(count, 2);
+
+    // A write torn part way through the second record
+    let intact = std::fs::read(path).unwrap();
+    let torn = &intact[..intact.len() - 20];
+    std::fs::write(path, torn).unwrap();
+    audit(serde_json::json!({ "event": "device", "device": "/dev/sdd" }));
+    assert_eq!(std::fs::read(path).unwrap(), torn, "nothing may be chained onto a torn record");
+    assert!(verify_audit_log(path).is_err());
+
+    // A complete line that isn't an entry
+    let mut garbled = intact.clone();
+    garbled.extend_from_slice(b"{\"seq\": 2, \"ti\n");
+    std::fs::write(path, &garbled).unwrap();
+    audit(serde_json::json!({ "event": "device", "device": "/dev/sdd" }));
+    assert_eq!(std::fs::read(path).unwrap(), garbled, "nothing may be chained onto a garbled record");
+
+    // Once the log is repaired, the chain carries on from the last entry
+    std::fs::write(path, &intact).unwrap();
+    audit(serde_json::json!({ "event": "device", "device": "/dev/sdd" }));
+    let (count, _) = verify_audit_log(path).unwrap();
+    assert_eq!(count, 3);
+}
@@ -0,0 +1,214 @@
+//! Fixtures shared by the integration tests: scratch files and directories
+//! that clean up after themselves, a loop device over one, and a writer
+//! that follows a script
+#![allow(dead_code)]  // Each test binary uses only some of them
+
+use std::collections::VecDeque;
+use std::io::{self, IoSlice, Write};
+use std::path::{Path, PathBuf};
+
+/// What the erase tests fill their files with, to look for afterwards
+pub const SECRET: &[u8; 16] = b"memerase secret!";
+
+/// `size` bytes of SECRET over and over
+pub fn secret(size: u64) -> Vec<u8> {
+    SECRET.iter().copied().cycle().take(size as usize).collect()
+}
+
+/// A name in `dir` no other test process will pick
+fn unique(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("memerase-{}-{}", name, std::process::id()))
+}
+
+/// A file with the given contents, removed when dropped
+pub struct TempFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl TempFile {
+    pub fn new(name: &str, contents: &[u8]) -> Self {
+        Self::in_dir(&std::env::temp_dir(), name, contents)
+    }
+
+    pub fn in_dir(dir: &Path, name: &str, contents: &[u8]) -> Self {
+        let path = unique(dir, name);
+        std::fs::write(&path, contents).unwrap();
+        TempFile { path, size: contents.len() as u64 }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// An empty directory, removed with everything in it when dropped
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        Self::in_dir(&std::env::temp_dir(), name)
+    }
+
+    pub fn in_dir(dir: &Path, name: &str) -> Self {
+        let path = unique(dir, name);
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    /// Write a file below the directory, creating its parents
+    pub fn write(&self, path: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    pub fn link(&self, link: &str, target: &str) {
+        let link = self.path.join(link);
+        std::fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(target, link).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// /dev/shm, or None with a note when it isn't a tmpfs
+pub fn shm() -> Option<PathBuf> {
+    let shm = PathBuf::from("/dev/shm");
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut fs: libc::statfs = unsafe { std::mem::zeroed() };
+        let c_path = std::ffi::CString::new(shm.as_os_str().as_bytes()).unwrap();
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut fs) } == 0 && fs.f_type == libc::TMPFS_MAGIC {
+            return Some(shm);
+        }
+    }
+    eprintln!("skipped: {} is not a tmpfs", shm.display());
+    None
+}
+
+/// A loop device over a backing file full of SECRET, detached and removed
+/// when dropped
+#[cfg(target_os = "linux")]
+pub struct LoopDevice {
+    pub path: PathBuf,
+    backing: TempFile,  // Removed after the Drop below detaches the device
+}
+
+#[cfg(target_os = "linux")]
+impl LoopDevice {
+    /// None, with a note, where loop devices can't be set up
+    pub fn new(name: &str, size: u64, mbr: Option<[u8; 512]>) -> Option<Self> {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped: loop devices need root");
+            return None;
+        }
+        let mut contents = secret(size);
+        if let Some(mbr) = mbr {
+            contents[..512].copy_from_slice(&mbr);
+        }
+        let backing = TempFile::new(&format!("loop-{}", name), &contents);
+
+        let attached = std::process::Command::new("losetup")
+            .args(["--find", "--show", "--partscan"]).arg(&backing.path).output();
+        match attached {
+            Ok(out) if out.status.success() => {
+                let path = PathBuf::from(String::from_utf8_lossy(&out.stdout).trim());
+                Some(LoopDevice { path, backing })
+            }
+            _ => {
+                eprintln!("skipped: losetup could not attach a loop device");
+                None
+            }
+        }
+    }
+
+    pub fn partition(&self, number: u32) -> PathBuf {
+        PathBuf::from(format!("{}p{}", self.path.display(), number))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for LoopDevice {
+    fn drop(&mut self) {
+        let _ = std::process::Command::new("losetup").arg("-d").arg(&self.path).status();
+    }
+}
+
+/// What a `Scripted` writer does with each call
+#[derive(Clone, Copy)]
+pub enum Step {
+    Accept(usize),  // Take at most this many bytes
+    Interrupt,      // Fail with EINTR
+    Fail,           // Fail for good
+}
+
+/// A writer that follows its script, then takes everything it is given
+pub struct Scripted {
+    steps: VecDeque<Step>,
+    vectored: bool,                // Else only the first non-empty slice, like the default write_vectored
+    pub received: Vec<u8>,
+    pub resumed_at: Vec<usize>,    // How much had been taken when each call came in
+    pub iovecs: Vec<Vec<usize>>,   // The slice lengths of each call
+}
+
+impl Scripted {
+    pub fn new(steps: &[Step]) -> Self {
+        Scripted {
+            steps: steps.iter().copied().collect(),
+            vectored: true,
+            received: Vec::new(),
+            resumed_at: Vec::new(),
+            iovecs: Vec::new(),
+        }
+    }
+
+    /// One that writes a single slice per vectored call
+    pub fn single_slice(steps: &[Step]) -> Self {
+        Scripted { vectored: false, ..Self::new(steps) }
+    }
+}
+
+impl Write for Scripted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut bufs: Vec<&[u8]> = bufs.iter().map(|buf| &**buf).collect();
+        if !self.vectored {
+            bufs = bufs.into_iter().find(|buf| !buf.is_empty()).into_iter().collect();
+        }
+        self.iovecs.push(bufs.iter().map(|buf| buf.len()).collect());
+        self.resumed_at.push(self.received.len());
+        let limit = match self.steps.pop_front() {
+            Some(Step::Accept(limit)) => limit,
+            Some(Step::Interrupt) => return Err(io::Error::from(io::ErrorKind::Interrupted)),
+            Some(Step::Fail) => return Err(io::Error::other("device went away")),
+            None => usize::MAX,
+        };
+        let before = self.received.len();
+        for buf in bufs {
+            let room = limit - (self.received.len() - before);
+            self.received.extend_from_slice(&buf[..buf.len().min(room)]);
+        }
+        Ok(self.received.len() - before)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+//! Loading a config file: the protected list under its current and old names
+
+mod common;
+
+use common::TempFile;
+use memerase::{Config, EraseError};
+
+fn config_file(name: &str, text: &str) -> TempFile {
+    TempFile::new(&format!("config-{}.toml", name), text.as_bytes())
+}
+
+#[test]
+fn the_old_blacklist_name_still_protects() {
+    let file = config_file("blacklist", "blacklist = [\"S3EVNX0K123456\"]\n");
+    let config = Config::load(Some(&file.path)).unwrap();
+    assert_eq!(config.protected, ["S3EVNX0K123456"]);
+    assert!(config.warnings().is_empty(), "{:?}", config.warnings());
+    assert_eq!(config.source("protected"), format!("config {}", file.path.display()));
+}
+
+#[test]
+fn protected_and_blacklist_in_one_file_are_both_kept() {
+    let file = config_file("both", "protected = [\"S3EVNX0K123456\", \"naa.5000c500a1b2c3d4\"]\n\
+                                   blacklist = [\"/dev/disk/by-id/ata-SYSTEM\"]\n");
+    let config = Config::load(Some(&file.path)).unwrap();
+    assert_eq!(config.protected, ["S3EVNX0K123456", "naa.5000c500a1b2c3d4", "/dev/disk/by-id/ata-SYSTEM"]);
+    assert!(config.warnings().is_empty(), "{:?}", config.warnings());
+}
+
+#[test]
+fn a_blacklist_of_the_wrong_type_names_the_file() {
+    let file = config_file("bad-blacklist", "protected = [\"S3EVNX0K123456\"]\nblacklist = \"S3EVNX0K654321\"\n");
+    match Config::load(Some(&file.path)) {
+        Err(EraseError::InvalidArgument(message)) => {
+            assert!(message.starts_with(&format!("Invalid config {}", file.path.display())), "{}", message);
+            assert!(message.contains("blacklist"), "{}", message);
+        }
+        other => panic!("expected an invalid config, got {:?}", other),
+    }
+}
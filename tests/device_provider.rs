@@ -0,0 +1,231 @@
+//! The safety checks against a fixture /sys, /proc and /dev, and against
+//! canned answers
+#![cfg(unix)]
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::TempDir;
+use memerase::device::{probe_filesystem, resolve_target, BusType};
+use memerase::{DeviceInfo, DeviceProvider, FakeDevices, SecureEraser, SysfsProvider};
+
+/// A scratch root holding a fixed SATA disk (sda), a USB stick (sdb) with a
+/// mounted partition under LVM, and a loop device
+struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let fixture = Fixture { dir: TempDir::new(name) };
+
+        fixture.disk("sda", "devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0/block/sda", 4096, false);
+        fixture.dir.write("sys/block/sda/device/model", "Samsung SSD 870\n");
+        fixture.dir.write("sys/block/sda/device/serial", "S5SSNF0T123456\n");
+
+        fixture.disk("sdb", "devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/host6/block/sdb", 8192, true);
+        fixture.dir.write("sys/block/sdb/device/model", "Cruzer Blade\n");
+        fixture.dir.write("sys/block/sdb/sdb1/partition", "1\n");
+        fixture.dir.write("sys/block/sdb/sdb1/start", "2048\n");
+        fixture.dir.write("sys/block/sdb/sdb1/holders/dm-0", "");
+        fixture.dir.write("dev/sdb1", "");
+        fixture.dir.link("dev/disk/by-id/usb-SanDisk_Cruzer_Blade-0:0", "../../sdb");
+        fixture.dir.write("proc/mounts", "/dev/sda1 / ext4 rw 0 0\n/dev/sdb1 /media/stick vfat rw 0 0\ntmpfs /tmp tmpfs rw 0 0\n");
+
+        fixture.disk("loop0", "devices/virtual/block/loop0", 512, false);
+        fixture
+    }
+
+    /// A disk's sysfs node where the kernel would put it, linked from
+    /// /sys/block, and a node of `size` bytes in /dev
+    fn disk(&self, name: &str, node: &str, size: usize, removable: bool) {
+        self.dir.write(&format!("sys/{}/removable", node), if removable { "1\n" } else { "0\n" });
+        self.dir.link(&format!("sys/block/{}", name), &format!("../{}", node));
+        self.dir.write(&format!("dev/{}", name), "\0".repeat(size));
+    }
+
+    fn provider(&self) -> SysfsProvider {
+        SysfsProvider::with_root(&self.dir.path)
+    }
+}
+
+#[test]
+fn lists_disks_but_not_loop_devices() {
+    let fixture = Fixture::new("list");
+    let devices = fixture.provider().list().unwrap();
+
+    let names: Vec<&str> = devices.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(names, ["sda", "sdb"]);
+    assert_eq!(devices[0].path, fixture.dir.path.join("dev/sda"));
+    assert_eq!(devices[0].size, 4096);
+    assert_eq!(devices[0].bus, Some(BusType::Sata));
+    assert!(!devices[0].is_removable);
+    assert_eq!(devices[1].bus, Some(BusType::Usb));
+    assert!(devices[1].is_removable);
+}
+
+#[test]
+fn a_mounted_partition_marks_its_disk_mounted() {
+    let fixture = Fixture::new("mounts");
+    let provider = fixture.provider();
+
+    assert_eq!(provider.mount_point(&fixture.dir.path.join("dev/sdb")).unwrap().as_deref(), Some("/media/stick"));
+    // sda1 is in /proc/mounts but not in the fixture's sysfs
+    assert_eq!(provider.mount_point(&fixture.dir.path.join("dev/sda")).unwrap(), None);
+    assert!(provider.list().unwrap().iter().any(|d| d.name == "sdb" && d.is_mounted));
+}
+
+#[test]
+fn holders_of_partitions_count_for_the_disk() {
+    let fixture = Fixture::new("holders");
+    let provider = fixture.provider();
+
+    assert_eq!(provider.holders("sdb"), ["dm-0"]);
+    assert!(provider.holders("sda").is_empty());
+}
+
+#[test]
+fn identity_comes_from_sysfs() {
+    let fixture = Fixture::new("identity");
+    let provider = fixture.provider();
+    let devices = provider.list().unwrap();
+
+    let identity = provider.identity(&devices[0]);
+    assert_eq!(identity.model.as_deref(), Some("Samsung SSD 870"));
+    assert_eq!(identity.serial.as_deref(), Some("S5SSNF0T123456"));
+    assert_eq!(identity.size, 4096);
+    assert_eq!(provider.identity(&devices[1]).serial, None);
+}
+
+#[test]
+fn by_id_links_resolve_to_their_disk() {
+    let fixture = Fixture::new("by-id");
+    let provider = fixture.provider();
+    let devices = provider.list().unwrap();
+
+    let link = fixture.dir.path.join("dev/disk/by-id/usb-SanDisk_Cruzer_Blade-0:0");
+    let target = resolve_target(&provider, &link, &devices, false).unwrap();
+    assert_eq!(target.name, "sdb");
+    assert!(resolve_target(&provider, &fixture.dir.path.join("dev/sdz"), &devices, false).is_err());
+}
+
+#[test]
+fn partitions_are_listed_with_their_filesystems() {
+    let fixture = Fixture::new("filesystems");
+
+    // ext4 straight on sda, no partition table
+    let mut ext4 = vec![0u8; 4096];
+    ext4[1024 + 0x38..1024 + 0x3a].copy_from_slice(&0xef53u16.to_le_bytes());
+    ext4[1024 + 0x60] = 0x40;  // Extents
+    ext4[1024 + 0x78..1024 + 0x7e].copy_from_slice(b"rootfs");
+    fixture.dir.write("dev/sda", &ext4);
+
+    let mut fat32 = vec![0u8; 8192];
+    fat32[3..11].copy_from_slice(b"MSWIN4.1");
+    fat32[71..82].copy_from_slice(b"BACKUPS    ");
+    fat32[82..90].copy_from_slice(b"FAT32   ");
+    fat32[510..512].copy_from_slice(&[0x55, 0xaa]);
+    fixture.dir.write("dev/sdb1", &fat32);
+    fixture.dir.write("sys/block/sdb/sdb1/size", "2048\n");
+    // A partition with no node to open is listed, just not probed
+    fixture.dir.write("sys/block/sdb/sdb2/partition", "2\n");
+
+    let devices = fixture.provider().list().unwrap();
+    assert_eq!(devices[0].fs_type.as_deref(), Some("ext4"));
+    assert_eq!(devices[0].label.as_deref(), Some("rootfs"));
+    assert!(devices[0].partitions.is_empty());
+
+    assert_eq!(devices[1].fs_type, None);
+    let partitions = &devices[1].partitions;
+    let names: Vec<&str> = partitions.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["sdb1", "sdb2"]);
+    assert_eq!(partitions[0].path, fixture.dir.path.join("dev/sdb1"));
+    assert_eq!(partitions[0].size, 2048 * 512);
+    assert_eq!(partitions[0].fs_type.as_deref(), Some("vfat"));
+    assert_eq!(partitions[0].label.as_deref(), Some("BACKUPS"));
+    assert_eq!((partitions[1].fs_type.as_deref(), partitions[1].label.as_deref()), (None, None));
+}
+
+#[test]
+fn ntfs_labels_come_from_the_volume_record() {
+    let fixture = Fixture::new("ntfs");
+
+    // 512 byte sectors, 4 KiB clusters, the MFT at cluster 4, 1 KiB records
+    let mut volume = vec![0u8; 32768];
+    volume[3..11].copy_from_slice(b"NTFS    ");
+    volume[11..13].copy_from_slice(&512u16.to_le_bytes());
+    volume[13] = 8;
+    volume[48..56].copy_from_slice(&4u64.to_le_bytes());
+    volume[64] = -10i8 as u8;
+    volume[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+    // $Volume, with its $VOLUME_NAME straddling the first sector's end so
+    // the update sequence has to be undone to read it
+    let mut record = vec![0u8; 1024];
+    record[..4].copy_from_slice(b"FILE");
+    record[4..6].copy_from_slice(&0x30u16.to_le_bytes());
+    record[6..8].copy_from_slice(&3u16.to_le_bytes());
+    record[0x14..0x16].copy_from_slice(&0x1e0u16.to_le_bytes());
+    let name: Vec<u8> = "Customer backups".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let attribute = 0x1e0;
+    record[attribute..attribute + 4].copy_from_slice(&0x60u32.to_le_bytes());
+    record[attribute + 4..attribute + 8].copy_from_slice(&0x38u32.to_le_bytes());
+    record[attribute + 0x10..attribute + 0x14].copy_from_slice(&(name.len() as u32).to_le_bytes());
+    record[attribute + 0x14..attribute + 0x16].copy_from_slice(&0x18u16.to_le_bytes());
+    record[attribute + 0x18..attribute + 0x18 + name.len()].copy_from_slice(&name);
+    record[attribute + 0x38..attribute + 0x3c].copy_from_slice(&0xffff_ffffu32.to_le_bytes());
+    for (i, end) in [510, 1022].into_iter().enumerate() {
+        let saved = [record[end], record[end + 1]];
+        record[0x32 + 2 * i..0x34 + 2 * i].copy_from_slice(&saved);
+        record[end..end + 2].copy_from_slice(&[0x01, 0x00]);
+    }
+    record[0x30..0x32].copy_from_slice(&[0x01, 0x00]);
+    volume[4 * 4096 + 3 * 1024..4 * 4096 + 4 * 1024].copy_from_slice(&record);
+    fixture.dir.write("dev/sdc", &volume);
+
+    let (fs_type, label) = probe_filesystem(&fixture.dir.path.join("dev/sdc"));
+    assert_eq!(fs_type.as_deref(), Some("ntfs"));
+    assert_eq!(label.as_deref(), Some("Customer backups"));
+    assert_eq!(probe_filesystem(&fixture.dir.path.join("dev/sdz")), (None, None));
+}
+
+fn disk(path: &str, size: u64) -> DeviceInfo {
+    DeviceInfo {
+        path: PathBuf::from(path),
+        name: Path::new(path).file_name().unwrap().to_string_lossy().into_owned(),
+        size,
+        is_removable: true,
+        is_mounted: false,
+        bus: Some(BusType::Usb),
+        fs_type: None,
+        label: None,
+        partitions: Vec::new(),
+    }
+}
+
+#[test]
+fn an_eraser_asks_its_provider() {
+    let mut eraser = SecureEraser::new();
+    eraser.set_device_provider(Box::new(FakeDevices::new()
+        .device(disk("/dev/sdx", 1 << 30))
+        .device(disk("/dev/sdy", 1 << 20))
+        .mounted("/dev/sdy", "/mnt")
+        .held_by("sdx", "md127")
+        .serial("sdx", "AA55")
+        .link("/dev/disk/by-id/usb-stick", "/dev/sdx")));
+
+    let devices = eraser.list_devices().unwrap();
+    assert_eq!(devices.len(), 2);
+    assert!(!devices[0].is_mounted);
+    assert!(devices[1].is_mounted);
+
+    let provider = eraser.device_provider();
+    assert_eq!(provider.size(Path::new("/dev/sdx")).unwrap(), 1 << 30);
+    assert!(provider.size(Path::new("/dev/sdz")).is_err());
+    assert_eq!(provider.holders("sdx"), ["md127"]);
+    assert_eq!(provider.identity(&devices[0]).serial.as_deref(), Some("AA55"));
+
+    let target = resolve_target(provider, Path::new("/dev/disk/by-id/usb-stick"), &devices, false).unwrap();
+    assert_eq!(target.path, Path::new("/dev/sdx"));
+}
@@ -0,0 +1,118 @@
+//! Full erases of temporary files, read back afterwards: every pattern,
+//! at the sizes where block handling goes wrong
+
+mod common;
+
+use std::path::Path;
+
+use common::{secret, TempFile, SECRET};
+use memerase::erase::ByteRange;
+use memerase::patterns::PassFill;
+use memerase::{DirectIo, EraseJob, EraseReport, SecureEraser, SyncMode, VerifyMode, WipePattern};
+
+const MB: u64 = 1024 * 1024;
+const BLOCK: u64 = MB;  // SecureEraser's default block size
+
+const PATTERNS: [WipePattern; 5] = [
+    WipePattern::Zeros,
+    WipePattern::Ones,
+    WipePattern::Random,
+    WipePattern::Dod3Pass,
+    WipePattern::Gutmann35,
+];
+
+/// A file full of SECRET
+fn secret_file(name: &str, size: u64) -> TempFile {
+    TempFile::new(&format!("test-{}", name), &secret(size))
+}
+
+fn erase(path: &Path, pattern: WipePattern) -> EraseReport {
+    let job = EraseJob::new(path).pattern(pattern).verify(Some(VerifyMode::Full)).build().unwrap();
+    SecureEraser::new().run(job).unwrap_or_else(|e| panic!("{:?} of {}: {}", pattern, path.display(), e))
+}
+
+/// The file kept its size and holds what the final pass wrote, with
+/// nothing of SECRET left
+fn assert_erased(scratch: &TempFile, pattern: WipePattern, report: &EraseReport) {
+    let contents = std::fs::read(&scratch.path).unwrap();
+    assert_eq!(contents.len() as u64, scratch.size, "{:?} changed the file size", pattern);
+    assert_eq!(report.device_size, scratch.size);
+    assert_eq!(report.passes.len(), pattern.pass_count());
+    assert_eq!(report.bytes_written, scratch.size * pattern.pass_count() as u64);
+    assert_eq!(report.verification_passed(), Some(true), "{:?} failed verification", pattern);
+
+    match SecureEraser::new().pass_plan(pattern).last().unwrap() {
+        PassFill::Fill(byte) => {
+            let stray = contents.iter().position(|b| b != byte);
+            assert_eq!(stray, None, "{:?} left a byte other than {:#04x}", pattern, byte);
+        }
+        PassFill::Random { .. } => {
+            let left = contents.windows(SECRET.len()).position(|window| window == SECRET);
+            assert_eq!(left, None, "{:?} left the secret in place", pattern);
+        }
+    }
+}
+
+#[test]
+fn tricky_sizes_for_every_pattern() {
+    for size in [1, 511, 4097, BLOCK - 1, BLOCK, BLOCK + 1, 3 * BLOCK + 17] {
+        for pattern in PATTERNS {
+            let scratch = secret_file(&format!("{}-{:?}", size, pattern), size);
+            let report = erase(&scratch.path, pattern);
+            assert_erased(&scratch, pattern, &report);
+        }
+    }
+}
+
+#[test]
+fn empty_file() {
+    for pattern in PATTERNS {
+        let scratch = secret_file(&format!("empty-{:?}", pattern), 0);
+        let report = erase(&scratch.path, pattern);
+        assert_eq!(report.bytes_written, 0);
+        assert_eq!(std::fs::metadata(&scratch.path).unwrap().len(), 0);
+    }
+}
+
+#[test]
+fn several_hundred_megabytes() {
+    // The batched fill path and the pipelined random one
+    for pattern in [WipePattern::Zeros, WipePattern::Random] {
+        let scratch = secret_file(&format!("large-{:?}", pattern), 300 * MB + 3);
+        let report = erase(&scratch.path, pattern);
+        assert_erased(&scratch, pattern, &report);
+    }
+}
+
+/// Zeros over `range` of a scratch file: the range is zero, and the bytes
+/// either side of it still hold SECRET
+fn assert_only_range_erased(scratch: &TempFile, range: ByteRange) {
+    let contents = std::fs::read(&scratch.path).unwrap();
+    let (start, end) = (range.offset as usize, range.end() as usize);
+    assert!(contents[start..end].iter().all(|&b| b == 0), "the range was not zeroed");
+    let untouched = |i: usize| contents[i] == SECRET[i % SECRET.len()];
+    assert!((0..start).chain(end..contents.len()).all(untouched), "bytes outside {:?} were written", range);
+}
+
+#[test]
+fn a_ranged_job_writes_only_inside_its_range() {
+    let scratch = secret_file("ranged-job", 2 * MB);
+    let range = ByteRange { offset: MB - 1000, length: 4096 };
+    let job = EraseJob::new(&scratch.path).pattern(WipePattern::Zeros).range(Some(range)).build().unwrap();
+    let report = SecureEraser::new().run(job).unwrap();
+    assert_eq!(report.bytes_written, range.length);
+    assert_only_range_erased(&scratch, range);
+}
+
+#[test]
+fn secure_erase_takes_the_range_from_set_range() {
+    let scratch = secret_file("ranged-args", 2 * MB);
+    let range = ByteRange { offset: 4096, length: MB + 17 };
+    let mut eraser = SecureEraser::new();
+    eraser.set_range(Some(range));
+    let report = eraser.secure_erase(&scratch.path, WipePattern::Zeros, Some(VerifyMode::Full), SyncMode::PerPass,
+                                     DirectIo::Off, false, false, None, None).unwrap();
+    assert_eq!(report.bytes_written, range.length);
+    assert_eq!(report.verification_passed(), Some(true));
+    assert_only_range_erased(&scratch, range);
+}
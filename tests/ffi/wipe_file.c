@@ -0,0 +1,91 @@
+/* Wipes a temporary file through the C API and reads it back. Built and run
+ * by CI (.github/workflows/ffi.yml) against the ffi feature's library and
+ * header, with Cargo.toml written from the dependency list at the end of
+ * main.rs:
+ *
+ *   cargo build --lib --release --features ffi
+ *   cc -Wall -Wextra -Werror -Itarget/include -o target/wipe_file tests/ffi/wipe_file.c -Ltarget/release -lmemerase
+ *   LD_LIBRARY_PATH=target/release target/wipe_file
+ */
+#define _GNU_SOURCE  /* memmem */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "memerase.h"
+
+#define SIZE (3 * 1024 * 1024 + 17)
+
+static const char SECRET[] = "memerase secret!";
+
+#define CHECK(cond)                                                  \
+    do {                                                             \
+        if (!(cond)) {                                               \
+            fprintf(stderr, "%s:%d: failed: %s\n", __FILE__, __LINE__, #cond); \
+            exit(1);                                                 \
+        }                                                            \
+    } while (0)
+
+static void on_progress(const MemeraseProgress *progress, void *user_data) {
+    int *calls = user_data;
+    CHECK(progress->bytes_done <= progress->total_bytes);
+    ++*calls;
+}
+
+int main(void) {
+    char path[64];
+    snprintf(path, sizeof path, "/tmp/memerase-ffi-%d", (int)getpid());
+
+    char *contents = malloc(SIZE);
+    CHECK(contents != NULL);
+    for (size_t i = 0; i < SIZE; i++) {
+        contents[i] = SECRET[i % (sizeof SECRET - 1)];
+    }
+    FILE *file = fopen(path, "wb");
+    CHECK(file != NULL);
+    CHECK(fwrite(contents, 1, SIZE, file) == SIZE);
+    fclose(file);
+
+    /* Counting needs no room for the entries */
+    size_t count = 0;
+    CHECK(memerase_list_devices(NULL, 0, &count) == MEMERASE_OK);
+
+    MemeraseJob *job = NULL;
+    MemeraseOptions options = {0};
+    options.pattern = MEMERASE_PATTERN_RANDOM;
+    CHECK(memerase_erase_start(path, &options, &job) == MEMERASE_ERR_USAGE);  /* A file needs allow_file */
+    CHECK(job == NULL);
+
+    int calls = 0;
+    options.verify = MEMERASE_VERIFY_FULL;
+    options.allow_file = true;
+    options.progress = on_progress;
+    options.user_data = &calls;
+    CHECK(memerase_erase_start(path, &options, &job) == MEMERASE_OK);
+    CHECK(memerase_job_wait(job) == MEMERASE_OK);
+
+    MemeraseProgress progress;
+    CHECK(!memerase_job_progress(job, &progress));
+    CHECK(progress.bytes_done == SIZE);
+    CHECK(calls > 0);
+    CHECK(memerase_job_error(job) == NULL);
+
+    char *report = memerase_job_report(job);
+    CHECK(report != NULL);
+    CHECK(strstr(report, "\"bytes_written\":3145745") != NULL);
+    CHECK(strstr(report, "\"first_mismatch\":null") != NULL);
+    memerase_string_free(report);
+    memerase_job_free(job);
+
+    file = fopen(path, "rb");
+    CHECK(file != NULL);
+    CHECK(fread(contents, 1, SIZE, file) == SIZE);
+    fclose(file);
+    unlink(path);
+    CHECK(memmem(contents, SIZE, SECRET, sizeof SECRET - 1) == NULL);
+    free(contents);
+
+    puts("wiped through the C API");
+    return 0;
+}
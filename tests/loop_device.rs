@@ -0,0 +1,253 @@
+//! The block device path on a real loop device: the size from
+//! BLKGETSIZE64, the exclusive open, the partition-mount check, a device
+//! that ends before its reported size, and the filesystems --format-after
+//! creates. Needs root and losetup (and mount for the filesystems); each
+//! test skips itself without them.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use common::{LoopDevice, SECRET};
+use memerase::device::{probe_filesystem, DeviceIdentity};
+use memerase::erase::{ByteRange, FsKind};
+use memerase::{DeviceInfo, DeviceProvider, DirectIo, EraseError, EraseJob, SecureEraser, SysfsProvider, VerifyMode, WipePattern};
+
+const MB: u64 = 1024 * 1024;
+
+/// An MBR with a single FAT32 partition from 1MB to the end of `size`
+fn one_partition(size: u64) -> [u8; 512] {
+    let mut mbr = [0u8; 512];
+    let entry = &mut mbr[446..462];
+    entry[4] = 0x0c;
+    entry[8..12].copy_from_slice(&2048u32.to_le_bytes());
+    entry[12..16].copy_from_slice(&((size / 512 - 2048) as u32).to_le_bytes());
+    mbr[510] = 0x55;
+    mbr[511] = 0xaa;
+    mbr
+}
+
+/// The system's devices, each claiming `extra` bytes more than it has, as
+/// some card readers do
+struct Oversized {
+    devices: SysfsProvider,
+    extra: u64,
+}
+
+impl DeviceProvider for Oversized {
+    fn list(&self) -> Result<Vec<DeviceInfo>, EraseError> {
+        self.devices.list()
+    }
+
+    fn size(&self, path: &Path) -> Result<u64, EraseError> {
+        Ok(self.devices.size(path)? + self.extra)
+    }
+
+    fn mount_point(&self, path: &Path) -> Result<Option<String>, EraseError> {
+        self.devices.mount_point(path)
+    }
+
+    fn holders(&self, name: &str) -> Vec<String> {
+        self.devices.holders(name)
+    }
+
+    fn identity(&self, device: &DeviceInfo) -> DeviceIdentity {
+        self.devices.identity(device)
+    }
+
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        self.devices.resolve(path)
+    }
+}
+
+fn erase(path: &Path, pattern: WipePattern) -> Result<memerase::EraseReport, EraseError> {
+    let job = EraseJob::new(path).pattern(pattern).verify(Some(VerifyMode::Full)).build()?;
+    SecureEraser::new().run(job)
+}
+
+#[test]
+fn size_comes_from_the_block_device() {
+    let Some(device) = LoopDevice::new("size", 16 * MB + 4096, None) else {
+        return;
+    };
+    assert!(std::fs::metadata(&device.path).unwrap().file_type().is_block_device());
+    assert_eq!(SysfsProvider::new().size(&device.path).unwrap(), 16 * MB + 4096);
+}
+
+#[test]
+fn erases_and_verifies_a_loop_device() {
+    let Some(device) = LoopDevice::new("erase", 32 * MB, None) else {
+        return;
+    };
+    for pattern in [WipePattern::Zeros, WipePattern::Random] {
+        let report = erase(&device.path, pattern).unwrap();
+        assert_eq!(report.device_size, 32 * MB);
+        assert_eq!(report.verification_passed(), Some(true));
+
+        let contents = std::fs::read(&device.path).unwrap();
+        assert_eq!(contents.len() as u64, 32 * MB);
+        assert!(!contents.windows(SECRET.len()).any(|window| window == SECRET), "{:?} left the secret", pattern);
+        if pattern == WipePattern::Zeros {
+            assert!(contents.iter().all(|&b| b == 0));
+        }
+    }
+}
+
+#[test]
+fn an_exclusive_holder_blocks_the_erase() {
+    let Some(device) = LoopDevice::new("excl", 8 * MB, None) else {
+        return;
+    };
+    let held = std::fs::OpenOptions::new().read(true).custom_flags(libc::O_EXCL).open(&device.path).unwrap();
+    let refused = erase(&device.path, WipePattern::Zeros);
+    assert!(matches!(refused, Err(EraseError::InUse { .. })), "{:?}", refused.map(|_| ()));
+
+    drop(held);
+    erase(&device.path, WipePattern::Zeros).unwrap();
+}
+
+#[test]
+fn a_mounted_partition_blocks_the_erase() {
+    let size = 64 * MB;
+    let Some(device) = LoopDevice::new("mounted", size, Some(one_partition(size))) else {
+        return;
+    };
+    let partition = device.partition(1);
+    if !partition.exists() {
+        eprintln!("skipped: the kernel created no partition node");
+        return;
+    }
+    SecureEraser::new().write_filesystem(&partition, FsKind::Fat32, Some("MEMERASE")).unwrap();
+
+    let mountpoint = std::env::temp_dir().join(format!("memerase-mnt-{}", std::process::id()));
+    std::fs::create_dir_all(&mountpoint).unwrap();
+    let mounted = Command::new("mount").arg(&partition).arg(&mountpoint).status().is_ok_and(|status| status.success());
+    if !mounted {
+        eprintln!("skipped: could not mount {}", partition.display());
+        let _ = std::fs::remove_dir(&mountpoint);
+        return;
+    }
+
+    let found = SysfsProvider::new().mount_point(&device.path);
+    let refused = erase(&device.path, WipePattern::Zeros);
+
+    let _ = Command::new("umount").arg(&mountpoint).status();
+    let _ = std::fs::remove_dir(&mountpoint);
+    assert_eq!(found.unwrap().as_deref(), mountpoint.to_str());
+    assert!(matches!(refused, Err(EraseError::Mounted { .. } | EraseError::InUse { .. })), "{:?}", refused.map(|_| ()));
+}
+
+#[test]
+fn a_device_smaller_than_reported_fails_the_erase() {
+    let Some(device) = LoopDevice::new("short", 8 * MB, None) else {
+        return;
+    };
+    for pattern in [WipePattern::Zeros, WipePattern::Random] {
+        let mut eraser = SecureEraser::new();
+        eraser.set_device_provider(Box::new(Oversized { devices: SysfsProvider::new(), extra: MB }));
+        let job = EraseJob::new(&device.path).pattern(pattern).build().unwrap();
+        match eraser.run(job) {
+            Err(EraseError::ShortDevice(short)) => {
+                assert_eq!((short.expected, short.reached), (9 * MB, 8 * MB));
+                assert_eq!(short.errno, Some(libc::ENOSPC));
+            }
+            other => panic!("{:?} claimed {:?}", pattern, other.map(|report| report.bytes_written)),
+        }
+    }
+}
+
+/// Format the whole device as `kind`, then mount it, write a file and read
+/// it back through the kernel's driver for `fs_type`
+fn assert_mounts(kind: FsKind, fs_type: &str, label: &str) {
+    let Some(device) = LoopDevice::new(fs_type, 64 * MB, None) else {
+        return;
+    };
+    let created = SecureEraser::new().write_filesystem(&device.path, kind, Some(label)).unwrap();
+    assert_eq!(created.label.as_deref(), Some(label));
+    assert_eq!(probe_filesystem(&device.path), (Some(fs_type.to_string()), Some(label.to_string())));
+
+    let mountpoint = std::env::temp_dir().join(format!("memerase-mnt-{}-{}", fs_type, std::process::id()));
+    std::fs::create_dir_all(&mountpoint).unwrap();
+    let mounted = Command::new("mount").args(["-t", fs_type]).arg(&device.path).arg(&mountpoint)
+        .status().is_ok_and(|status| status.success());
+    if !mounted {
+        eprintln!("skipped: could not mount {} as {}", device.path.display(), fs_type);
+        let _ = std::fs::remove_dir(&mountpoint);
+        return;
+    }
+
+    let file = mountpoint.join("memerase.txt");
+    let written = std::fs::write(&file, SECRET);
+    let read = std::fs::read(&file);
+    let entries = std::fs::read_dir(&mountpoint).map(|dir| dir.count());
+
+    let _ = Command::new("umount").arg(&mountpoint).status();
+    let _ = std::fs::remove_dir(&mountpoint);
+    written.unwrap();
+    assert_eq!(read.unwrap(), SECRET);
+    assert_eq!(entries.unwrap(), 1, "a fresh {} should hold only the file written to it", fs_type);
+}
+
+#[test]
+fn a_new_fat32_filesystem_mounts() {
+    assert_mounts(FsKind::Fat32, "vfat", "MEMERASE");
+}
+
+#[test]
+fn a_new_exfat_filesystem_mounts() {
+    assert_mounts(FsKind::Exfat, "exfat", "Wiped 2026");
+}
+
+#[test]
+fn a_bad_label_is_refused_before_anything_is_written() {
+    let Some(device) = LoopDevice::new("label", 8 * MB, None) else {
+        return;
+    };
+    let mut eraser = SecureEraser::new();
+    for (kind, label) in [(FsKind::Fat32, "TWELVE CHARS"), (FsKind::Fat32, "A/B"), (FsKind::Exfat, "twelve chars")] {
+        let refused = eraser.write_filesystem(&device.path, kind, Some(label));
+        assert!(matches!(refused, Err(EraseError::InvalidArgument(_))), "{:?} {:?}: {:?}", kind, label, refused.map(|_| ()));
+    }
+    let contents = std::fs::read(&device.path).unwrap();
+    assert_eq!(&contents[..SECRET.len()], SECRET);
+}
+
+#[test]
+fn a_stamped_erase_writes_its_record_through_the_exclusive_open() {
+    let Some(device) = LoopDevice::new("stamp", 8 * MB, None) else {
+        return;
+    };
+    let job = EraseJob::new(&device.path).pattern(WipePattern::Zeros).verify(Some(VerifyMode::Full))
+        .direct_io(DirectIo::On).stamp_key(Some(b"loop key")).build().unwrap();
+    let report = SecureEraser::new().run(job).unwrap();
+    assert!(report.stamped);
+    assert_eq!(report.verification_passed(), Some(true));
+
+    let mut eraser = SecureEraser::new();
+    let stamp = eraser.read_stamp(&device.path).unwrap().expect("no stamp in the last sector");
+    assert!(stamp.is_authentic(b"loop key"));
+    assert_eq!(stamp.verification, "passed");
+    // The stamp's sector is left out when the erase is checked again
+    let result = eraser.verify_device(&device.path, WipePattern::Zeros, VerifyMode::Full).unwrap();
+    assert!(result.passed());
+}
+
+#[test]
+fn a_range_ending_mid_sector_goes_through_the_buffered_tail() {
+    let Some(device) = LoopDevice::new("ragged", 8 * MB, None) else {
+        return;
+    };
+    let range = ByteRange { offset: 0, length: 4 * MB + 100 };
+    let job = EraseJob::new(&device.path).pattern(WipePattern::Zeros).direct_io(DirectIo::On)
+        .range(Some(range)).build().unwrap();
+    let report = SecureEraser::new().run(job).unwrap();
+    assert_eq!(report.bytes_written, range.length);
+
+    let contents = std::fs::read(&device.path).unwrap();
+    let end = range.length as usize;
+    assert!(contents[..end].iter().all(|&b| b == 0), "the range was not fully zeroed");
+    assert_eq!(contents[end..end + 12], SECRET[(end % 16)..], "the range overran its end");
+}
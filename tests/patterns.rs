@@ -0,0 +1,83 @@
+//! The bytes each pass writes, pinned down with a seeded eraser
+
+mod common;
+
+use common::TempFile;
+use memerase::patterns::PassFill;
+use memerase::{EraseJob, SecureEraser, WipePattern};
+
+/// The first 16 bytes of the random pass `SecureEraser::with_seed(42)` plans
+const SEED_42_RANDOM: [u8; 16] = [
+    0x77, 0x82, 0x96, 0x86, 0xf2, 0x9f, 0xd1, 0x12, 0x0e, 0xe6, 0xc1, 0x1c, 0x2f, 0xac, 0x79, 0x90,
+];
+
+fn bytes(fill: &PassFill, offset: u64, len: usize) -> Vec<u8> {
+    let mut buffer = vec![0u8; len];
+    fill.fill(offset, &mut buffer);
+    buffer
+}
+
+#[test]
+fn a_seed_fixes_the_random_data() {
+    let plan = SecureEraser::with_seed(42).pass_plan(WipePattern::Random);
+    assert_eq!(bytes(&plan[0], 0, 16), SEED_42_RANDOM);
+
+    let again = SecureEraser::with_seed(42).pass_plan(WipePattern::Random);
+    assert_eq!(plan[0].seed_hex(), again[0].seed_hex());
+    let other = SecureEraser::with_seed(43).pass_plan(WipePattern::Random);
+    assert_ne!(plan[0].seed_hex(), other[0].seed_hex());
+}
+
+#[test]
+fn set_seed_restarts_the_stream() {
+    let mut eraser = SecureEraser::new();
+    eraser.pass_plan(WipePattern::Random);
+    eraser.set_seed(42);
+    assert_eq!(eraser.seed(), 42);
+    assert_eq!(bytes(&eraser.pass_plan(WipePattern::Random)[0], 0, 16), SEED_42_RANDOM);
+}
+
+#[test]
+fn fixed_passes_of_each_method() {
+    let fills = |pattern| -> Vec<Option<u8>> {
+        SecureEraser::with_seed(1).pass_plan(pattern).iter()
+            .map(|pass| match *pass {
+                PassFill::Fill(byte) => Some(byte),
+                PassFill::Random { .. } => None,
+            })
+            .collect()
+    };
+    assert_eq!(fills(WipePattern::Zeros), [Some(0x00)]);
+    assert_eq!(fills(WipePattern::Ones), [Some(0xff)]);
+    assert_eq!(fills(WipePattern::Dod3Pass), [Some(0x00), Some(0xff), None]);
+    assert_eq!(fills(WipePattern::Gutmann35), [None, None, None, None, Some(0x55), Some(0xaa), Some(0x92), Some(0x49), Some(0x24)]);
+
+    // Every random pass of a plan gets its own key
+    let plan = SecureEraser::with_seed(1).pass_plan(WipePattern::Gutmann35);
+    let mut keys: Vec<String> = plan.iter().filter_map(PassFill::seed_hex).collect();
+    keys.dedup();
+    assert_eq!(keys.len(), 4);
+}
+
+#[test]
+fn random_data_depends_only_on_the_offset() {
+    let pass = SecureEraser::with_seed(7).pass_plan(WipePattern::Random)[0];
+    let whole = bytes(&pass, 0, 4096);
+    for offset in [1, 3, 4, 5, 511, 1000] {
+        assert_eq!(bytes(&pass, offset, 64), whole[offset as usize..offset as usize + 64], "offset {}", offset);
+    }
+}
+
+#[test]
+fn the_report_records_the_seed() {
+    let mut files = Vec::new();
+    for _ in 0..2 {
+        let file = TempFile::new("seed", &[0x5a; 10_000]);
+        let job = EraseJob::new(&file.path).pattern(WipePattern::Random).build().unwrap();
+        let report = SecureEraser::with_seed(42).run(job).unwrap();
+        assert_eq!(report.seed, 42);
+        files.push(std::fs::read(&file.path).unwrap());
+    }
+    assert_eq!(files[0], files[1]);
+    assert_eq!(files[0][..16], SEED_42_RANDOM);
+}
@@ -0,0 +1,62 @@
+//! `write_fully` against a writer that stops short and gets interrupted:
+//! where each retry resumes and how far the position moves
+
+mod common;
+
+use std::io;
+
+use common::{Scripted, Step};
+use memerase::device::write_fully;
+use memerase::EraseError;
+
+/// Where the write starts on the device
+const START: u64 = 3 * 1024 * 1024;
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn short_and_interrupted_writes_resume_where_they_stopped() {
+    let data = data(4096);
+    let mut out = Scripted::new(&[
+        Step::Accept(512),
+        Step::Interrupt,
+        Step::Accept(1),
+        Step::Interrupt,
+        Step::Interrupt,
+        Step::Accept(1000),
+    ]);
+    let mut position = START;
+    write_fully(&mut out, &data, &mut position).unwrap();
+
+    assert_eq!(out.received, data, "every byte exactly once, in order");
+    assert_eq!(out.resumed_at, [0, 512, 512, 513, 513, 513, 1513]);
+    assert_eq!(position, START + data.len() as u64);
+}
+
+#[test]
+fn a_failure_part_way_is_tagged_with_where_the_device_stopped() {
+    let data = data(4096);
+    let mut out = Scripted::new(&[Step::Accept(700), Step::Interrupt, Step::Accept(300), Step::Fail]);
+    let mut position = START;
+    let error = write_fully(&mut out, &data, &mut position).unwrap_err();
+
+    assert_eq!(out.received, &data[..1000]);
+    assert_eq!(out.resumed_at, [0, 700, 700, 1000]);
+    assert_eq!(position, START + 1000, "only the bytes the writer took are counted");
+    assert_eq!(error.kind(), io::ErrorKind::Other);
+    assert_eq!(EraseError::from(error).offset(), Some(START + 1000));
+}
+
+#[test]
+fn a_write_that_takes_nothing_fails_instead_of_spinning() {
+    let data = data(4096);
+    let mut out = Scripted::new(&[Step::Accept(4000), Step::Accept(0)]);
+    let mut position = START;
+    let error = write_fully(&mut out, &data, &mut position).unwrap_err();
+
+    assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    assert_eq!(position, START + 4000);
+    assert_eq!(EraseError::from(error).offset(), Some(START + 4000));
+}
@@ -0,0 +1,153 @@
+//! Shredding files on a tmpfs, watched with inotify: the renames that
+//! scramble the name before the unlink, the reset timestamps, and the plain
+//! overwrite-and-unlink of --no-rename
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use common::TempDir;
+use memerase::{SecureEraser, SparseMode, WipePattern};
+
+const NAME: &str = "quarterly-accounts-2026.ods";
+
+/// A directory on /dev/shm holding one file full of secrets, plus a hard
+/// link to it in a sibling directory that outlives the shred. None when
+/// /dev/shm isn't a tmpfs.
+struct Shm {
+    _root: TempDir,  // Removes the lot when dropped
+    dir: PathBuf,
+    file: PathBuf,
+    link: PathBuf,
+}
+
+impl Shm {
+    fn new(name: &str) -> Option<Self> {
+        let root = TempDir::in_dir(&common::shm()?, &format!("shred-{}", name));
+        let file = root.write(&format!("watched/{}", NAME), b"net profit: 1,000,000\n".repeat(5000));
+        let link = root.path.join("links").join(NAME);
+        std::fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::fs::hard_link(&file, &link).unwrap();
+        Some(Shm { dir: root.path.join("watched"), _root: root, file, link })
+    }
+}
+
+/// Renames and deletions in one directory, in the order they happened
+struct Watch {
+    fd: i32,
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    MovedFrom(String),
+    MovedTo(String),
+    Deleted(String),
+}
+
+impl Watch {
+    fn new(dir: &Path) -> Self {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        assert!(fd >= 0, "inotify_init1: {}", std::io::Error::last_os_error());
+        let c_dir = CString::new(dir.as_os_str().as_bytes()).unwrap();
+        let mask = libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_DELETE;
+        assert!(unsafe { libc::inotify_add_watch(fd, c_dir.as_ptr(), mask) } >= 0);
+        Watch { fd }
+    }
+
+    fn events(&self) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = unsafe { libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            if n <= 0 {
+                return events;
+            }
+            let mut data = &buffer[..n as usize];
+            // struct inotify_event: wd, mask, cookie, len, then the name
+            while data.len() >= 16 {
+                let field = |at: usize| u32::from_ne_bytes(data[at..at + 4].try_into().unwrap());
+                let (mask, len) = (field(4), field(12) as usize);
+                let name = &data[16..16 + len];
+                let name = String::from_utf8_lossy(&name[..name.iter().position(|&b| b == 0).unwrap_or(len)]).into_owned();
+                if mask & libc::IN_MOVED_FROM != 0 {
+                    events.push(Event::MovedFrom(name));
+                } else if mask & libc::IN_MOVED_TO != 0 {
+                    events.push(Event::MovedTo(name));
+                } else if mask & libc::IN_DELETE != 0 {
+                    events.push(Event::Deleted(name));
+                }
+                data = &data[16 + len..];
+            }
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+fn shred(path: &Path, scramble: bool) {
+    let result = SecureEraser::new().shred_file(path, WipePattern::Zeros, None, scramble, SparseMode::Full).unwrap();
+    assert_eq!(result.error, None);
+    assert_eq!(result.bytes_overwritten, 22 * 5000);
+}
+
+fn modified(path: &Path) -> std::time::SystemTime {
+    std::fs::metadata(path).unwrap().modified().unwrap()
+}
+
+#[test]
+fn shredding_renames_through_shorter_names_before_the_unlink() {
+    let Some(shm) = Shm::new("scramble") else {
+        return;
+    };
+    let watch = Watch::new(&shm.dir);
+    shred(&shm.file, true);
+    let events = watch.events();
+
+    assert_eq!(std::fs::read_dir(&shm.dir).unwrap().count(), 0, "something was left in the directory");
+    assert!(!shm.file.exists());
+
+    // Every rename is a moved-from/moved-to pair, starting from the real name
+    let (renames, deleted) = events.split_at(events.len() - 1);
+    assert!(renames.len() >= 4, "only {} rename events: {:?}", renames.len(), events);
+    let mut from = NAME.to_string();
+    let mut lengths = Vec::new();
+    for pair in renames.chunks(2) {
+        let [Event::MovedFrom(old), Event::MovedTo(new)] = pair else {
+            panic!("not a rename: {:?} in {:?}", pair, events);
+        };
+        assert_eq!(*old, from);
+        assert_ne!(*new, NAME);
+        lengths.push(new.len());
+        from = new.clone();
+    }
+    assert_eq!(deleted, [Event::Deleted(from.clone())], "the unlink should remove the last random name");
+    assert_eq!(lengths[0], NAME.len());
+    assert!(lengths.windows(2).all(|pair| pair[1] < pair[0]), "name lengths {:?}", lengths);
+    assert_eq!(lengths.last(), Some(&1));
+
+    // The inode lives on through the link: emptied, and its times reset
+    assert_eq!(std::fs::metadata(&shm.link).unwrap().len(), 0);
+    assert_eq!(modified(&shm.link), std::time::UNIX_EPOCH);
+}
+
+#[test]
+fn no_rename_just_overwrites_and_unlinks() {
+    let Some(shm) = Shm::new("no-rename") else {
+        return;
+    };
+    let before = modified(&shm.link);
+    let watch = Watch::new(&shm.dir);
+    shred(&shm.file, false);
+
+    assert_eq!(watch.events(), [Event::Deleted(NAME.to_string())]);
+    assert_eq!(std::fs::read_dir(&shm.dir).unwrap().count(), 0);
+    assert_eq!(std::fs::metadata(&shm.link).unwrap().len(), 0);
+    assert!(modified(&shm.link) >= before, "--no-rename should leave the timestamps alone");
+}
@@ -0,0 +1,64 @@
+//! Sizes as they are shown and as options accept them
+
+use memerase::report::{format_size, parse_size};
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const GIB: u64 = 1024 * MIB;
+const TIB: u64 = 1024 * GIB;
+
+#[test]
+fn formatting_at_the_unit_boundaries() {
+    assert_eq!(format_size(0), "0 B");
+    assert_eq!(format_size(1023), "1023 B");
+    assert_eq!(format_size(1024), "1.0 KiB");
+    assert_eq!(format_size(1536), "1.5 KiB");
+    // Just under the point where one decimal place would round to 1024.0
+    assert_eq!(format_size(1048524), "1023.9 KiB");
+    assert_eq!(format_size(1048525), "1.0 MiB");
+    assert_eq!(format_size(MIB - 1), "1.0 MiB");
+    assert_eq!(format_size(MIB), "1.0 MiB");
+    assert_eq!(format_size(TIB), "1.0 TiB");
+    assert_eq!(format_size(u64::MAX), "16.0 EiB");
+}
+
+#[test]
+fn a_500_gb_disk_rounds_to_one_decimal() {
+    assert_eq!(format_size(500_107_862_016), "465.8 GiB");
+    assert_eq!(format_size(465 * GIB + 8 * GIB / 10), "465.8 GiB");
+    assert_eq!(format_size(465 * GIB + 76 * GIB / 1000), "465.1 GiB");
+}
+
+#[test]
+fn parsing_suffixes() {
+    assert_eq!(parse_size("4096"), Ok(4096));
+    assert_eq!(parse_size("512K"), Ok(512 * KIB));
+    assert_eq!(parse_size("4M"), Ok(4 * MIB));
+    assert_eq!(parse_size("1.5T"), Ok(TIB + TIB / 2));
+    assert_eq!(parse_size("0.5K"), Ok(512));
+    assert_eq!(parse_size(" 4 MiB "), Ok(4 * MIB));
+    assert_eq!(parse_size("2gb"), Ok(2 * GIB));
+    assert_eq!(parse_size(".25M"), Ok(MIB / 4));
+}
+
+#[test]
+fn parsing_rejects_garbage() {
+    for garbage in ["", "K", "abc", "4X", "-1", "1.2.3", "4 M B", "1.5"] {
+        assert!(parse_size(garbage).is_err(), "{:?} was accepted as {:?}", garbage, parse_size(garbage));
+    }
+}
+
+#[test]
+fn parsing_rejects_overflow() {
+    assert_eq!(parse_size("18446744073709551615"), Ok(u64::MAX));
+    // Too many bytes, and fractions with too many digits to scale exactly
+    for too_large in [
+        "18446744073709551616",
+        "16777216T",
+        "16384P",
+        "0.999999999999999999999999999P",
+        "1.0000000000000000000000000000000000000001K",
+    ] {
+        assert!(parse_size(too_large).is_err(), "{:?} was accepted as {:?}", too_large, parse_size(too_large));
+    }
+}
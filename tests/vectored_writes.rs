@@ -0,0 +1,83 @@
+//! Fill passes written as vectored copies of one block: resuming a short
+//! write in the middle of an iovec, and what batching buys on a tmpfs
+
+mod common;
+
+use std::io;
+use std::time::Instant;
+
+use common::{Scripted, Step, TempFile};
+use memerase::device::write_copies;
+use memerase::{EraseJob, SecureEraser, WipePattern};
+
+const KB: usize = 1024;
+const MB: u64 = 1024 * 1024;
+
+#[test]
+fn a_short_vectored_write_resumes_mid_iovec() {
+    let block: Vec<u8> = (0..4 * KB).map(|i| (i % 253) as u8).collect();
+    let mut out = Scripted::new(&[
+        Step::Accept(5000),
+        Step::Interrupt,
+        Step::Accept(3192),
+        Step::Accept(1),
+        Step::Interrupt,
+        Step::Accept(4096 + 100),
+    ]);
+    let mut written = 0;
+    write_copies(&mut out, &block, 4, &mut written).unwrap();
+
+    assert_eq!(written, 4 * block.len());
+    assert_eq!(out.received, block.repeat(4), "every copy exactly once, in order");
+    assert_eq!(out.iovecs, [
+        vec![4096, 4096, 4096, 4096],
+        vec![3192, 4096, 4096],        // 904 bytes into the second copy
+        vec![3192, 4096, 4096],        // the same again after EINTR
+        vec![4096, 4096],              // the short write ended on a copy boundary
+        vec![4095, 4096],              // one byte in, then EINTR
+        vec![4095, 4096],
+        vec![3995],                    // 101 bytes into the last copy
+    ]);
+}
+
+#[test]
+fn an_error_leaves_the_count_where_the_writer_stopped() {
+    // A writer without write_vectored of its own takes one slice at a time
+    let mut out = Scripted::single_slice(&[Step::Accept(4096), Step::Accept(4096), Step::Accept(1808), Step::Fail]);
+    let mut written = 0;
+    let error = write_copies(&mut out, &[0xaa; 4096], 4, &mut written).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Other);
+    assert_eq!(written, 10_000);
+    assert_eq!(out.iovecs, [vec![4096], vec![4096], vec![4096], vec![2288]]);
+    // Carrying on where it stopped finishes the run
+    write_copies(&mut Scripted::single_slice(&[]), &[0xaa; 4096], 4, &mut written).unwrap();
+    assert_eq!(written, 4 * 4096);
+}
+
+/// Zero-fill throughput on a tmpfs-backed file, one block per write (as
+/// before batching) against vectored batches. Run with
+/// `cargo test --release --test vectored_writes -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn benchmark_write_batch() {
+    let Some(shm) = common::shm() else {
+        return;
+    };
+    let size = 1024 * MB;
+    let file = TempFile::in_dir(&shm, "bench", &vec![0xffu8; size as usize]);
+
+    for batch in [1, 8, 64] {
+        let mut best = 0.0f64;
+        for _ in 0..3 {
+            let mut eraser = SecureEraser::new();
+            eraser.set_block_size(64 * KB).unwrap();
+            eraser.set_write_batch(batch);
+            let job = EraseJob::new(&file.path).pattern(WipePattern::Zeros).build().unwrap();
+            let started = Instant::now();
+            let report = eraser.run(job).unwrap();
+            assert_eq!(report.bytes_written, size);
+            best = best.max(size as f64 / MB as f64 / started.elapsed().as_secs_f64());
+        }
+        println!("write batch {:>2}: {:>6.0} MB/s (best of 3, 64 KiB blocks, 1 GiB)", batch, best);
+    }
+}
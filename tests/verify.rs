@@ -0,0 +1,155 @@
+//! Reading back files that already hold a pattern: the block count clamped
+//! to the file size, the partial last block, and where a mismatch is found
+
+mod common;
+
+use std::io::Read;
+use std::time::Instant;
+
+use common::TempFile;
+use memerase::patterns::{first_difference, first_non_matching_byte};
+use memerase::{SecureEraser, VerifyMode, VerifyResult, WipePattern};
+
+const MB: u64 = 1024 * 1024;
+const BLOCK: u64 = MB;  // SecureEraser's default block size
+const CHUNK: usize = 4096;  // What the compare helpers check at once
+
+/// A file of zeros, as a Zeros erase leaves it
+fn zeroed(name: &str, size: u64) -> TempFile {
+    TempFile::new(&format!("verify-{}", name), &vec![0u8; size as usize])
+}
+
+/// Put a non-zero byte at `offset`
+fn corrupt(file: &TempFile, offset: u64) {
+    let mut contents = std::fs::read(&file.path).unwrap();
+    contents[offset as usize] = 0xa5;
+    std::fs::write(&file.path, contents).unwrap();
+}
+
+fn verify_zeros(file: &TempFile) -> VerifyResult {
+    SecureEraser::new().verify_device(&file.path, WipePattern::Zeros, VerifyMode::Full)
+        .unwrap_or_else(|e| panic!("verifying {} bytes: {}", file.size, e))
+}
+
+#[test]
+fn awkward_sizes_verify_in_full() {
+    // One byte past a block, under a block, whole blocks, and whole blocks
+    // with a partial one after them
+    for size in [BLOCK + 1, 4097, 4 * MB, 4 * MB + 700] {
+        let zeroed = zeroed(&format!("size-{}", size), size);
+        let result = verify_zeros(&zeroed);
+        assert!(result.passed(), "{} bytes: first mismatch at {:?}", size, result.first_mismatch);
+        assert_eq!(result.device_size, size);
+        assert_eq!(result.bytes_checked, size, "{} bytes read back", size);
+        assert_eq!(result.bytes_verified, size);
+        assert_eq!(result.blocks_checked, size.div_ceil(BLOCK), "{} bytes", size);
+        assert_eq!(result.blocks_matched, result.blocks_checked);
+    }
+}
+
+#[test]
+fn a_stray_byte_in_the_partial_last_block_is_found() {
+    for size in [BLOCK + 1, 4097, 4 * MB + 700] {
+        let zeroed = zeroed(&format!("last-{}", size), size);
+        corrupt(&zeroed, size - 1);
+        let result = verify_zeros(&zeroed);
+        assert_eq!(result.first_mismatch, Some(size - 1), "{} bytes", size);
+        assert_eq!(result.blocks_checked - result.blocks_matched, 1);
+        // Every block but the last one read back clean
+        assert_eq!(result.bytes_verified, (size - 1) / BLOCK * BLOCK);
+    }
+}
+
+#[test]
+fn the_chunked_compare_finds_the_first_differing_byte() {
+    // Two whole chunks and a five byte tail
+    let len = 2 * CHUNK + 5;
+    let expected: Vec<u8> = (0..len).map(|i| (i * 7 % 256) as u8).collect();
+    assert_eq!(first_non_matching_byte(&vec![0x55; len], 0x55), None);
+    assert_eq!(first_difference(&expected, &expected), None);
+
+    // The first byte, a word in, either side of a chunk boundary, the first
+    // and last bytes of the tail
+    for at in [0, 8, 1001, CHUNK - 1, CHUNK, 2 * CHUNK - 1, 2 * CHUNK, len - 1] {
+        let mut filled = vec![0x55; len];
+        filled[at] = 0x54;
+        assert_eq!(first_non_matching_byte(&filled, 0x55), Some(at), "fill, mismatch at {}", at);
+
+        let mut actual = expected.clone();
+        actual[at] ^= 0x80;
+        assert_eq!(first_difference(&actual, &expected), Some(at), "data, mismatch at {}", at);
+
+        // A later difference in the same chunk doesn't hide it
+        if at + 9 < len {
+            filled[at + 9] = 0;
+            actual[at + 9] ^= 1;
+            assert_eq!(first_non_matching_byte(&filled, 0x55), Some(at));
+            assert_eq!(first_difference(&actual, &expected), Some(at));
+        }
+    }
+
+    // Shorter than a chunk, and of different lengths
+    assert_eq!(first_non_matching_byte(&[9, 9, 8], 9), Some(2));
+    assert_eq!(first_non_matching_byte(&[], 9), None);
+    assert_eq!(first_difference(&[1, 2, 3], &[1, 2, 4]), Some(2));
+    assert_eq!(first_difference(&[1, 2, 3], &[1, 2]), None);
+}
+
+#[test]
+fn verification_reports_the_exact_offset_of_the_first_mismatch() {
+    let size = 3 * BLOCK + 5;
+    for at in [0, 8, CHUNK as u64, BLOCK - 1, 2 * BLOCK + 8, size - 3] {
+        let zeroed = zeroed(&format!("at-{}", at), size);
+        corrupt(&zeroed, at);
+        let result = verify_zeros(&zeroed);
+        assert_eq!(result.first_mismatch, Some(at));
+        assert_eq!(result.mismatches.len(), 1);
+        assert_eq!(result.mismatches[0].offset, at);
+        assert!(result.mismatches[0].actual.starts_with("a5"), "dump {:?}", result.mismatches[0].actual);
+    }
+}
+
+/// Full verification of a zero-filled file on a tmpfs against a plain
+/// sequential read of it, and the fill compare against a plain slice
+/// compare. Run with
+/// `cargo test --release --test verify -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn benchmark_constant_fill_verification() {
+    let size = 1024 * MB;
+    let Some(shm) = common::shm() else {
+        return;
+    };
+    let zeroed = TempFile::in_dir(&shm, "verify-bench", &vec![0u8; size as usize]);
+    let rate = |started: Instant| size as f64 / MB as f64 / started.elapsed().as_secs_f64();
+
+    let mut read = 0.0f64;
+    let mut verify = 0.0f64;
+    for _ in 0..3 {
+        let started = Instant::now();
+        let mut file = std::fs::File::open(&zeroed.path).unwrap();
+        let mut buffer = vec![0u8; BLOCK as usize];
+        while file.read(&mut buffer).unwrap() > 0 {}
+        read = read.max(rate(started));
+
+        let started = Instant::now();
+        assert!(verify_zeros(&zeroed).passed());
+        verify = verify.max(rate(started));
+    }
+    println!("sequential read:   {:>6.0} MB/s", read);
+    println!("full verification: {:>6.0} MB/s ({:.0}% of read speed)", verify, verify / read * 100.0);
+
+    let block = vec![0u8; BLOCK as usize];
+    let expected = vec![0u8; BLOCK as usize];
+    let blocks = size / BLOCK;
+    let started = Instant::now();
+    for _ in 0..blocks {
+        assert_eq!(first_non_matching_byte(std::hint::black_box(&block), 0), None);
+    }
+    let fill = rate(started);
+    let started = Instant::now();
+    for _ in 0..blocks {
+        assert!(std::hint::black_box(&block) == &expected);
+    }
+    println!("compare: first_non_matching_byte {:.0} MB/s, slice == {:.0} MB/s", fill, rate(started));
+}
@@ -0,0 +1,501 @@
+//! Progress bars, structured progress events and the lifecycle log
+
+use std::io::{self, Write};
+use std::collections::HashMap;
+use std::path::Path;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use tracing::warn;
+
+#[cfg(target_os = "linux")]
+use std::os::unix::io::RawFd;
+
+use crate::{audit::*, device::*, erase::*, patterns::*, report::*, verify::*};
+
+pub(crate) const PROGRESS_REFRESH: std::time::Duration = std::time::Duration::from_millis(250);
+pub(crate) const PLAIN_STATUS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10); // Status lines when not on a terminal
+
+/// Output level every informational print goes through: 0 with --quiet,
+/// 1 by default, 2 and up with each --verbose. Starts at 0 so a library
+/// caller gets no progress bars until it asks for them.
+pub static VERBOSITY: std::sync::atomic::AtomicU8 = std::sync::atomic::AtomicU8::new(0);
+
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Set by --progress json, which reserves stdout for events
+pub static JSON_PROGRESS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+pub fn json_progress() -> bool {
+    JSON_PROGRESS.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Text meant for people: stdout, or stderr while stdout carries JSON
+/// events. Shown even under --quiet; the "output" target bypasses the filter.
+#[macro_export]
+macro_rules! human {
+    () => {
+        ::tracing::info!(target: "output", "")
+    };
+    ($($arg:tt)*) => {
+        ::tracing::info!(target: "output", $($arg)*)
+    };
+}
+
+/// The bar currently on screen, suspended while log lines are written so
+/// they don't land in the middle of it
+pub static ACTIVE_BAR: std::sync::Mutex<Option<indicatif::WeakProgressBar>> = std::sync::Mutex::new(None);
+
+/// Bars for erases running side by side, as the daemon's jobs do: one per
+/// device, labelled with it, under a summary of the bytes of all of them
+pub struct MultiBars {
+    multi: MultiProgress,
+    summary: ProgressBar,  // On screen only while a job runs
+    jobs: std::sync::Mutex<HashMap<u64, JobBytes>>,  // Since the screen was last idle
+}
+
+#[derive(Clone, Copy)]
+struct JobBytes {
+    done: u64,
+    total: u64,
+    running: bool,
+}
+
+pub static MULTI_BARS: std::sync::OnceLock<MultiBars> = std::sync::OnceLock::new();
+
+impl MultiBars {
+    /// Draw every later bar through one MultiProgress. Only on a terminal:
+    /// elsewhere each bar already prints its own status lines, prefixed
+    /// with its device.
+    pub fn enable() {
+        use std::io::IsTerminal;
+
+        if verbosity() == 0 || json_progress() || !io::stderr().is_terminal() {
+            return;
+        }
+        let summary = ProgressBar::hidden();
+        summary.set_style(
+            ProgressStyle::default_bar()
+                .template(&bar_template("{prefix:.bold} [{bar:40.green/blue}] {bytes}/{total_bytes} ({percent}%) {msg}"))
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        summary.set_prefix("all");
+        let _ = MULTI_BARS.set(MultiBars { multi: MultiProgress::new(), summary, jobs: std::sync::Mutex::new(HashMap::new()) });
+    }
+
+    /// Bytes `done` of `total` for job `id`, which the summary adds up
+    pub fn job_progress(&self, id: u64, done: u64, total: u64) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if !jobs.values().any(|job| job.running) {
+            self.multi.insert(0, self.summary.clone());
+        }
+        jobs.insert(id, JobBytes { done, total, running: true });
+        self.update_summary(&jobs);
+    }
+
+    /// Job `id` is over; once none is left the summary goes, and starts
+    /// over with the next job
+    pub fn job_done(&self, id: u64) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(&id) {
+            job.running = false;
+        }
+        if jobs.values().any(|job| job.running) {
+            self.update_summary(&jobs);
+        } else {
+            jobs.clear();
+            self.multi.remove(&self.summary);
+        }
+    }
+
+    fn update_summary(&self, jobs: &HashMap<u64, JobBytes>) {
+        self.summary.set_length(jobs.values().map(|job| job.total).sum());
+        self.summary.set_position(jobs.values().map(|job| job.done).sum());
+        let running = jobs.values().filter(|job| job.running).count();
+        self.summary.set_message(format!("{} of {} device(s) running", running, jobs.len()));
+    }
+}
+
+/// Run `write` with the bars on screen cleared, so a line written to the
+/// terminal lands above them
+pub fn suspend_bars(write: impl FnOnce()) {
+    if let Some(bars) = MULTI_BARS.get() {
+        bars.multi.suspend(write);
+        return;
+    }
+    let bar = ACTIVE_BAR.lock().ok().and_then(|bar| bar.as_ref().and_then(|b| b.upgrade()));
+    match bar {
+        Some(bar) if !bar.is_hidden() && !bar.is_finished() => bar.suspend(write),
+        _ => write(),
+    }
+}
+
+/// Takes a bar off the MultiProgress when dropped, leaving its last message
+/// in a line above the others; see `label_bar`
+pub struct BarSlot(ProgressBar);
+
+impl Drop for BarSlot {
+    fn drop(&mut self) {
+        let Some(bars) = MULTI_BARS.get() else { return };
+        let pb = &self.0;
+        if pb.is_hidden() {
+            return;
+        }
+        let line = format!("{} {}", pb.prefix(), pb.message());
+        bars.multi.remove(pb);
+        if pb.is_finished() {
+            let _ = bars.multi.println(line);
+        }
+    }
+}
+
+/// Label `pb` with the device (its serial, or its name), which tells the
+/// bars apart when several erases share the terminal. Keep the returned
+/// slot as long as the bar: a finished bar left in place would hold its
+/// line under the summary for good.
+pub fn label_bar(pb: &ProgressBar, device: &Path) -> BarSlot {
+    let name = device.file_name().map_or_else(|| device.display().to_string(), |name| name.to_string_lossy().into_owned());
+    pb.set_prefix(device_serial(&name).unwrap_or(name));
+    BarSlot(pb.clone())
+}
+
+/// Cleared by --no-color or a non-empty NO_COLOR
+pub static COLOR: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+
+/// One line of `--progress json` output
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    Start {
+        device: &'a Path,
+        method: WipePattern,
+        passes: usize,
+        device_size: u64,
+        total_bytes: u64,   // All passes together
+    },
+    Progress {
+        pass: usize,
+        passes: usize,
+        bytes_done: u64,    // Within the current pass
+        total: u64,
+        rate_mb_s: f64,
+        eta_secs: Option<f64>,
+    },
+    PassComplete(&'a PassReport),
+    Verify(&'a VerifyResult),
+    Result(&'a EraseReport),
+    Error {
+        device: &'a Path,
+        error: String,
+    },
+}
+
+/// Where `--log-target` sends lifecycle events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogTarget {
+    Stderr,      // A `tracing` event with target "log", which the binary writes to stderr
+    #[cfg(unix)]
+    Syslog,      // LOG_DAEMON
+    #[cfg(target_os = "linux")]
+    Journald,    // Native protocol, with structured fields
+}
+
+impl std::str::FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "stderr" => Ok(LogTarget::Stderr),
+            #[cfg(unix)]
+            "syslog" => Ok(LogTarget::Syslog),
+            #[cfg(target_os = "linux")]
+            "journald" | "journal" => Ok(LogTarget::Journald),
+            #[cfg(not(unix))]
+            "syslog" => Err(format!("Log target {} is not available on this platform", s)),
+            #[cfg(not(target_os = "linux"))]
+            "journald" | "journal" => Err(format!("Log target {} is not available on this platform", s)),
+            _ => Err(format!("Invalid log target: {}", s)),
+        }
+    }
+}
+
+pub(crate) static LOG_TARGET: std::sync::OnceLock<LogTarget> = std::sync::OnceLock::new();
+
+/// Device, serial and method of the erase in progress, set by its start
+/// event so later events can be tagged with them
+pub(crate) static LOG_CONTEXT: std::sync::Mutex<Vec<(&'static str, String)>> = std::sync::Mutex::new(Vec::new());
+
+// syslog(3) priorities, also used by the journal
+pub(crate) const LOG_PRIORITY_ERR: u8 = 3;
+pub(crate) const LOG_PRIORITY_NOTICE: u8 = 5;
+pub(crate) const LOG_PRIORITY_INFO: u8 = 6;
+
+pub fn set_log_target(target: LogTarget) {
+    #[cfg(unix)]
+    if target == LogTarget::Syslog {
+        static IDENT: &[u8] = b"secure-eraser\0";
+        unsafe {
+            libc::openlog(IDENT.as_ptr() as *const libc::c_char, libc::LOG_PID, libc::LOG_DAEMON);
+        }
+    }
+    let _ = LOG_TARGET.set(target);
+}
+
+/// Turn a state transition into one log message for the `--log-target`
+pub(crate) fn log_lifecycle(event: &ProgressEvent) {
+    if LOG_TARGET.get().is_none() {
+        return;
+    }
+    let Ok(mut context) = LOG_CONTEXT.lock() else {
+        return;
+    };
+    let device = |context: &[(&str, String)]| {
+        context.iter().find(|(key, _)| *key == "DEVICE").map_or_else(String::new, |(_, value)| value.clone())
+    };
+    let (priority, message, result) = match event {
+        ProgressEvent::Start { device: path, method, passes, .. } => {
+            context.clear();
+            context.push(("DEVICE", path.display().to_string()));
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            if let Some(serial) = device_serial(&name) {
+                context.push(("SERIAL", serial));
+            }
+            context.push(("METHOD", format!("{:?}", method)));
+            (LOG_PRIORITY_NOTICE, format!("Erase of {} started: {:?}, {} pass(es)", path.display(), method, passes), None)
+        }
+        ProgressEvent::Progress { .. } => return,
+        ProgressEvent::PassComplete(pass) => (
+            LOG_PRIORITY_INFO,
+            format!("Pass {} ({}) on {} complete at {:.1} MB/s", pass.pass, pass.pattern, device(&context), pass.throughput_mb_s),
+            None,
+        ),
+        ProgressEvent::Verify(result) if result.passed() => (
+            LOG_PRIORITY_INFO,
+            format!("Verification of {} passed ({:.1}% coverage)", device(&context), result.coverage_percent),
+            None,
+        ),
+        ProgressEvent::Verify(result) => (
+            LOG_PRIORITY_ERR,
+            format!("Verification of {} FAILED at offset {}", device(&context), result.first_mismatch.unwrap_or(0)),
+            None,
+        ),
+        ProgressEvent::Result(report) => {
+            let result = match report.verification_passed() {
+                Some(false) => "verify-failed",
+                _ => "success",
+            };
+            (LOG_PRIORITY_NOTICE, format!("Erase of {} finished: {}", report.device.display(), result), Some(result))
+        }
+        ProgressEvent::Error { device: path, error } => {
+            if context.is_empty() {
+                context.push(("DEVICE", path.display().to_string()));
+            }
+            (LOG_PRIORITY_ERR, format!("Erase of {} failed: {}", path.display(), error), Some("failed"))
+        }
+    };
+    let mut fields = context.clone();
+    if let Some(result) = result {
+        fields.push(("RESULT", result.to_string()));
+    }
+    write_log(priority, &message, &fields);
+}
+
+pub(crate) fn write_log(priority: u8, message: &str, fields: &[(&str, String)]) {
+    let tags = fields.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(" ");
+    match LOG_TARGET.get() {
+        None => {}
+        Some(LogTarget::Stderr) => tracing::info!(target: "log", "[{}] {} [{}]", rfc3339(unix_time()), message, tags),
+        #[cfg(unix)]
+        Some(LogTarget::Syslog) => {
+            let line = format!("{} [{}]", message, tags).replace('\0', "");
+            if let Ok(line) = std::ffi::CString::new(line) {
+                unsafe {
+                    libc::syslog(libc::LOG_DAEMON | priority as libc::c_int, c"%s".as_ptr(), line.as_ptr());
+                }
+            }
+        }
+        #[cfg(target_os = "linux")]
+        Some(LogTarget::Journald) => {
+            let mut entry = Vec::new();
+            let mut field = |key: &str, value: &str| {
+                // Values with a newline need the length-prefixed binary form
+                if value.contains('\n') {
+                    entry.extend_from_slice(key.as_bytes());
+                    entry.push(b'\n');
+                    entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+                    entry.extend_from_slice(value.as_bytes());
+                } else {
+                    entry.extend_from_slice(format!("{}={}", key, value).as_bytes());
+                }
+                entry.push(b'\n');
+            };
+            field("MESSAGE", message);
+            field("PRIORITY", &priority.to_string());
+            field("SYSLOG_IDENTIFIER", "secure-eraser");
+            field("SYSLOG_FACILITY", "3");
+            for (key, value) in fields {
+                field(key, value);
+            }
+            let sent = std::os::unix::net::UnixDatagram::unbound()
+                .and_then(|socket| socket.send_to(&entry, "/run/systemd/journal/socket"));
+            static WARNED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+            if let Err(e) = sent {
+                if !WARNED.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                    warn!("Warning: cannot log to the journal: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Write one event as a line of JSON, flushed so a pipe reader sees it now
+pub fn emit(event: ProgressEvent) {
+    // Lifecycle events, not progress ticks, go to the audit log and the
+    // --log-target
+    if !matches!(event, ProgressEvent::Progress { .. }) {
+        if let Ok(value) = serde_json::to_value(&event) {
+            audit(value);
+        }
+        log_lifecycle(&event);
+    }
+    if !json_progress() {
+        return;
+    }
+    if let Ok(line) = serde_json::to_string(&event) {
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+/// Progress bar for a long operation; hidden entirely under --quiet. When
+/// stderr isn't a terminal the bar isn't drawn and `what` heads a plain,
+/// timestamped status line every PLAIN_STATUS_INTERVAL instead.
+pub fn progress_bar(len: u64, what: &'static str) -> ProgressBar {
+    use std::io::IsTerminal;
+
+    if verbosity() == 0 || json_progress() {
+        return ProgressBar::hidden();
+    }
+    if let Some(bars) = MULTI_BARS.get() {
+        return bars.multi.add(ProgressBar::new(len));
+    }
+    if io::stderr().is_terminal() {
+        let pb = ProgressBar::new(len);
+        if let Ok(mut active) = ACTIVE_BAR.lock() {
+            *active = Some(pb.downgrade());
+        }
+        return pb;
+    }
+
+    let pb = ProgressBar::hidden();
+    pb.set_length(len);
+    let weak = pb.downgrade();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(PLAIN_STATUS_INTERVAL);
+        let Some(pb) = weak.upgrade() else { return };
+        if pb.is_finished() {
+            return;
+        }
+        let percent = match pb.length() {
+            Some(len) if len > 0 => pb.position() as f64 / len as f64 * 100.0,
+            _ => 0.0,
+        };
+        // Among several erases' lines, say whose this is
+        let prefix = pb.prefix();
+        let device = if prefix.is_empty() { String::new() } else { format!("{} ", prefix) };
+        progress_note(format!("{}{}: {:.1}% {}", device, what, percent, pb.message()));
+    });
+    pb
+}
+
+/// A line printed above the progress bar (timestamped when no bar is drawn)
+pub fn progress_note(line: impl AsRef<str>) {
+    tracing::info!(target: "note", "{}", line.as_ref());
+}
+
+/// A bar template, with its color specs removed when color is off. Among
+/// several erases' bars it starts with the bar's device.
+pub fn bar_template(template: &str) -> String {
+    let labelled;
+    let template = match MULTI_BARS.get() {
+        Some(_) if !template.contains("{prefix") => {
+            labelled = format!("{{prefix:.bold}} {}", template);
+            &labelled
+        }
+        _ => template,
+    };
+    if COLOR.load(std::sync::atomic::Ordering::Relaxed) {
+        return template.to_string();
+    }
+
+    // "{bar:40.cyan/blue}" -> "{bar:40}", "{spinner:.green}" -> "{spinner}"
+    let mut plain = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else { break };
+        plain.push_str(&rest[..start]);
+        let key = &rest[start + 1..start + len];
+        match key.split_once(':') {
+            Some((name, spec)) => {
+                let width = spec.split('.').next().unwrap_or("");
+                if width.is_empty() {
+                    plain.push_str(&format!("{{{}}}", name));
+                } else {
+                    plain.push_str(&format!("{{{}:{}}}", name, width));
+                }
+            }
+            None => plain.push_str(&format!("{{{}}}", key)),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    plain.push_str(rest);
+    plain
+}
+
+/// format_size, with the exact byte count added at -vv and up
+pub fn size_label(bytes: u64) -> String {
+    if verbosity() >= 2 {
+        format!("{} ({} bytes)", format_size(bytes), bytes)
+    } else {
+        format_size(bytes)
+    }
+}
+
+/// Rough human duration: "45s", "12m 5s", "9h 40m", "13d 2h"
+pub fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        s if s < 86400 => format!("{}h {}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d {}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
+pub static PAUSED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Terminal settings to put back if a second Ctrl-C exits while `p` is
+/// being watched
+#[cfg(unix)]
+pub static SAVED_TERMINAL: std::sync::OnceLock<(RawFd, libc::termios)> = std::sync::OnceLock::new();
+
+/// Block while a pause is requested (returning early on an interrupt) and
+/// report how long that took
+pub(crate) fn wait_while_paused(pb: &ProgressBar) -> std::time::Duration {
+    if !PAUSED.load(std::sync::atomic::Ordering::SeqCst) {
+        return std::time::Duration::ZERO;
+    }
+
+    let started = std::time::Instant::now();
+    let message = pb.message();
+    pb.set_message("Paused (SIGUSR2 or 'p' to resume)");
+    while PAUSED.load(std::sync::atomic::Ordering::SeqCst) && !is_interrupted() {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        pb.tick();
+    }
+    pb.set_message(message);
+    started.elapsed()
+}
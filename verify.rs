@@ -0,0 +1,875 @@
+//! Reading a wiped device back: sampled, full and hashed verification, and scans
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use rand::Rng;
+use indicatif::ProgressStyle;
+use serde::Serialize;
+
+use crate::{device::*, erase::*, error::*, patterns::*, ui::*};
+use crate::*;
+
+/// Where sampled verification places its reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SampleStrategy {
+    Random,   // First, middle and last block plus random offsets
+    Head,     // Consecutive blocks from the start of the device
+    Spread,   // Evenly spaced across the device
+    Edges,    // Dense in the first and last 1GB, sparse in between
+}
+
+impl std::str::FromStr for SampleStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(SampleStrategy::Random),
+            "head" => Ok(SampleStrategy::Head),
+            "spread" => Ok(SampleStrategy::Spread),
+            "edges" => Ok(SampleStrategy::Edges),
+            _ => Err(format!("Invalid verify strategy: {}", s)),
+        }
+    }
+}
+
+/// How thoroughly the final pass is read back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum VerifyMode {
+    Sample { count: usize, strategy: SampleStrategy },
+    Percent { percent: u8, seed: u64 },  // Seeded random blocks covering a share of the device
+    Full,           // Every byte of the device
+    Hash,           // Re-read everything and compare per-segment BLAKE3 digests
+}
+
+impl std::str::FromStr for VerifyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sample" => Ok(VerifyMode::Sample {
+                count: DEFAULT_VERIFY_SAMPLES,
+                strategy: SampleStrategy::Random,
+            }),
+            "full" => Ok(VerifyMode::Full),
+            "hash" => Ok(VerifyMode::Hash),
+            _ => Err(format!("Invalid verify mode: {}", s)),
+        }
+    }
+}
+
+/// A single block read back during sampled verification
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleResult {
+    pub offset: u64,
+    pub len: usize,
+    pub matched: bool,
+}
+
+/// A block that did not read back as expected, with a short hex dump taken
+/// at the first differing byte
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub offset: u64,
+    pub expected: String,
+    pub actual: String,
+    pub unreadable: bool,
+}
+
+/// Outcome of reading back the final pass
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyResult {
+    pub mode: VerifyMode,
+    pub device_size: u64,
+    pub bytes_checked: u64,
+    pub bytes_verified: u64,
+    pub blocks_checked: u64,
+    pub blocks_matched: u64,
+    pub coverage_percent: f64,
+    pub first_mismatch: Option<u64>,
+    pub mismatches: Vec<Mismatch>,   // First MAX_REPORTED_MISMATCHES only
+    pub excused: u64,                // Mismatches inside sectors skipped as unwritable
+    pub samples: Vec<SampleResult>,
+    #[serde(skip)]
+    failed: Vec<BadBlock>,           // Every failing block, merged; for the bad-block map
+}
+
+impl VerifyResult {
+    fn new(mode: VerifyMode, device_size: u64) -> Self {
+        Self {
+            mode,
+            device_size,
+            bytes_checked: 0,
+            bytes_verified: 0,
+            blocks_checked: 0,
+            blocks_matched: 0,
+            coverage_percent: 0.0,
+            first_mismatch: None,
+            mismatches: Vec::new(),
+            excused: 0,
+            samples: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Account for one checked block (or hash segment)
+    fn record(&mut self, offset: u64, len: usize, mismatch: Option<Mismatch>) {
+        self.blocks_checked += 1;
+        self.bytes_checked += len as u64;
+        self.coverage_percent = if self.device_size > 0 {
+            self.bytes_checked as f64 / self.device_size as f64 * 100.0
+        } else {
+            100.0
+        };
+
+        if let Some(ref mismatch) = mismatch {
+            // An unreadable block is bad from where the read stopped
+            let (kind, start) = if mismatch.unreadable { (BadBlockKind::Unreadable, mismatch.offset) } else { (BadBlockKind::Mismatch, offset) };
+            let bad = BadBlock { offset: start, length: offset + len as u64 - start, kind };
+            match self.failed.last_mut() {
+                Some(last) if last.kind == kind && last.offset + last.length == bad.offset => last.length += bad.length,
+                _ => self.failed.push(bad),
+            }
+        }
+
+        let matched = mismatch.is_none();
+        match mismatch {
+            None => {
+                self.blocks_matched += 1;
+                self.bytes_verified += len as u64;
+            }
+            Some(mismatch) => {
+                if self.first_mismatch.is_none_or(|first| mismatch.offset < first) {
+                    self.first_mismatch = Some(mismatch.offset);
+                }
+                if self.mismatches.len() < MAX_REPORTED_MISMATCHES {
+                    self.mismatches.push(mismatch);
+                }
+            }
+        }
+
+        if !matches!(self.mode, VerifyMode::Full) {
+            self.samples.push(SampleResult { offset, len, matched });
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.first_mismatch.is_none()
+    }
+
+    /// Drop the mismatches explained by sectors the erase already reported
+    /// unwritable: differing bytes inside them, or an unreadable block or
+    /// hash segment (`span` bytes from the mismatch) overlapping them
+    pub(crate) fn excuse(&mut self, unwritable: &[ByteRange], span: u64) {
+        if unwritable.is_empty() {
+            return;
+        }
+        // first_mismatch can only be cleared if every mismatch was listed
+        let listed_all = (self.blocks_checked - self.blocks_matched) as usize == self.mismatches.len();
+        let hashed = self.mode == VerifyMode::Hash;
+        let before = self.mismatches.len();
+        self.mismatches.retain(|mismatch| {
+            let window = if mismatch.unreadable || hashed { span } else { 1 };
+            !unwritable.iter().any(|bad| bad.offset < mismatch.offset + window && mismatch.offset < bad.end())
+        });
+        self.excused = (before - self.mismatches.len()) as u64;
+        self.failed.retain(|failed| !unwritable.iter().any(|bad| bad.offset < failed.offset + failed.length && failed.offset < bad.end()));
+        if listed_all {
+            self.first_mismatch = self.mismatches.iter().map(|mismatch| mismatch.offset).min();
+        }
+    }
+}
+
+/// Why a range is in the bad-block map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BadBlockKind {
+    Write,       // Kept failing to write and was skipped
+    Unreadable,  // Could not be read back by verification
+    Mismatch,    // Read back, but not as written
+}
+
+impl std::fmt::Display for BadBlockKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BadBlockKind::Write => "write",
+            BadBlockKind::Unreadable => "unreadable",
+            BadBlockKind::Mismatch => "mismatch",
+        })
+    }
+}
+
+/// One range of the bad-block map, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BadBlock {
+    pub offset: u64,
+    pub length: u64,
+    pub kind: BadBlockKind,
+}
+
+/// Everything that went wrong on the medium, in device order: sectors the
+/// erase skipped, then what verification could not read or found changed
+pub(crate) fn bad_block_map(unwritable: &[ByteRange], verification: Option<&VerifyResult>) -> Vec<BadBlock> {
+    let mut map: Vec<BadBlock> = unwritable.iter()
+        .map(|bad| BadBlock { offset: bad.offset, length: bad.length, kind: BadBlockKind::Write })
+        .collect();
+    if let Some(result) = verification {
+        map.extend_from_slice(&result.failed);
+    }
+    map.sort_by_key(|bad| bad.offset);
+    map
+}
+
+/// Statistics for one contiguous region of a scanned device
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanRegion {
+    pub offset: u64,
+    pub length: u64,
+    pub bytes_scanned: u64,
+    pub zero_fraction: f64,
+    pub entropy: f64,          // Shannon entropy in bits per byte (0.0 - 8.0)
+    pub constant_byte: Option<u8>,
+    pub signatures: Vec<String>,
+}
+
+/// Overall answer to "does this disk still contain data?"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ScanVerdict {
+    Blank,          // Every scanned region is a single repeated byte
+    RandomFill,     // High-entropy data with no recognizable structure
+    Mixed,          // Only blank and random regions
+    ResidualData,   // Signatures or structured data found
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanReport {
+    pub device: PathBuf,
+    pub mode: VerifyMode,
+    pub device_size: u64,
+    pub bytes_scanned: u64,
+    pub regions: Vec<ScanRegion>,
+    pub verdict: ScanVerdict,
+}
+
+/// Computes one BLAKE3 digest per fixed-size segment of a byte stream, so
+/// memory stays bounded (32 bytes per segment) however large the device is
+pub struct SegmentHasher {
+    segment_size: u64,
+    filled: u64,
+    hasher: blake3::Hasher,
+    digests: Vec<[u8; 32]>,
+}
+
+impl SegmentHasher {
+    pub fn new(segment_size: u64) -> Self {
+        Self {
+            segment_size,
+            filled: 0,
+            hasher: blake3::Hasher::new(),
+            digests: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let room = (self.segment_size - self.filled) as usize;
+            let take = std::cmp::min(room, data.len());
+            self.hasher.update(&data[..take]);
+            self.filled += take as u64;
+            data = &data[take..];
+
+            if self.filled == self.segment_size {
+                self.digests.push(*self.hasher.finalize().as_bytes());
+                self.hasher.reset();
+                self.filled = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<[u8; 32]> {
+        if self.filled > 0 {
+            self.digests.push(*self.hasher.finalize().as_bytes());
+        }
+        self.digests
+    }
+}
+
+impl SecureEraser {
+    /// Read every byte of `extents` back and compare it with `expected`
+    pub(crate) fn verify_extents(
+        &self,
+        path: &Path,
+        expected: PassFill,
+        size: u64,
+        extents: &[(u64, u64)],
+    ) -> Result<bool, EraseError> {
+        let mut file = File::open(path)?;
+        let mut buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+        for &(start, len) in extents {
+            let mut offset = start;
+            while offset < start + len {
+                let end = std::cmp::min(start + len, size);
+                let (read, mismatch) = check_block(&mut file, offset, end, &mut buffer, expected)?;
+                if mismatch.is_some() || read == 0 {
+                    return Ok(mismatch.is_none());
+                }
+                offset += read as u64;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Verify a device against a pattern without writing anything, e.g. to
+    /// re-check a drive wiped in an earlier session or by another tool
+    ///
+    /// ```
+    /// use memerase::{EraseError, SecureEraser, VerifyMode, WipePattern};
+    ///
+    /// let path = std::env::temp_dir().join(format!("memerase-doc-verify-{}", std::process::id()));
+    /// std::fs::write(&path, vec![0u8; 4 * 1024 * 1024])?;
+    ///
+    /// let mut eraser = SecureEraser::new();
+    /// let result = eraser.verify_device(&path, WipePattern::Zeros, VerifyMode::Full)?;
+    /// assert!(result.passed());
+    /// assert_eq!(result.bytes_verified, 4 * 1024 * 1024);
+    ///
+    /// // A single stray byte is found and located
+    /// std::fs::write(&path, [vec![0u8; 4096], vec![0xAA], vec![0u8; 4095]].concat())?;
+    /// let result = eraser.verify_device(&path, WipePattern::Zeros, VerifyMode::Full)?;
+    /// assert!(!result.passed());
+    /// assert_eq!(result.first_mismatch, Some(4096));
+    /// # std::fs::remove_file(&path)?;
+    /// # Ok::<(), EraseError>(())
+    /// ```
+    pub fn verify_device(
+        &mut self,
+        device_path: &Path,
+        pattern: WipePattern,
+        mode: VerifyMode,
+    ) -> Result<VerifyResult, EraseError> {
+        if pattern.final_pass_is_random() {
+            return Err(EraseError::Unsupported { what: format!(
+                "Pattern {:?} ends with a random pass and cannot be verified without the original data",
+                pattern
+            ) });
+        }
+
+        let file = File::open(device_path).map_err(|e| EraseError::open(device_path, e))?;
+        let mut device_size = self.get_device_size(&file, device_path)?;
+        drop(file);
+
+        // A stamped device carries its record in the last sector; leave it out
+        if self.read_stamp(device_path)?.is_some() {
+            device_size -= STAMP_SIZE;
+        }
+
+        let passes = self.pass_plan(pattern);
+        let expected = *passes.last().ok_or_else(|| EraseError::Other("Pattern produced no passes".to_string()))?;
+
+        let _cancel_scope = CancelScope::enter(self.cancel.clone());
+        self.run_verification(device_path, expected, ByteRange::whole(device_size), mode)
+    }
+
+    /// Read-only scan reporting per-region entropy, zero fraction and known
+    /// filesystem/partition signatures. Never opens the device for writing.
+    pub fn scan_device(
+        &mut self,
+        device_path: &Path,
+        mode: VerifyMode,
+    ) -> Result<ScanReport, EraseError> {
+        let mut file = File::open(device_path).map_err(|e| EraseError::open(device_path, e))?;
+        let device_size = self.get_device_size(&file, device_path)?;
+        let total_blocks = device_size.div_ceil(self.block_size as u64);
+        let region_count = total_blocks.clamp(1, SCAN_REGIONS);
+        let blocks_per_region = total_blocks.div_ceil(region_count);
+
+        let mut read_buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+        let mut regions = Vec::new();
+
+        for region_index in 0..region_count {
+            let first_block = region_index * blocks_per_region;
+            let end_block = std::cmp::min(first_block + blocks_per_region, total_blocks);
+            let offset = first_block * self.block_size as u64;
+            let end = std::cmp::min(end_block * self.block_size as u64, device_size);
+
+            // Always read the region's first block so partition starts are probed
+            let blocks: Vec<u64> = match mode {
+                VerifyMode::Full | VerifyMode::Hash | VerifyMode::Percent { .. } => {
+                    (first_block..end_block).collect()
+                }
+                VerifyMode::Sample { count, .. } => {
+                    let per_region = std::cmp::max(1, count as u64 / region_count);
+                    let mut picked = std::collections::BTreeSet::new();
+                    picked.insert(first_block);
+                    while (picked.len() as u64) < std::cmp::min(per_region, end_block - first_block) {
+                        picked.insert(self.rng.gen_range(first_block..end_block));
+                    }
+                    picked.into_iter().collect()
+                }
+            };
+
+            let mut histogram = [0u64; 256];
+            let mut signatures = Vec::new();
+            let mut bytes_scanned = 0u64;
+
+            for block in blocks {
+                let block_offset = block * self.block_size as u64;
+                let len = std::cmp::min(self.block_size as u64, device_size - block_offset) as usize;
+                file.seek(SeekFrom::Start(block_offset))?;
+                file.read_exact(&mut read_buffer[..len])?;
+
+                for &byte in &read_buffer[..len] {
+                    histogram[byte as usize] += 1;
+                }
+                for signature in detect_signatures(&read_buffer[..len], block_offset, device_size) {
+                    if !signatures.contains(&signature) {
+                        signatures.push(signature);
+                    }
+                }
+                bytes_scanned += len as u64;
+            }
+
+            let distinct: Vec<usize> = (0..256).filter(|&b| histogram[b] > 0).collect();
+            regions.push(ScanRegion {
+                offset,
+                length: end - offset,
+                bytes_scanned,
+                zero_fraction: if bytes_scanned > 0 { histogram[0] as f64 / bytes_scanned as f64 } else { 0.0 },
+                entropy: shannon_entropy(&histogram, bytes_scanned),
+                constant_byte: if distinct.len() == 1 { Some(distinct[0] as u8) } else { None },
+                signatures,
+            });
+        }
+
+        let verdict = scan_verdict(&regions);
+        Ok(ScanReport {
+            device: device_path.to_path_buf(),
+            mode,
+            device_size,
+            bytes_scanned: regions.iter().map(|r| r.bytes_scanned).sum(),
+            regions,
+            verdict,
+        })
+    }
+
+    /// Read back `range` of the device, which the final pass filled with `expected`
+    pub(crate) fn run_verification(
+        &mut self,
+        device_path: &Path,
+        expected: PassFill,
+        range: ByteRange,
+        mode: VerifyMode,
+    ) -> Result<VerifyResult, EraseError> {
+        match mode {
+            VerifyMode::Sample { count, strategy } => {
+                let blocks = self.pick_sample_blocks(range.length, count, strategy);
+                self.verify_erase(device_path, expected, range, mode, blocks)
+            }
+            VerifyMode::Percent { percent, seed } => {
+                let blocks = pick_percent_blocks(range.length, self.block_size, percent, seed);
+                self.verify_erase(device_path, expected, range, mode, blocks)
+            }
+            VerifyMode::Full => self.verify_full(device_path, expected, range),
+            VerifyMode::Hash => Err(EraseError::Unsupported { what: "Hash verification needs digests recorded while writing".to_string() }),
+        }
+    }
+
+    /// Verify erase by reading back data (sample verification of `blocks`,
+    /// counted from the start of `range`)
+    fn verify_erase(
+        &self,
+        device_path: &Path,
+        expected: PassFill,
+        range: ByteRange,
+        mode: VerifyMode,
+        blocks: Vec<u64>,
+    ) -> Result<VerifyResult, EraseError> {
+        let mut file = File::open(device_path).map_err(|e| EraseError::open(device_path, e))?;
+        let mut read_buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+        let mut result = VerifyResult::new(mode, range.length);
+
+        for block in blocks {
+            check_interrupted()?;
+            let offset = range.offset + block * self.block_size as u64;
+            let (len, mismatch) = check_block(&mut file, offset, range.end(), &mut read_buffer, expected)?;
+            result.record(offset, len, mismatch);
+        }
+
+        Ok(result)
+    }
+
+    /// Choose which blocks to sample according to `strategy`. Every strategy
+    /// except `Head` includes the last (possibly partial) block. Sorted so
+    /// reads seek forward.
+    fn pick_sample_blocks(&mut self, device_size: u64, count: usize, strategy: SampleStrategy) -> Vec<u64> {
+        let total_blocks = device_size.div_ceil(self.block_size as u64);
+
+        if total_blocks <= count as u64 {
+            return (0..total_blocks).collect();
+        }
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let mut blocks = std::collections::BTreeSet::new();
+        let last = total_blocks - 1;
+
+        match strategy {
+            SampleStrategy::Head => {
+                blocks.extend(0..count as u64);
+            }
+            SampleStrategy::Random => {
+                for block in [0, total_blocks / 2, last] {
+                    if blocks.len() < count {
+                        blocks.insert(block);
+                    }
+                }
+                while blocks.len() < count {
+                    blocks.insert(self.rng.gen_range(0..total_blocks));
+                }
+            }
+            SampleStrategy::Spread => {
+                blocks.extend(spaced_blocks(0, last, count as u64));
+            }
+            SampleStrategy::Edges => {
+                let edge_blocks = std::cmp::min(EDGE_SPAN / self.block_size as u64, total_blocks / 2);
+                let edge_count = std::cmp::max(1, count as u64 * 2 / 5);
+                let middle_count = count as u64 - std::cmp::min(count as u64, edge_count * 2);
+
+                blocks.extend(spaced_blocks(0, edge_blocks.saturating_sub(1), edge_count));
+                blocks.extend(spaced_blocks(total_blocks - edge_blocks.max(1), last, edge_count));
+                if middle_count > 0 && total_blocks > 2 * edge_blocks {
+                    blocks.extend(spaced_blocks(edge_blocks, total_blocks - edge_blocks - 1, middle_count));
+                }
+            }
+        }
+
+        blocks.into_iter().collect()
+    }
+
+    /// Verify erase by re-reading the device and comparing per-segment digests
+    /// recorded while the final pass was written
+    pub(crate) fn verify_hashes(
+        &self,
+        device_path: &Path,
+        digests: &[[u8; 32]],
+        range: ByteRange,
+    ) -> Result<VerifyResult, EraseError> {
+        let mut file = File::open(device_path).map_err(|e| EraseError::open(device_path, e))?;
+        let mut read_buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+        let mut result = VerifyResult::new(VerifyMode::Hash, range.length);
+
+        let pb = progress_bar(range.length, "hashing");
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&bar_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) hashing"))
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        let _slot = label_bar(&pb, device_path);
+
+        for (segment, expected) in digests.iter().enumerate() {
+            let offset = range.offset + segment as u64 * HASH_SEGMENT_SIZE;
+            let end = std::cmp::min(offset + HASH_SEGMENT_SIZE, range.end());
+            let mut hasher = blake3::Hasher::new();
+            let mut position = offset;
+            let mut short_read = false;
+
+            file.seek(SeekFrom::Start(offset))?;
+            while position < end {
+                check_interrupted()?;
+                let len = std::cmp::min(self.block_size as u64, end - position) as usize;
+                match file.read_exact(&mut read_buffer[..len]) {
+                    Ok(()) => {}
+                    Err(e) if is_device_gone(&e) => return Err(e.into()),
+                    Err(_) => {
+                        short_read = true;
+                        break;
+                    }
+                }
+                hasher.update(&read_buffer[..len]);
+                position += len as u64;
+                pb.set_position(position - range.offset);
+            }
+
+            // Digests only localize a mismatch to its segment, so the dump
+            // shows digest prefixes rather than data
+            let actual = hasher.finalize();
+            let mismatch = if short_read {
+                Some(Mismatch {
+                    offset: position,
+                    expected: hex_string(&expected[..MISMATCH_DUMP_LEN]),
+                    actual: "<unreadable>".to_string(),
+                    unreadable: true,
+                })
+            } else if actual.as_bytes() != expected {
+                Some(Mismatch {
+                    offset,
+                    expected: hex_string(&expected[..MISMATCH_DUMP_LEN]),
+                    actual: hex_string(&actual.as_bytes()[..MISMATCH_DUMP_LEN]),
+                    unreadable: false,
+                })
+            } else {
+                None
+            };
+            result.record(offset, (end - offset) as usize, mismatch);
+        }
+
+        pb.finish_and_clear();
+        Ok(result)
+    }
+
+    /// Verify erase by reading back all of `range`
+    fn verify_full(
+        &self,
+        device_path: &Path,
+        expected: PassFill,
+        range: ByteRange,
+    ) -> Result<VerifyResult, EraseError> {
+        let mut file = File::open(device_path).map_err(|e| EraseError::open(device_path, e))?;
+        let mut read_buffer = AlignedBuffer::new(self.block_size, DIRECT_IO_ALIGN);
+        let mut result = VerifyResult::new(VerifyMode::Full, range.length);
+
+        let pb = progress_bar(range.length, "verifying");
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&bar_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) verifying"))
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        let _slot = label_bar(&pb, device_path);
+
+        // Keep going past mismatches so every failing region gets reported;
+        // only running out of readable data ends the scan early
+        while result.bytes_checked < range.length {
+            check_interrupted()?;
+            let offset = range.offset + result.bytes_checked;
+            let (len, mismatch) = check_block(&mut file, offset, range.end(), &mut read_buffer, expected)?;
+            let unreadable = mismatch.as_ref().is_some_and(|m| m.unreadable);
+
+            result.record(offset, len, mismatch);
+            pb.set_position(result.bytes_checked);
+
+            let known_bad = self.unwritable.iter().any(|bad| bad.offset < offset + len as u64 && offset < bad.end());
+            if unreadable && !known_bad {
+                break;
+            }
+        }
+
+        pb.finish_and_clear();
+        Ok(result)
+    }
+}
+
+/// Distinct random blocks covering at least `percent` of the device, chosen
+/// from `seed` so the same selection can be reproduced later
+pub(crate) fn pick_percent_blocks(device_size: u64, block_size: usize, percent: u8, seed: u64) -> Vec<u64> {
+    use rand::SeedableRng;
+
+    let total_blocks = device_size.div_ceil(block_size as u64);
+    let count = (total_blocks * std::cmp::min(percent, 100) as u64).div_ceil(100);
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut blocks: Vec<u64> = rand::seq::index::sample(&mut rng, total_blocks as usize, count as usize)
+        .into_iter()
+        .map(|block| block as u64)
+        .collect();
+    blocks.sort_unstable();
+    blocks
+}
+
+/// `count` block indices evenly spaced over `first..=last`, including both ends
+pub(crate) fn spaced_blocks(first: u64, last: u64, count: u64) -> Vec<u64> {
+    match count {
+        0 => Vec::new(),
+        1 => vec![first],
+        _ => (0..count)
+            .map(|i| first + (last - first) * i / (count - 1))
+            .collect(),
+    }
+}
+
+/// Shannon entropy (bits per byte) of a byte histogram
+pub(crate) fn shannon_entropy(histogram: &[u64; 256], total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    histogram.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Recognize common partition table and filesystem signatures at the start
+/// of a block (partitions are normally 1 MiB aligned, so this catches most)
+pub(crate) fn detect_signatures(block: &[u8], block_offset: u64, device_size: u64) -> Vec<String> {
+    let mut found = Vec::new();
+    let has = |offset: usize, magic: &[u8]| {
+        block.len() >= offset + magic.len() && &block[offset..offset + magic.len()] == magic
+    };
+
+    if has(510, &[0x55, 0xAA]) {
+        found.push(if block_offset == 0 { "MBR" } else { "boot sector" }.to_string());
+    }
+    if block_offset == 0 && has(512, b"EFI PART") {
+        found.push("GPT".to_string());
+    }
+    // Backup GPT header lives in the last 512-byte sector of the device
+    let backup_gpt = device_size.saturating_sub(512);
+    if backup_gpt >= block_offset && backup_gpt - block_offset + 8 <= block.len() as u64
+        && has((backup_gpt - block_offset) as usize, b"EFI PART") {
+        found.push("GPT (backup)".to_string());
+    }
+    if has(1024 + 56, &[0x53, 0xEF]) {
+        found.push("ext2/3/4".to_string());
+    }
+    if has(3, b"NTFS    ") {
+        found.push("NTFS".to_string());
+    }
+    if has(3, b"EXFAT   ") {
+        found.push("exFAT".to_string());
+    }
+    if has(82, b"FAT32   ") || has(54, b"FAT16   ") || has(54, b"FAT12   ") {
+        found.push("FAT".to_string());
+    }
+    if has(0, b"XFSB") {
+        found.push("XFS".to_string());
+    }
+    if has(0x10040, b"_BHRfS_M") {
+        found.push("btrfs".to_string());
+    }
+    if has(0, b"LUKS\xba\xbe") {
+        found.push("LUKS".to_string());
+    }
+    if has(4086, b"SWAPSPACE2") {
+        found.push("swap".to_string());
+    }
+
+    found
+}
+
+pub(crate) fn scan_verdict(regions: &[ScanRegion]) -> ScanVerdict {
+    let mut blank = 0;
+    let mut random = 0;
+
+    for region in regions {
+        if !region.signatures.is_empty() {
+            return ScanVerdict::ResidualData;
+        }
+        if region.constant_byte.is_some() || region.bytes_scanned == 0 {
+            blank += 1;
+        } else if region.entropy >= 7.9 {
+            random += 1;
+        } else {
+            return ScanVerdict::ResidualData;
+        }
+    }
+
+    match (blank, random) {
+        (_, 0) => ScanVerdict::Blank,
+        (0, _) => ScanVerdict::RandomFill,
+        _ => ScanVerdict::Mixed,
+    }
+}
+
+/// Human-readable summary lines for a verification result
+pub fn describe_verification(result: &VerifyResult) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    match result.mode {
+        VerifyMode::Sample { count, strategy } => {
+            lines.push(format!("Strategy: {:?}, {} samples requested", strategy, count));
+        }
+        VerifyMode::Percent { percent, seed } => {
+            lines.push(format!("Random {}% coverage, seed {}", percent, seed));
+        }
+        _ => {}
+    }
+    lines.push(format!(
+        "Checked {} blocks, {} matched, coverage {:.2}% of device",
+        result.blocks_checked, result.blocks_matched, result.coverage_percent
+    ));
+
+    for mismatch in &result.mismatches {
+        lines.push(format!("  Mismatch at offset {}", mismatch.offset));
+        lines.push(format!("    expected: {}", mismatch.expected));
+        lines.push(format!("    actual:   {}", mismatch.actual));
+    }
+    let unreported = (result.blocks_checked - result.blocks_matched - result.excused) as usize - result.mismatches.len();
+    if unreported > 0 {
+        lines.push(format!("  ... and {} more mismatching blocks", unreported));
+    }
+    if result.excused > 0 {
+        lines.push(format!("  {} mismatching {} overlap sectors skipped as unwritable and are not counted",
+                           result.excused, if result.mode == VerifyMode::Hash { "segment(s)" } else { "block(s)" }));
+    }
+
+    lines.push(match result.first_mismatch {
+        Some(offset) => format!(
+            "Warning: Verification failed! First mismatch at offset {} ({} bytes verified)",
+            offset, result.bytes_verified
+        ),
+        None => format!(
+            "Verification successful! ({} bytes verified)",
+            result.bytes_verified
+        ),
+    });
+
+    lines
+}
+
+/// Read back the block at `offset` and compare it against `expected`.
+///
+/// The block is clamped to `end` (of the device or checked range), so the
+/// final partial block is only compared over its valid prefix. Returns the block length and, on failure,
+/// the absolute offset of the first mismatching byte with a hex dump; running
+/// out of data early counts as a mismatch at the offset where the data ended.
+pub(crate) fn check_block(
+    file: &mut File,
+    offset: u64,
+    end: u64,
+    buffer: &mut [u8],
+    expected: PassFill,
+) -> io::Result<(usize, Option<Mismatch>)> {
+    let len = std::cmp::min(buffer.len() as u64, end.saturating_sub(offset)) as usize;
+    let chunk = &mut buffer[..len];
+
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut filled = 0;
+    while filled < len {
+        let unreadable = match file.read(&mut chunk[filled..]) {
+            Ok(0) => true,
+            Ok(n) => {
+                filled += n;
+                false
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => false,
+            Err(e) if is_device_gone(&e) => return Err(e),
+            Err(_) => true,
+        };
+        if unreadable {
+            let dump_len = std::cmp::min(MISMATCH_DUMP_LEN, len - filled);
+            return Ok((len, Some(Mismatch {
+                offset: offset + filled as u64,
+                expected: hex_string(&expected.bytes_at(offset + filled as u64, dump_len)),
+                actual: "<unreadable>".to_string(),
+                unreadable: true,
+            })));
+        }
+    }
+
+    let mismatch = expected.first_mismatch(chunk, offset).map(|pos| {
+        let dump_len = std::cmp::min(MISMATCH_DUMP_LEN, len - pos);
+        Mismatch {
+            offset: offset + pos as u64,
+            expected: hex_string(&expected.bytes_at(offset + pos as u64, dump_len)),
+            actual: hex_string(&chunk[pos..pos + dump_len]),
+            unreadable: false,
+        }
+    });
+    Ok((len, mismatch))
+}